@@ -1,5 +1,8 @@
+use std::path::PathBuf;
+
 use cu::pre::*;
 use dejj_utils::Config;
+use exstractor::ErrorKind;
 static LOGO: &str = r" _____  ______    __    __  
 /\  __-.\  ___\  /\ \  /\ \ 
 \ \ \/\ \\  __\ _\_\ \_\_\ \
@@ -39,6 +42,14 @@ impl CmdMain {
 #[derive(clap::Subcommand)]
 pub enum CmdSubcommand {
     Extract(CmdExtract),
+    Shrink(CmdShrink),
+    Export(CmdExport),
+    Check(CmdCheck),
+    Clean(CmdClean),
+    Diff(CmdDiff),
+    Query(CmdQuery),
+    MergeDb(CmdMergeDb),
+    Repro(CmdRepro),
     /// Print the version
     Version(cu::cli::Flags),
 }
@@ -47,6 +58,14 @@ impl AsRef<cu::cli::Flags> for CmdSubcommand {
     fn as_ref(&self) -> &cu::cli::Flags {
         match self {
             Self::Extract(cmd) => cmd.as_ref(),
+            Self::Shrink(cmd) => cmd.as_ref(),
+            Self::Export(cmd) => cmd.as_ref(),
+            Self::Check(cmd) => cmd.as_ref(),
+            Self::Clean(cmd) => cmd.as_ref(),
+            Self::Diff(cmd) => cmd.as_ref(),
+            Self::Query(cmd) => cmd.as_ref(),
+            Self::MergeDb(cmd) => cmd.as_ref(),
+            Self::Repro(cmd) => cmd.as_ref(),
             Self::Version(cmd) => cmd.as_ref(),
         }
     }
@@ -66,18 +85,317 @@ pub fn main(args: CmdMain) -> cu::Result<()> {
         return Ok(());
     };
 
-    let config = Config::load(args.config)?;
+    if let CmdSubcommand::Check(_) = cmd {
+        // config errors are part of the report
+        return exstractor::check(args.config);
+    }
+    if let CmdSubcommand::Repro(cmd) = cmd {
+        // the config file is bundled
+        return exstractor::repro(args.config, cmd.into());
+    }
+    if let CmdSubcommand::Diff(cmd) = cmd {
+        // the databases to compare are not from the config
+        return exstractor::diff(cmd.into());
+    }
+    if let CmdSubcommand::MergeDb(cmd) = cmd {
+        // the databases to merge are not from the config
+        return exstractor::merge_db(cmd.into());
+    }
+
+    let mut exit_code = exstractor::EXIT_SUCCESS;
+    let result = Config::load(args.config)
+        .context(ErrorKind::Config)
+        .and_then(|config| match cmd {
+            CmdSubcommand::Extract(cmd) => {
+                let summary = exstractor::run(config, cmd.into())?;
+                exit_code = summary.exit_code;
+                Ok(())
+            }
+            CmdSubcommand::Shrink(cmd) => exstractor::shrink(&config, cmd.into()),
+            CmdSubcommand::Export(cmd) => exstractor::export(&config, cmd.into()),
+            CmdSubcommand::Clean(cmd) => exstractor::clean(&config, cmd.into()),
+            CmdSubcommand::Query(cmd) => exstractor::query(&config, cmd.into()),
+            CmdSubcommand::Check(_)
+            | CmdSubcommand::Diff(_)
+            | CmdSubcommand::MergeDb(_)
+            | CmdSubcommand::Repro(_)
+            | CmdSubcommand::Version(_) => Ok(()),
+        });
 
-    match cmd {
-        CmdSubcommand::Extract(_) => exstractor::run(config),
-        CmdSubcommand::Version(_) => Ok(()),
+    // categorized errors exit with the code of the category, so automation
+    // can tell them apart without parsing the logs
+    if let Err(e) = &result {
+        let Some(kind) = ErrorKind::of(e) else {
+            return result;
+        };
+        cu::error!("{e:?}");
+        std::process::exit(kind.exit_code());
     }
+    if exit_code == exstractor::EXIT_PARTIAL {
+        cu::warn!("extraction is partial, some compilation units needed degraded settings or were skipped");
+    }
+    if exit_code != exstractor::EXIT_SUCCESS {
+        std::process::exit(exit_code);
+    }
+    result
 }
 
 /// Extract database artifacts from DWARF info from an ELF file
 #[derive(Debug, clap::Parser, AsRef)]
 pub struct CmdExtract {
+    /// Skip the type optimizer, and emit the layouts as-is in DWARF
+    #[clap(long)]
+    pub no_optimize: bool,
+    /// Log everything that happens to one type through all stages.
+    /// The type can be a goff (i.e. 0x1234) or a fully-qualified name
+    #[clap(long, value_name = "NAME_OR_GOFF")]
+    pub trace_type: Option<String>,
+    /// Only load symbols and emit a symbol listing (symbols.json), without type layouts
+    #[clap(long)]
+    pub symbols_only: bool,
+    /// Remove the lock file of the output directory left by another run.
+    /// Only use this if no other extract run is active
+    #[clap(long)]
+    pub force_unlock: bool,
+    /// Save timing of each stage, type counts of each compilation unit and
+    /// other stats of the run to this path as JSON
+    #[clap(long, value_name = "PATH")]
+    pub stats_out: Option<PathBuf>,
+    /// Save the final types in debug format to this path. This is very large for real programs,
+    /// and is only for debugging the extractor
+    #[clap(long, value_name = "PATH")]
+    pub dump_hstage: Option<PathBuf>,
+    /// Skip compilation units that still fail to load or reduce with the safest settings,
+    /// and continue with the rest. The failed units are saved to failures.json
+    #[clap(long)]
+    pub keep_going: bool,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdExtract> for exstractor::ExtractOptions {
+    fn from(cmd: CmdExtract) -> Self {
+        Self {
+            no_optimize: cmd.no_optimize,
+            trace_type: cmd.trace_type,
+            symbols_only: cmd.symbols_only,
+            skip_build: false,
+            force_unlock: cmd.force_unlock,
+            stats_out: cmd.stats_out,
+            dump_hstage: cmd.dump_hstage,
+            keep_going: cmd.keep_going,
+        }
+    }
+}
+
+/// Shrink an extracted database into a lite database for editor tooling
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdShrink {
+    /// Path to the database to shrink. Default is the database emitted by extract
+    #[clap(short, long)]
+    pub input: Option<PathBuf>,
+    /// Path to save the lite database. Default is `database.lite.json` next to the input
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    /// Keep declaration-only types (declared but never defined) instead of replacing them with u8
+    #[clap(long)]
+    pub keep_decls: bool,
+    /// Make structs with a name matching this regex opaque. Can be specified multiple times
+    #[clap(long, default_value = "^std::")]
+    pub strip: Vec<String>,
+    /// Make template instantiations larger than this many bytes opaque
+    #[clap(long)]
+    pub template_size_threshold: Option<u32>,
+    /// Only keep types that are referenced from symbols
+    #[clap(long)]
+    pub gc: bool,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdShrink> for exstractor::ShrinkOptions {
+    fn from(cmd: CmdShrink) -> Self {
+        Self {
+            input: cmd.input,
+            output: cmd.output,
+            keep_decls: cmd.keep_decls,
+            strip: cmd.strip,
+            template_size_threshold: cmd.template_size_threshold,
+            gc: cmd.gc,
+        }
+    }
+}
+
+/// Export an extracted database to other formats, without re-running extraction
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdExport {
+    /// Format to export (for example, hover or tyyaml). Can be specified multiple times.
+    /// Default is the formats in export.on-extract in the config
+    #[clap(short, long = "format", value_name = "NAME")]
+    pub formats: Vec<String>,
+    /// Path to the database to export. Default is the database emitted by extract
+    #[clap(short, long)]
+    pub input: Option<PathBuf>,
+    /// Only export these types (comma-separated fully-qualified names), without symbols
+    #[clap(long, value_name = "NAMES", value_delimiter = ',')]
+    pub roots: Vec<String>,
+    /// Also export all types transitively referenced by the roots.
+    /// Otherwise, references to other types are exported as byte arrays
+    #[clap(long, requires = "roots")]
+    pub closure: bool,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdExport> for exstractor::ExportOptions {
+    fn from(cmd: CmdExport) -> Self {
+        Self {
+            formats: cmd.formats,
+            input: cmd.input,
+            roots: cmd.roots,
+            closure: cmd.closure,
+        }
+    }
+}
+
+/// Validate the config and inputs for extract, without running the extraction
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdCheck {
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+/// Remove stale cache files in the extract output directory, such as the clang AST cache
+/// of source files that are no longer in compile_commands.json
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdClean {
+    /// Only print the files that would be removed
+    #[clap(long)]
+    pub dry_run: bool,
     #[clap(flatten)]
     #[as_ref]
     pub common: cu::cli::Flags,
 }
+
+impl From<CmdClean> for exstractor::CleanOptions {
+    fn from(cmd: CmdClean) -> Self {
+        Self { dry_run: cmd.dry_run }
+    }
+}
+
+/// Compare two extraction outputs, and report added, removed and changed types
+/// and symbols, for example between two versions of the program
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdDiff {
+    /// Old database, or the extract output directory that contains it
+    pub old: PathBuf,
+    /// New database, or the extract output directory that contains it
+    pub new: PathBuf,
+    /// Save the report as JSON to this path
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdDiff> for exstractor::DiffOptions {
+    fn from(cmd: CmdDiff) -> Self {
+        Self {
+            old: cmd.old,
+            new: cmd.new,
+            output: cmd.output,
+        }
+    }
+}
+
+/// Query an extracted database: fuzzy search type names, print the layout of a type
+/// with offsets and padding, list symbols that reference a type, look up the symbol
+/// at an address, or look up a symbol by its demangled name.
+/// Starts an interactive session if no query is given
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdQuery {
+    /// Queries to run, for example `layout uking::ui::PauseMenuDataMgr` or `addr 0x71001234`.
+    /// Run the `help` query for the available queries
+    pub queries: Vec<String>,
+    /// Path to the database to query, or a directory with TyYAML output
+    /// (`types.yaml` and `symbols.yaml`). Default is the database emitted by extract
+    #[clap(short, long)]
+    pub input: Option<PathBuf>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdQuery> for exstractor::QueryOptions {
+    fn from(cmd: CmdQuery) -> Self {
+        Self {
+            input: cmd.input,
+            queries: cmd.queries,
+        }
+    }
+}
+
+/// Combine databases from multiple extractions into one, for example a program and
+/// a module loaded by it. Types with the same names are linked, and symbols are
+/// combined by link name
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdMergeDb {
+    /// Databases to merge, or the extract output directories that contain them.
+    /// When the same symbol is in multiple inputs, the first input is kept
+    #[clap(required = true)]
+    pub inputs: Vec<PathBuf>,
+    /// Hex offsets to add to the addresses of each input, in the same order as
+    /// the inputs (for example `--offsets 0,0x8000000`). Missing offsets are 0
+    #[clap(long, value_delimiter = ',')]
+    pub offsets: Vec<String>,
+    /// Path to save the merged database
+    #[clap(short, long)]
+    pub output: PathBuf,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdMergeDb> for exstractor::MergeDbOptions {
+    fn from(cmd: CmdMergeDb) -> Self {
+        Self {
+            inputs: cmd.inputs,
+            offsets: cmd.offsets,
+            output: cmd.output,
+        }
+    }
+}
+
+/// Bundle the DWARF of one compilation unit, its compile command, the config
+/// and the symbols defined in it into a tarball, which can be attached to
+/// a bug report without sharing the whole program
+#[derive(Debug, clap::Parser, AsRef)]
+pub struct CmdRepro {
+    /// Name of the compilation unit, or the end of the name if it is unique
+    #[clap(long)]
+    pub cu: String,
+    /// Offset of the type in the DWARF to report, for example `0x0001f3a0`
+    #[clap(long = "type")]
+    pub ty: Option<String>,
+    /// Path to save the tarball. Default is repro.tar in the extract output
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    #[clap(flatten)]
+    #[as_ref]
+    pub common: cu::cli::Flags,
+}
+
+impl From<CmdRepro> for exstractor::ReproOptions {
+    fn from(cmd: CmdRepro) -> Self {
+        Self {
+            cu: cmd.cu,
+            ty: cmd.ty,
+            output: cmd.output,
+        }
+    }
+}
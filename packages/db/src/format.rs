@@ -0,0 +1,139 @@
+use cu::pre::*;
+
+/// Version of the format, which is bumped on every incompatible change
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Type index for references to types that are missing from the database
+pub const NO_TYPE_INDEX: u32 = u32::MAX;
+
+/// Root of the compact database file.
+///
+/// Types are referenced by their u32 index in `types` instead of the Goff,
+/// and names are referenced by their u32 index in `strings`, so each name
+/// is only stored once. The names are indexed by hash for lookups without scanning
+#[derive(Debug, Default, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DbArtifact {
+    pub version: u32,
+    /// Names of types, members, enumerators and symbols
+    pub strings: Vec<String>,
+    /// Named types first, followed by primitives and types made of other types,
+    /// such as pointers and arrays
+    pub types: Vec<DbType>,
+    /// Symbols, sorted by address
+    pub symbols: Vec<DbSymbol>,
+    /// Index of types by hash of the name, sorted by hash
+    pub type_names: Vec<DbNameIndex>,
+    /// Index of symbols by hash of the link name, sorted by hash
+    pub symbol_names: Vec<DbNameIndex>,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DbType {
+    /// Name of the type, or the spelling for types made of other types
+    pub name: u32,
+    /// Stable ID of named types, which does not change between builds. 0 for other types
+    pub stable_id: u64,
+    pub kind: DbTypeKind,
+    /// Size of the type, 0 if unsized
+    pub size: u32,
+    /// Types this type is made of:
+    /// - Pointer and array: the pointee or element type
+    /// - Subroutine: the return type, then the argument types
+    /// - Pointer to member data: the class type, then the pointee type
+    /// - Pointer to member function: the class type, the return type, then the argument types
+    pub inner: Vec<u32>,
+    /// Number of elements of arrays, 0 for other types
+    pub len: u32,
+    /// Members of structs and unions
+    pub members: Vec<DbMember>,
+    /// (name, value) of enumerators of enums
+    pub enumerators: Vec<(u32, i64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[rkyv(derive(Debug, Clone, Copy, PartialEq, Eq))]
+pub enum DbTypeKind {
+    Prim,
+    Enum,
+    Union,
+    Struct,
+    Pointer,
+    Array,
+    Subroutine,
+    PtmData,
+    PtmFunc,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DbMember {
+    pub offset: u32,
+    /// Size of the member, 0 if unsized
+    pub size: u32,
+    /// Name of the member, empty for unnamed members like base classes
+    pub name: u32,
+    /// Index of the member type, or [`NO_TYPE_INDEX`]
+    pub type_index: u32,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DbSymbol {
+    pub address: u32,
+    pub link_name: u32,
+    /// Index of the symbol type, or [`NO_TYPE_INDEX`]
+    pub type_index: u32,
+}
+
+#[derive(Debug, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct DbNameIndex {
+    /// Hash of the name, see [`name_hash`]
+    pub hash: u64,
+    /// Index into the types or symbols
+    pub index: u32,
+}
+
+/// Hash of a name in the name indices
+pub fn name_hash(name: &str) -> u64 {
+    fxhash::hash64(name)
+}
+
+impl DbArtifact {
+    /// Sort the symbols by address, and build the name indices from the types
+    /// and symbols. This must be called before saving.
+    ///
+    /// Types are not reordered, since they are referenced by index
+    pub fn build_indices(&mut self) {
+        let strings = &self.strings;
+        let string = |i: u32| {
+            strings
+                .get(i as usize)
+                .map(String::as_str)
+                .unwrap_or_default()
+        };
+        self.symbols.sort_by(|a, b| {
+            (a.address, string(a.link_name)).cmp(&(b.address, string(b.link_name)))
+        });
+        self.type_names = name_indices(self.types.iter().map(|x| string(x.name)));
+        self.symbol_names = name_indices(self.symbols.iter().map(|x| string(x.link_name)));
+    }
+
+    /// Serialize the artifact to bytes
+    pub fn to_bytes(&self) -> cu::Result<Vec<u8>> {
+        let bytes = cu::check!(
+            rkyv::to_bytes::<rkyv::rancor::Error>(self),
+            "failed to serialize database"
+        )?;
+        Ok(bytes.into_vec())
+    }
+}
+
+fn name_indices<'a>(names: impl Iterator<Item = &'a str>) -> Vec<DbNameIndex> {
+    let mut indices = names
+        .enumerate()
+        .map(|(i, name)| DbNameIndex {
+            hash: name_hash(name),
+            index: i as u32,
+        })
+        .collect::<Vec<_>>();
+    indices.sort_by_key(|x| (x.hash, x.index));
+    indices
+}
@@ -0,0 +1,9 @@
+//! Read-only access to the compact database exported with `dejj export --format db`.
+//!
+//! The file is memory-mapped and read in place, so lookups by name and by address
+//! do not deserialize the whole database. This is intended for runtimes with tight
+//! memory budgets, such as mod loaders that need to find symbols by name
+mod format;
+pub use format::*;
+mod reader;
+pub use reader::*;
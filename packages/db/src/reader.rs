@@ -0,0 +1,315 @@
+use std::fs::File;
+use std::path::Path;
+
+use cu::pre::*;
+use memmap2::Mmap;
+
+use crate::{
+    ArchivedDbArtifact, ArchivedDbNameIndex, ArchivedDbSymbol, ArchivedDbType, FORMAT_VERSION,
+    name_hash,
+};
+
+/// Reader of a memory-mapped compact database file.
+///
+/// The file is validated once when opened. Lookups read the mapped file
+/// in place and return references into it
+pub struct DbReader {
+    mmap: Mmap,
+}
+
+impl DbReader {
+    /// Map the database file into memory and validate it.
+    ///
+    /// The file must not be modified while the reader is alive
+    pub fn open(path: impl AsRef<Path>) -> cu::Result<Self> {
+        let path = path.as_ref();
+        let file = cu::check!(
+            File::open(path),
+            "failed to open database {}",
+            path.display()
+        )?;
+        // safety: the database is only written by the exporter,
+        // and is not expected to change while being read
+        let mmap = cu::check!(
+            unsafe { Mmap::map(&file) },
+            "failed to map database {}",
+            path.display()
+        )?;
+        let artifact = rkyv::access::<ArchivedDbArtifact, rkyv::rancor::Error>(&mmap);
+        let artifact = cu::check!(artifact, "invalid database file {}", path.display())?;
+        let version = artifact.version.to_native();
+        cu::ensure!(
+            version == FORMAT_VERSION,
+            "unsupported database version {version}, expected {FORMAT_VERSION}. Please export the database again"
+        )?;
+        Ok(Self { mmap })
+    }
+
+    /// Get the root of the database
+    pub fn artifact(&self) -> &ArchivedDbArtifact {
+        // safety: validated when opening
+        unsafe { rkyv::access_unchecked::<ArchivedDbArtifact>(&self.mmap) }
+    }
+
+    /// Get the string at the index, as referenced by names of types, members and symbols
+    pub fn string(&self, index: u32) -> Option<&str> {
+        self.artifact()
+            .strings
+            .get(index as usize)
+            .map(|x| x.as_str())
+    }
+
+    /// Get the type at the index, as referenced by members, symbols and other types
+    pub fn type_at(&self, index: u32) -> Option<&ArchivedDbType> {
+        self.artifact().types.get(index as usize)
+    }
+
+    /// Find a type by name, or by spelling for types made of other types, like `Foo*`
+    pub fn type_by_name(&self, name: &str) -> Option<&ArchivedDbType> {
+        let types = &self.artifact().types;
+        find_by_name(&self.artifact().type_names, name, |i| {
+            let t = types.get(i)?;
+            (self.string(t.name.to_native())? == name).then_some(t)
+        })
+    }
+
+    /// Find a symbol by link name
+    pub fn symbol_by_name(&self, link_name: &str) -> Option<&ArchivedDbSymbol> {
+        let symbols = &self.artifact().symbols;
+        find_by_name(&self.artifact().symbol_names, link_name, |i| {
+            let s = symbols.get(i)?;
+            (self.string(s.link_name.to_native())? == link_name).then_some(s)
+        })
+    }
+
+    /// Get the symbols at the address. There could be more than one symbol
+    /// at the same address, for example constructors with different variants
+    pub fn symbols_at(&self, address: u32) -> &[ArchivedDbSymbol] {
+        let symbols = self.artifact().symbols.as_slice();
+        let start = symbols.partition_point(|s| s.address.to_native() < address);
+        let end = symbols.partition_point(|s| s.address.to_native() <= address);
+        &symbols[start..end]
+    }
+
+    /// Get the last symbol at or before the address, i.e. the function or data
+    /// that likely contains the address
+    pub fn symbol_containing(&self, address: u32) -> Option<&ArchivedDbSymbol> {
+        let symbols = self.artifact().symbols.as_slice();
+        let end = symbols.partition_point(|s| s.address.to_native() <= address);
+        symbols[..end].last()
+    }
+}
+
+/// Find by hash in the name index, then check the name, since names
+/// could have the same hash
+fn find_by_name<'a, T>(
+    index: &[ArchivedDbNameIndex],
+    name: &str,
+    get: impl Fn(usize) -> Option<&'a T>,
+) -> Option<&'a T> {
+    let hash = name_hash(name);
+    let start = index.partition_point(|x| x.hash.to_native() < hash);
+    index[start..]
+        .iter()
+        .take_while(|x| x.hash.to_native() == hash)
+        .find_map(|x| get(x.index.to_native() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        ArchivedDbTypeKind, DbArtifact, DbMember, DbSymbol, DbType, DbTypeKind, NO_TYPE_INDEX,
+    };
+
+    fn make_type(name: u32, kind: DbTypeKind, size: u32) -> DbType {
+        DbType {
+            name,
+            stable_id: 0,
+            kind,
+            size,
+            inner: vec![],
+            len: 0,
+            members: vec![],
+            enumerators: vec![],
+        }
+    }
+
+    fn make_artifact() -> DbArtifact {
+        let strings = [
+            "",
+            "Foo",
+            "Kind",
+            "A",
+            "B",
+            "bar",
+            "Foo*",
+            "Foo* ()",
+            "_ZN3Foo3getEv",
+            "sFoo",
+            "sFooAlias",
+        ];
+        let mut artifact = DbArtifact {
+            version: FORMAT_VERSION,
+            strings: strings.iter().map(|x| x.to_string()).collect(),
+            types: vec![
+                DbType {
+                    stable_id: 1,
+                    members: vec![DbMember {
+                        offset: 0,
+                        size: 8,
+                        name: 5,
+                        type_index: 2,
+                    }],
+                    ..make_type(1, DbTypeKind::Struct, 8)
+                },
+                DbType {
+                    stable_id: 2,
+                    enumerators: vec![(3, 0), (4, 1)],
+                    ..make_type(2, DbTypeKind::Enum, 4)
+                },
+                DbType {
+                    inner: vec![0],
+                    ..make_type(6, DbTypeKind::Pointer, 8)
+                },
+                DbType {
+                    inner: vec![2],
+                    ..make_type(7, DbTypeKind::Subroutine, 0)
+                },
+            ],
+            symbols: vec![
+                DbSymbol {
+                    address: 0x200,
+                    link_name: 8,
+                    type_index: 3,
+                },
+                DbSymbol {
+                    address: 0x100,
+                    link_name: 9,
+                    type_index: 0,
+                },
+                DbSymbol {
+                    address: 0x100,
+                    link_name: 10,
+                    type_index: 0,
+                },
+            ],
+            ..Default::default()
+        };
+        artifact.build_indices();
+        artifact
+    }
+
+    /// Write the artifact to a file unique to the test, and open it
+    fn write_and_open(artifact: &DbArtifact, test_name: &str) -> cu::Result<(PathBuf, DbReader)> {
+        let path =
+            std::env::temp_dir().join(format!("dejj-db-{test_name}-{}.bin", std::process::id()));
+        std::fs::write(&path, artifact.to_bytes()?)?;
+        let reader = DbReader::open(&path)?;
+        Ok((path, reader))
+    }
+
+    #[test]
+    fn test_round_trip() -> cu::Result<()> {
+        let artifact = make_artifact();
+        let (path, reader) = write_and_open(&artifact, "round-trip")?;
+        let archived = reader.artifact();
+        assert_eq!(archived.version.to_native(), FORMAT_VERSION);
+        assert_eq!(archived.strings.len(), artifact.strings.len());
+        assert_eq!(archived.types.len(), artifact.types.len());
+        assert_eq!(archived.symbols.len(), artifact.symbols.len());
+        for (a, b) in archived.types.iter().zip(&artifact.types) {
+            assert_eq!(a.name.to_native(), b.name);
+            assert_eq!(a.stable_id.to_native(), b.stable_id);
+            assert_eq!(format!("{:?}", a.kind), format!("{:?}", b.kind));
+            assert_eq!(a.size.to_native(), b.size);
+            assert_eq!(a.inner.len(), b.inner.len());
+            assert_eq!(a.members.len(), b.members.len());
+            assert_eq!(a.enumerators.len(), b.enumerators.len());
+        }
+        for (a, b) in archived.symbols.iter().zip(&artifact.symbols) {
+            assert_eq!(a.address.to_native(), b.address);
+            assert_eq!(a.link_name.to_native(), b.link_name);
+            assert_eq!(a.type_index.to_native(), b.type_index);
+        }
+        let member = &archived.types[0].members[0];
+        assert_eq!(reader.string(member.name.to_native()), Some("bar"));
+        let pointer = reader.type_at(member.type_index.to_native());
+        assert_eq!(pointer.map(|x| x.kind), Some(ArchivedDbTypeKind::Pointer));
+        let pointee = pointer.and_then(|x| reader.type_at(x.inner[0].to_native()));
+        assert_eq!(
+            pointee.and_then(|x| reader.string(x.name.to_native())),
+            Some("Foo")
+        );
+        assert!(reader.string(archived.strings.len() as u32).is_none());
+        drop(reader);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_mismatch() -> cu::Result<()> {
+        let mut artifact = make_artifact();
+        artifact.version = FORMAT_VERSION + 1;
+        let path = std::env::temp_dir().join(format!(
+            "dejj-db-version-mismatch-{}.bin",
+            std::process::id()
+        ));
+        std::fs::write(&path, artifact.to_bytes()?)?;
+        assert!(DbReader::open(&path).is_err());
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_by_name() -> cu::Result<()> {
+        let (path, reader) = write_and_open(&make_artifact(), "lookup-by-name")?;
+        let name = |t: &ArchivedDbType| reader.string(t.name.to_native());
+        let foo = reader.type_by_name("Foo").and_then(name);
+        assert_eq!(foo, Some("Foo"));
+        let kind = reader.type_by_name("Kind").map(|x| x.enumerators.len());
+        assert_eq!(kind, Some(2));
+        let pointer = reader.type_by_name("Foo*").map(|x| x.kind);
+        assert_eq!(pointer, Some(ArchivedDbTypeKind::Pointer));
+        assert!(reader.type_by_name("Missing").is_none());
+
+        let symbol = reader.symbol_by_name("sFoo");
+        assert_eq!(symbol.map(|x| x.address.to_native()), Some(0x100));
+        let type_index = symbol.map(|x| x.type_index.to_native());
+        let ty = type_index.and_then(|i| reader.type_at(i));
+        assert_eq!(ty.and_then(name), Some("Foo"));
+        assert!(reader.symbol_by_name("sMissing").is_none());
+        assert!(reader.type_at(NO_TYPE_INDEX).is_none());
+        drop(reader);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_by_address() -> cu::Result<()> {
+        let (path, reader) = write_and_open(&make_artifact(), "lookup-by-address")?;
+        let names = |symbols: &[ArchivedDbSymbol]| {
+            symbols
+                .iter()
+                .filter_map(|x| reader.string(x.link_name.to_native()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(reader.symbols_at(0x100)), vec!["sFoo", "sFooAlias"]);
+        assert!(reader.symbols_at(0x180).is_empty());
+
+        let containing = |address| {
+            reader
+                .symbol_containing(address)
+                .and_then(|x| reader.string(x.link_name.to_native()))
+        };
+        assert_eq!(containing(0xff), None);
+        assert_eq!(containing(0x100), Some("sFooAlias"));
+        assert_eq!(containing(0x1ff), Some("sFooAlias"));
+        assert_eq!(containing(0x300), Some("_ZN3Foo3getEv"));
+        drop(reader);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
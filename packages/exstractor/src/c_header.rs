@@ -0,0 +1,492 @@
+//! C header shared by the exporters for decompilers.
+//!
+//! C has no namespaces, templates or inheritance, so type names are mangled into
+//! C identifiers, and base classes become the first members of the derived struct.
+//! With `flatten-bases` in the options of the format, the members of the bases are
+//! inlined into the derived struct instead (see `exstructs::algorithm::flatten_bases`).
+//! Struct layouts are preserved with explicit padding
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use cu::pre::*;
+use dejj_utils::{Config, PtmRepr};
+use exstructs::{
+    Bitfield, Enum, Goff, GoffMap, GoffSet, HType, Member, Qualifiers, SizeMap, SpecialMember,
+    Struct, algorithm, tree_node_count,
+};
+use tyyaml::{Prim, Tree};
+
+use crate::database::Database;
+use crate::emit;
+use crate::export::ExportContext;
+
+/// Generate a C header with all types in the database. The comment is
+/// put at the top of the header, for example to describe how to import it
+pub(crate) fn generate(db: &Database, ctx: &ExportContext, comment: &str) -> cu::Result<String> {
+    let flattened;
+    let db = if ctx.options.flatten_bases {
+        let mut copy = db.clone();
+        cu::check!(
+            algorithm::flatten_bases(&mut copy.types),
+            "failed to flatten base classes"
+        )?;
+        flattened = copy;
+        &flattened
+    } else {
+        db
+    };
+    let sizes = db.sizes(ctx.config)?;
+    HeaderWriter::new(db, &sizes, ctx.config)?.write(comment)
+}
+
+struct HeaderWriter<'a> {
+    db: &'a Database,
+    sizes: &'a SizeMap,
+    config: &'a Config,
+    /// C identifier of each named type
+    names: GoffMap<String>,
+    /// Types that are already defined
+    defined: GoffSet,
+    out: String,
+}
+
+impl<'a> HeaderWriter<'a> {
+    fn new(db: &'a Database, sizes: &'a SizeMap, config: &'a Config) -> cu::Result<Self> {
+        let type_names = emit::type_names(&db.types, emit::name_policy(config))?;
+        let mut used = BTreeSet::new();
+        let mut names = GoffMap::default();
+        for (k, t) in &db.types {
+            if matches!(t, HType::Prim(_)) {
+                continue;
+            }
+            let name = match type_names.get(k) {
+                Some(name) => name.clone(),
+                None => exstructs::anonymous_type_name(*k),
+            };
+            names.insert(*k, unique_identifier(&name, &mut used));
+        }
+        Ok(Self {
+            db,
+            sizes,
+            config,
+            names,
+            defined: GoffSet::default(),
+            out: String::new(),
+        })
+    }
+
+    fn write(mut self, comment: &str) -> cu::Result<String> {
+        let db = self.db;
+        let _ = writeln!(self.out, "/* Generated by dejj. {comment} */\n");
+        for (name, ty) in [
+            ("u8", "unsigned char"),
+            ("u16", "unsigned short"),
+            ("u32", "unsigned int"),
+            ("u64", "unsigned long long"),
+            ("i8", "signed char"),
+            ("i16", "short"),
+            ("i32", "int"),
+            ("i64", "long long"),
+            ("f32", "float"),
+            ("f64", "double"),
+        ] {
+            let _ = writeln!(self.out, "typedef {ty} {name};");
+        }
+        for name in ["u128", "i128", "f128"] {
+            let _ = writeln!(self.out, "typedef u8 {name}[16];");
+        }
+        self.out.push('\n');
+
+        // forward declare structs and unions, so they can be used in pointers
+        for (k, t) in &db.types {
+            let keyword = match t {
+                HType::Struct(_) => "struct",
+                HType::Union(_) => "union",
+                _ => continue,
+            };
+            let name = &self.names[k];
+            let _ = writeln!(self.out, "typedef {keyword} {name} {name};");
+        }
+        self.out.push('\n');
+
+        // enums have no dependencies
+        let mut enumerator_names = BTreeSet::new();
+        for (k, t) in &db.types {
+            if let HType::Enum(data) = t {
+                self.write_enum(*k, &data.data, &mut enumerator_names);
+            }
+        }
+
+        // callbacks only reference types through pointers, and aliases of
+        // structs and unions only need the forward declarations
+        for (name, ty) in &db.typedefs {
+            let name = sanitize_identifier(name);
+            let decl = self.declare(ty, &[], name);
+            let _ = writeln!(self.out, "typedef {decl};");
+        }
+        self.out.push('\n');
+
+        for k in db.types.keys() {
+            self.define_recur(*k, 0)?;
+        }
+        Ok(self.out)
+    }
+
+    fn write_enum(&mut self, k: Goff, data: &Enum, enumerator_names: &mut BTreeSet<String>) {
+        let name = self.names[&k].clone();
+        // enums are 4 bytes in the parsers of decompilers, other sizes are typedefs
+        // of integers, with the values in a separate enum
+        let enum_name = if data.byte_size == 4 {
+            name.clone()
+        } else {
+            format!("{name}_values")
+        };
+        self.write_source(k);
+        let _ = writeln!(self.out, "typedef enum {enum_name} {{");
+        for e in &data.enumerators {
+            // enumerators are global in C
+            let mut enumerator = sanitize_identifier(&e.name);
+            if !enumerator_names.insert(enumerator.clone()) {
+                enumerator = unique_identifier(&format!("{name}_{}", e.name), enumerator_names);
+            }
+            let _ = writeln!(self.out, "    {enumerator} = {},", e.value);
+        }
+        let _ = writeln!(self.out, "}} {enum_name};");
+        if data.byte_size != 4 {
+            let repr = match (data.byte_size, data.is_signed) {
+                (1, false) => "u8",
+                (2, false) => "u16",
+                (8, false) => "u64",
+                (_, false) => "u32",
+                (1, true) => "i8",
+                (2, true) => "i16",
+                (8, true) => "i64",
+                (_, true) => "i32",
+            };
+            let _ = writeln!(self.out, "typedef {repr} {name};");
+        }
+        self.out.push('\n');
+    }
+
+    /// Cite where the type is defined in the original source, if known
+    fn write_source(&mut self, k: Goff) {
+        let db = self.db;
+        let source = match db.types.get(&k) {
+            Some(HType::Enum(data)) => &data.source,
+            Some(HType::Union(data)) => &data.source,
+            Some(HType::Struct(data)) => &data.source,
+            _ => return,
+        };
+        if let Some(source) = source {
+            let _ = writeln!(self.out, "/* {source} */");
+        }
+    }
+
+    /// Define the struct or union after the types it contains by value
+    fn define_recur(&mut self, k: Goff, depth: usize) -> cu::Result<()> {
+        cu::ensure!(depth < 1000, "type {k} contains itself by value")?;
+        if !self.defined.insert(k) {
+            return Ok(());
+        }
+        let db = self.db;
+        let members = match db.types.get(&k) {
+            Some(HType::Struct(data)) => &data.data.members,
+            Some(HType::Union(data)) => &data.data.members,
+            _ => return Ok(()),
+        };
+        for m in members {
+            let mut deps = vec![];
+            value_dependencies(&m.ty, &mut deps);
+            for dep in deps {
+                self.define_recur(dep, depth + 1)?;
+            }
+        }
+        match db.types.get(&k) {
+            Some(HType::Struct(data)) => self.write_struct(k, &data.data),
+            Some(HType::Union(data)) => {
+                let name = self.names[&k].clone();
+                self.write_source(k);
+                let _ = writeln!(self.out, "union {name} {{");
+                let mut member_names = BTreeSet::new();
+                for m in &data.data.members {
+                    let member_name = self.member_name(m, &mut member_names);
+                    self.write_member(m, member_name);
+                }
+                self.out.push_str("};\n\n");
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn write_struct(&mut self, k: Goff, data: &Struct) {
+        let name = self.names[&k].clone();
+        let has_vfptr = data
+            .members
+            .iter()
+            .any(|m| matches!(m.special, Some(SpecialMember::Vfptr)));
+        let vtable_name = format!("{name}_vtbl");
+        if has_vfptr && !data.vtable.is_empty() {
+            self.write_vtable(&vtable_name, data);
+        }
+
+        self.write_source(k);
+        let _ = writeln!(self.out, "struct {name} {{");
+        let mut member_names = BTreeSet::new();
+        let mut end = 0;
+        for m in &data.members {
+            if m.offset < end {
+                // overlapping members cannot be represented in C
+                let _ = writeln!(
+                    self.out,
+                    "    /* skipped overlapping member at 0x{:x} */",
+                    m.offset
+                );
+                continue;
+            }
+            if m.offset > end {
+                let _ = writeln!(self.out, "    u8 _pad_0x{end:x}[{}];", m.offset - end);
+            }
+            let member_name = self.member_name(m, &mut member_names);
+            if matches!(m.special, Some(SpecialMember::Vfptr)) && !data.vtable.is_empty() {
+                let _ = writeln!(self.out, "    {vtable_name}* {member_name};");
+            } else {
+                self.write_member(m, member_name);
+            }
+            end = m.offset + self.member_size(m);
+        }
+        if data.byte_size > end {
+            let _ = writeln!(self.out, "    u8 _pad_0x{end:x}[{}];", data.byte_size - end);
+        }
+        self.out.push_str("};\n\n");
+    }
+
+    fn write_vtable(&mut self, vtable_name: &str, data: &Struct) {
+        let _ = writeln!(self.out, "typedef struct {vtable_name} {{");
+        let mut slots = BTreeMap::new();
+        for (i, entry) in &data.vtable {
+            slots.entry(*i).or_insert(entry);
+        }
+        let mut names = BTreeSet::new();
+        let mut next = 0;
+        for (i, entry) in slots {
+            while next < i {
+                let _ = writeln!(self.out, "    void* _slot{next};");
+                next += 1;
+            }
+            let name = unique_identifier(&entry.name, &mut names);
+            let ty = Tree::Ptr(Box::new(Tree::Sub(entry.function_types.clone())));
+            let decl = self.declare(&ty, &[], name);
+            let _ = writeln!(self.out, "    {decl};");
+            next = i + 1;
+        }
+        let _ = writeln!(self.out, "}} {vtable_name};\n");
+    }
+
+    fn write_member(&mut self, m: &Member, name: String) {
+        let qualifiers = m.qualifiers.for_tree(&m.ty);
+        let Some(SpecialMember::BitfieldGroup(_, bitfields)) = &m.special else {
+            let decl = self.declare(&m.ty, qualifiers, name);
+            let _ = writeln!(self.out, "    {decl};");
+            return;
+        };
+        let storage = self.declare(&m.ty, qualifiers, String::new());
+        let mut bitfields = bitfields.iter().collect::<Vec<&Bitfield>>();
+        bitfields.sort_by_key(|b| b.bit_offset);
+        let mut bit = 0;
+        for b in bitfields {
+            if b.bit_offset < bit {
+                continue;
+            }
+            if b.bit_offset > bit {
+                let _ = writeln!(self.out, "    {storage} : {};", b.bit_offset - bit);
+            }
+            match &b.name {
+                Some(name) => {
+                    let name = sanitize_identifier(name);
+                    let _ = writeln!(self.out, "    {storage} {name} : {};", b.bit_size);
+                }
+                None => {
+                    let _ = writeln!(self.out, "    {storage} : {};", b.bit_size);
+                }
+            }
+            bit = b.bit_offset + b.bit_size;
+        }
+    }
+
+    fn member_name(&self, m: &Member, used: &mut BTreeSet<String>) -> String {
+        let name = match (&m.name, &m.special) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(SpecialMember::Vfptr)) => "__vftable".to_string(),
+            (None, Some(SpecialMember::Base)) => match &m.ty {
+                Tree::Base(k) => format!("base_{}", self.type_name(*k)),
+                _ => format!("base_0x{:x}", m.offset),
+            },
+            (None, _) => format!("field_0x{:x}", m.offset),
+        };
+        unique_identifier(&name, used)
+    }
+
+    fn member_size(&self, m: &Member) -> u32 {
+        match &m.special {
+            Some(SpecialMember::Bitfield(size)) | Some(SpecialMember::BitfieldGroup(size, _)) => {
+                *size
+            }
+            _ => self.sizes.get_tree_optional(&m.ty).unwrap_or(0),
+        }
+    }
+
+    fn type_name(&self, k: Goff) -> String {
+        if let Some(prim) = k.to_prim() {
+            return prim_name(prim).to_string();
+        }
+        match self.names.get(&k) {
+            Some(name) => name.clone(),
+            None => "void".to_string(),
+        }
+    }
+
+    /// Make a C declaration of the declarator with the type. The qualifiers
+    /// are for the nodes of the tree in pre-order, and could be empty
+    fn declare(&self, tree: &Tree<Goff>, qualifiers: &[u8], declarator: String) -> String {
+        let qualifier = Qualifiers::to_c_prefix(qualifiers.first().copied().unwrap_or_default());
+        let inner_qualifiers = qualifiers.get(1..).unwrap_or_default();
+        match tree {
+            Tree::Base(k) => {
+                let name = format!("{qualifier}{}", self.type_name(*k));
+                if declarator.is_empty() {
+                    name
+                } else {
+                    format!("{name} {declarator}")
+                }
+            }
+            Tree::Array(inner, len) => {
+                self.declare(inner, inner_qualifiers, format!("{declarator}[{len}]"))
+            }
+            Tree::Ptr(inner) => {
+                let pointer = format!("*{qualifier}{declarator}");
+                let pointer = pointer.trim_end();
+                let declarator = match inner.as_ref() {
+                    Tree::Array(..) | Tree::Sub(_) => format!("({pointer})"),
+                    _ => pointer.to_string(),
+                };
+                self.declare(inner, inner_qualifiers, declarator)
+            }
+            Tree::Sub(args) => {
+                let Some((retty, params)) = args.split_first() else {
+                    return self.declare(&Tree::Base(Goff::prim(Prim::Void)), &[], declarator);
+                };
+                let mut rest = inner_qualifiers
+                    .get(tree_node_count(retty)..)
+                    .unwrap_or_default();
+                let retty_qualifiers = inner_qualifiers;
+                let params = params
+                    .iter()
+                    .map(|x| {
+                        let decl = self.declare(x, rest, String::new());
+                        rest = rest.get(tree_node_count(x)..).unwrap_or_default();
+                        decl
+                    })
+                    .collect::<Vec<_>>();
+                let params = if params.is_empty() {
+                    "void".to_string()
+                } else {
+                    params.join(", ")
+                };
+                self.declare(retty, retty_qualifiers, format!("{declarator}({params})"))
+            }
+            // pointer to members have no equivalent in C
+            Tree::Ptmd(..) => ptm_declaration(&self.config.extract.ptmd_repr, declarator),
+            Tree::Ptmf(..) => ptm_declaration(&self.config.extract.ptmf_repr, declarator),
+        }
+    }
+}
+
+/// Declare a pointer-to-member with the representation in the config, as an array
+/// or an inline struct with padding between the members
+fn ptm_declaration(repr: &PtmRepr, declarator: String) -> String {
+    let repr = match repr {
+        PtmRepr::Array(prim, len) => return format!("{} {declarator}[{len}]", prim_name(*prim)),
+        PtmRepr::Struct(repr) => repr,
+    };
+    let mut out = "struct { ".to_string();
+    let mut end = 0;
+    for m in &repr.members {
+        if m.offset > end {
+            out += &format!("u8 _pad_{end:x}[{}]; ", m.offset - end);
+        }
+        out += &format!("{} {}; ", prim_name(m.ty), m.name);
+        end = m.offset + m.ty.byte_size().unwrap_or_default();
+    }
+    if let Some(size) = repr.size {
+        if size > end {
+            out += &format!("u8 _pad_{end:x}[{}]; ", size - end);
+        }
+    }
+    out += "} ";
+    out += &declarator;
+    out
+}
+
+/// Types that must be defined before the tree can be used by value
+fn value_dependencies(tree: &Tree<Goff>, out: &mut Vec<Goff>) {
+    match tree {
+        Tree::Base(k) => out.push(*k),
+        Tree::Array(inner, _) => value_dependencies(inner, out),
+        Tree::Ptr(_) | Tree::Sub(_) | Tree::Ptmd(..) | Tree::Ptmf(..) => {}
+    }
+}
+
+fn prim_name(prim: Prim) -> &'static str {
+    match prim {
+        Prim::Void => "void",
+        Prim::Bool => "bool",
+        Prim::U8 => "u8",
+        Prim::U16 => "u16",
+        Prim::U32 => "u32",
+        Prim::U64 => "u64",
+        Prim::U128 => "u128",
+        Prim::I8 => "i8",
+        Prim::I16 => "i16",
+        Prim::I32 => "i32",
+        Prim::I64 => "i64",
+        Prim::I128 => "i128",
+        Prim::F32 => "f32",
+        Prim::F64 => "f64",
+        Prim::F128 => "f128",
+    }
+}
+
+/// Replace characters that are not allowed in C identifiers, for example
+/// `ns::Foo<int>` becomes `ns__Foo_int_`
+fn sanitize_identifier(name: &str) -> String {
+    let mut output = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            output.push(c);
+        } else if c != ' ' {
+            output.push('_');
+        }
+    }
+    if output.is_empty() || output.starts_with(|c: char| c.is_ascii_digit()) {
+        output.insert(0, '_');
+    }
+    output
+}
+
+/// Sanitize the name, and add a suffix if it's already used
+fn unique_identifier(name: &str, used: &mut BTreeSet<String>) -> String {
+    let name = sanitize_identifier(name);
+    if used.insert(name.clone()) {
+        return name;
+    }
+    let mut i = 2;
+    loop {
+        let candidate = format!("{name}_{i}");
+        if used.insert(candidate.clone()) {
+            return candidate;
+        }
+        i += 1;
+    }
+}
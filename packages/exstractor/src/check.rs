@@ -0,0 +1,181 @@
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+use dejj_utils::{Config, TypeParserBackend};
+
+use crate::compdb::Compdb;
+use crate::dwarf::{ArcBuf, Dwarf, SplitDwarfPaths};
+use crate::export::ExporterRegistry;
+use crate::run;
+
+/// Validate the config and the inputs for extraction, without running it.
+/// Prints a report of all checks, and errors if any of them failed
+pub fn check(config_path: impl AsRef<Path>) -> cu::Result<()> {
+    let config_path = config_path.as_ref();
+    let mut report = CheckReport::default();
+    let config = match Config::load(config_path) {
+        Ok(config) => {
+            report.add("config", Ok(format!("loaded {}", config_path.display())));
+            config
+        }
+        Err(e) => {
+            report.add("config", Err(e));
+            report.print();
+            cu::bail!("config check failed");
+        }
+    };
+
+    let elf_bytes = match ArcBuf::map(&config.paths.elf) {
+        Ok(bytes) => {
+            report.add("elf", check_elf(&config, bytes.clone()));
+            Some(bytes)
+        }
+        Err(e) => {
+            report.add("elf", Err(e));
+            None
+        }
+    };
+    report.add(
+        "symbols",
+        check_symbols(&config, elf_bytes.as_ref().map(ArcBuf::bytes)),
+    );
+    report.add("compdb", check_compdb(&config));
+    report.add("system-headers", check_system_headers(&config));
+    report.add("clang", check_clang(&config));
+    report.add("export", check_export(&config));
+
+    report.print();
+    let failed = report.failed_count();
+    if failed > 0 {
+        cu::bail!("{failed} check(s) failed");
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct CheckReport {
+    items: Vec<(&'static str, cu::Result<String>)>,
+}
+
+impl CheckReport {
+    fn add(&mut self, name: &'static str, result: cu::Result<String>) {
+        self.items.push((name, result));
+    }
+
+    fn failed_count(&self) -> usize {
+        self.items.iter().filter(|(_, r)| r.is_err()).count()
+    }
+
+    fn print(&self) {
+        use std::fmt::Write as _;
+        let mut output = String::new();
+        let _ = writeln!(output, "=== Check ===");
+        let width = self.items.iter().map(|(n, _)| n.len()).max().unwrap_or(0);
+        for (name, result) in &self.items {
+            match result {
+                Ok(message) => {
+                    let _ = writeln!(output, " [ OK ] {name:>width$}: {message}");
+                }
+                Err(e) => {
+                    let _ = writeln!(output, " [FAIL] {name:>width$}: {e:#}");
+                }
+            }
+        }
+        cu::print!("{output}");
+    }
+}
+
+fn check_elf(config: &Config, bytes: ArcBuf) -> cu::Result<String> {
+    let split_paths = SplitDwarfPaths::for_object_file(&config.paths.elf);
+    let dwarf = Dwarf::try_parse(bytes, split_paths)?;
+    let mut iter = Dwarf::iter_units(&dwarf);
+    let mut count = 0;
+    while cu::check!(iter.next_unit(), "failed to read compilation unit")?.is_some() {
+        count += 1;
+    }
+    cu::ensure!(count > 0, "no compilation units found in DWARF")?;
+    Ok(format!("found {count} compilation units"))
+}
+
+fn check_symbols(config: &Config, elf_bytes: Option<&[u8]>) -> cu::Result<String> {
+    let source = run::symbol_source(config, elf_bytes)?;
+    let symbols = source.load()?;
+    Ok(format!(
+        "loaded {} functions and {} data from {}",
+        symbols.funcs.len(),
+        symbols.data.len(),
+        source.name()
+    ))
+}
+
+fn check_compdb(config: &Config) -> cu::Result<String> {
+    let compile_commands = Compdb::load(config)?;
+    cu::ensure!(
+        !compile_commands.is_empty(),
+        "compile_commands.json has no entries"
+    )?;
+    let missing = compile_commands
+        .commands()
+        .map(|command| &command.file)
+        .filter(|file| {
+            let path = PathBuf::from(file);
+            let path = if path.is_absolute() {
+                path
+            } else {
+                config.paths.build_dir.join(path)
+            };
+            !path.exists()
+        })
+        .collect::<Vec<_>>();
+    if let Some(first) = missing.first() {
+        cu::bail!(
+            "{} of {} files do not exist, first is: {first}",
+            missing.len(),
+            compile_commands.len()
+        );
+    }
+    Ok(format!(
+        "{} entries, all files exist",
+        compile_commands.len()
+    ))
+}
+
+fn check_system_headers(config: &Config) -> cu::Result<String> {
+    for path in &config.paths.system_header_paths {
+        cu::ensure!(
+            path.is_dir(),
+            "system header path does not exist: {}",
+            path.display()
+        )?;
+    }
+    Ok(format!(
+        "{} paths exist",
+        config.paths.system_header_paths.len()
+    ))
+}
+
+fn check_clang(config: &Config) -> cu::Result<String> {
+    let backend = config.extract.type_parser.backend;
+    if backend == TypeParserBackend::Builtin {
+        return Ok("not needed, using the built-in type parser".to_string());
+    }
+    let clang = cu::bin::find("clang", [cu::bin::from_env("CLANG"), cu::bin::in_PATH()]);
+    match clang {
+        Ok(clang) => Ok(format!("found {}", clang.display())),
+        Err(_) if backend == TypeParserBackend::Auto => {
+            Ok("not found, using the built-in type parser".to_string())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn check_export(config: &Config) -> cu::Result<String> {
+    let registry = ExporterRegistry::default();
+    for format in &config.export.on_extract {
+        registry.get(format)?;
+    }
+    Ok(format!(
+        "exporting on extract: {}",
+        config.export.on_extract.join(", ")
+    ))
+}
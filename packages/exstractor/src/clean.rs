@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use cu::pre::*;
+use dejj_utils::Config;
+
+use crate::compdb::Compdb;
+use crate::lock::OutputLock;
+use crate::lstage;
+
+/// Options for cleaning stale cache files in the output directory
+#[derive(Debug, Default)]
+pub struct CleanOptions {
+    /// Only report what would be removed
+    pub dry_run: bool,
+}
+
+/// Remove cache files in the output directory that are not used with the current
+/// compile_commands.json and config:
+/// - clang AST cache of source files that are no longer in compile_commands.json
+///   (for example, renamed files)
+/// - the l2mcache in the format that is not enabled in the config
+/// - the demangler cache in the old format
+pub fn clean(config: &Config, options: CleanOptions) -> cu::Result<()> {
+    if !config.paths.extract_output.exists() {
+        cu::info!("output directory does not exist, nothing to clean");
+        return Ok(());
+    }
+    let _lock = OutputLock::acquire(config, false)?;
+    let compile_commands = Compdb::load(config)?;
+    let mut stems = BTreeSet::new();
+    // the cache is named by the remapped file name
+    for command in compile_commands.commands() {
+        stems.insert(llvmutils::type_parse_cache_stem(&command.file)?);
+    }
+
+    let mut stale = vec![];
+    let type_parse_dir = lstage::type_parse_cache_dir(config);
+    if type_parse_dir.exists() {
+        for entry in cu::fs::read_dir(&type_parse_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name().into_utf8()?;
+            // leave files not created by the type parser alone
+            let Some(stem) = type_parse_cache_stem_of(&file_name) else {
+                continue;
+            };
+            if !stems.contains(stem) {
+                stale.push(entry.path());
+            }
+        }
+    }
+    let unused_l2mcache = if config.extract.debug.l2mcache {
+        "l2mcache.bin"
+    } else {
+        "l2mcache.json"
+    };
+    let unused_l2mcache = config.paths.extract_output.join(unused_l2mcache);
+    if unused_l2mcache.exists() {
+        stale.push(unused_l2mcache);
+    }
+    // demangler cache in the old format, replaced by demangler_cache.jsonl
+    let old_demangler_cache = config.paths.extract_output.join("demangler_cache.json");
+    if old_demangler_cache.exists() {
+        stale.push(old_demangler_cache);
+    }
+
+    if stale.is_empty() {
+        cu::info!("no stale cache files found");
+        return Ok(());
+    }
+    let mut total_size = 0;
+    for path in &stale {
+        let size = disk_size(path);
+        total_size += size;
+        if options.dry_run {
+            cu::print!(
+                "would remove {} ({})",
+                path.try_to_rel().display(),
+                human_size(size)
+            );
+            continue;
+        }
+        cu::debug!("removing {}", path.try_to_rel().display());
+        if path.is_dir() {
+            cu::fs::rec_remove(path)?;
+        } else {
+            cu::fs::remove(path)?;
+        }
+    }
+    if options.dry_run {
+        cu::info!(
+            "{} stale cache files, {} can be reclaimed",
+            stale.len(),
+            human_size(total_size)
+        );
+    } else {
+        cu::info!(
+            "removed {} stale cache files, reclaimed {}",
+            stale.len(),
+            human_size(total_size)
+        );
+    }
+    Ok(())
+}
+
+/// Get the cache stem (`<file name>_<16 hex digits>`) from a file name in the
+/// type parse cache directory. The stem is followed by an extension like `.cpp` or `.cpp.json`
+fn type_parse_cache_stem_of(file_name: &str) -> Option<&str> {
+    file_name
+        .match_indices('.')
+        .map(|(i, _)| &file_name[..i])
+        .find(|prefix| {
+            let Some((_, hash)) = prefix.rsplit_once('_') else {
+                return false;
+            };
+            hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+}
+
+/// Size of the file, or total size of files in the directory
+fn disk_size(path: &Path) -> u64 {
+    let Ok(meta) = std::fs::metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| disk_size(&entry.path()))
+        .sum()
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
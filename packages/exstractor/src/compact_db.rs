@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use dejj_db::{DbArtifact, DbMember, DbSymbol, DbType, DbTypeKind, FORMAT_VERSION, NO_TYPE_INDEX};
+use exstructs::{Goff, GoffMap, HType, Member, SizeMap};
+use tyyaml::{Tree, Ty};
+
+use crate::database::Database;
+use crate::emit;
+use crate::export::{ExportContext, Exporter};
+
+/// Compact binary database (`db.bin`) for the `dejj-db` reader, which
+/// memory-maps the file and looks up types and symbols without deserializing it.
+///
+/// Types are referenced by index instead of Goff, and names by index into a string table
+pub struct CompactDbExporter;
+impl Exporter for CompactDbExporter {
+    fn name(&self) -> &'static str {
+        "db"
+    }
+    fn file_extension(&self) -> &'static str {
+        "bin"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        let artifact = cu::check!(build_artifact(db, ctx), "failed to build compact database")?;
+        let bytes = artifact.to_bytes()?;
+        cu::fs::write(&ctx.output_path, &bytes)?;
+        cu::hint!(
+            "compact database with {} types and {} symbols ({} bytes) saved to {}",
+            artifact.types.len(),
+            artifact.symbols.len(),
+            bytes.len(),
+            ctx.output_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
+
+fn build_artifact(db: &Database, ctx: &ExportContext) -> cu::Result<DbArtifact> {
+    let names = emit::type_names(&db.types, emit::name_policy(ctx.config))?;
+    let sizes = db.sizes(ctx.config)?;
+    let mut builder = ArtifactBuilder {
+        db,
+        names: &names,
+        sizes: &sizes,
+        strings: Default::default(),
+        indices: Default::default(),
+        artifact: DbArtifact {
+            version: FORMAT_VERSION,
+            ..Default::default()
+        },
+    };
+
+    // named types first, sorted by name, so the indices are stable
+    // when unrelated types change
+    let mut named = db
+        .types
+        .iter()
+        .filter(|(_, t)| !matches!(t, HType::Prim(_)))
+        .map(|(k, _)| (builder.spelling(&Tree::Base(*k)), *k))
+        .collect::<Vec<_>>();
+    named.sort();
+    for (name, k) in &named {
+        let t = cu::check!(db.types.get(k), "unexpected missing type {k}")?;
+        let kind = match t {
+            HType::Prim(_) => cu::bail!("unexpected primitive type {k} in named types"),
+            HType::Enum(_) => DbTypeKind::Enum,
+            HType::Union(_) => DbTypeKind::Union,
+            HType::Struct(_) => DbTypeKind::Struct,
+        };
+        let db_type = DbType {
+            name: builder.string(name),
+            stable_id: db.stable_ids.get(*k).map(|x| x.0).unwrap_or_default(),
+            kind,
+            size: t.byte_size().unwrap_or_default(),
+            inner: vec![],
+            len: 0,
+            members: vec![],
+            enumerators: vec![],
+        };
+        builder.push_type(Tree::Base(*k), db_type);
+    }
+    // then fill in the members, which could add more types
+    for (i, (_, k)) in named.iter().enumerate() {
+        let t = cu::check!(db.types.get(k), "unexpected missing type {k}")?;
+        match t {
+            HType::Prim(_) => {}
+            HType::Enum(data) => {
+                let enumerators = data
+                    .data
+                    .enumerators
+                    .iter()
+                    .map(|e| (builder.string(&e.name), e.value))
+                    .collect();
+                builder.artifact.types[i].enumerators = enumerators;
+            }
+            HType::Union(data) => {
+                let members = builder.members(&data.data.members);
+                builder.artifact.types[i].members = members;
+            }
+            HType::Struct(data) => {
+                let members = builder.members(&data.data.members);
+                builder.artifact.types[i].members = members;
+            }
+        }
+    }
+
+    for symbol in db.symbols.values() {
+        let db_symbol = DbSymbol {
+            address: symbol.address,
+            link_name: builder.string(&symbol.link_name),
+            type_index: builder.type_index(&symbol.ty),
+        };
+        builder.artifact.symbols.push(db_symbol);
+    }
+    let mut artifact = builder.artifact;
+    artifact.build_indices();
+    Ok(artifact)
+}
+
+struct ArtifactBuilder<'a> {
+    db: &'a Database,
+    names: &'a GoffMap<String>,
+    sizes: &'a SizeMap,
+    /// Index of each string in the string table
+    strings: BTreeMap<String, u32>,
+    /// Index of each type tree in the types
+    indices: BTreeMap<Tree<Goff>, u32>,
+    artifact: DbArtifact,
+}
+
+impl ArtifactBuilder<'_> {
+    fn members(&mut self, members: &[Member]) -> Vec<DbMember> {
+        members
+            .iter()
+            .map(|m| DbMember {
+                offset: m.offset,
+                size: self.sizes.get_tree_optional(&m.ty).unwrap_or_default(),
+                name: self.string(m.name.as_ref().map(|x| x.as_ref()).unwrap_or_default()),
+                type_index: self.type_index(&m.ty),
+            })
+            .collect()
+    }
+
+    /// Get the index of the string in the string table, adding it if needed
+    fn string(&mut self, s: &str) -> u32 {
+        if let Some(i) = self.strings.get(s) {
+            return *i;
+        }
+        let i = self.artifact.strings.len() as u32;
+        self.artifact.strings.push(s.to_string());
+        self.strings.insert(s.to_string(), i);
+        i
+    }
+
+    fn push_type(&mut self, tree: Tree<Goff>, db_type: DbType) -> u32 {
+        let i = self.artifact.types.len() as u32;
+        self.artifact.types.push(db_type);
+        self.indices.insert(tree, i);
+        i
+    }
+
+    /// Get the index of the type, adding it and the types it's made of if needed.
+    ///
+    /// All named types are added upfront, so only primitives and types made of
+    /// other types are added here
+    fn type_index(&mut self, tree: &Tree<Goff>) -> u32 {
+        if let Some(i) = self.indices.get(tree) {
+            return *i;
+        }
+        let (kind, inner, len) = match tree {
+            Tree::Base(k) => match self.db.types.get(k) {
+                Some(HType::Prim(_)) => (DbTypeKind::Prim, vec![], 0),
+                _ => return NO_TYPE_INDEX,
+            },
+            Tree::Array(elem, len) => (DbTypeKind::Array, vec![self.type_index(elem)], *len),
+            Tree::Ptr(pointee) => (DbTypeKind::Pointer, vec![self.type_index(pointee)], 0),
+            Tree::Sub(types) => {
+                let inner = types.iter().map(|x| self.type_index(x)).collect();
+                (DbTypeKind::Subroutine, inner, 0)
+            }
+            Tree::Ptmd(base, pointee) => {
+                let inner = vec![
+                    self.type_index(&Tree::Base(*base)),
+                    self.type_index(pointee),
+                ];
+                (DbTypeKind::PtmData, inner, 0)
+            }
+            Tree::Ptmf(base, types) => {
+                let mut inner = vec![self.type_index(&Tree::Base(*base))];
+                inner.extend(types.iter().map(|x| self.type_index(x)));
+                (DbTypeKind::PtmFunc, inner, 0)
+            }
+        };
+        let name = self.spelling(tree);
+        let db_type = DbType {
+            name: self.string(&name),
+            stable_id: 0,
+            kind,
+            size: self.sizes.get_tree_optional(tree).unwrap_or_default(),
+            inner,
+            len,
+            members: vec![],
+            enumerators: vec![],
+        };
+        self.push_type(tree.clone(), db_type)
+    }
+
+    fn spelling(&self, tree: &Tree<Goff>) -> String {
+        let tree = tree.clone().map(|k| match self.db.types.get(&k) {
+            Some(HType::Prim(p)) => Ty::Prim(*p),
+            _ => Ty::Named(
+                self.names
+                    .get(&k)
+                    .cloned()
+                    .unwrap_or_else(|| exstructs::anonymous_type_name(k)),
+            ),
+        });
+        tree.to_string()
+    }
+}
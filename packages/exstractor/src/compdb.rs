@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use dejj_utils::{Config, ExtractCompdbConfig};
+use llvmutils::CompileCommand;
+
+/// The compile commands from compile_commands.json, with the rules
+/// in `[extract.compdb]` applied
+pub struct Compdb {
+    /// Commands by the file name in compile_commands.json (before remapping),
+    /// which is the name of the compilation unit in DWARF
+    commands: BTreeMap<String, CompileCommand>,
+    remap_prefixes: Vec<(String, String)>,
+    fallback_command: Option<String>,
+}
+
+impl Compdb {
+    /// Load compile_commands.json from the path in the config
+    pub fn load(config: &Config) -> cu::Result<Self> {
+        let commands = llvmutils::parse_compdb(&config.paths.compdb)?;
+        Ok(Self::new(commands, &config.extract.compdb))
+    }
+
+    fn new(commands: BTreeMap<String, CompileCommand>, config: &ExtractCompdbConfig) -> Self {
+        let remap_prefixes = config.remap_prefixes.clone();
+        let commands = commands
+            .into_iter()
+            .filter(|(file, _)| {
+                if !config.include.is_empty() && !config.include.iter().any(|r| r.is_match(file)) {
+                    return false;
+                }
+                !config.exclude.iter().any(|r| r.is_match(file))
+            })
+            .map(|(file, command)| {
+                let command = CompileCommand {
+                    file: remap_path(&command.file, &remap_prefixes),
+                    command: command
+                        .command
+                        .iter()
+                        .map(|arg| remap_arg(arg, &remap_prefixes))
+                        .collect(),
+                };
+                (file, command)
+            })
+            .collect();
+        Self {
+            commands,
+            remap_prefixes,
+            fallback_command: config.fallback_command.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Iterate over the remapped commands
+    pub fn commands(&self) -> impl Iterator<Item = &CompileCommand> {
+        self.commands.values()
+    }
+
+    /// Get the compile command for a compilation unit.
+    ///
+    /// The fallback command is used if there is no entry for the unit
+    pub fn get(&self, name: &str) -> cu::Result<Option<CompileCommand>> {
+        if let Some(command) = self.commands.get(name) {
+            return Ok(Some(command.clone()));
+        }
+        let file = remap_path(name, &self.remap_prefixes);
+        // the names in DWARF could already be the remapped paths
+        if let Some(command) = self.commands.values().find(|c| c.file == file) {
+            return Ok(Some(command.clone()));
+        }
+        let Some(fallback) = &self.fallback_command else {
+            return Ok(None);
+        };
+        let mut command = cu::check!(
+            CompileCommand::from_shell(file.clone(), fallback),
+            "invalid extract.compdb.fallback-command"
+        )?;
+        for arg in &mut command.command {
+            *arg = arg.replace("{file}", &file);
+        }
+        cu::debug!("using fallback compile command for {name}");
+        Ok(Some(command))
+    }
+
+    /// Flags for clangd in `compile_flags.txt`, with the paths remapped.
+    /// See [`llvmutils::clangd_flags`]
+    pub fn clangd_flags(&self) -> Vec<String> {
+        llvmutils::clangd_flags(self.commands.values())
+    }
+}
+
+/// Replace the first matching prefix of the path
+fn remap_path(path: &str, remap_prefixes: &[(String, String)]) -> String {
+    for (from, to) in remap_prefixes {
+        if let Some(rest) = path.strip_prefix(from.as_str()) {
+            return format!("{to}{rest}");
+        }
+    }
+    path.to_string()
+}
+
+/// Replace the first matching prefix of the path in the arg. The path could be
+/// after a flag, like `-I/src/include` or `--sysroot=/src/sysroot`
+fn remap_arg(arg: &str, remap_prefixes: &[(String, String)]) -> String {
+    for (from, to) in remap_prefixes {
+        let Some(i) = arg.find(from.as_str()) else {
+            continue;
+        };
+        let flag = &arg[..i];
+        if flag.is_empty() || (flag.starts_with('-') && !flag.contains('/')) {
+            return format!("{flag}{to}{}", &arg[i + from.len()..]);
+        }
+    }
+    arg.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remaps() -> Vec<(String, String)> {
+        vec![("/src".to_string(), "/home/me/project".to_string())]
+    }
+
+    #[test]
+    fn test_remap_path() {
+        let remaps = remaps();
+        assert_eq!(
+            remap_path("/src/foo.cpp", &remaps),
+            "/home/me/project/foo.cpp"
+        );
+        assert_eq!(remap_path("/usr/src/foo.cpp", &remaps), "/usr/src/foo.cpp");
+    }
+
+    #[test]
+    fn test_clangd_flags() -> cu::Result<()> {
+        let command = |file: &str, args: &str| {
+            let file = file.to_string();
+            let command = CompileCommand::from_shell(file.clone(), args)?;
+            Ok::<_, cu::Error>((file, command))
+        };
+        let commands = [
+            command(
+                "/src/a.cpp",
+                "clang++ -std=c++17 -I /src/include -DA -c /src/a.cpp -o a.o",
+            )?,
+            command(
+                "/src/b.cpp",
+                "clang++ -std=c++17 -I /src/include -I /src/b -c b.cpp -o b.o -MD -MF b.d",
+            )?,
+        ];
+        let config = ExtractCompdbConfig {
+            remap_prefixes: remaps(),
+            ..Default::default()
+        };
+        let compdb = Compdb::new(commands.into_iter().collect(), &config);
+        assert_eq!(
+            compdb.clangd_flags(),
+            ["-std=c++17", "-I", "/home/me/project/include"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_remap_arg() {
+        let remaps = remaps();
+        assert_eq!(
+            remap_arg("-I/src/include", &remaps),
+            "-I/home/me/project/include"
+        );
+        assert_eq!(
+            remap_arg("--sysroot=/src/sysroot", &remaps),
+            "--sysroot=/home/me/project/sysroot"
+        );
+        assert_eq!(
+            remap_arg("/src/foo.cpp", &remaps),
+            "/home/me/project/foo.cpp"
+        );
+        assert_eq!(
+            remap_arg("-I/usr/src/include", &remaps),
+            "-I/usr/src/include"
+        );
+        assert_eq!(remap_arg("-O2", &remaps), "-O2");
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::{Goff, GoffMap, HType, SizeMap, StableIdMap, SymbolInfo};
+use tyyaml::{Document, Tree};
+
+use crate::demangled_index::DemangledIndex;
+use crate::dwarf_loader::LineRow;
+use crate::stages::{self, HStage};
+
+/// The final output of extraction, which post-processing commands
+/// can load without re-running the extraction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Database {
+    pub types: GoffMap<HType>,
+    /// Symbols by link name
+    pub symbols: BTreeMap<String, SymbolInfo>,
+    /// Named function pointer (callback) typedefs and aliases of types
+    /// with identical layouts, by name
+    #[serde(default)]
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
+    /// Enumerators of anonymous enums by fully-qualified name, and macro constants by name
+    #[serde(default)]
+    pub constants: BTreeMap<String, i64>,
+    /// Static call graph, if enabled in the config. Sorted by caller address
+    #[serde(default)]
+    pub calls: Vec<CallEdge>,
+    /// Mapping from addresses to source lines, if enabled in the config
+    #[serde(default)]
+    pub lines: LineTable,
+    /// Demangled names of the symbols, if enabled in the config
+    #[serde(default)]
+    pub demangled: DemangledIndex,
+    /// IDs of the types that are stable across builds, for comparing and caching
+    /// outputs from different builds. Goffs are only unique within one database
+    #[serde(default)]
+    pub stable_ids: StableIdMap,
+}
+
+/// A direct call from one function to another, from the call site debug info
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CallEdge {
+    /// Address of the calling function
+    pub caller_address: u32,
+    /// Return address of the call, i.e. the address after the call instruction.
+    /// None if the call site does not have the return address
+    pub return_address: Option<u32>,
+    /// Link name of the calling function
+    pub caller: String,
+    /// Link name of the called function
+    pub callee: String,
+}
+
+/// Compact mapping from addresses to source lines, from `.debug_line`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LineTable {
+    /// Paths of the source files
+    pub files: Vec<String>,
+    /// (address, index in files, line), sorted by address. Each row
+    /// covers the addresses until the next row
+    pub rows: Vec<(u32, u32, u32)>,
+}
+
+impl LineTable {
+    pub(crate) fn from_rows(mut rows: Vec<LineRow>) -> Self {
+        rows.sort_unstable();
+        // inline functions are defined in multiple units
+        rows.dedup_by_key(|x| x.address);
+        let files = rows
+            .iter()
+            .map(|x| x.file.as_str())
+            .collect::<BTreeSet<_>>();
+        let indices = files
+            .iter()
+            .enumerate()
+            .map(|(i, file)| (*file, i as u32))
+            .collect::<BTreeMap<_, _>>();
+        let rows = rows
+            .iter()
+            .map(|x| (x.address, indices[x.file.as_str()], x.line))
+            .collect();
+        Self {
+            files: files.into_iter().map(|x| x.to_string()).collect(),
+            rows,
+        }
+    }
+
+    /// Get the file and line of the address, from the last row at or before the address
+    pub fn lookup(&self, address: u32) -> Option<(&str, u32)> {
+        let i = self.rows.partition_point(|x| x.0 <= address);
+        let (_, file, line) = self.rows.get(i.checked_sub(1)?)?;
+        Some((self.files.get(*file as usize)?, *line))
+    }
+}
+
+impl Database {
+    /// Path of the database emitted by extraction
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("database.json")
+    }
+
+    /// Path of the database before the type optimizer is applied
+    pub fn raw_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("database.raw.json")
+    }
+
+    pub(crate) fn from_hstage(
+        stage: &HStage,
+        constants: &BTreeMap<String, i64>,
+        calls: &[CallEdge],
+        lines: &LineTable,
+    ) -> Self {
+        Self {
+            types: stage.types.clone(),
+            symbols: stage.symbols.clone(),
+            typedefs: stage.typedefs.clone(),
+            constants: constants.clone(),
+            calls: calls.to_vec(),
+            lines: lines.clone(),
+            demangled: Default::default(),
+            stable_ids: StableIdMap::new(&stage.types),
+        }
+    }
+
+    /// Convert a TyYAML document to a database with only types, symbols and typedefs.
+    /// Names of the types are the names in the document
+    pub fn from_tyyaml(doc: &Document) -> cu::Result<Self> {
+        let (types, symbols, typedefs) = exstructs::from_tyyaml_document(doc)?;
+        Ok(Self {
+            stable_ids: StableIdMap::new(&types),
+            types,
+            symbols,
+            typedefs,
+            constants: Default::default(),
+            calls: vec![],
+            lines: Default::default(),
+            demangled: Default::default(),
+        })
+    }
+
+    /// Compute the size and alignment of every type in the database
+    pub fn sizes(&self, config: &Config) -> cu::Result<SizeMap> {
+        stages::size_map(&self.types, config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> cu::Result<Self> {
+        let path = path.as_ref();
+        let content = cu::fs::read_string(path)?;
+        let database = cu::check!(
+            json::parse(&content),
+            "failed to parse database from {}",
+            path.display()
+        )?;
+        Ok(database)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> cu::Result<()> {
+        let content = cu::check!(json::stringify(self), "failed to serialize database")?;
+        cu::fs::write(path, content)
+    }
+}
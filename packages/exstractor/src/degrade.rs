@@ -0,0 +1,53 @@
+/// Safer settings to retry a compilation unit with, after it fails
+/// to load (stage0) or reduce (stage1) with the full feature set
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Degradation {
+    /// Keep template args with unrecognized shapes in names as opaque strings,
+    /// regardless of `extract.type-parser.allow-unknown-template-args`
+    pub lenient_names: bool,
+    /// Ignore unexpected DWARF tags in type entries instead of failing
+    pub ignore_unknown_tags: bool,
+    /// Do not load virtual functions of structs
+    pub skip_vtables: bool,
+}
+
+impl Degradation {
+    /// Settings to try in order, from the full feature set to the safest
+    pub const LEVELS: [Self; 4] = [
+        Self {
+            lenient_names: false,
+            ignore_unknown_tags: false,
+            skip_vtables: false,
+        },
+        Self {
+            lenient_names: true,
+            ignore_unknown_tags: false,
+            skip_vtables: false,
+        },
+        Self {
+            lenient_names: true,
+            ignore_unknown_tags: true,
+            skip_vtables: false,
+        },
+        Self {
+            lenient_names: true,
+            ignore_unknown_tags: true,
+            skip_vtables: true,
+        },
+    ];
+
+    /// Names of the degraded features
+    pub fn describe(&self) -> Vec<&'static str> {
+        let mut output = vec![];
+        if self.lenient_names {
+            output.push("lenient-names");
+        }
+        if self.ignore_unknown_tags {
+            output.push("ignore-unknown-tags");
+        }
+        if self.skip_vtables {
+            output.push("skip-vtables");
+        }
+        output
+    }
+}
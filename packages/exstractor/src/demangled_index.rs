@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use exstructs::SymbolInfo;
+use llvmutils::{DemangledName, Demangler};
+
+/// Demangled names of the symbols in the database, for looking up
+/// symbols by their names in the source code
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DemangledIndex {
+    /// Demangled symbols by link name
+    pub symbols: BTreeMap<String, DemangledSymbol>,
+    /// Addresses of the symbols by demangled name, sorted. Multiple symbols can have
+    /// the same demangled name, for example the complete and base object constructors
+    pub addresses: BTreeMap<String, Vec<u32>>,
+}
+
+/// A symbol with its demangled name split into parts, see [`DemangledName`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DemangledSymbol {
+    pub address: u32,
+    pub demangled: String,
+    /// Namespace or class that the symbol is in, empty for global symbols
+    pub scope: String,
+    /// Unqualified name, including the template arguments
+    pub name: String,
+    /// Types of the parameters as spelled in the demangled name,
+    /// None if the symbol is not a function
+    pub params: Option<Vec<String>>,
+}
+
+impl DemangledIndex {
+    /// Demangle every symbol and build the index
+    pub(crate) fn build(
+        symbols: &BTreeMap<String, SymbolInfo>,
+        demangler: &Demangler,
+    ) -> cu::Result<Self> {
+        let mut demangled_symbols = BTreeMap::new();
+        for (link_name, symbol) in symbols {
+            let demangled = demangler.demangle(link_name)?;
+            let DemangledName {
+                scope,
+                name,
+                params,
+            } = DemangledName::parse(&demangled);
+            let symbol = DemangledSymbol {
+                address: symbol.address,
+                demangled,
+                scope,
+                name,
+                params,
+            };
+            demangled_symbols.insert(link_name.clone(), symbol);
+        }
+        Ok(Self::from_symbols(demangled_symbols))
+    }
+
+    /// Build the reverse index from the demangled symbols
+    pub(crate) fn from_symbols(symbols: BTreeMap<String, DemangledSymbol>) -> Self {
+        let mut addresses = BTreeMap::<String, Vec<u32>>::new();
+        for symbol in symbols.values() {
+            addresses
+                .entry(symbol.demangled.clone())
+                .or_default()
+                .push(symbol.address);
+        }
+        for x in addresses.values_mut() {
+            x.sort_unstable();
+            x.dedup();
+        }
+        Self { symbols, addresses }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Get the addresses of the symbols with the demangled name
+    pub fn lookup(&self, demangled: &str) -> &[u32] {
+        self.addresses
+            .get(demangled)
+            .map(|x| x.as_slice())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use cu::pre::*;
+use exstructs::algorithm::NamePolicy;
+use regex::Regex;
+use tyyaml::{MemberDef, TypeDef};
+
+use crate::database::Database;
+use crate::emit::EmitModel;
+
+/// Options for comparing two extraction outputs
+#[derive(Debug)]
+pub struct DiffOptions {
+    /// Old database, or the output directory that contains it
+    pub old: PathBuf,
+    /// New database, or the output directory that contains it
+    pub new: PathBuf,
+    /// Save the report as JSON to this path
+    pub output: Option<PathBuf>,
+}
+
+/// Differences between two databases, for example from two versions of the program.
+///
+/// Types are matched by name and symbols are matched by link name, since
+/// the offsets in the debug info are not stable between builds. Anonymous types
+/// are only compared as part of the types that contain them
+#[derive(Debug, Default, Serialize)]
+pub struct DiffReport {
+    pub added_types: Vec<String>,
+    pub removed_types: Vec<String>,
+    pub changed_types: Vec<TypeDiff>,
+    pub added_symbols: Vec<String>,
+    pub removed_symbols: Vec<String>,
+    pub changed_symbols: Vec<SymbolDiff>,
+}
+
+/// Changes to a type that exists in both databases
+#[derive(Debug, Serialize)]
+pub struct TypeDiff {
+    pub name: String,
+    /// Description of each change, for example `size changed from 0x10 to 0x18`
+    pub changes: Vec<String>,
+}
+
+/// Changes to a symbol that exists in both databases
+#[derive(Debug, Serialize)]
+pub struct SymbolDiff {
+    pub name: String,
+    pub old_address: u32,
+    pub new_address: u32,
+    /// Old type, if the type changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_type: Option<String>,
+    /// New type, if the type changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_type: Option<String>,
+}
+
+/// Compare two extracted databases, and report the added, removed and changed types and symbols
+pub fn diff(options: DiffOptions) -> cu::Result<()> {
+    let old = load_model(&options.old)?;
+    let new = load_model(&options.new)?;
+    let report = DiffReport::new(&old, &new);
+    report.print();
+    if let Some(path) = &options.output {
+        cu::fs::write_json_pretty(path, &report)?;
+        cu::hint!("diff report saved to {}", path.try_to_rel().display());
+    }
+    Ok(())
+}
+
+fn load_model(path: &Path) -> cu::Result<EmitModel> {
+    let path = if path.is_dir() {
+        path.join("database.json")
+    } else {
+        path.to_path_buf()
+    };
+    let db = Database::load(&path)?;
+    cu::check!(
+        // the databases are not from the config, so the default names are compared
+        EmitModel::from_database(&db, NamePolicy::default()),
+        "failed to convert database {}",
+        path.try_to_rel().display()
+    )
+}
+
+impl DiffReport {
+    pub fn new(old: &EmitModel, new: &EmitModel) -> Self {
+        let mut report = Self::default();
+        for (name, old_type) in &old.types {
+            if is_anonymous(name) {
+                continue;
+            }
+            match new.types.get(name) {
+                None => report.removed_types.push(name.clone()),
+                Some(new_type) => {
+                    let changes = type_changes(old_type, new_type);
+                    if !changes.is_empty() {
+                        report.changed_types.push(TypeDiff {
+                            name: name.clone(),
+                            changes,
+                        });
+                    }
+                }
+            }
+        }
+        for name in new.types.keys() {
+            if !is_anonymous(name) && !old.types.contains_key(name) {
+                report.added_types.push(name.clone());
+            }
+        }
+
+        for (name, old_symbol) in &old.symbols {
+            let Some(new_symbol) = new.symbols.get(name) else {
+                report.removed_symbols.push(name.clone());
+                continue;
+            };
+            let old_type = normalize_type(&old_symbol.ty.to_string());
+            let new_type = normalize_type(&new_symbol.ty.to_string());
+            if old_symbol.address == new_symbol.address && old_type == new_type {
+                continue;
+            }
+            let type_changed = old_type != new_type;
+            report.changed_symbols.push(SymbolDiff {
+                name: name.clone(),
+                old_address: old_symbol.address,
+                new_address: new_symbol.address,
+                old_type: type_changed.then_some(old_type),
+                new_type: type_changed.then_some(new_type),
+            });
+        }
+        for name in new.symbols.keys() {
+            if !old.symbols.contains_key(name) {
+                report.added_symbols.push(name.clone());
+            }
+        }
+        report
+    }
+
+    /// Print the report to the log
+    pub fn print(&self) {
+        for name in &self.removed_types {
+            cu::info!("removed type {name}");
+        }
+        for name in &self.added_types {
+            cu::info!("added type {name}");
+        }
+        for t in &self.changed_types {
+            cu::info!("changed type {}:", t.name);
+            for change in &t.changes {
+                cu::info!("  {change}");
+            }
+        }
+        for name in &self.removed_symbols {
+            cu::info!("removed symbol {name}");
+        }
+        for name in &self.added_symbols {
+            cu::info!("added symbol {name}");
+        }
+        for s in &self.changed_symbols {
+            if s.old_address != s.new_address {
+                cu::info!(
+                    "symbol {} moved from 0x{:08x} to 0x{:08x}",
+                    s.name,
+                    s.old_address,
+                    s.new_address
+                );
+            }
+            if let (Some(old_type), Some(new_type)) = (&s.old_type, &s.new_type) {
+                cu::info!(
+                    "symbol {} type changed from {old_type} to {new_type}",
+                    s.name
+                );
+            }
+        }
+        cu::hint!(
+            "types: {} added, {} removed, {} changed; symbols: {} added, {} removed, {} changed",
+            self.added_types.len(),
+            self.removed_types.len(),
+            self.changed_types.len(),
+            self.added_symbols.len(),
+            self.removed_symbols.len(),
+            self.changed_symbols.len()
+        );
+    }
+}
+
+fn is_anonymous(name: &str) -> bool {
+    name.starts_with("[anonymous ")
+}
+
+/// Remove the offsets from the names of anonymous types in the type string,
+/// since they are different in every build
+fn normalize_type(ty: &str) -> String {
+    static ANONYMOUS: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\[anonymous 0x[0-9a-fA-F]+\]").unwrap());
+    ANONYMOUS.replace_all(ty, "[anonymous]").into_owned()
+}
+
+fn type_changes(old: &TypeDef, new: &TypeDef) -> Vec<String> {
+    let mut changes = vec![];
+    match (old, new) {
+        (
+            TypeDef::Enum {
+                size: old_size,
+                signed: old_signed,
+                enumerators: old_enumerators,
+                ..
+            },
+            TypeDef::Enum {
+                size: new_size,
+                signed: new_signed,
+                enumerators: new_enumerators,
+                ..
+            },
+        ) => {
+            size_change(*old_size, *new_size, &mut changes);
+            if old_signed != new_signed {
+                let signedness = |signed: bool| if signed { "signed" } else { "unsigned" };
+                changes.push(format!(
+                    "underlying type changed from {} to {}",
+                    signedness(*old_signed),
+                    signedness(*new_signed)
+                ));
+            }
+            let old_values = old_enumerators
+                .iter()
+                .map(|e| (e.name.as_str(), e.value))
+                .collect::<BTreeMap<_, _>>();
+            let new_values = new_enumerators
+                .iter()
+                .map(|e| (e.name.as_str(), e.value))
+                .collect::<BTreeMap<_, _>>();
+            for (name, old_value) in &old_values {
+                match new_values.get(name) {
+                    None => changes.push(format!("removed enumerator {name}")),
+                    Some(new_value) if new_value != old_value => changes.push(format!(
+                        "enumerator {name} changed from {old_value} to {new_value}"
+                    )),
+                    _ => {}
+                }
+            }
+            for (name, new_value) in &new_values {
+                if !old_values.contains_key(name) {
+                    changes.push(format!("added enumerator {name} = {new_value}"));
+                }
+            }
+        }
+        (
+            TypeDef::Union {
+                size: old_size,
+                members: old_members,
+                ..
+            },
+            TypeDef::Union {
+                size: new_size,
+                members: new_members,
+                ..
+            },
+        ) => {
+            size_change(*old_size, *new_size, &mut changes);
+            member_changes(old_members, new_members, &mut changes);
+        }
+        (
+            TypeDef::Struct {
+                size: old_size,
+                members: old_members,
+                vtable: old_vtable,
+                ..
+            },
+            TypeDef::Struct {
+                size: new_size,
+                members: new_members,
+                vtable: new_vtable,
+                ..
+            },
+        ) => {
+            size_change(*old_size, *new_size, &mut changes);
+            member_changes(old_members, new_members, &mut changes);
+            let old_vtable = old_vtable
+                .iter()
+                .map(|v| (v.index, &v.name))
+                .collect::<BTreeMap<_, _>>();
+            let new_vtable = new_vtable
+                .iter()
+                .map(|v| (v.index, &v.name))
+                .collect::<BTreeMap<_, _>>();
+            if old_vtable != new_vtable {
+                changes.push(format!(
+                    "virtual functions changed from {} to {} entries",
+                    old_vtable.len(),
+                    new_vtable.len()
+                ));
+            }
+        }
+        (TypeDef::Typedef { ty: old_ty }, TypeDef::Typedef { ty: new_ty }) => {
+            let old_ty = normalize_type(&old_ty.to_string());
+            let new_ty = normalize_type(&new_ty.to_string());
+            if old_ty != new_ty {
+                changes.push(format!("type changed from {old_ty} to {new_ty}"));
+            }
+        }
+        _ => changes.push(format!(
+            "kind changed from {} to {}",
+            kind_name(old),
+            kind_name(new)
+        )),
+    }
+    changes
+}
+
+fn kind_name(t: &TypeDef) -> &'static str {
+    match t {
+        TypeDef::Enum { .. } => "enum",
+        TypeDef::Union { .. } => "union",
+        TypeDef::Struct { .. } => "struct",
+        TypeDef::Typedef { .. } => "typedef",
+    }
+}
+
+fn size_change(old_size: u32, new_size: u32, changes: &mut Vec<String>) {
+    if old_size != new_size {
+        changes.push(format!(
+            "size changed from 0x{old_size:x} to 0x{new_size:x}"
+        ));
+    }
+}
+
+/// Compare the members by name. Unnamed members (like base classes)
+/// are matched by their type instead
+fn member_changes(old: &[MemberDef], new: &[MemberDef], changes: &mut Vec<String>) {
+    let old_members = member_map(old);
+    let new_members = member_map(new);
+    for (key, (old_offset, old_ty)) in &old_members {
+        let Some((new_offset, new_ty)) = new_members.get(key) else {
+            changes.push(format!("removed member {key} at 0x{old_offset:x}"));
+            continue;
+        };
+        if old_offset != new_offset {
+            changes.push(format!(
+                "member {key} moved from 0x{old_offset:x} to 0x{new_offset:x}"
+            ));
+        }
+        if old_ty != new_ty {
+            changes.push(format!(
+                "member {key} type changed from {old_ty} to {new_ty}"
+            ));
+        }
+    }
+    for (key, (new_offset, _)) in &new_members {
+        if !old_members.contains_key(key) {
+            changes.push(format!("added member {key} at 0x{new_offset:x}"));
+        }
+    }
+}
+
+/// Map the members by name to (offset, type)
+fn member_map(members: &[MemberDef]) -> BTreeMap<String, (u32, String)> {
+    let mut output = BTreeMap::new();
+    for m in members {
+        let ty = normalize_type(&m.ty.to_string());
+        let key = match (&m.name, &m.special) {
+            (Some(name), _) => name.clone(),
+            (None, Some(special)) => format!("({special} {ty})"),
+            (None, None) => format!("(anonymous {ty})"),
+        };
+        output.insert(key, (m.offset, ty));
+    }
+    output
+}
@@ -0,0 +1,210 @@
+use std::borrow::Cow;
+
+use cu::pre::*;
+use elf::ElfBytes;
+use elf::endian::LittleEndian as ElfLittleEndian;
+
+use crate::dwarf::elf::read_elf_section;
+
+/// Object file that holds the DWARF sections.
+///
+/// Only little-endian images are supported, since the DWARF is read as little-endian
+pub enum Container<'a> {
+    Elf(ElfBytes<'a, ElfLittleEndian>),
+    /// Mach-O image or dSYM companion file
+    MachO(SectionTable<'a>),
+    /// PE/COFF image with DWARF sections (for example, built with MinGW)
+    Pe(SectionTable<'a>),
+}
+
+/// Section name and data, for containers that are parsed here
+pub struct SectionTable<'a> {
+    sections: Vec<(String, &'a [u8])>,
+}
+
+impl<'a> Container<'a> {
+    /// Detect the container format by the magic and parse the headers
+    pub fn parse(buf: &'a [u8]) -> cu::Result<Self> {
+        match buf.get(0..4) {
+            Some([0x7f, b'E', b'L', b'F']) => {
+                let elf_data = ElfBytes::<ElfLittleEndian>::minimal_parse(buf);
+                let elf_data = cu::check!(elf_data, "failed to parse ELF")?;
+                Ok(Self::Elf(elf_data))
+            }
+            Some([0xce, 0xfa, 0xed, 0xfe]) => {
+                let table = cu::check!(parse_macho(buf, false), "failed to parse Mach-O")?;
+                Ok(Self::MachO(table))
+            }
+            Some([0xcf, 0xfa, 0xed, 0xfe]) => {
+                let table = cu::check!(parse_macho(buf, true), "failed to parse Mach-O")?;
+                Ok(Self::MachO(table))
+            }
+            Some([0xca, 0xfe, 0xba, 0xbe]) => {
+                cu::bail!(
+                    "universal (fat) Mach-O is not supported, extract the slice with lipo first"
+                )
+            }
+            Some([b'M', b'Z', _, _]) => {
+                let table = cu::check!(parse_pe(buf), "failed to parse PE")?;
+                Ok(Self::Pe(table))
+            }
+            _ => cu::bail!("unknown object file format, expected ELF, Mach-O or PE"),
+        }
+    }
+
+    pub fn format_name(&self) -> &'static str {
+        match self {
+            Self::Elf(..) => "ELF",
+            Self::MachO(_) => "Mach-O",
+            Self::Pe(_) => "PE",
+        }
+    }
+
+    /// Get the data of a DWARF section by its ELF name (for example, `.debug_info`).
+    /// Compressed ELF sections are decompressed. Returns None if the section does not exist
+    pub fn section_data(&self, name: &str) -> cu::Result<Option<Cow<'a, [u8]>>> {
+        match self {
+            Self::Elf(elf_data) => read_elf_section(elf_data, name),
+            Self::MachO(table) => {
+                // __debug_info in the __DWARF segment, truncated to 16 bytes
+                let name = format!("__{}", name.strip_prefix('.').unwrap_or(name));
+                let name = &name[..name.len().min(16)];
+                Ok(table.get(name).map(Cow::Borrowed))
+            }
+            Self::Pe(table) => Ok(table.get(name).map(Cow::Borrowed)),
+        }
+    }
+}
+
+impl<'a> SectionTable<'a> {
+    fn get(&self, name: &str) -> Option<&'a [u8]> {
+        self.sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, data)| *data)
+    }
+}
+
+fn parse_macho(buf: &[u8], is_64: bool) -> cu::Result<SectionTable<'_>> {
+    const LC_SEGMENT: u32 = 0x1;
+    const LC_SEGMENT_64: u32 = 0x19;
+    let ncmds = read_u32(buf, 16)?;
+    let mut offset = if is_64 { 32 } else { 28 };
+    let mut sections = vec![];
+    for _ in 0..ncmds {
+        let cmd = read_u32(buf, offset)?;
+        let cmdsize = read_u32(buf, offset + 4)? as usize;
+        cu::ensure!(cmdsize > 0, "invalid load command size at 0x{offset:x}")?;
+        // (nsects offset, first section offset, section size, size/offset field offsets)
+        let layout = match cmd {
+            LC_SEGMENT => Some((48, 56, 68, 36, 40)),
+            LC_SEGMENT_64 => Some((64, 72, 80, 40, 48)),
+            _ => None,
+        };
+        if let Some((nsects_off, sects_off, sect_size, size_off, offset_off)) = layout {
+            let nsects = read_u32(buf, offset + nsects_off)? as usize;
+            for i in 0..nsects {
+                let sect = offset + sects_off + i * sect_size;
+                let name = read_fixed_str(buf, sect, 16)?;
+                let size = if cmd == LC_SEGMENT_64 {
+                    read_u64(buf, sect + size_off)? as usize
+                } else {
+                    read_u32(buf, sect + size_off)? as usize
+                };
+                let file_offset = read_u32(buf, sect + offset_off)? as usize;
+                if file_offset == 0 {
+                    // zero-fill section, no data in file
+                    continue;
+                }
+                let data = read_bytes(buf, file_offset, size)?;
+                cu::trace!("found Mach-O section {name} at 0x{file_offset:x}");
+                sections.push((name, data));
+            }
+        }
+        offset += cmdsize;
+    }
+    Ok(SectionTable { sections })
+}
+
+fn parse_pe(buf: &[u8]) -> cu::Result<SectionTable<'_>> {
+    let pe_offset = read_u32(buf, 0x3c)? as usize;
+    cu::ensure!(
+        read_bytes(buf, pe_offset, 4)? == b"PE\0\0",
+        "missing PE signature"
+    )?;
+    let coff = pe_offset + 4;
+    let nsects = read_u16(buf, coff + 2)? as usize;
+    let symtab_offset = read_u32(buf, coff + 8)? as usize;
+    let nsyms = read_u32(buf, coff + 12)? as usize;
+    let optional_header_size = read_u16(buf, coff + 16)? as usize;
+    // the string table follows the COFF symbol table
+    let strtab_offset = symtab_offset + nsyms * 18;
+
+    let sects_offset = coff + 20 + optional_header_size;
+    let mut sections = vec![];
+    for i in 0..nsects {
+        let sect = sects_offset + i * 40;
+        let short_name = read_fixed_str(buf, sect, 8)?;
+        // names longer than 8 bytes (like .debug_info) are stored as "/<offset into string table>"
+        let name = match short_name.strip_prefix('/') {
+            Some(index) if symtab_offset != 0 => {
+                let index = cu::check!(
+                    index.parse::<usize>(),
+                    "invalid long section name: {short_name}"
+                )?;
+                read_c_str(buf, strtab_offset + index)?
+            }
+            _ => short_name,
+        };
+        let virtual_size = read_u32(buf, sect + 8)? as usize;
+        let raw_size = read_u32(buf, sect + 16)? as usize;
+        let raw_offset = read_u32(buf, sect + 20)? as usize;
+        // raw data is padded to file alignment
+        let size = if virtual_size == 0 {
+            raw_size
+        } else {
+            virtual_size.min(raw_size)
+        };
+        let data = read_bytes(buf, raw_offset, size)?;
+        cu::trace!("found PE section {name} at 0x{raw_offset:x}");
+        sections.push((name, data));
+    }
+    Ok(SectionTable { sections })
+}
+
+fn read_bytes(buf: &[u8], offset: usize, len: usize) -> cu::Result<&[u8]> {
+    let end = cu::check!(offset.checked_add(len), "offset overflow")?;
+    let bytes = buf.get(offset..end);
+    cu::check!(bytes, "out of bound read at 0x{offset:x} (len=0x{len:x})")
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> cu::Result<u16> {
+    let bytes = read_bytes(buf, offset, 2)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> cu::Result<u32> {
+    let bytes = read_bytes(buf, offset, 4)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> cu::Result<u64> {
+    let bytes = read_bytes(buf, offset, 8)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Read a NUL-padded name of fixed length
+fn read_fixed_str(buf: &[u8], offset: usize, len: usize) -> cu::Result<String> {
+    let bytes = read_bytes(buf, offset, len)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(len);
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+fn read_c_str(buf: &[u8], offset: usize) -> cu::Result<String> {
+    let bytes = cu::check!(buf.get(offset..), "out of bound read at 0x{offset:x}")?;
+    let end = cu::check!(
+        bytes.iter().position(|b| *b == 0),
+        "unterminated string at 0x{offset:x}"
+    )?;
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
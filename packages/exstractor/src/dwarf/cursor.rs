@@ -0,0 +1,95 @@
+use std::borrow::Cow;
+
+use cu::pre::*;
+
+use crate::dwarf::{Die, In, Tag, Unit};
+
+/// Lazy depth-first cursor over the entries in a unit.
+///
+/// Unlike [`EntriesTree`](crate::dwarf::EntriesTree), only the entry
+/// at the cursor is materialized, and the caller can skip the children of
+/// entries that are not interesting
+pub struct DieCursor<'x> {
+    pub(crate) unit: &'x Unit,
+    pub(crate) cursor: gimli::EntriesCursor<'x, 'x, In<'static>>,
+    /// Depth of the current entry, relative to the starting entry
+    pub(crate) depth: isize,
+    /// Entries deeper than this are skipped, set by `skip_children`
+    pub(crate) skip_below: Option<isize>,
+    /// Only entries with these tags are returned, if not empty
+    pub(crate) tags: Vec<Tag>,
+    pub(crate) started: bool,
+    pub(crate) done: bool,
+}
+
+impl<'x> DieCursor<'x> {
+    /// Only return entries with one of the tags. Children of entries with
+    /// other tags are still visited, unless skipped with [`skip_children`](Self::skip_children)
+    pub fn with_tags(mut self, tags: impl IntoIterator<Item = Tag>) -> Self {
+        self.tags.extend(tags);
+        self
+    }
+
+    /// Get the unit
+    pub fn unit(&self) -> &'x Unit {
+        self.unit
+    }
+
+    /// Depth of the current entry, relative to the entry the cursor started at (which is 0)
+    pub fn depth(&self) -> isize {
+        self.depth
+    }
+
+    /// Do not visit the children of the entry last returned by [`next_entry`](Self::next_entry)
+    pub fn skip_children(&mut self) {
+        self.skip_below = Some(self.depth);
+    }
+
+    /// Move to the next entry in depth-first order. Returns `None` after
+    /// the subtree of the starting entry is exhausted
+    pub fn next_entry(&mut self) -> cu::Result<Option<Die<'x, '_>>> {
+        if self.done {
+            return Ok(None);
+        }
+        loop {
+            let next = cu::check!(
+                self.cursor.next_dfs(),
+                "failed to read next entry in {}",
+                self.unit
+            )?;
+            let Some((delta, entry)) = next else {
+                self.done = true;
+                return Ok(None);
+            };
+            let tag = entry.tag();
+            if self.started {
+                self.depth += delta;
+                if self.depth <= 0 {
+                    // left the subtree of the starting entry
+                    self.done = true;
+                    return Ok(None);
+                }
+            } else {
+                self.started = true;
+            }
+            if let Some(depth) = self.skip_below {
+                if self.depth > depth {
+                    continue;
+                }
+                self.skip_below = None;
+            }
+            if self.tags.is_empty() || self.tags.contains(&tag) {
+                break;
+            }
+        }
+        let entry = cu::check!(
+            self.cursor.current(),
+            "failed to get current entry in {}",
+            self.unit
+        )?;
+        Ok(Some(Die {
+            unit: self.unit,
+            entry: Cow::Borrowed(entry),
+        }))
+    }
+}
@@ -191,6 +191,15 @@ impl<'x> Die<'x, '_> {
         let value = self.unit.attr_unsigned(offset, attr, value)?;
         Ok(Some(value))
     }
+    /// Get the `DW_AT_decl_file` index and `DW_AT_decl_line` of the entry,
+    /// None if the entry does not have a file. The line is 0 if missing
+    pub fn decl_file_line(&self) -> cu::Result<Option<(u64, u32)>> {
+        let Some(file) = self.uint_opt(DW_AT_decl_file)? else {
+            return Ok(None);
+        };
+        let line = self.uint_opt(DW_AT_decl_line)?.unwrap_or_default();
+        Ok(Some((file, line as u32)))
+    }
     /// Get an attr of an entry as flag
     pub fn flag(&self, attr: DwAt) -> cu::Result<bool> {
         let offset = self.goff();
@@ -243,6 +252,20 @@ impl<'x> Die<'x, '_> {
         }
     }
 
+    /// Check the DW_AT_virtuality of a DIE, for example if an inheritance is virtual
+    pub fn is_virtual(&self) -> cu::Result<bool> {
+        let offset = self.goff();
+        let virtuality = cu::check!(
+            self.entry.attr_value(DW_AT_virtuality),
+            "failed to read DW_AT_virtuality for entry at {offset}"
+        )?;
+        match virtuality {
+            None | Some(AttributeValue::Virtuality(DW_VIRTUALITY_none)) => Ok(false),
+            Some(AttributeValue::Virtuality(_)) => Ok(true),
+            _ => cu::bail!("expecting DW_AT_virtuality to be Virtuality, at entry {offset}"),
+        }
+    }
+
     /// Read an attribute of a DIE, expecting a unit reference (local offset)
     pub fn loff(&self, attr: DwAt) -> cu::Result<Loff> {
         let t = self.loff_opt(attr)?;
@@ -315,6 +338,66 @@ impl<'x> Die<'x, '_> {
         Ok(prim)
     }
 
+    /// Get the end address (exclusive) of a function from DW_AT_high_pc,
+    /// which is either an address or the size from the low pc
+    pub fn high_pc(&self, low_pc: u64) -> cu::Result<Option<u64>> {
+        let offset = self.goff();
+        let value = cu::check!(
+            self.entry.attr_value(DW_AT_high_pc),
+            "failed to read DW_AT_high_pc at offset {offset}"
+        )?;
+        match value {
+            None => Ok(None),
+            Some(AttributeValue::Addr(x)) => Ok(Some(x)),
+            Some(value) => {
+                let size = self.unit.attr_unsigned(offset, DW_AT_high_pc, value)?;
+                Ok(Some(low_pc + size))
+            }
+        }
+    }
+
+    /// Get the offset of the location from the frame base of the function, if
+    /// DW_AT_location is a single DW_OP_fbreg. None for other locations,
+    /// such as registers or location lists
+    pub fn frame_offset(&self) -> cu::Result<Option<i64>> {
+        let offset = self.goff();
+        let value = cu::check!(
+            self.entry.attr_value(DW_AT_location),
+            "failed to read DW_AT_location at offset {offset}"
+        )?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        self.unit.attr_frame_offset(offset, value)
+    }
+
+    /// Get the offset in the vtable where the offset of a virtual base
+    /// is stored, for a virtual DW_TAG_inheritance
+    pub fn vbase_offset(&self) -> cu::Result<Option<i64>> {
+        let offset = self.goff();
+        let value = cu::check!(
+            self.entry.attr_value(DW_AT_data_member_location),
+            "failed to read DW_AT_data_member_location at offset {offset}"
+        )?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        self.unit.attr_vbase_offset(offset, value)
+    }
+
+    /// Get DW_AT_frame_base of a function as `"cfa"`, `"reg<n>"` or `"breg<n>+<offset>"`
+    pub fn frame_base(&self) -> cu::Result<Option<String>> {
+        let offset = self.goff();
+        let value = cu::check!(
+            self.entry.attr_value(DW_AT_frame_base),
+            "failed to read DW_AT_frame_base at offset {offset}"
+        )?;
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        self.unit.attr_frame_base(offset, value)
+    }
+
     pub fn is_inlined(&self) -> cu::Result<bool> {
         let offset = self.goff();
         let inline = cu::check!(
@@ -382,6 +465,8 @@ impl<'x> Die<'x, '_> {
             nsmaps.qualifiers.get(&offset),
             "cannot find namespace for entry {offset}, with name '{name}'"
         )?;
-        Ok(NamespacedName::namespaced(namespace, name))
+        // tags are recorded when loading the namespaces
+        let (name, _) = exstructs::split_abi_tags(name);
+        Ok(NamespacedName::namespaced(namespace, &name))
     }
 }
@@ -1,79 +1,383 @@
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use cu::pre::*;
+use dashmap::DashMap;
+use dejj_utils::{AddressRelocator, SymbolMaps, SymbolSource};
 use elf::ElfBytes;
+use elf::compression::CompressionHeader;
 use elf::endian::LittleEndian as ElfLittleEndian;
-use gimli::{DwarfFileType, EndianSlice, LittleEndian as DwarfLittleEndian};
+use gimli::{
+    AbbreviationsCacheStrategy, DwarfFileType, EndianSlice, LittleEndian as DwarfLittleEndian,
+    Section, SectionId,
+};
+use memmap2::Mmap;
 
-use crate::dwarf::{In, UnitIter};
+use crate::dwarf::{Container, In, SplitDwarf, SplitDwarfPaths, UnitIter};
 
-/// Holder of Dwarf info, backed by a shared ELF buffer
+/// Holder of Dwarf info, backed by a shared object file buffer
 pub struct Dwarf {
     pub(crate) dwarf: gimli::Dwarf<In<'static>>,
+    /// Decoded strings in .debug_str by offset, shared by all units
+    pub(crate) strings: DashMap<usize, &'static str>,
+    /// Added to unit offsets to get the goffs. Non-zero for split DWARF,
+    /// so the goffs don't overlap with the main file
+    pub(crate) goff_base: usize,
+    pub(crate) split: SplitDwarf,
+    /// The main file, since split DWARF borrows sections (like .debug_addr) from it
+    _parent: Option<Arc<Dwarf>>,
     _buf: ArcBuf,
 }
 
 impl Dwarf {
-    /// Parse the DWARF in the ELF bytes
-    pub fn try_parse(buf: Arc<[u8]>) -> cu::Result<Arc<Self>> {
-        let raw_buf = ArcBuf::new(buf);
-        // safety: the lifetime of raw_buf_ref is managed
-        // by the Arc.
-        let raw_buf_ref: &'static [u8] = unsafe { &*raw_buf.0 };
-        let elf_data = ElfBytes::<ElfLittleEndian>::minimal_parse(raw_buf_ref);
-        let elf_data = cu::check!(elf_data, "failed to parse ELF")?;
-
-        let mut dwarf = gimli::Dwarf::load(|section| {
-            let section_name = section.name();
-            cu::trace!("loading ELF section {section_name}");
-            let header = cu::check!(
-                elf_data.section_header_by_name(section_name),
-                "cannot read ELF section header for section {section_name}"
-            )?;
-            let endian_slice = match header {
-                Some(header) => {
-                    let start = header.sh_offset as usize;
-                    let end = start + header.sh_size as usize;
-                    cu::debug!("found ELF section {section_name} at byte start=0x{start:016x}, end=0x{end:016x}");
-                    EndianSlice::new(&raw_buf_ref[start..end], DwarfLittleEndian)
-                }
-                None => {
-                    cu::trace!("did not found ELF section {section_name}");
-                    EndianSlice::new(&[], DwarfLittleEndian)
-                }
-            };
-            cu::Ok(endian_slice)
-        })
-        .context("failed to load DWARF from ELF")?;
-        dwarf.file_type = DwarfFileType::Main;
+    /// Parse the DWARF in the object file bytes. The object file could be
+    /// an ELF, a Mach-O (including dSYM) or a PE image.
+    ///
+    /// Split DWARF for skeleton units are loaded from the paths when
+    /// iterating the units
+    pub fn try_parse(raw_buf: ArcBuf, split_paths: SplitDwarfPaths) -> cu::Result<Arc<Self>> {
+        let dwarf = load_sections(&raw_buf, DwarfFileType::Main)?;
+        let split = SplitDwarf::load(split_paths)?;
 
         Ok(Arc::new(Self {
             dwarf,
+            strings: DashMap::new(),
+            goff_base: 0,
+            split,
+            _parent: None,
             _buf: raw_buf,
         }))
     }
 
+    /// Create the holder for split DWARF loaded for a skeleton unit in parent
+    pub(super) fn new_split(
+        mut dwarf: gimli::Dwarf<In<'static>>,
+        buf: ArcBuf,
+        goff_base: usize,
+        parent: &Arc<Self>,
+    ) -> Arc<Self> {
+        dwarf.populate_abbreviations_cache(AbbreviationsCacheStrategy::Duplicates);
+        Arc::new(Self {
+            dwarf,
+            strings: DashMap::new(),
+            goff_base,
+            split: SplitDwarf::default(),
+            _parent: Some(Arc::clone(parent)),
+            _buf: buf,
+        })
+    }
+
+    /// Size of .debug_info, which is the range of the unit offsets
+    pub(crate) fn debug_info_len(&self) -> usize {
+        self.dwarf.debug_info.reader().len()
+    }
+
+    /// Iterate the units in .debug_info. Each unit is only parsed when reached.
+    /// Use [`Unit::cursor`] to scan the entries lazily
     pub fn iter_units(self_: &Arc<Self>) -> UnitIter {
         let iter = self_.dwarf.debug_info.units();
         UnitIter {
             debug_info_iter: iter,
             dwarf: Arc::clone(&self_),
+            next_split_base: self_.goff_base + self_.debug_info_len(),
         }
     }
 }
 
-struct ArcBuf(*const [u8]);
-impl ArcBuf {
-    fn new(buf: Arc<[u8]>) -> Self {
-        Self(Arc::into_raw(buf))
+/// Load the DWARF sections from an object file (or a .dwo file)
+pub(super) fn load_sections(
+    raw_buf: &ArcBuf,
+    file_type: DwarfFileType,
+) -> cu::Result<gimli::Dwarf<In<'static>>> {
+    let container = Container::parse(raw_buf.as_static())?;
+    let format = container.format_name();
+    cu::debug!("parsing DWARF from {format}");
+
+    let is_dwo = file_type == DwarfFileType::Dwo;
+    let dwarf = gimli::Dwarf::load(|section| load_section(raw_buf, &container, section, is_dwo));
+    let mut dwarf = cu::check!(dwarf, "failed to load DWARF from {format}")?;
+    dwarf.file_type = file_type;
+    // units usually share a few abbreviation tables,
+    // so only parse each table once
+    dwarf.populate_abbreviations_cache(AbbreviationsCacheStrategy::Duplicates);
+    Ok(dwarf)
+}
+
+/// Load one DWARF section. Sections in split DWARF files have the `.dwo` suffix.
+/// Decompressed sections are kept in the buffer of the file
+pub(super) fn load_section(
+    buf: &ArcBuf,
+    container: &Container<'static>,
+    section: SectionId,
+    is_dwo: bool,
+) -> cu::Result<In<'static>> {
+    let format = container.format_name();
+    let section_name = if is_dwo {
+        section.dwo_name()
+    } else {
+        Some(section.name())
+    };
+    let Some(section_name) = section_name else {
+        return Ok(EndianSlice::new(&[], DwarfLittleEndian));
+    };
+    cu::trace!("loading {format} section {section_name}");
+    let endian_slice = match container.section_data(section_name)? {
+        Some(Cow::Borrowed(data)) => EndianSlice::new(data, DwarfLittleEndian),
+        Some(Cow::Owned(data)) => EndianSlice::new(buf.keep_decompressed(data), DwarfLittleEndian),
+        None => {
+            cu::trace!("did not found {format} section {section_name}");
+            EndianSlice::new(&[], DwarfLittleEndian)
+        }
+    };
+    Ok(endian_slice)
+}
+
+/// Read an ELF section by name, decompressing it if the section header has
+/// `SHF_COMPRESSED` (zlib or zstd), or if only the legacy `.zdebug_*` section
+/// (zlib with a `ZLIB` header) exists in place of `.debug_*`
+pub(super) fn read_elf_section<'a>(
+    elf_data: &ElfBytes<'a, ElfLittleEndian>,
+    name: &str,
+) -> cu::Result<Option<Cow<'a, [u8]>>> {
+    if let Some((data, compression)) = read_elf_section_raw(elf_data, name)? {
+        let Some(compression) = compression else {
+            return Ok(Some(Cow::Borrowed(data)));
+        };
+        let size = compression.ch_size as usize;
+        let data = match compression.ch_type {
+            elf::abi::ELFCOMPRESS_ZLIB => decompress_zlib(data, size),
+            elf::abi::ELFCOMPRESS_ZSTD => decompress_zstd(data, size),
+            x => cu::bail!("unsupported compression type {x} in ELF section {name}"),
+        };
+        let data = cu::check!(data, "failed to decompress ELF section {name}")?;
+        return Ok(Some(Cow::Owned(data)));
     }
+    let Some(suffix) = name.strip_prefix(".debug_") else {
+        return Ok(None);
+    };
+    let legacy_name = format!(".zdebug_{suffix}");
+    let Some((data, _)) = read_elf_section_raw(elf_data, &legacy_name)? else {
+        return Ok(None);
+    };
+    let data = cu::check!(
+        decompress_zdebug(data),
+        "failed to decompress ELF section {legacy_name}"
+    )?;
+    Ok(Some(Cow::Owned(data)))
+}
+
+/// Read the bytes of an ELF section as in the file, and the compression header if any
+fn read_elf_section_raw<'a>(
+    elf_data: &ElfBytes<'a, ElfLittleEndian>,
+    name: &str,
+) -> cu::Result<Option<(&'a [u8], Option<CompressionHeader>)>> {
+    let header = cu::check!(
+        elf_data.section_header_by_name(name),
+        "cannot read ELF section header for section {name}"
+    )?;
+    let Some(header) = header else {
+        return Ok(None);
+    };
+    let data = cu::check!(
+        elf_data.section_data(&header),
+        "failed to read ELF section {name}"
+    )?;
+    cu::debug!(
+        "found ELF section {name} at byte start=0x{:016x}, end=0x{:016x}",
+        header.sh_offset,
+        header.sh_offset + header.sh_size
+    );
+    Ok(Some(data))
+}
+
+/// Decompress a legacy `.zdebug_*` section, which is `ZLIB`, the decompressed size
+/// in 64-bit big-endian, then the zlib stream
+fn decompress_zdebug(data: &[u8]) -> cu::Result<Vec<u8>> {
+    let Some((header, data)) = data.split_at_checked(12) else {
+        cu::bail!("section is too small for the ZLIB header");
+    };
+    cu::ensure!(&header[..4] == b"ZLIB", "missing ZLIB header")?;
+    let size = u64::from_be_bytes(header[4..].try_into().unwrap());
+    decompress_zlib(data, size as usize)
+}
+
+fn decompress_zlib(data: &[u8], size: usize) -> cu::Result<Vec<u8>> {
+    let decoder = flate2::read::ZlibDecoder::new(data);
+    cu::check!(
+        read_decompressed(decoder, data.len(), size),
+        "failed to decompress zlib data"
+    )
 }
-impl Drop for ArcBuf {
-    fn drop(&mut self) {
-        unsafe {
-            Arc::from_raw(self.0);
+
+fn decompress_zstd(data: &[u8], size: usize) -> cu::Result<Vec<u8>> {
+    let decoder = cu::check!(
+        zstd::stream::read::Decoder::new(data),
+        "failed to create zstd decoder"
+    )?;
+    cu::check!(
+        read_decompressed(decoder, data.len(), size),
+        "failed to decompress zstd data"
+    )
+}
+
+/// Max ratio of decompressed to compressed size to allocate upfront.
+/// The size in the header is not trusted, since a corrupted header
+/// could make us allocate way more memory than needed
+const MAX_PREALLOC_RATIO: usize = 8;
+
+/// Read the decompressed data, which is expected to be `size` bytes
+fn read_decompressed(
+    decoder: impl Read,
+    compressed_size: usize,
+    size: usize,
+) -> cu::Result<Vec<u8>> {
+    let capacity = size.min(compressed_size.saturating_mul(MAX_PREALLOC_RATIO));
+    let mut output = Vec::with_capacity(capacity);
+    // read 1 more byte than expected to detect data larger than the header says,
+    // without decompressing all of it
+    decoder.take(size as u64 + 1).read_to_end(&mut output)?;
+    cu::ensure!(
+        output.len() == size,
+        "decompressed size mismatch: expected 0x{size:x}, got 0x{:x}",
+        output.len()
+    )?;
+    Ok(output)
+}
+
+/// Function and data symbols defined in an ELF. The symbols are read from `.symtab`,
+/// falling back to `.dynsym` for symbols not in `.symtab` (for example, if the ELF is stripped)
+pub struct ElfSymbolSource<'a> {
+    pub bytes: &'a [u8],
+    pub relocator: AddressRelocator<'a>,
+}
+
+impl SymbolSource for ElfSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        "ELF"
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let buf = self.bytes;
+        let elf_data = ElfBytes::<ElfLittleEndian>::minimal_parse(buf);
+        let elf_data = cu::check!(elf_data, "failed to parse ELF")?;
+        let symtab = cu::check!(elf_data.symbol_table(), "failed to read ELF .symtab")?;
+        let dynsym = cu::check!(
+            elf_data.dynamic_symbol_table(),
+            "failed to read ELF .dynsym"
+        )?;
+        cu::ensure!(
+            symtab.is_some() || dynsym.is_some(),
+            "ELF has neither .symtab nor .dynsym"
+        )?;
+
+        let mut output = SymbolMaps::default();
+        for (section, table) in [(".symtab", symtab), (".dynsym", dynsym)] {
+            let Some((symbols, strings)) = table else {
+                cu::debug!("ELF section {section} not found");
+                continue;
+            };
+            for symbol in symbols.iter() {
+                if symbol.is_undefined() || symbol.st_name == 0 {
+                    continue;
+                }
+                let map = match symbol.st_symtype() {
+                    elf::abi::STT_FUNC => &mut output.funcs,
+                    elf::abi::STT_OBJECT => &mut output.data,
+                    _ => continue,
+                };
+                let name = cu::check!(
+                    strings.get(symbol.st_name as usize),
+                    "failed to read symbol name in {section}"
+                )?;
+                if name.is_empty() || map.contains_key(name) {
+                    continue;
+                }
+                let address = symbol.st_value;
+                let rel_address = cu::check!(
+                    self.relocator.relocate(address),
+                    "address 0x{address:x} of symbol '{name}' is less than base address and not in any relocated section"
+                )?;
+                cu::ensure!(
+                    rel_address <= u32::MAX as u64,
+                    "relative address of symbol '{name}' is too big, this is likely wrong"
+                )?;
+                map.insert(name.to_string(), rel_address as u32);
+            }
         }
+
+        Ok(output)
+    }
+}
+
+/// Get the lowest virtual address of the loadable segments in the ELF program headers
+pub fn elf_base_address(buf: &[u8]) -> cu::Result<u64> {
+    let elf_data = ElfBytes::<ElfLittleEndian>::minimal_parse(buf);
+    let elf_data = cu::check!(elf_data, "failed to parse ELF")?;
+    let segments = cu::check!(elf_data.segments(), "ELF has no program headers")?;
+    let base_address = segments
+        .iter()
+        .filter(|x| x.p_type == elf::abi::PT_LOAD)
+        .map(|x| x.p_vaddr)
+        .min();
+    let base_address = cu::check!(base_address, "ELF has no loadable segments")?;
+    cu::debug!("detected base address from ELF: 0x{base_address:x}");
+    Ok(base_address)
+}
+
+/// Shared buffer of a memory-mapped object file.
+///
+/// Only the pages of the sections that are accessed are loaded by the OS,
+/// so huge binaries don't need to be read into memory
+#[derive(Clone)]
+pub struct ArcBuf(Arc<ArcBufInner>);
+struct ArcBufInner {
+    mmap: Mmap,
+    /// Decompressed sections of the file, which are borrowed the same way as the mapping
+    decompressed: Mutex<Vec<Box<[u8]>>>,
+}
+impl ArcBuf {
+    /// Map the file at the path
+    pub fn map(path: &Path) -> cu::Result<Self> {
+        let file = cu::check!(File::open(path), "failed to open {}", path.display())?;
+        // safety: the object files are build outputs, which are not expected
+        // to change during extraction
+        let mmap = cu::check!(
+            unsafe { Mmap::map(&file) },
+            "failed to map {}",
+            path.display()
+        )?;
+        Ok(Self(Arc::new(ArcBufInner {
+            mmap,
+            decompressed: Default::default(),
+        })))
+    }
+    /// Get the bytes of the file
+    pub fn bytes(&self) -> &[u8] {
+        &self.0.mmap
+    }
+    /// Get the bytes. The lifetime is managed by the Arc, so the
+    /// bytes must not outlive this holder
+    pub(super) fn as_static(&self) -> &'static [u8] {
+        // safety: the mapping is alive as long as self, and is not moved
+        // when the Arc is moved
+        unsafe { std::slice::from_raw_parts(self.0.mmap.as_ptr(), self.0.mmap.len()) }
+    }
+    /// Keep the decompressed data of a section alive with the file, and get the bytes.
+    /// Same as [`as_static`](Self::as_static), the bytes must not outlive this holder
+    pub(super) fn keep_decompressed(&self, data: Vec<u8>) -> &'static [u8] {
+        let data = data.into_boxed_slice();
+        // safety: the boxed data is not moved when the box is moved,
+        // and is only dropped with the holder
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        // the data must be kept even if another thread panicked while holding the lock
+        let mut decompressed = self
+            .0
+            .decompressed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        decompressed.push(data);
+        bytes
     }
 }
-unsafe impl Send for ArcBuf {}
-unsafe impl Sync for ArcBuf {}
@@ -1,10 +1,16 @@
 #![allow(non_upper_case_globals)]
 
+mod container;
+pub use container::*;
 mod elf;
 pub use elf::*;
+mod split;
+pub use split::*;
 mod unit;
 pub use unit::*;
 mod die;
 pub use die::*;
+mod cursor;
+pub use cursor::*;
 mod util;
 pub use util::*;
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cu::pre::*;
+use gimli::{DwarfFileType, DwoId, EndianSlice, LittleEndian as DwarfLittleEndian};
+
+use crate::dwarf::elf::{ArcBuf, load_section, load_sections};
+use crate::dwarf::{Container, Dwarf, In};
+
+/// Where to find split DWARF, for skeleton units (with DW_AT_dwo_name)
+/// that have the debug info in a separate .dwo file
+#[derive(Debug, Default, Clone)]
+pub struct SplitDwarfPaths {
+    /// Directories to search for .dwo files, after DW_AT_comp_dir of the unit
+    pub search_dirs: Vec<PathBuf>,
+    /// DWARF package (.dwp) with the split units of all .dwo files
+    pub package: Option<PathBuf>,
+}
+
+impl SplitDwarfPaths {
+    /// Search the directory of the object file, and use `<file>.dwp` if it exists
+    pub fn for_object_file(path: &Path) -> Self {
+        let search_dirs = path.parent().map(|x| x.to_path_buf()).into_iter().collect();
+        let mut package = path.as_os_str().to_owned();
+        package.push(".dwp");
+        let package = PathBuf::from(package);
+        Self {
+            search_dirs,
+            package: package.exists().then_some(package),
+        }
+    }
+}
+
+/// Split DWARF loader of the main file
+#[derive(Default)]
+pub(crate) struct SplitDwarf {
+    search_dirs: Vec<PathBuf>,
+    package: Option<DwarfPackage>,
+}
+
+struct DwarfPackage {
+    package: gimli::DwarfPackage<In<'static>>,
+    buf: ArcBuf,
+}
+
+impl SplitDwarf {
+    pub(super) fn load(paths: SplitDwarfPaths) -> cu::Result<Self> {
+        let package = match paths.package {
+            None => None,
+            Some(path) => {
+                let buf = ArcBuf::map(&path)?;
+                let container = Container::parse(buf.as_static())?;
+                let empty = EndianSlice::new(&[], DwarfLittleEndian);
+                let package = gimli::DwarfPackage::load(
+                    |section| load_section(&buf, &container, section, true),
+                    empty,
+                );
+                let package =
+                    cu::check!(package, "failed to load DWARF package {}", path.display())?;
+                cu::debug!("loaded DWARF package {}", path.display());
+                Some(DwarfPackage { package, buf })
+            }
+        };
+        Ok(Self {
+            search_dirs: paths.search_dirs,
+            package,
+        })
+    }
+
+    /// Find the .dwo file by DW_AT_dwo_name, which is usually relative to DW_AT_comp_dir
+    fn find_dwo(&self, dwo_name: &str, comp_dir: Option<&str>) -> cu::Result<PathBuf> {
+        let dwo_path = Path::new(dwo_name);
+        let mut candidates = vec![];
+        if let Some(comp_dir) = comp_dir {
+            // no-op if dwo_path is absolute
+            candidates.push(Path::new(comp_dir).join(dwo_path));
+        }
+        for dir in &self.search_dirs {
+            candidates.push(dir.join(dwo_path));
+            // the build directory might have been moved, so also try the file name only
+            if let Some(file_name) = dwo_path.file_name() {
+                candidates.push(dir.join(file_name));
+            }
+        }
+        match candidates.into_iter().find(|x| x.exists()) {
+            Some(path) => Ok(path),
+            None => {
+                cu::hint!(
+                    "put the .dwo files next to the object file, or create a .dwp with llvm-dwp"
+                );
+                cu::bail!("cannot find split DWARF file {dwo_name}");
+            }
+        }
+    }
+}
+
+impl Dwarf {
+    /// Load the split DWARF for a skeleton unit in this file, from the DWARF package
+    /// if it has the unit, otherwise from the .dwo file
+    pub(crate) fn load_split(
+        self_: &Arc<Self>,
+        dwo_id: DwoId,
+        dwo_name: &str,
+        comp_dir: Option<&str>,
+        goff_base: usize,
+    ) -> cu::Result<Arc<Self>> {
+        if let Some(package) = &self_.split.package {
+            let dwarf = cu::check!(
+                package.package.find_cu(dwo_id, &self_.dwarf),
+                "failed to find split unit {dwo_name} in DWARF package"
+            )?;
+            match dwarf {
+                Some(dwarf) => {
+                    return Ok(Self::new_split(
+                        dwarf,
+                        package.buf.clone(),
+                        goff_base,
+                        self_,
+                    ));
+                }
+                None => {
+                    cu::debug!("split unit {dwo_name} is not in DWARF package, finding .dwo file")
+                }
+            }
+        }
+        let path = self_.split.find_dwo(dwo_name, comp_dir)?;
+        cu::trace!("loading split DWARF from {}", path.display());
+        let buf = ArcBuf::map(&path)?;
+        let dwarf = load_sections(&buf, DwarfFileType::Dwo);
+        let mut dwarf = cu::check!(dwarf, "failed to load split DWARF from {}", path.display())?;
+        // .debug_addr and range lists are in the main file
+        dwarf.make_dwo(&self_.dwarf);
+        Ok(Self::new_split(dwarf, buf, goff_base, self_))
+    }
+}
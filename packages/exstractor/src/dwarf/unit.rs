@@ -1,15 +1,27 @@
 use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
 use std::sync::Arc;
 
 use cu::pre::*;
 use exstructs::Goff;
-use gimli::{Abbreviations, AttributeValue, DwAt, Operation, UnitSectionOffset};
+use gimli::constants::{
+    DW_AT_GNU_dwo_name, DW_AT_GNU_macros, DW_AT_comp_dir, DW_AT_dwo_name, DW_AT_language,
+    DW_AT_macro_info, DW_AT_macros, DW_AT_producer,
+};
+use gimli::{
+    Abbreviations, AttributeValue, DebugMacroOffset, DwAt, DwLang, DwoId, MacroEntry, MacroIter,
+    Operation, UnitSectionOffset,
+};
 
-use crate::dwarf::{Die, Dwarf, EntriesTree, In, Loff};
+use crate::dwarf::{Die, DieCursor, Dwarf, EntriesTree, In, Loff};
 
 pub struct UnitIter {
     pub(crate) debug_info_iter: gimli::DebugInfoUnitHeadersIter<In<'static>>,
     pub(crate) dwarf: Arc<Dwarf>,
+    /// goff base for the next split DWARF loaded, after the main file
+    /// and the split DWARF already loaded
+    pub(crate) next_split_base: usize,
 }
 
 impl UnitIter {
@@ -21,6 +33,94 @@ impl UnitIter {
         let Some(header) = header else {
             return Ok(None);
         };
+        let unit = Unit::new(&self.dwarf, header, None)?;
+        let Some(dwo_id) = unit.unit.dwo_id else {
+            return Ok(Some(unit));
+        };
+        // skeleton unit, the entries are in the split unit
+        let split = cu::check!(
+            self.load_split_unit(&unit, dwo_id),
+            "failed to load split unit for {unit}"
+        )?;
+        Ok(Some(split))
+    }
+
+    fn load_split_unit(&mut self, skeleton: &Unit, dwo_id: DwoId) -> cu::Result<Unit> {
+        let mut tree = skeleton.tree()?;
+        let root = tree.root()?;
+        let entry = root.entry();
+        let dwo_name = match entry.str_opt(DW_AT_dwo_name)? {
+            Some(x) => Some(x),
+            None => entry.str_opt(DW_AT_GNU_dwo_name)?,
+        };
+        let dwo_name = cu::check!(dwo_name, "skeleton unit has no DW_AT_dwo_name")?;
+        let comp_dir = entry.str_opt(DW_AT_comp_dir)?;
+
+        let goff_base = self.next_split_base;
+        let dwarf = Dwarf::load_split(&self.dwarf, dwo_id, dwo_name, comp_dir, goff_base)?;
+        let next_split_base = goff_base + dwarf.debug_info_len();
+        // goffs are archived as u32, and the top is used by primitives
+        cu::ensure!(
+            next_split_base < 0xFFFF0000,
+            "too much split DWARF, offsets do not fit in 32 bits"
+        )?;
+        self.next_split_base = next_split_base;
+
+        let mut headers = dwarf.dwarf.debug_info.units();
+        while let Some(header) = cu::check!(headers.next(), "failed to read split unit header")? {
+            let mut unit = Unit::new(&dwarf, header, Some(skeleton))?;
+            if unit.unit.dwo_id != Some(dwo_id) {
+                continue;
+            }
+            if unit.name.is_empty() {
+                unit.name = skeleton.name.clone();
+            }
+            if unit.producer.is_none() {
+                unit.producer = skeleton.producer.clone();
+            }
+            if unit.language.is_none() {
+                unit.language = skeleton.language;
+            }
+            cu::trace!("loaded split unit for {skeleton} from {dwo_name}");
+            return Ok(unit);
+        }
+        cu::bail!(
+            "split unit with id 0x{:016x} not found in {dwo_name}",
+            dwo_id.0
+        );
+    }
+}
+
+/// Holder of a Unit in .debug_info
+#[derive(Display)]
+#[display("compilation unit at {} ({})", self.offset, self.name)]
+pub struct Unit {
+    unit: gimli::Unit<In<'static>>,
+    header: gimli::UnitHeader<In<'static>>,
+    abbrevs: Arc<Abbreviations>,
+    dwarf: Arc<Dwarf>,
+    /// name of the unit (typically file name)
+    pub name: String,
+    /// offset of the unit
+    pub offset: Goff,
+    /// DWARF version of the unit. Units in the same binary can have different versions
+    pub version: u16,
+    /// Size of addresses in the unit, in bytes
+    pub address_size: u8,
+    /// DW_AT_producer of the unit (typically compiler name and version)
+    pub producer: Option<String>,
+    /// DW_AT_language of the unit
+    pub language: Option<DwLang>,
+}
+
+impl Unit {
+    /// Parse the unit and read the metadata from the root entry. For split units,
+    /// the relocated attributes (like DW_AT_addr_base) are copied from the skeleton unit
+    fn new(
+        dwarf: &Arc<Dwarf>,
+        header: gimli::UnitHeader<In<'static>>,
+        skeleton: Option<&Unit>,
+    ) -> cu::Result<Self> {
         let offset = match header.offset() {
             UnitSectionOffset::DebugInfoOffset(o) => o.0,
             UnitSectionOffset::DebugTypesOffset(o) => {
@@ -29,21 +129,29 @@ impl UnitIter {
                 );
             }
         };
-        let unit = cu::check!(
-            gimli::Unit::new(&self.dwarf.dwarf, header),
-            "failed to create debug info unit"
-        )?;
+        // abbreviation tables are cached in Dwarf by offset
         let abbrevs = cu::check!(
-            header.abbreviations(&self.dwarf.dwarf.debug_abbrev),
+            dwarf.dwarf.abbreviations(&header),
             "failed to create debug info unit abbrevs"
         )?;
+        let mut unit = cu::check!(
+            gimli::Unit::new_with_abbreviations(&dwarf.dwarf, header, Arc::clone(&abbrevs)),
+            "failed to create debug info unit"
+        )?;
+        if let Some(skeleton) = skeleton {
+            unit.copy_relocated_attributes(&skeleton.unit);
+        }
         let mut unit = Unit {
             unit,
             header,
             abbrevs,
-            dwarf: Arc::clone(&self.dwarf),
+            dwarf: Arc::clone(dwarf),
             name: String::new(),
-            offset: offset.into(),
+            offset: (dwarf.goff_base + offset).into(),
+            version: header.version(),
+            address_size: header.address_size(),
+            producer: None,
+            language: None,
         };
 
         let mut tree = cu::check!(
@@ -55,33 +163,61 @@ impl UnitIter {
             "failed to parse root node when creating debug info unit"
         )?;
         let entry = root.entry();
-        let name = cu::check!(entry.name(), "failed to get name of compilation unit")?;
-        unit.name = name.to_string();
-        Ok(Some(unit))
+        // type units and partial units may not have a name
+        let name = cu::check!(entry.name_opt(), "failed to get name of compilation unit")?;
+        let name = name.unwrap_or_default().to_string();
+        let producer = cu::check!(
+            entry.str_opt(DW_AT_producer),
+            "failed to get producer of compilation unit"
+        )?;
+        let producer = producer.map(|x| x.to_string());
+        let language = cu::check!(
+            entry.entry.attr_value(DW_AT_language),
+            "failed to get language of compilation unit"
+        )?;
+        let language = match language {
+            Some(AttributeValue::Language(x)) => Some(x),
+            _ => None,
+        };
+        unit.name = name;
+        unit.producer = producer;
+        unit.language = language;
+        Ok(unit)
     }
-}
 
-/// Holder of a Unit in .debug_info
-#[derive(Display)]
-#[display("compilation unit at {} ({})", self.offset, self.name)]
-pub struct Unit {
-    unit: gimli::Unit<In<'static>>,
-    header: gimli::UnitHeader<In<'static>>,
-    abbrevs: Abbreviations,
-    dwarf: Arc<Dwarf>,
-    /// name of the unit (typically file name)
-    pub name: String,
-    /// offset of the unit
-    pub offset: Goff,
-}
-
-impl Unit {
     pub fn tree(&self) -> cu::Result<EntriesTree<'_>> {
         self.entries_tree(None)
     }
     pub fn tree_at(&self, loff: Loff) -> cu::Result<EntriesTree<'_>> {
         self.entries_tree(Some(loff))
     }
+    /// Create a lazy cursor starting at the root of the unit
+    pub fn cursor(&self) -> DieCursor<'_> {
+        self.make_cursor(self.unit.entries())
+    }
+    /// Create a lazy cursor over the subtree of the entry at offset
+    pub fn cursor_at(&self, loff: Loff) -> cu::Result<DieCursor<'_>> {
+        let cursor = cu::check!(
+            self.unit.entries_at_offset(loff.into()),
+            "failed to create cursor at {} for {self}",
+            self.goff(loff)
+        )?;
+        Ok(self.make_cursor(cursor))
+    }
+    fn make_cursor<'x>(
+        &'x self,
+        cursor: gimli::EntriesCursor<'x, 'x, In<'static>>,
+    ) -> DieCursor<'x> {
+        DieCursor {
+            unit: self,
+            cursor,
+            depth: 0,
+            skip_below: None,
+            tags: Vec::new(),
+            started: false,
+            done: false,
+        }
+    }
     fn entries_tree(&self, loff: Option<Loff>) -> cu::Result<EntriesTree<'_>> {
         let tree = match loff {
             None => cu::check!(
@@ -111,6 +247,183 @@ impl Unit {
         })
     }
 
+    /// Get the object-like macros defined at the end of the unit (name -> replacement text),
+    /// from `.debug_macro` (DWARF 5 or the GNU extension) or `.debug_macinfo`.
+    /// Function-like macros are ignored. Empty if the unit has no macro info,
+    /// which is the case unless the unit is compiled with `-g3` or `-fdebug-macro`
+    pub fn object_macros(&self) -> cu::Result<BTreeMap<String, String>> {
+        let mut tree = self.tree()?;
+        let root = tree.root()?;
+        let entry = root.entry();
+        let mut macros = cu::check!(
+            entry.entry.attr_value(DW_AT_macros),
+            "failed to read DW_AT_macros of {self}"
+        )?;
+        if macros.is_none() {
+            macros = cu::check!(
+                entry.entry.attr_value(DW_AT_GNU_macros),
+                "failed to read DW_AT_GNU_macros of {self}"
+            )?;
+        }
+        let macinfo = cu::check!(
+            entry.entry.attr_value(DW_AT_macro_info),
+            "failed to read DW_AT_macro_info of {self}"
+        )?;
+        let iter = match (macros, macinfo) {
+            (Some(AttributeValue::DebugMacroRef(offset)), _) => self.dwarf.dwarf.macros(offset),
+            // DW_AT_GNU_macros is not recognized by gimli
+            (Some(AttributeValue::SecOffset(offset)), _) => {
+                self.dwarf.dwarf.macros(DebugMacroOffset(offset))
+            }
+            (_, Some(AttributeValue::DebugMacinfoRef(offset))) => self.dwarf.dwarf.macinfo(offset),
+            _ => return Ok(BTreeMap::new()),
+        };
+        let iter = cu::check!(iter, "failed to read macro info of {self}")?;
+        let mut output = BTreeMap::new();
+        let mut imported = BTreeSet::new();
+        self.read_macros(iter, &mut output, &mut imported)?;
+        Ok(output)
+    }
+
+    fn read_macros(
+        &self,
+        mut iter: MacroIter<In<'static>>,
+        output: &mut BTreeMap<String, String>,
+        imported: &mut BTreeSet<usize>,
+    ) -> cu::Result<()> {
+        let unit_ref = self.unit.unit_ref(&self.dwarf.dwarf);
+        while let Some(entry) = cu::check!(iter.next(), "failed to read macro entry in {self}")? {
+            match entry {
+                MacroEntry::Define { text, .. } => {
+                    let text = cu::check!(
+                        text.string(unit_ref),
+                        "failed to read macro definition in {self}"
+                    )?;
+                    let text = cu::check!(
+                        text.to_string(),
+                        "failed to decode macro definition in {self}"
+                    )?;
+                    // the name is followed by a space for object-like macros,
+                    // or the parameters for function-like macros
+                    let Some(end) = text.find([' ', '(']) else {
+                        // defined with no replacement text
+                        output.insert(text.to_string(), String::new());
+                        continue;
+                    };
+                    if text[end..].starts_with('(') {
+                        continue;
+                    }
+                    let value = text[end + 1..].trim();
+                    output.insert(text[..end].to_string(), value.to_string());
+                }
+                MacroEntry::Undef { name, .. } => {
+                    let name =
+                        cu::check!(name.string(unit_ref), "failed to read macro name in {self}")?;
+                    if let Ok(name) = name.to_string() {
+                        output.remove(name);
+                    }
+                }
+                MacroEntry::Import { offset } => {
+                    // the same macro unit (for example, from a common header)
+                    // is usually imported many times
+                    if !imported.insert(offset.0) {
+                        continue;
+                    }
+                    let iter = cu::check!(
+                        self.dwarf.dwarf.macros(offset),
+                        "failed to read imported macro unit in {self}"
+                    )?;
+                    self.read_macros(iter, output, imported)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the rows of the line number program (`.debug_line`) of the unit,
+    /// as (address, file path, line). Rows without a line number and the ends
+    /// of sequences are skipped. Empty if the unit has no line program
+    pub fn line_rows(&self) -> cu::Result<Vec<(u64, String, u32)>> {
+        let Some(program) = self.unit.line_program.clone() else {
+            return Ok(vec![]);
+        };
+        let mut files = BTreeMap::<u64, String>::new();
+        let mut output = vec![];
+        let mut rows = program.rows();
+        loop {
+            let row = cu::check!(rows.next_row(), "failed to read line program of {self}")?;
+            let Some((header, row)) = row else {
+                break;
+            };
+            if row.end_sequence() {
+                continue;
+            }
+            let Some(line) = row.line() else {
+                continue;
+            };
+            let file_index = row.file_index();
+            let file = match files.get(&file_index) {
+                Some(file) => file.clone(),
+                None => {
+                    let file = self.line_file_path(header, file_index)?;
+                    files.insert(file_index, file.clone());
+                    file
+                }
+            };
+            output.push((row.address(), file, line.get() as u32));
+        }
+        Ok(output)
+    }
+
+    /// Resolve a file index (e.g. from `DW_AT_decl_file`) to the file path
+    /// in the line program. None if the unit has no line program or the index
+    /// does not refer to a file (0 before DWARF 5)
+    pub fn decl_file_path(&self, file_index: u64) -> cu::Result<Option<String>> {
+        let Some(program) = &self.unit.line_program else {
+            return Ok(None);
+        };
+        let header = program.header();
+        if header.file(file_index).is_none() {
+            return Ok(None);
+        }
+        self.line_file_path(header, file_index).map(Some)
+    }
+
+    fn line_file_path(
+        &self,
+        header: &gimli::LineProgramHeader<In<'static>>,
+        file_index: u64,
+    ) -> cu::Result<String> {
+        let file = cu::check!(
+            header.file(file_index),
+            "invalid file index {file_index} in line program of {self}"
+        )?;
+        let name = self.attr_string(file.path_name())?;
+        if name.starts_with('/') {
+            return Ok(name.to_string());
+        }
+        let Some(directory) = file.directory(header) else {
+            return Ok(name.to_string());
+        };
+        let directory = self.attr_string(directory)?;
+        if directory.is_empty() {
+            return Ok(name.to_string());
+        }
+        Ok(format!("{}/{name}", directory.trim_end_matches('/')))
+    }
+
+    /// Byte range of the unit in .debug_info, including the unit header
+    pub fn debug_info_range(&self) -> Range<usize> {
+        let start = self.offset.0 - self.dwarf.goff_base;
+        start..start + self.header.length_including_self()
+    }
+
+    /// If the entries of the unit are loaded from split DWARF
+    pub fn is_split(&self) -> bool {
+        self.dwarf.goff_base != 0
+    }
+
     /// Convert local offset in this compilation unit to global offset
     pub fn goff(&self, loff: Loff) -> Goff {
         loff.to_global(self.offset)
@@ -121,14 +434,25 @@ impl Unit {
         &'x self,
         value: AttributeValue<In<'static>>,
     ) -> cu::Result<&'x str> {
+        let key = match value {
+            AttributeValue::DebugStrRef(offset) => Some(offset.0),
+            _ => None,
+        };
+        if let Some(cached) = key.and_then(|k| self.dwarf.strings.get(&k)) {
+            return Ok(*cached);
+        }
         let value = cu::check!(
             self.dwarf.dwarf.attr_string(&self.unit, value),
             "failed to get attribute value as string in {self}"
         )?;
-        cu::check!(
+        let value = cu::check!(
             value.to_string(),
             "failed to decode attribute value as string in {self}"
-        )
+        )?;
+        if let Some(key) = key {
+            self.dwarf.strings.insert(key, value);
+        }
+        Ok(value)
     }
     /// Get an attribute value as signed integer
     pub(crate) fn attr_signed(
@@ -161,6 +485,8 @@ impl Unit {
             AttributeValue::Data8(x) => Ok(x),
             AttributeValue::Udata(x) => Ok(x),
             AttributeValue::Addr(x) => Ok(x),
+            AttributeValue::FileIndex(x) => Ok(x),
+            AttributeValue::Accessibility(x) => Ok(x.0 as u64),
             // this is used for vtable elem location
             AttributeValue::Exprloc(expr) => {
                 let mut ops = expr.operations(self.unit.encoding());
@@ -181,4 +507,103 @@ impl Unit {
             }
         }
     }
+
+    /// Get the offset from the frame base if the location is a single DW_OP_fbreg
+    pub(crate) fn attr_frame_offset(
+        &self,
+        offset: Goff,
+        attr: AttributeValue<In<'_>>,
+    ) -> cu::Result<Option<i64>> {
+        let AttributeValue::Exprloc(expr) = attr else {
+            // location lists
+            return Ok(None);
+        };
+        let mut ops = expr.operations(self.unit.encoding());
+        let op = cu::check!(
+            ops.next(),
+            "failed to read DW_AT_location ops for entry {offset}"
+        )?;
+        let Some(Operation::FrameOffset {
+            offset: frame_offset,
+        }) = op
+        else {
+            return Ok(None);
+        };
+        // more ops means the value is computed, for example from a pointer on the stack
+        let next = cu::check!(
+            ops.next(),
+            "failed to read DW_AT_location ops for entry {offset}"
+        )?;
+        if next.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(frame_offset))
+    }
+
+    /// Get the offset in the vtable (from the address point) where the offset
+    /// of a virtual base is stored, from the `DW_AT_data_member_location` of
+    /// a virtual inheritance. None if the expression is not in the Itanium form
+    /// `dup, deref, constu N, minus, deref, plus` (or with `plus_uconst N`)
+    pub(crate) fn attr_vbase_offset(
+        &self,
+        offset: Goff,
+        attr: AttributeValue<In<'_>>,
+    ) -> cu::Result<Option<i64>> {
+        let AttributeValue::Exprloc(expr) = attr else {
+            return Ok(None);
+        };
+        let mut ops = expr.operations(self.unit.encoding());
+        let mut list = vec![];
+        while let Some(op) = cu::check!(
+            ops.next(),
+            "failed to read DW_AT_data_member_location ops for entry {offset}"
+        )? {
+            list.push(op);
+        }
+        let slot = match list.as_slice() {
+            [
+                Operation::Pick { index: 0 },
+                Operation::Deref { .. },
+                Operation::UnsignedConstant { value },
+                Operation::Minus,
+                Operation::Deref { .. },
+                Operation::Plus,
+            ] => -(*value as i64),
+            [
+                Operation::Pick { index: 0 },
+                Operation::Deref { .. },
+                Operation::PlusConstant { value },
+                Operation::Deref { .. },
+                Operation::Plus,
+            ] => *value as i64,
+            _ => return Ok(None),
+        };
+        Ok(Some(slot))
+    }
+
+    /// Get the frame base of a function as `"cfa"`, `"reg<n>"` or `"breg<n>+<offset>"`,
+    /// with DWARF register numbers. None if the frame base is a more complex expression
+    pub(crate) fn attr_frame_base(
+        &self,
+        offset: Goff,
+        attr: AttributeValue<In<'_>>,
+    ) -> cu::Result<Option<String>> {
+        let AttributeValue::Exprloc(expr) = attr else {
+            return Ok(None);
+        };
+        let mut ops = expr.operations(self.unit.encoding());
+        let op = cu::check!(
+            ops.next(),
+            "failed to read DW_AT_frame_base ops for entry {offset}"
+        )?;
+        let base = match op {
+            Some(Operation::CallFrameCFA) => "cfa".to_string(),
+            Some(Operation::Register { register }) => format!("reg{}", register.0),
+            Some(Operation::RegisterOffset {
+                register, offset, ..
+            }) => format!("breg{}{offset:+}", register.0),
+            _ => return Ok(None),
+        };
+        Ok(Some(base))
+    }
 }
@@ -0,0 +1,133 @@
+use cu::pre::*;
+use gimli::constants::*;
+use symlist::SymbolList;
+
+use crate::database::CallEdge;
+use crate::dwarf::{DieNode, Unit};
+
+/// Load the static call graph of the unit from the call site entries
+/// (`DW_TAG_call_site`, or `DW_TAG_GNU_call_site` before DWARF 5).
+///
+/// Only direct calls from functions in the symbol list are loaded, since
+/// the callee of indirect calls is not known statically
+pub fn load_call_graph(unit: &Unit, symbol_list: &SymbolList) -> cu::Result<Vec<CallEdge>> {
+    let mut ctx = LoadCallCtx {
+        symbol_list,
+        caller: None,
+        edges: vec![],
+        indirect_count: 0,
+    };
+    let mut tree = unit.tree()?;
+    let root = tree.root()?;
+    cu::check!(
+        load_calls_recur(root, &mut ctx),
+        "failed to load call sites for {unit}"
+    )?;
+    cu::trace!(
+        "loaded {} direct calls from {unit}, skipped {} indirect calls",
+        ctx.edges.len(),
+        ctx.indirect_count
+    );
+    Ok(ctx.edges)
+}
+
+struct LoadCallCtx<'a> {
+    symbol_list: &'a SymbolList,
+    /// Function being processed
+    caller: Option<Caller>,
+    edges: Vec<CallEdge>,
+    indirect_count: usize,
+}
+
+struct Caller {
+    link_name: String,
+    address: u32,
+    low_pc: u64,
+}
+
+fn load_calls_recur(node: DieNode<'_, '_>, ctx: &mut LoadCallCtx) -> cu::Result<()> {
+    let entry = node.entry();
+    let offset = entry.goff();
+    match entry.tag() {
+        DW_TAG_subprogram => {
+            let caller = cu::check!(
+                load_caller(&node, ctx.symbol_list),
+                "failed to load caller function at {offset}"
+            )?;
+            // nested functions are processed as their own caller
+            let outer = std::mem::replace(&mut ctx.caller, caller);
+            let result = node.for_each_child(|child| load_calls_recur(child, ctx));
+            ctx.caller = outer;
+            return result;
+        }
+        DW_TAG_call_site | DW_TAG_GNU_call_site => {
+            cu::check!(
+                load_call_site(&node, ctx),
+                "failed to load call site at {offset}"
+            )?;
+            return Ok(());
+        }
+        _ => {}
+    }
+    // call sites could be in lexical blocks and inlined subroutines
+    node.for_each_child(|child| load_calls_recur(child, ctx))
+}
+
+fn load_caller(node: &DieNode<'_, '_>, symbol_list: &SymbolList) -> cu::Result<Option<Caller>> {
+    let entry = node.entry();
+    let low_pc = entry.uint_opt(DW_AT_low_pc)?;
+    let Some(low_pc) = low_pc else {
+        // declaration or inlined function
+        return Ok(None);
+    };
+    let Some(link_name) = super::load_func_linkage_name(&entry)? else {
+        return Ok(None);
+    };
+    // functions not in the symbol list cannot be mapped to addresses
+    let Some(address) = symbol_list.get_address(&link_name) else {
+        return Ok(None);
+    };
+    Ok(Some(Caller {
+        link_name,
+        address,
+        low_pc,
+    }))
+}
+
+fn load_call_site(node: &DieNode<'_, '_>, ctx: &mut LoadCallCtx) -> cu::Result<()> {
+    let Some(caller) = &ctx.caller else {
+        return Ok(());
+    };
+    let entry = node.entry();
+    let origin = match entry.loff_opt(DW_AT_call_origin)? {
+        Some(x) => Some(x),
+        // GNU call sites use abstract origin
+        None => entry.loff_opt(DW_AT_abstract_origin)?,
+    };
+    let Some(origin) = origin else {
+        ctx.indirect_count += 1;
+        return Ok(());
+    };
+    let callee = entry.unit().entry_at(origin)?;
+    let Some(callee) = super::load_func_linkage_name(&callee)? else {
+        ctx.indirect_count += 1;
+        return Ok(());
+    };
+    let return_pc = match entry.uint_opt(DW_AT_call_return_pc)? {
+        Some(x) => Some(x),
+        None => entry.uint_opt(DW_AT_low_pc)?,
+    };
+    // the pc is mapped to the address space of the symbols
+    // by the offset from the start of the caller
+    let return_address = return_pc
+        .and_then(|pc| pc.checked_sub(caller.low_pc))
+        .and_then(|offset| u32::try_from(offset).ok())
+        .and_then(|offset| caller.address.checked_add(offset));
+    ctx.edges.push(CallEdge {
+        caller: caller.link_name.clone(),
+        caller_address: caller.address,
+        return_address,
+        callee,
+    });
+    Ok(())
+}
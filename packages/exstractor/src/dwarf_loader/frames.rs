@@ -0,0 +1,255 @@
+use cu::pre::*;
+use exstructs::NamespaceMaps;
+use gimli::constants::*;
+use symlist::SymbolList;
+
+use crate::dwarf::{Die, DieNode, Loff, Unit};
+
+/// Stack frame layout of a function
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionFrame {
+    /// Link name of the function
+    pub name: String,
+    /// Address of the function
+    pub address: u32,
+    /// Frame base that the offsets are relative to (DW_AT_frame_base).
+    /// `"cfa"` for the canonical frame address, `"reg<n>"` for a register,
+    /// or `"breg<n>+<offset>"` for an offset from a register, with DWARF register numbers.
+    /// None if the frame base is not a simple location
+    pub frame_base: Option<String>,
+    /// Parameters and local variables on the stack, sorted by offset
+    pub variables: Vec<FrameVariable>,
+}
+
+/// A parameter or local variable of a function at a fixed offset from the frame base
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameVariable {
+    /// Offset from the frame base (DW_OP_fbreg)
+    pub offset: i64,
+    pub name: String,
+    /// Type of the variable in C++-like syntax, from the debug info of the unit
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// If the variable is a parameter
+    pub is_param: bool,
+}
+
+/// Load the stack frame layouts of the functions in the unit, from the locations
+/// of the parameters and variables (`DW_AT_location` with `DW_OP_fbreg`).
+///
+/// Only functions in the symbol list are loaded. Variables in registers or with
+/// location lists (i.e. that move during the function) are skipped, and variables
+/// in nested scopes are included, so different variables could share the same offset
+pub fn load_frames(
+    unit: &Unit,
+    nsmaps: &NamespaceMaps,
+    symbol_list: &SymbolList,
+) -> cu::Result<Vec<FunctionFrame>> {
+    let mut ctx = LoadFrameCtx {
+        nsmaps,
+        symbol_list,
+        frames: vec![],
+    };
+    let mut tree = unit.tree()?;
+    let root = tree.root()?;
+    cu::check!(
+        load_frames_recur(root, &mut ctx),
+        "failed to load frames for {unit}"
+    )?;
+    cu::trace!("loaded {} frames from {unit}", ctx.frames.len());
+    Ok(ctx.frames)
+}
+
+struct LoadFrameCtx<'a> {
+    nsmaps: &'a NamespaceMaps,
+    symbol_list: &'a SymbolList,
+    frames: Vec<FunctionFrame>,
+}
+
+fn load_frames_recur(node: DieNode<'_, '_>, ctx: &mut LoadFrameCtx) -> cu::Result<()> {
+    let entry = node.entry();
+    let offset = entry.goff();
+    if entry.tag() == DW_TAG_subprogram {
+        let frame = cu::check!(
+            load_frame_header(&entry, ctx.symbol_list),
+            "failed to load function at {offset}"
+        )?;
+        let Some(mut frame) = frame else {
+            return node.for_each_child(|child| load_frames_recur(child, ctx));
+        };
+        cu::check!(
+            node.for_each_child(|child| load_frame_variables_recur(child, &mut frame, ctx)),
+            "failed to load frame variables for function at {offset}"
+        )?;
+        frame.variables.sort_by_key(|x| x.offset);
+        ctx.frames.push(frame);
+        return Ok(());
+    }
+    node.for_each_child(|child| load_frames_recur(child, ctx))
+}
+
+fn load_frame_header(
+    entry: &Die<'_, '_>,
+    symbol_list: &SymbolList,
+) -> cu::Result<Option<FunctionFrame>> {
+    if entry.uint_opt(DW_AT_low_pc)?.is_none() {
+        // declaration or inlined function
+        return Ok(None);
+    }
+    let Some(name) = super::load_func_linkage_name(entry)? else {
+        return Ok(None);
+    };
+    // functions not in the symbol list cannot be mapped to addresses
+    let Some(address) = symbol_list.get_address(&name) else {
+        return Ok(None);
+    };
+    let frame_base = entry.frame_base()?;
+    Ok(Some(FunctionFrame {
+        name,
+        address,
+        frame_base,
+        variables: vec![],
+    }))
+}
+
+fn load_frame_variables_recur(
+    node: DieNode<'_, '_>,
+    frame: &mut FunctionFrame,
+    ctx: &mut LoadFrameCtx,
+) -> cu::Result<()> {
+    let entry = node.entry();
+    let offset = entry.goff();
+    let is_param = match entry.tag() {
+        DW_TAG_formal_parameter => true,
+        DW_TAG_variable => false,
+        DW_TAG_lexical_block | DW_TAG_inlined_subroutine => {
+            // variables of inlined functions are in the frame of the caller
+            return node.for_each_child(|child| load_frame_variables_recur(child, frame, ctx));
+        }
+        // nested functions (for example, in local classes) have their own frames
+        DW_TAG_subprogram => return load_frames_recur(node, ctx),
+        _ => return Ok(()),
+    };
+    let Some(frame_offset) = entry.frame_offset()? else {
+        return Ok(());
+    };
+    let name = cu::check!(
+        super::load_func_param_name(&entry),
+        "failed to load name of variable at {offset}"
+    )?;
+    let Some(name) = name else {
+        // unnamed parameters still take space, but there is nothing to label
+        return Ok(());
+    };
+    let ty = cu::check!(
+        super::load_func_param_type(&entry),
+        "failed to load type of variable at {offset}"
+    )?;
+    let ty = match ty {
+        Some(loff) => cu::check!(
+            load_type_name(entry.unit(), loff, ctx.nsmaps, 0),
+            "failed to load type name of variable at {offset}"
+        )?,
+        None => "void".to_string(),
+    };
+    frame.variables.push(FrameVariable {
+        offset: frame_offset,
+        name,
+        ty,
+        is_param,
+    });
+    Ok(())
+}
+
+/// Type names could be recursive through pointers to incomplete types,
+/// which are named before reaching the limit in valid DWARF
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// Get the name of the type at the offset in C++-like syntax. Qualifiers (const and volatile)
+/// are dropped, since they are not useful for labeling the stack
+fn load_type_name(
+    unit: &Unit,
+    loff: Loff,
+    nsmaps: &NamespaceMaps,
+    depth: usize,
+) -> cu::Result<String> {
+    cu::ensure!(depth < MAX_TYPE_DEPTH, "type is nested too deep")?;
+    let entry = unit.entry_at(loff)?;
+    let offset = entry.goff();
+    let inner = || -> cu::Result<String> {
+        match entry.loff_opt(DW_AT_type)? {
+            Some(loff) => load_type_name(unit, loff, nsmaps, depth + 1),
+            None => Ok("void".to_string()),
+        }
+    };
+    let name = match entry.tag() {
+        DW_TAG_base_type | DW_TAG_unspecified_type => entry.name()?.to_string(),
+        DW_TAG_const_type | DW_TAG_volatile_type | DW_TAG_restrict_type => inner()?,
+        DW_TAG_pointer_type => {
+            let pointee = entry.loff_opt(DW_AT_type)?;
+            if let Some(pointee) = pointee {
+                let pointee = unit.entry_at(pointee)?;
+                if pointee.tag() == DW_TAG_subroutine_type {
+                    return load_subroutine_name(&pointee, "(*)", nsmaps, depth);
+                }
+            }
+            format!("{}*", inner()?)
+        }
+        DW_TAG_reference_type => format!("{}&", inner()?),
+        DW_TAG_rvalue_reference_type => format!("{}&&", inner()?),
+        DW_TAG_array_type => match super::load_array_subrange_count(&entry)? {
+            Some(count) => format!("{}[{count}]", inner()?),
+            None => format!("{}[]", inner()?),
+        },
+        DW_TAG_subroutine_type => load_subroutine_name(&entry, "", nsmaps, depth)?,
+        DW_TAG_typedef
+        | DW_TAG_structure_type
+        | DW_TAG_class_type
+        | DW_TAG_union_type
+        | DW_TAG_enumeration_type => {
+            if entry.name_opt()?.is_none() {
+                return Ok(format!("(anonymous at {offset})"));
+            }
+            match entry.qual_name(nsmaps) {
+                Ok(name) => name.to_string(),
+                // types defined in functions are not in the namespace map
+                Err(_) => entry.name()?.to_string(),
+            }
+        }
+        DW_TAG_ptr_to_member_type => {
+            let pointee = inner()?;
+            let containing = entry.loff(DW_AT_containing_type)?;
+            let containing = load_type_name(unit, containing, nsmaps, depth + 1)?;
+            format!("{pointee} {containing}::*")
+        }
+        tag => cu::bail!("unexpected tag {tag} for type at {offset}"),
+    };
+    Ok(name)
+}
+
+fn load_subroutine_name(
+    entry: &Die<'_, '_>,
+    declarator: &str,
+    nsmaps: &NamespaceMaps,
+    depth: usize,
+) -> cu::Result<String> {
+    let unit = entry.unit();
+    let retty = match entry.loff_opt(DW_AT_type)? {
+        Some(loff) => load_type_name(unit, loff, nsmaps, depth + 1)?,
+        None => "void".to_string(),
+    };
+    let mut params = vec![];
+    entry.for_each_child(|child| {
+        let entry = child.entry();
+        match entry.tag() {
+            DW_TAG_formal_parameter => {
+                let loff = entry.loff(DW_AT_type)?;
+                params.push(load_type_name(unit, loff, nsmaps, depth + 1)?);
+            }
+            DW_TAG_unspecified_parameters => params.push("...".to_string()),
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(format!("{retty} {declarator}({})", params.join(", ")))
+}
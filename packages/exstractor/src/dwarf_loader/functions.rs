@@ -1,4 +1,5 @@
 use cu::pre::*;
+use exstructs::MethodInfo;
 use gimli::constants::*;
 
 use crate::dwarf::{Die, Loff};
@@ -233,6 +234,31 @@ pub fn load_func_param_name(entry: &Die<'_, '_>) -> cu::Result<Option<String>> {
     Ok(None)
 }
 
+/// Check if the parameter is implicit, like the `this` parameter of member functions
+pub fn load_func_param_is_artificial(entry: &Die<'_, '_>) -> cu::Result<bool> {
+    let offset = entry.goff();
+    let is_artificial = cu::check!(
+        entry.flag(DW_AT_artificial),
+        "failed to read artificial flag for function param entry at {offset}"
+    )?;
+    if is_artificial {
+        return Ok(true);
+    }
+    // concrete instances of inlined functions only have the flag on the abstract origin
+    let abstract_origin = cu::check!(
+        entry.loff_opt(DW_AT_abstract_origin),
+        "failed to read abstract origin for function param at {offset}"
+    )?;
+    let Some(abstract_origin) = abstract_origin else {
+        return Ok(false);
+    };
+    let entry = cu::check!(
+        entry.unit().entry_at(abstract_origin),
+        "failed to read abstract origin entry for function param at {offset}"
+    )?;
+    load_func_param_is_artificial(&entry)
+}
+
 pub fn load_func_param_type(entry: &Die<'_, '_>) -> cu::Result<Option<Loff>> {
     let offset = entry.goff();
     let loff = cu::check!(
@@ -278,3 +304,254 @@ pub fn load_func_param_type(entry: &Die<'_, '_>) -> cu::Result<Option<Loff>> {
     }
     Ok(None)
 }
+
+/// Get the number of parameters in the signature, if the function name is a full
+/// signature like `foo(int, char*)`, which some producers emit as `DW_AT_name`.
+///
+/// Returns None if the name is not a signature. `(void)` has no parameters,
+/// and the variadic `...` is not counted
+pub fn signature_param_count(name: &str) -> Option<usize> {
+    // cv and ref qualifiers of member functions
+    let mut name = name.trim_end();
+    loop {
+        let stripped = ["const", "volatile", "&", "noexcept"]
+            .iter()
+            .find_map(|q| name.strip_suffix(q));
+        match stripped {
+            Some(x) => name = x.trim_end(),
+            None => break,
+        }
+    }
+    let inner = name.strip_suffix(')')?;
+    // find the matching open paren from the end
+    let mut depth = 0;
+    let mut start = None;
+    for (i, c) in inner.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => {
+                start = Some(i);
+                break;
+            }
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    let start = start?;
+    if inner[..start].ends_with("operator") {
+        // the parens are the name of `operator()`, not the parameters
+        return None;
+    }
+    let params = inner[start + 1..].trim();
+    if params.is_empty() || params == "void" {
+        return Some(0);
+    }
+    let mut count = 0;
+    let mut depth = 0;
+    let mut current = String::new();
+    for c in params.chars().chain(std::iter::once(',')) {
+        match c {
+            '(' | '<' | '[' => depth += 1,
+            ')' | '>' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                if current.trim() != "..." {
+                    count += 1;
+                }
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    Some(count)
+}
+
+/// Get the declared file index and line of a function or variable, following the
+/// abstract origin and specification, since definitions out of line usually
+/// only have the location on the declaration
+pub fn load_decl_file_line<'a>(entry: &'a Die<'_, '_>) -> cu::Result<Option<(u64, u32)>> {
+    let offset = entry.goff();
+    let file_line = cu::check!(
+        entry.decl_file_line(),
+        "failed to read declared location for entry at {offset}"
+    )?;
+    if file_line.is_some() {
+        return Ok(file_line);
+    }
+    let abstract_origin = cu::check!(
+        entry.loff_opt(DW_AT_abstract_origin),
+        "failed to read abstract origin for entry at {offset}"
+    )?;
+    if let Some(abstract_origin) = abstract_origin {
+        let entry = cu::check!(
+            entry.unit().entry_at(abstract_origin),
+            "failed to read abstract origin entry for entry at {offset}"
+        )?;
+        let file_line = cu::check!(
+            load_decl_file_line(&entry),
+            "failed to load declared location from abstract origin entry, for entry at {offset}"
+        )?;
+        if file_line.is_some() {
+            return Ok(file_line);
+        }
+    }
+    let specification = cu::check!(
+        entry.loff_opt(DW_AT_specification),
+        "failed to read specification for entry at {offset}"
+    )?;
+    if let Some(specification) = specification {
+        let entry = cu::check!(
+            entry.unit().entry_at(specification),
+            "failed to read specification entry for entry at {offset}"
+        )?;
+        let file_line = cu::check!(
+            load_decl_file_line(&entry),
+            "failed to load declared location from specification entry, for entry at {offset}"
+        )?;
+        if file_line.is_some() {
+            return Ok(file_line);
+        }
+    }
+    Ok(None)
+}
+
+/// Load the metadata of a member function from the function and its declaration
+/// in the class. `this_param` is the type of the first parameter if it's artificial (`this`).
+///
+/// Return None if the function is not a member function, i.e. it has no `this`
+/// and is not virtual, artificial or deleted
+pub fn load_func_method_info(
+    entry: &Die<'_, '_>,
+    this_param: Option<Loff>,
+) -> cu::Result<Option<MethodInfo>> {
+    let offset = entry.goff();
+    let virtual_index = cu::check!(
+        load_func_vtable_index(entry),
+        "failed to load vtable index for function at {offset}"
+    )?;
+    let is_artificial = cu::check!(
+        load_func_flag(entry, DW_AT_artificial),
+        "failed to load artificial flag for function at {offset}"
+    )?;
+    let is_deleted = cu::check!(
+        load_func_flag(entry, DW_AT_deleted),
+        "failed to load deleted flag for function at {offset}"
+    )?;
+    let qualifiers = match this_param {
+        None => 0,
+        Some(loff) => {
+            let qualifiers = cu::check!(
+                super::load_type_qualifiers(entry.unit(), Some(loff)),
+                "failed to load qualifiers of this for function at {offset}"
+            )?;
+            // the qualifiers of the pointee of `this`
+            qualifiers.0.get(1).copied().unwrap_or_default()
+        }
+    };
+    let method = MethodInfo {
+        has_this: this_param.is_some(),
+        qualifiers,
+        virtual_index,
+        is_artificial,
+        is_deleted,
+    };
+    if method == MethodInfo::default() {
+        return Ok(None);
+    }
+    Ok(Some(method))
+}
+
+/// Get the vtable index of a function, which is only on the declaration in the class
+fn load_func_vtable_index(entry: &Die<'_, '_>) -> cu::Result<Option<u32>> {
+    let offset = entry.goff();
+    let index = cu::check!(
+        entry.vtable_index(),
+        "failed to read vtable index for function at {offset}"
+    )?;
+    if index.is_some() {
+        return Ok(index);
+    }
+    let abstract_origin = cu::check!(
+        entry.loff_opt(DW_AT_abstract_origin),
+        "failed to read abstract origin for function at {offset}"
+    )?;
+    if let Some(abstract_origin) = abstract_origin {
+        let entry = cu::check!(
+            entry.unit().entry_at(abstract_origin),
+            "failed to read abstract origin entry for function at {offset}"
+        )?;
+        let index = cu::check!(
+            load_func_vtable_index(&entry),
+            "failed to load vtable index from abstract origin entry, for function at {offset}"
+        )?;
+        if index.is_some() {
+            return Ok(index);
+        }
+    }
+    let specification = cu::check!(
+        entry.loff_opt(DW_AT_specification),
+        "failed to read specification for function at {offset}"
+    )?;
+    if let Some(specification) = specification {
+        let entry = cu::check!(
+            entry.unit().entry_at(specification),
+            "failed to read specification entry for function at {offset}"
+        )?;
+        let index = cu::check!(
+            load_func_vtable_index(&entry),
+            "failed to load vtable index from specification entry, for function at {offset}"
+        )?;
+        if index.is_some() {
+            return Ok(index);
+        }
+    }
+    Ok(None)
+}
+
+/// Check a flag of a function, or of its declaration
+fn load_func_flag(entry: &Die<'_, '_>, attr: DwAt) -> cu::Result<bool> {
+    let offset = entry.goff();
+    let flag = cu::check!(
+        entry.flag(attr),
+        "failed to read {attr} for function at {offset}"
+    )?;
+    if flag {
+        return Ok(true);
+    }
+    let abstract_origin = cu::check!(
+        entry.loff_opt(DW_AT_abstract_origin),
+        "failed to read abstract origin for function at {offset}"
+    )?;
+    if let Some(abstract_origin) = abstract_origin {
+        let entry = cu::check!(
+            entry.unit().entry_at(abstract_origin),
+            "failed to read abstract origin entry for function at {offset}"
+        )?;
+        let flag = cu::check!(
+            load_func_flag(&entry, attr),
+            "failed to load {attr} from abstract origin entry, for function at {offset}"
+        )?;
+        if flag {
+            return Ok(true);
+        }
+    }
+    let specification = cu::check!(
+        entry.loff_opt(DW_AT_specification),
+        "failed to read specification for function at {offset}"
+    )?;
+    if let Some(specification) = specification {
+        let entry = cu::check!(
+            entry.unit().entry_at(specification),
+            "failed to read specification entry for function at {offset}"
+        )?;
+        let flag = cu::check!(
+            load_func_flag(&entry, attr),
+            "failed to load {attr} from specification entry, for function at {offset}"
+        )?;
+        if flag {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
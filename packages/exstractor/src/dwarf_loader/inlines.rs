@@ -0,0 +1,152 @@
+use cu::pre::*;
+use gimli::constants::*;
+use symlist::SymbolList;
+
+use crate::dwarf::{DieNode, Unit};
+
+/// A function inlined into a symbol (`DW_TAG_inlined_subroutine`)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InlinedCall {
+    /// Link name of the inlined function
+    pub inlined: String,
+    pub site: InlineSite,
+}
+
+/// Where a function is inlined
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct InlineSite {
+    /// Link name of the symbol that the function is inlined into
+    pub caller: String,
+    /// Address of the caller
+    pub caller_address: u32,
+    /// Address of the start of the inlined code, in the address space of the symbols.
+    /// None if the inlined code is in multiple ranges without an entry point
+    pub address: Option<u32>,
+    /// Source location of the inlined call, as `file:line`, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call_site: Option<String>,
+}
+
+/// Load the functions inlined into the functions of the unit.
+///
+/// Only inlined calls in functions in the symbol list are loaded. Functions inlined into
+/// other inlined code are recorded as inlined into the outermost function, since that
+/// is where the code is in the binary
+pub fn load_inlined_calls(unit: &Unit, symbol_list: &SymbolList) -> cu::Result<Vec<InlinedCall>> {
+    let mut ctx = LoadInlineCtx {
+        symbol_list,
+        caller: None,
+        calls: vec![],
+    };
+    let mut tree = unit.tree()?;
+    let root = tree.root()?;
+    cu::check!(
+        load_inlines_recur(root, &mut ctx),
+        "failed to load inlined subroutines for {unit}"
+    )?;
+    cu::trace!("loaded {} inlined calls from {unit}", ctx.calls.len());
+    Ok(ctx.calls)
+}
+
+struct LoadInlineCtx<'a> {
+    symbol_list: &'a SymbolList,
+    /// Function being processed
+    caller: Option<Caller>,
+    calls: Vec<InlinedCall>,
+}
+
+struct Caller {
+    link_name: String,
+    address: u32,
+    low_pc: u64,
+}
+
+fn load_inlines_recur(node: DieNode<'_, '_>, ctx: &mut LoadInlineCtx) -> cu::Result<()> {
+    let entry = node.entry();
+    let offset = entry.goff();
+    match entry.tag() {
+        DW_TAG_subprogram => {
+            let caller = cu::check!(
+                load_caller(&node, ctx.symbol_list),
+                "failed to load caller function at {offset}"
+            )?;
+            // nested functions are processed as their own caller
+            let outer = std::mem::replace(&mut ctx.caller, caller);
+            let result = node.for_each_child(|child| load_inlines_recur(child, ctx));
+            ctx.caller = outer;
+            return result;
+        }
+        DW_TAG_inlined_subroutine => {
+            cu::check!(
+                load_inlined_call(&node, ctx),
+                "failed to load inlined subroutine at {offset}"
+            )?;
+        }
+        _ => {}
+    }
+    // inlined subroutines could be in lexical blocks and other inlined subroutines
+    node.for_each_child(|child| load_inlines_recur(child, ctx))
+}
+
+fn load_caller(node: &DieNode<'_, '_>, symbol_list: &SymbolList) -> cu::Result<Option<Caller>> {
+    let entry = node.entry();
+    let low_pc = entry.uint_opt(DW_AT_low_pc)?;
+    let Some(low_pc) = low_pc else {
+        // declaration or abstract instance of inlined function
+        return Ok(None);
+    };
+    let Some(link_name) = super::load_func_linkage_name(&entry)? else {
+        return Ok(None);
+    };
+    // functions not in the symbol list cannot be mapped to addresses
+    let Some(address) = symbol_list.get_address(&link_name) else {
+        return Ok(None);
+    };
+    Ok(Some(Caller {
+        link_name,
+        address,
+        low_pc,
+    }))
+}
+
+fn load_inlined_call(node: &DieNode<'_, '_>, ctx: &mut LoadInlineCtx) -> cu::Result<()> {
+    let Some(caller) = &ctx.caller else {
+        return Ok(());
+    };
+    let entry = node.entry();
+    // the abstract origin could be the abstract instance or the declaration,
+    // which the linkage name is loaded through
+    let Some(inlined) = super::load_func_linkage_name(&entry)? else {
+        return Ok(());
+    };
+    let pc = match entry.uint_opt(DW_AT_low_pc)? {
+        Some(x) => Some(x),
+        None => entry.uint_opt(DW_AT_entry_pc)?,
+    };
+    // the pc is mapped to the address space of the symbols
+    // by the offset from the start of the caller
+    let address = pc
+        .and_then(|pc| pc.checked_sub(caller.low_pc))
+        .and_then(|offset| u32::try_from(offset).ok())
+        .and_then(|offset| caller.address.checked_add(offset));
+    let call_site = match entry.uint_opt(DW_AT_call_file)? {
+        None => None,
+        Some(file) => {
+            let line = entry.uint_opt(DW_AT_call_line)?.unwrap_or_default();
+            entry
+                .unit()
+                .decl_file_path(file)?
+                .map(|file| format!("{file}:{line}"))
+        }
+    };
+    ctx.calls.push(InlinedCall {
+        inlined,
+        site: InlineSite {
+            caller: caller.link_name.clone(),
+            caller_address: caller.address,
+            address,
+            call_site,
+        },
+    });
+    Ok(())
+}
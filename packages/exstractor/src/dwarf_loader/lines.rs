@@ -0,0 +1,108 @@
+use cu::pre::*;
+use dejj_utils::LineTableMode;
+use gimli::constants::*;
+use symlist::SymbolList;
+
+use crate::dwarf::{DieNode, Unit};
+
+/// A row in the line table, mapped to the address space of the symbols
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineRow {
+    pub address: u32,
+    pub file: String,
+    pub line: u32,
+}
+
+/// Load the line table of the unit from `.debug_line`.
+///
+/// The addresses in the line program are mapped to the address space of the symbols
+/// by the offset from the start of the function that contains them, so only rows in functions
+/// in the symbol list are loaded. With [`LineTableMode::Functions`], only the row at the
+/// entry point of each function is loaded
+pub fn load_line_table(
+    unit: &Unit,
+    symbol_list: &SymbolList,
+    mode: LineTableMode,
+) -> cu::Result<Vec<LineRow>> {
+    if mode == LineTableMode::Off {
+        return Ok(vec![]);
+    }
+    let mut functions = vec![];
+    let mut tree = unit.tree()?;
+    let root = tree.root()?;
+    cu::check!(
+        load_function_ranges_recur(root, symbol_list, &mut functions),
+        "failed to load function ranges for {unit}"
+    )?;
+    if functions.is_empty() {
+        return Ok(vec![]);
+    }
+    functions.sort_unstable();
+
+    let rows = unit.line_rows()?;
+    let mut output = vec![];
+    for (pc, file, line) in rows {
+        if mode == LineTableMode::Functions
+            && functions.binary_search_by_key(&pc, |f| f.low_pc).is_err()
+        {
+            continue;
+        }
+        // the function with the greatest low_pc that is not after the pc
+        let i = functions.partition_point(|f| f.low_pc <= pc);
+        let Some(function) = i.checked_sub(1).map(|i| &functions[i]) else {
+            continue;
+        };
+        if pc >= function.high_pc {
+            continue;
+        }
+        let Ok(offset) = u32::try_from(pc - function.low_pc) else {
+            continue;
+        };
+        let Some(address) = function.address.checked_add(offset) else {
+            continue;
+        };
+        output.push(LineRow {
+            address,
+            file,
+            line,
+        });
+    }
+    // only keep the first row of each address, and skip rows
+    // that have the same file and line as the previous row
+    output.dedup_by(|b, a| {
+        a.address == b.address
+            || (mode == LineTableMode::Full && a.file == b.file && a.line == b.line)
+    });
+    cu::trace!("loaded {} line rows from {unit}", output.len());
+    Ok(output)
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct FunctionRange {
+    low_pc: u64,
+    high_pc: u64,
+    address: u32,
+}
+
+fn load_function_ranges_recur(
+    node: DieNode<'_, '_>,
+    symbol_list: &SymbolList,
+    functions: &mut Vec<FunctionRange>,
+) -> cu::Result<()> {
+    let entry = node.entry();
+    if entry.tag() == DW_TAG_subprogram {
+        if let Some(low_pc) = entry.uint_opt(DW_AT_low_pc)? {
+            let high_pc = entry.high_pc(low_pc)?;
+            let link_name = super::load_func_linkage_name(&entry)?;
+            let address = link_name.and_then(|x| symbol_list.get_address(&x));
+            if let (Some(high_pc), Some(address)) = (high_pc, address) {
+                functions.push(FunctionRange {
+                    low_pc,
+                    high_pc,
+                    address,
+                });
+            }
+        }
+    }
+    node.for_each_child(|child| load_function_ranges_recur(child, symbol_list, functions))
+}
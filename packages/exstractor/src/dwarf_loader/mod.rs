@@ -6,3 +6,13 @@ mod types;
 pub use types::*;
 mod functions;
 pub use functions::*;
+mod calls;
+pub use calls::*;
+mod frames;
+pub use frames::*;
+mod inlines;
+pub use inlines::*;
+mod lines;
+pub use lines::*;
+mod qualifiers;
+pub use qualifiers::*;
@@ -1,12 +1,12 @@
 use std::collections::BTreeMap;
 
 use cu::pre::*;
-use exstructs::{Goff, GoffMap, NameSeg, Namespace, NamespaceMaps};
+use dejj_utils::{AnonymousNamespaceMode, Config, FunctionLocalTypesMode};
+use exstructs::{ArcStr, Goff, GoffMap, NameSeg, Namespace, NamespaceMaps};
 use gimli::constants::*;
 
 use crate::dwarf::{self, DieNode, Unit};
 
-#[derive(Default)]
 struct LoadNamespaceCtx {
     // the difference between qualifier and namespace
     // is that qualifier contains types/subprograms,
@@ -15,9 +15,35 @@ struct LoadNamespaceCtx {
     current_namespace: NamespaceStack,
     offset_to_ns: GoffMap<Namespace>,
     offset_to_qual: GoffMap<Namespace>,
+    abi_tags: BTreeMap<String, Vec<String>>,
+    /// Segment for anonymous namespaces
+    anonymous_namespace: NameSeg,
+    /// Qualify types in functions by the scope of the function instead
+    hoist_function_local: bool,
 }
 
 impl LoadNamespaceCtx {
+    /// Remove the ABI tags from the name of a type or namespace in the current
+    /// qualifier, recording the tags
+    fn strip_abi_tags(&mut self, name: &str) -> String {
+        let (stripped, tags) = exstructs::split_abi_tags(name);
+        if tags.is_empty() {
+            return name.to_string();
+        }
+        let stripped = stripped.into_owned();
+        // names in functions cannot be referenced from outside
+        let Ok(mut source) = self.current_qualifier.curr().to_cpp_typedef_source() else {
+            return stripped;
+        };
+        if !source.is_empty() {
+            source.push_str("::");
+        }
+        source.push_str(&stripped);
+        cu::trace!("removed ABI tags {tags:?} from {source}");
+        self.abi_tags.insert(source, tags);
+        stripped
+    }
+
     fn register_current_at_offset(&mut self, off: Goff) {
         self.offset_to_qual
             .insert(off, self.current_qualifier.curr());
@@ -44,9 +70,23 @@ impl NamespaceStack {
 }
 
 /// Load the namespaces in this compilation unit as a global offset map
-pub fn load_namespaces(unit: &Unit) -> cu::Result<NamespaceMaps> {
+pub fn load_namespaces(unit: &Unit, config: &Config) -> cu::Result<NamespaceMaps> {
     cu::trace!("loading namespaces for {unit}");
-    let mut ctx = LoadNamespaceCtx::default();
+    let anonymous_namespace = match config.extract.anonymous_namespaces {
+        AnonymousNamespaceMode::Merge => NameSeg::Anonymous,
+        AnonymousNamespaceMode::Distinct => {
+            NameSeg::AnonymousNamespace(ArcStr::from(unit.name.as_str()))
+        }
+    };
+    let mut ctx = LoadNamespaceCtx {
+        current_qualifier: Default::default(),
+        current_namespace: Default::default(),
+        offset_to_ns: Default::default(),
+        offset_to_qual: Default::default(),
+        abi_tags: Default::default(),
+        anonymous_namespace,
+        hoist_function_local: config.extract.function_local_types == FunctionLocalTypesMode::Hoist,
+    };
     cu::check!(
         load_namespaces_root(unit, &mut ctx),
         "failed to load namespaces for {unit}"
@@ -79,6 +119,7 @@ pub fn load_namespaces(unit: &Unit) -> cu::Result<NamespaceMaps> {
         qualifiers: ctx.offset_to_qual,
         namespaces: ctx.offset_to_ns,
         by_src: by_src_map,
+        abi_tags: ctx.abi_tags,
     })
 }
 
@@ -99,8 +140,9 @@ fn load_namespace_recur(node: DieNode<'_, '_>, ctx: &mut LoadNamespaceCtx) -> cu
         // only push the qualifier stack for types
         match entry.name_opt()? {
             Some(name) => {
+                let name = ctx.strip_abi_tags(name);
                 ctx.current_qualifier
-                    .push(NameSeg::Type(offset, name.into()));
+                    .push(NameSeg::Type(offset, name.as_str().into()));
             }
             None => {
                 ctx.current_qualifier.push(NameSeg::Anonymous);
@@ -118,6 +160,10 @@ fn load_namespace_recur(node: DieNode<'_, '_>, ctx: &mut LoadNamespaceCtx) -> cu
                 node.for_each_child(|child| load_namespace_recur(child, ctx))?;
             }
             // types could be defined inside a function
+            DW_TAG_subprogram if ctx.hoist_function_local => {
+                ctx.register_current_at_offset(offset);
+                node.for_each_child(|child| load_namespace_recur(child, ctx))?;
+            }
             DW_TAG_subprogram => {
                 ctx.register_current_at_offset(offset);
                 let linkage_name = super::load_func_linkage_name(&entry)?;
@@ -147,13 +193,15 @@ fn load_namespace_recur(node: DieNode<'_, '_>, ctx: &mut LoadNamespaceCtx) -> cu
                 ctx.register_current_at_offset(offset);
                 match entry.name_opt()? {
                     Some(name) => {
-                        let seg = NameSeg::Name(name.into());
+                        let name = ctx.strip_abi_tags(name);
+                        let seg = NameSeg::Name(name.as_str().into());
                         ctx.current_qualifier.push(seg.clone());
                         ctx.current_namespace.push(seg);
                     }
                     None => {
-                        ctx.current_qualifier.push(NameSeg::Anonymous);
-                        ctx.current_namespace.push(NameSeg::Anonymous);
+                        let seg = ctx.anonymous_namespace.clone();
+                        ctx.current_qualifier.push(seg.clone());
+                        ctx.current_namespace.push(seg);
                     }
                 };
                 node.for_each_child(|child| load_namespace_recur(child, ctx))?;
@@ -0,0 +1,104 @@
+use exstructs::Qualifiers;
+use gimli::constants::*;
+
+use crate::dwarf::{Loff, Unit};
+
+/// Type trees are flattened through typedefs, which are named before
+/// reaching the limit in valid DWARF
+const MAX_QUALIFIER_DEPTH: usize = 64;
+
+/// Load the cv-qualifiers of the type at the offset, in the shape of the
+/// type tree after the typedefs and qualifiers are flattened. None is void
+pub fn load_type_qualifiers(unit: &Unit, loff: Option<Loff>) -> cu::Result<Qualifiers> {
+    let mut out = vec![];
+    load_qualifiers_recur(unit, loff, 0, 0, &mut out)?;
+    Ok(Qualifiers::new(out))
+}
+
+/// Load the cv-qualifiers of a function type (i.e. `Tree::Sub`), from the
+/// return type and the parameter types
+pub fn load_func_qualifiers(
+    unit: &Unit,
+    retty: Option<Loff>,
+    params: &[Loff],
+) -> cu::Result<Qualifiers> {
+    let mut out = vec![0];
+    load_qualifiers_recur(unit, retty, 0, 0, &mut out)?;
+    for param in params {
+        load_qualifiers_recur(unit, Some(*param), 0, 0, &mut out)?;
+    }
+    Ok(Qualifiers::new(out))
+}
+
+fn load_qualifiers_recur(
+    unit: &Unit,
+    mut loff: Option<Loff>,
+    mut bits: u8,
+    mut depth: usize,
+    out: &mut Vec<u8>,
+) -> cu::Result<()> {
+    loop {
+        let Some(l) = loff else {
+            out.push(bits);
+            return Ok(());
+        };
+        cu::ensure!(depth < MAX_QUALIFIER_DEPTH, "type is nested too deep")?;
+        depth += 1;
+        let entry = unit.entry_at(l)?;
+        let next = entry.loff_opt(DW_AT_type)?;
+        match entry.tag() {
+            DW_TAG_const_type => bits |= Qualifiers::CONST,
+            DW_TAG_volatile_type => bits |= Qualifiers::VOLATILE,
+            DW_TAG_restrict_type | DW_TAG_typedef => {}
+            DW_TAG_pointer_type | DW_TAG_reference_type => {
+                out.push(bits);
+                return load_qualifiers_recur(unit, next, 0, depth, out);
+            }
+            DW_TAG_array_type => {
+                // qualifiers of an array apply to the elements
+                out.push(0);
+                return load_qualifiers_recur(unit, next, bits, depth, out);
+            }
+            DW_TAG_subroutine_type => {
+                out.push(bits);
+                return load_subroutine_qualifiers(unit, l, depth, out);
+            }
+            DW_TAG_ptr_to_member_type => {
+                out.push(bits);
+                if let Some(pointee) = next {
+                    if unit.entry_at(pointee)?.tag() == DW_TAG_subroutine_type {
+                        // the types of PTMF are not in a subroutine node
+                        return load_subroutine_qualifiers(unit, pointee, depth, out);
+                    }
+                }
+                return load_qualifiers_recur(unit, next, 0, depth, out);
+            }
+            _ => {
+                out.push(bits);
+                return Ok(());
+            }
+        }
+        loff = next;
+    }
+}
+
+fn load_subroutine_qualifiers(
+    unit: &Unit,
+    loff: Loff,
+    depth: usize,
+    out: &mut Vec<u8>,
+) -> cu::Result<()> {
+    let entry = unit.entry_at(loff)?;
+    load_qualifiers_recur(unit, entry.loff_opt(DW_AT_type)?, 0, depth, out)?;
+    entry.for_each_child(|child| {
+        let entry = child.entry();
+        if entry.tag() != DW_TAG_formal_parameter {
+            return Ok(());
+        }
+        // void parameters are skipped in the tree
+        if let Some(param) = entry.loff_opt(DW_AT_type)? {
+            load_qualifiers_recur(unit, Some(param), 0, depth, out)?;
+        }
+        Ok(())
+    })
+}
@@ -1,26 +1,38 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Instant;
 
 use cu::pre::*;
-use dejj_utils::Config;
+use dejj_utils::{Config, VtableMergeMode};
 use exstructs::{
-    ArcStr, EnumUndeterminedSize, Enumerator, Goff, GoffMap, LType, LTypeData, LTypeDecl, Member,
-    NamespaceMaps, SpecialMember, Struct, SymbolInfo, TemplateArg, Union, VtableEntry,
+    Access, ArcStr, BaseClass, Bitfield, EnumUndeterminedSize, Enumerator, Goff, GoffMap, LType,
+    LTypeData, LTypeDecl, Member, NamespaceMaps, Qualifiers, SourceLoc, SpecialMember, Struct,
+    SymbolInfo, TemplateArg, Union, VtableEntry,
 };
 use gimli::constants::*;
 use symlist::SymbolList;
 use tyyaml::{Prim, Tree};
 
+use crate::degrade::Degradation;
 use crate::dwarf::{self, Die, DieNode, Unit};
+use crate::dwarf_loader;
 use crate::stages::LStage;
 
+/// Time to load each part of stage0, for the run stats
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LStageTimes {
+    pub types_millis: u64,
+    pub symbols_millis: u64,
+}
+
 /// Load the type data from DWARF units
 pub fn load_lstage(
     unit: &Unit,
     config: Arc<Config>,
     nsmaps: NamespaceMaps,
     symbol_list: Arc<SymbolList>,
-) -> cu::Result<LStage> {
+    degradation: Degradation,
+) -> cu::Result<(LStage, LStageTimes)> {
     let pointer_type = config.extract.pointer_type()?;
     let mut types = GoffMap::default();
     // add primitive types
@@ -32,32 +44,105 @@ pub fn load_lstage(
         config,
         types,
         nsmaps,
+        degradation,
+        source_files: Default::default(),
     };
-    cu::check!(
-        load_types_root(unit, &mut ctx),
-        "failed to load types for {unit}"
-    )?;
-    cu::trace!("loaded {} types from {unit}", ctx.types.len());
-
     let mut ctx2 = LoadSymbolCtx {
+        config: Arc::clone(&ctx.config),
         loaded: Default::default(),
         symbol_list,
+        source_files: Default::default(),
     };
-    cu::check!(
-        load_symbols_root(unit, &mut ctx2),
-        "failed to load symbols for {unit}"
-    )?;
+
+    // symbols do not depend on the types, so they are loaded on another thread,
+    // so units with many functions are not waiting behind the type walk
+    let mut times = LStageTimes::default();
+    let (types_result, symbols_result) = std::thread::scope(|s| {
+        let symbols_handle = s.spawn(|| {
+            let start = Instant::now();
+            let result = load_symbols_root(unit, &mut ctx2);
+            (result, start.elapsed().as_millis() as u64)
+        });
+        let start = Instant::now();
+        let types_result = load_types_root(unit, &mut ctx);
+        times.types_millis = start.elapsed().as_millis() as u64;
+        (types_result, symbols_handle.join())
+    });
+    cu::check!(types_result, "failed to load types for {unit}")?;
+    cu::trace!("loaded {} types from {unit}", ctx.types.len());
+    let Ok((symbols_result, symbols_millis)) = symbols_result else {
+        cu::bail!("symbol loading thread panicked for {unit}");
+    };
+    times.symbols_millis = symbols_millis;
+    cu::check!(symbols_result, "failed to load symbols for {unit}")?;
     cu::trace!("loaded {} symbols from {unit}", ctx2.loaded.len());
 
-    Ok(LStage {
+    // function pointer typedefs are eliminated like other typedefs to trees,
+    // so we keep the names separately
+    let mut typedefs = BTreeMap::new();
+    for (k, t) in &ctx.types {
+        let LType::Typedef { name, target } = t else {
+            continue;
+        };
+        if !is_function_pointer(*target, &ctx.types, 0) {
+            continue;
+        }
+        let Ok(name) = name.to_cpp_typedef_source() else {
+            // local typedefs in functions cannot be referenced from outside
+            continue;
+        };
+        typedefs.entry(name).or_insert(Tree::Base(*k));
+    }
+
+    let stage = LStage {
         offset: unit.offset.into(),
         name: unit.name.to_string(),
         types: ctx.types,
         config: ctx.config,
         ns: ctx.nsmaps,
         symbols: ctx2.loaded,
-    })
+        typedefs,
+    };
+    Ok((stage, times))
 }
+/// Check if the type is (or is an alias of) a pointer to function or member function
+fn is_function_pointer(k: Goff, types: &GoffMap<LType>, depth: usize) -> bool {
+    if depth > 1000 {
+        return false;
+    }
+    let Some(t) = types.get(&k) else {
+        return false;
+    };
+    match t {
+        LType::Typedef { target: inner, .. }
+        | LType::Alias(inner)
+        | LType::Tree(Tree::Base(inner)) => is_function_pointer(*inner, types, depth + 1),
+        LType::Tree(Tree::Ptmf(_, _)) => true,
+        LType::Tree(Tree::Ptr(pointee)) => match pointee.as_ref() {
+            Tree::Sub(_) => true,
+            Tree::Base(inner) => is_subroutine(*inner, types, depth + 1),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_subroutine(k: Goff, types: &GoffMap<LType>, depth: usize) -> bool {
+    if depth > 1000 {
+        return false;
+    }
+    let Some(t) = types.get(&k) else {
+        return false;
+    };
+    match t {
+        LType::Typedef { target: inner, .. }
+        | LType::Alias(inner)
+        | LType::Tree(Tree::Base(inner)) => is_subroutine(*inner, types, depth + 1),
+        LType::Tree(Tree::Sub(_)) => true,
+        _ => false,
+    }
+}
+
 fn load_types_root(unit: &Unit, ctx: &mut LoadTypeCtx) -> cu::Result<()> {
     let mut tree = unit.tree()?;
     let root = tree.root()?;
@@ -68,12 +153,47 @@ fn load_types_recur(mut node: DieNode<'_, '_>, ctx: &mut LoadTypeCtx) -> cu::Res
     let entry = node.entry();
     let tag = entry.tag();
     if dwarf::is_type_tag(tag) {
-        node = load_type_at(node, ctx)?;
+        if is_skipped(entry.goff(), &ctx.config) {
+            let ty = load_skipped_type(&entry, ctx);
+            ctx.types.insert(entry.goff(), ty);
+        } else {
+            node = load_type_at(node, ctx)?;
+        }
     }
 
     node.for_each_child(|child| load_types_recur(child, ctx))
 }
 
+fn is_skipped(offset: Goff, config: &Config) -> bool {
+    config.extract.skip_offsets.contains(&usize::from(offset))
+}
+
+/// Make an opaque placeholder for a type entry in the skip list,
+/// reading as little from the entry as possible
+fn load_skipped_type(entry: &Die<'_, '_>, ctx: &LoadTypeCtx) -> LType {
+    let offset = entry.goff();
+    cu::warn!("skipping type entry at {offset} in {}", entry.unit());
+    match entry.tag() {
+        DW_TAG_pointer_type | DW_TAG_reference_type => return LType::Prim(ctx.pointer_type),
+        _ => {}
+    }
+    let byte_size = match entry.uint_opt(DW_AT_byte_size) {
+        Ok(Some(x)) if x > 0 && x <= u32::MAX as u64 => x as u32,
+        _ => {
+            cu::warn!("cannot get size of skipped type entry at {offset}, treating it as 1 byte");
+            1
+        }
+    };
+    let mut data = Struct::zst();
+    data.byte_size = byte_size;
+    data.make_opaque();
+    LType::Struct(LTypeData {
+        name: None,
+        data,
+        source: None,
+    })
+}
+
 /// Load the type at the node. The node must be a type
 fn load_type_at<'a, 'b>(
     node: DieNode<'a, 'b>,
@@ -188,39 +308,7 @@ fn load_type_at<'a, 'b>(
             LType::Tree(Tree::Sub(subroutine_types))
         }
         // PTMD/PTMF
-        DW_TAG_ptr_to_member_type => {
-            let this_ty_loff = cu::check!(
-                entry.loff(DW_AT_containing_type),
-                "failed to read this type for pointer-to-member type at {offset}"
-            )?;
-            let this_ty_goff = entry.to_global(this_ty_loff);
-            let pointee_ty_loff = cu::check!(
-                entry.loff_opt(DW_AT_type),
-                "failed to read pointee type for pointer-to-member type at {offset}"
-            )?;
-
-            if let Some(pointee_ty_loff) = pointee_ty_loff {
-                let pointee_entry = cu::check!(
-                    entry.unit().entry_at(pointee_ty_loff),
-                    "failed to read pointee type entry for pointer-to-member type at {offset}"
-                )?;
-                if pointee_entry.tag() == DW_TAG_subroutine_type {
-                    // PTMF
-                    let subroutine_types = cu::check!(
-                        load_subroutine_types_from_entry(&pointee_entry, false),
-                        "failed to read pointee subroutine type for pointer-to-member-function type at {offset}"
-                    )?;
-                    LType::Tree(Tree::ptmf(this_ty_goff, subroutine_types))
-                } else {
-                    // PTMD
-                    let pointee_ty_goff = entry.to_global(pointee_ty_loff);
-                    LType::Tree(Tree::ptmd(this_ty_goff, Tree::Base(pointee_ty_goff)))
-                }
-            } else {
-                // PTMD to void
-                LType::Tree(Tree::ptmd(this_ty_goff, Tree::Base(Goff::prim(Prim::Void))))
-            }
-        }
+        DW_TAG_ptr_to_member_type => load_ptm_type_from_entry(&entry, ctx)?,
         DW_TAG_base_type => LType::Prim(entry.prim_type()?),
         DW_TAG_enumeration_type => load_enum_type_from_entry(&entry, ctx)?,
         DW_TAG_union_type => load_union_type_from_entry(&entry, ctx)?,
@@ -231,6 +319,156 @@ fn load_type_at<'a, 'b>(
     Ok(node)
 }
 
+fn load_ptm_type_from_entry(entry: &Die<'_, '_>, ctx: &LoadTypeCtx) -> cu::Result<LType> {
+    let offset = entry.goff();
+    let this_ty_loff = cu::check!(
+        entry.loff_opt(DW_AT_containing_type),
+        "failed to read this type for pointer-to-member type at {offset}"
+    )?;
+    let pointee_ty_loff = cu::check!(
+        entry.loff_opt(DW_AT_type),
+        "failed to read pointee type for pointer-to-member type at {offset}"
+    )?;
+
+    let Some(pointee_ty_loff) = pointee_ty_loff else {
+        // PTMD to void
+        let Some(this_ty_loff) = this_ty_loff else {
+            return opaque_ptm_type(entry, false, ctx);
+        };
+        let this_ty_goff = entry.to_global(this_ty_loff);
+        return Ok(LType::Tree(Tree::ptmd(
+            this_ty_goff,
+            Tree::Base(Goff::prim(Prim::Void)),
+        )));
+    };
+    let pointee_entry = cu::check!(
+        entry.unit().entry_at(pointee_ty_loff),
+        "failed to read pointee type entry for pointer-to-member type at {offset}"
+    )?;
+    if pointee_entry.tag() == DW_TAG_subroutine_type {
+        // PTMF
+        let this_ty_goff = match this_ty_loff {
+            Some(loff) => entry.to_global(loff),
+            None => {
+                // some producers omit the containing type,
+                // but the class is still known from the artificial this parameter
+                let this_ty_goff = cu::check!(
+                    load_this_type_from_subroutine(&pointee_entry),
+                    "failed to find this type from pointee of pointer-to-member type at {offset}"
+                )?;
+                match this_ty_goff {
+                    Some(goff) => goff,
+                    None => return opaque_ptm_type(entry, true, ctx),
+                }
+            }
+        };
+        let subroutine_types = cu::check!(
+            load_subroutine_types_from_entry(&pointee_entry, false),
+            "failed to read pointee subroutine type for pointer-to-member-function type at {offset}"
+        )?;
+        Ok(LType::Tree(Tree::ptmf(this_ty_goff, subroutine_types)))
+    } else {
+        // PTMD
+        let Some(this_ty_loff) = this_ty_loff else {
+            return opaque_ptm_type(entry, false, ctx);
+        };
+        let this_ty_goff = entry.to_global(this_ty_loff);
+        let pointee_ty_goff = entry.to_global(pointee_ty_loff);
+        Ok(LType::Tree(Tree::ptmd(
+            this_ty_goff,
+            Tree::Base(pointee_ty_goff),
+        )))
+    }
+}
+
+/// Load the cv-qualifiers of the member type, if qualifiers are kept in the config
+fn load_member_qualifiers(
+    entry: &Die<'_, '_>,
+    type_loff: dwarf::Loff,
+    ctx: &LoadTypeCtx,
+) -> cu::Result<Qualifiers> {
+    if !ctx.config.extract.keep_qualifiers {
+        return Ok(Qualifiers::default());
+    }
+    dwarf_loader::load_type_qualifiers(entry.unit(), Some(type_loff))
+}
+
+/// Load the access specifier of a member or inheritance entry
+fn load_access(entry: &Die<'_, '_>) -> cu::Result<Option<Access>> {
+    let Some(value) = entry.uint_opt(DW_AT_accessibility)? else {
+        return Ok(None);
+    };
+    let access = match DwAccess(value as u8) {
+        DW_ACCESS_public => Access::Public,
+        DW_ACCESS_protected => Access::Protected,
+        DW_ACCESS_private => Access::Private,
+        _ => cu::bail!("invalid DW_AT_accessibility {value} at {}", entry.goff()),
+    };
+    Ok(Some(access))
+}
+
+/// Get the class of a member function subroutine type, from the type of the
+/// artificial `this` parameter. Returns None if there is no such parameter
+fn load_this_type_from_subroutine(entry: &Die<'_, '_>) -> cu::Result<Option<Goff>> {
+    let mut this_ptr_loff = None;
+    let mut is_first = true;
+    entry.for_each_child(|child| {
+        let entry = child.entry();
+        if !is_first || entry.tag() != DW_TAG_formal_parameter {
+            return Ok(());
+        }
+        is_first = false;
+        if !entry.flag(DW_AT_artificial)? {
+            return Ok(());
+        }
+        this_ptr_loff = entry.loff_opt(DW_AT_type)?;
+        Ok(())
+    })?;
+    let Some(mut loff) = this_ptr_loff else {
+        return Ok(None);
+    };
+    // this could be `const T*`, or `T* const`
+    let mut is_ptr = false;
+    loop {
+        let ty_entry = entry.unit().entry_at(loff)?;
+        let next = match ty_entry.tag() {
+            DW_TAG_pointer_type if !is_ptr => {
+                is_ptr = true;
+                ty_entry.loff_opt(DW_AT_type)?
+            }
+            DW_TAG_const_type | DW_TAG_volatile_type | DW_TAG_restrict_type => {
+                ty_entry.loff_opt(DW_AT_type)?
+            }
+            _ if is_ptr => return Ok(Some(ty_entry.goff())),
+            _ => return Ok(None),
+        };
+        match next {
+            Some(next) => loff = next,
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Fallback for pointer-to-member types whose class cannot be found,
+/// represented as an opaque blob with the size from the config
+fn opaque_ptm_type(entry: &Die<'_, '_>, is_func: bool, ctx: &LoadTypeCtx) -> cu::Result<LType> {
+    let offset = entry.goff();
+    cu::warn!(
+        "cannot find containing type for pointer-to-member type at {offset} in {}, treating it as opaque",
+        entry.unit()
+    );
+    let repr = if is_func {
+        &ctx.config.extract.ptmf_repr
+    } else {
+        &ctx.config.extract.ptmd_repr
+    };
+    let (prim, len) = repr.as_array()?;
+    Ok(LType::Tree(Tree::Array(
+        Box::new(Tree::Base(Goff::prim(prim))),
+        len,
+    )))
+}
+
 fn load_subroutine_types_from_entry(
     entry: &Die<'_, '_>,
     allow_other_tags: bool,
@@ -348,12 +586,19 @@ fn load_enum_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu::
         result,
         "failed to collect enumerators for enum type at {offset}"
     )?;
+    let source = cu::check!(
+        load_source_loc(entry, &mut ctx.source_files),
+        "failed to load source location for enum type at {offset}"
+    )?;
     let data = LTypeData {
         name,
         data: EnumUndeterminedSize {
             byte_size_or_base,
+            // resolved after the base type is loaded
+            is_signed: None,
             enumerators,
         },
+        source,
     };
     Ok(LType::Enum(data))
 }
@@ -400,6 +645,7 @@ fn load_union_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu:
 
     let mut template_args = Vec::new();
     let mut members = Vec::<Member>::with_capacity(16);
+    let degradation = ctx.degradation;
     entry.for_each_child(|child| {
         let entry = child.entry();
         let offset = entry.goff();
@@ -420,6 +666,11 @@ fn load_union_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu:
                         name,
                         ty: Tree::Base(type_offset),
                         special: None,
+                        qualifiers: cu::check!(
+                            load_member_qualifiers(&entry, type_loff, ctx),
+                            "failed to load qualifiers for union member at {offset}"
+                        )?,
+                        access: load_access(&entry)?,
                     }),
                     Some(old) => {
                         // update the name if we have it now
@@ -452,6 +703,9 @@ fn load_union_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu:
                     "unsupported virtual function in union at {offset}"
                 )?;
             }
+            tag if degradation.ignore_unknown_tags => {
+                cu::debug!("ignoring unexpected tag {tag} at {offset} while processing union");
+            }
             tag => {
                 cu::bail!("unexpected tag {tag} at {offset} while processing union");
             }
@@ -459,6 +713,10 @@ fn load_union_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu:
         Ok(())
     })?;
 
+    let source = cu::check!(
+        load_source_loc(entry, &mut ctx.source_files),
+        "failed to load source location for union type at {offset}"
+    )?;
     let data = LTypeData {
         name,
         data: Union {
@@ -466,11 +724,44 @@ fn load_union_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu:
             byte_size,
             members,
         },
+        source,
     };
 
     Ok(LType::Union(data))
 }
 
+/// Get the offset of the first bit of a bitfield from the start of its storage,
+/// counting from the least significant bit
+fn load_bitfield_bit_offset(
+    entry: &Die<'_, '_>,
+    member_offset: u32,
+    byte_size: u32,
+    bit_size: u64,
+) -> cu::Result<u32> {
+    let storage_bits = byte_size as u64 * 8;
+    // DWARF 4+: offset in bits from the start of the containing struct
+    if let Some(data_bit_offset) = entry.uint_opt(DW_AT_data_bit_offset)? {
+        let bit_offset = data_bit_offset.checked_sub(member_offset as u64 * 8);
+        let bit_offset = cu::check!(bit_offset, "bitfield data_bit_offset is before the storage")?;
+        cu::ensure!(
+            bit_offset + bit_size <= storage_bits,
+            "bitfield does not fit in the storage (bit_offset={bit_offset}, bit_size={bit_size}, byte_size={byte_size})"
+        )?;
+        return Ok(bit_offset as u32);
+    }
+    // DWARF 2/3: offset in bits from the most significant bit of the storage,
+    // to the most significant bit of the bitfield
+    if let Some(msb_offset) = entry.uint_opt(DW_AT_bit_offset)? {
+        let bit_offset = storage_bits.checked_sub(msb_offset + bit_size);
+        let bit_offset = cu::check!(
+            bit_offset,
+            "bitfield does not fit in the storage (bit_offset={msb_offset}, bit_size={bit_size}, byte_size={byte_size})"
+        )?;
+        return Ok(bit_offset as u32);
+    }
+    Ok(0)
+}
+
 fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu::Result<LType> {
     let offset = entry.goff();
     let is_decl = cu::check!(
@@ -514,6 +805,8 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
     let mut vtable = Vec::default();
     let mut template_args = Vec::new();
     let mut members = Vec::<Member>::with_capacity(16);
+    let mut bases = Vec::new();
+    let degradation = ctx.degradation;
 
     let result = entry.for_each_child(|child| {
         let entry = child.entry();
@@ -521,7 +814,8 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
         match entry.tag() {
             DW_TAG_member => {
                 if entry.flag(DW_AT_external)? {
-                    // static member
+                    // static member, the definition is loaded
+                    // as a data symbol through DW_AT_specification
                     return Ok(());
                 }
                 // member might be anonymous union
@@ -557,6 +851,8 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
                         name: None,
                         ty: Tree::Base(Goff::prim(ctx.pointer_type)),
                         special: Some(SpecialMember::Vfptr),
+                        qualifiers: Qualifiers::default(),
+                        access: None,
                     }
                 } else {
                     Member {
@@ -564,37 +860,94 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
                         name: name.map(ArcStr::from),
                         ty: Tree::Base(type_offset),
                         special: None,
+                        qualifiers: cu::check!(
+                            load_member_qualifiers(&entry, type_loff, ctx),
+                            "failed to load qualifiers for struct member at {offset}"
+                        )?,
+                        access: load_access(&entry)?,
                     }
                 };
 
-                if cu::check!(
+                let bit_size = cu::check!(
                     entry.uint_opt(DW_AT_bit_size),
                     "failed to check if struct member is bitfield at {offset}"
-                )?
-                .is_some()
-                {
+                )?;
+                if let Some(bit_size) = bit_size {
                     let bitfield_byte_size = cu::check!(
                         entry.uint(DW_AT_byte_size),
                         "failed to get byte size of struct bitfield member at {offset}"
                     )?;
-                    // bitfields are merged into one member of that type
-                    // bitfield names are ignored for now
                     cu::ensure!(
                         bitfield_byte_size < u32::MAX as u64,
                         "bitfield_byte_size is too big for member at {offset}. This is unlikely to be correct."
                     )?;
-                    member.special = Some(SpecialMember::Bitfield(bitfield_byte_size as u32));
-                    // can merge with last member if it's the same bitfield
+                    let bitfield_byte_size = bitfield_byte_size as u32;
+                    if !ctx.config.extract.preserve_bitfields {
+                        // bitfields are merged into one member of that type
+                        member.special = Some(SpecialMember::Bitfield(bitfield_byte_size));
+                        // can merge with last member if it's the same bitfield
+                        if let Some(prev) = members.last_mut() {
+                            if prev.offset == member.offset && matches!(prev.special, Some(SpecialMember::Bitfield(_))) {
+                                *prev = member;
+                                return Ok(());
+                            }
+                        }
+                        members.push(member);
+                        return Ok(());
+                    }
+                    let bit_offset = cu::check!(
+                        load_bitfield_bit_offset(&entry, member_offset, bitfield_byte_size, bit_size),
+                        "failed to get bit offset of struct bitfield member at {offset}"
+                    )?;
+                    let bitfield = Bitfield {
+                        name: member.name.clone(),
+                        bit_offset,
+                        bit_size: bit_size as u32,
+                    };
+                    // add to the group of last member if it shares the same storage
                     if let Some(prev) = members.last_mut() {
-                        if prev.offset == member.offset && matches!(prev.special, Some(SpecialMember::Bitfield(_))) {
-                            *prev = member;
-                            return Ok(());
+                        if let Some(SpecialMember::BitfieldGroup(prev_byte_size, bitfields)) = &mut prev.special {
+                            if prev.offset == member.offset && *prev_byte_size == bitfield_byte_size {
+                                bitfields.push(bitfield);
+                                return Ok(());
+                            }
                         }
                     }
+                    member.special = Some(SpecialMember::BitfieldGroup(bitfield_byte_size, vec![bitfield]));
                 }
                 members.push(member);
             }
             DW_TAG_inheritance => {
+                let type_loff = cu::check!(
+                    entry.loff_opt(DW_AT_type),
+                    "failed to get struct base class type at {offset}"
+                )?;
+                let type_loff = cu::check!(type_loff, "unexpected void-typed struct base class at {offset}")?;
+                let type_offset = entry.to_global(type_loff);
+                let access = load_access(&entry)?;
+                let is_virtual = cu::check!(
+                    entry.is_virtual(),
+                    "failed to check if struct base class is virtual at {offset}"
+                )?;
+                if is_virtual {
+                    // the location of a virtual base is an expression that reads
+                    // the vtable, and the base is not part of the layout
+                    let vbase_offset = cu::check!(
+                        entry.vbase_offset(),
+                        "failed to get virtual base offset location at {offset}"
+                    )?;
+                    if vbase_offset.is_none() {
+                        cu::debug!("unrecognized location of virtual base at {offset}");
+                    }
+                    bases.push(BaseClass {
+                        offset: None,
+                        is_virtual: true,
+                        vbase_offset,
+                        access,
+                        ty: Tree::Base(type_offset),
+                    });
+                    return Ok(());
+                }
                 let member_offset = cu::check!(
                     entry.uint(DW_AT_data_member_location),
                     "failed to get struct base class offset at {offset}"
@@ -604,20 +957,26 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
                     "member_offset is too big for base class at {offset}. This is unlikely to be correct."
                 )?;
                 let member_offset = member_offset as u32;
-                let type_loff = cu::check!(
-                    entry.loff_opt(DW_AT_type),
-                    "failed to get struct base class type at {offset}"
-                )?;
-                let type_loff = cu::check!(type_loff, "unexpected void-typed struct base class at {offset}")?;
-                let type_offset = entry.to_global(type_loff);
+                bases.push(BaseClass {
+                    offset: Some(member_offset),
+                    is_virtual: false,
+                    vbase_offset: None,
+                    access,
+                    ty: Tree::Base(type_offset),
+                });
                 members.push(Member {
                     offset: member_offset,
                     name: None, // we will assign name to base members in a later step
                     ty: Tree::Base(type_offset),
                     special: Some(SpecialMember::Base),
+                    qualifiers: Qualifiers::default(),
+                    access,
                 });
             }
             DW_TAG_subprogram => {
+                if degradation.skip_vtables {
+                    return Ok(());
+                }
                 let Some(velem) = cu::check!(
                     entry.vtable_index(),
                     "failed to get struct virtual function vtable index at {offset}"
@@ -626,13 +985,24 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
                     // not virtual function, no need to process
                     return Ok(());
                 };
+                let linkage_name = cu::check!(
+                    dwarf_loader::load_func_linkage_name(&entry),
+                    "failed to get virtual function linkage name at {offset}"
+                )?;
+                if linkage_name.as_deref().is_some_and(is_thunk_linkage_name) {
+                    // thunks adjust this and forward to the real function,
+                    // they do not occupy the slot of the real function
+                    cu::debug!("skipping vtable thunk at {offset}");
+                    return Ok(());
+                }
                 let name = cu::check!(entry.name(), "failed to get virtual function name at {offset}")?;
                 let name = ArcStr::from(name);
                 let function_types = cu::check!(
                     load_subroutine_types_from_entry(&entry, false),
                     "failed to read virtual function data at {offset}"
                 )?;
-                vtable.push((velem, VtableEntry { name, function_types }));
+                let access = load_access(&entry)?;
+                vtable.push((velem, VtableEntry { name, function_types, access }));
             }
             // template args
             DW_TAG_template_type_parameter | DW_TAG_template_value_parameter | DW_TAG_GNU_template_parameter_pack => {
@@ -648,6 +1018,13 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
             | DW_TAG_typedef => {
                 // ignore subtypes, since they will be recursed into later
             }
+            DW_TAG_variable => {
+                // static member declaration (DWARF 5), the definition
+                // is loaded as a data symbol through DW_AT_specification
+            }
+            tag if degradation.ignore_unknown_tags => {
+                cu::debug!("ignoring unexpected tag {tag} at {offset} while processing struct");
+            }
             tag => cu::bail!("unexpected tag {tag} at {offset}"),
         }
         Ok(())
@@ -656,6 +1033,10 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
         result,
         "failed to process struct data for entry at {offset}"
     )?;
+    let vtable = cu::check!(
+        dedupe_vtable_slots(vtable, ctx.config.extract.vtable_merge),
+        "failed to merge vtable slots for struct at {offset}"
+    )?;
 
     // members may not come sorted by offset, we do that now
     // and ensure no duplicates
@@ -687,6 +1068,10 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
         );
     }
 
+    let source = cu::check!(
+        load_source_loc(entry, &mut ctx.source_files),
+        "failed to load source location for struct type at {offset}"
+    )?;
     let data = LTypeData {
         name,
         data: Struct {
@@ -694,12 +1079,54 @@ fn load_struct_type_from_entry(entry: &Die<'_, '_>, ctx: &mut LoadTypeCtx) -> cu
             byte_size,
             members,
             vtable,
+            bases,
+            is_decl: false,
         },
+        source,
     };
 
     Ok(LType::Struct(data))
 }
 
+/// Itanium ABI thunks: `_ZTh` (non-virtual), `_ZTv` (virtual) and `_ZTc` (covariant return)
+fn is_thunk_linkage_name(name: &str) -> bool {
+    name.starts_with("_ZTh") || name.starts_with("_ZTv") || name.starts_with("_ZTc")
+}
+
+/// Remove duplicated vtable slots. The same slot can be declared more than once
+/// for covariant-return overrides
+fn dedupe_vtable_slots(
+    vtable: Vec<(u32, VtableEntry)>,
+    mode: VtableMergeMode,
+) -> cu::Result<Vec<(u32, VtableEntry)>> {
+    let mut output = Vec::<(u32, VtableEntry)>::with_capacity(vtable.len());
+    for (i, entry) in vtable {
+        let existing = output
+            .iter()
+            .find(|(j, e)| e.is_dtor() == entry.is_dtor() && (entry.is_dtor() || *j == i));
+        let Some((_, existing)) = existing else {
+            output.push((i, entry));
+            continue;
+        };
+        if existing.name == entry.name {
+            continue;
+        }
+        if mode == VtableMergeMode::Strict {
+            cu::bail!(
+                "vtable slot {i} has different functions: {:?} and {:?}",
+                existing.name,
+                entry.name
+            );
+        }
+        cu::warn!(
+            "vtable slot {i} has different functions: {:?} and {:?}, keeping the first one",
+            existing.name,
+            entry.name
+        );
+    }
+    Ok(output)
+}
+
 /// Load template type parameters into the output vec
 ///
 /// The entry should be one of:
@@ -755,7 +1182,7 @@ fn load_template_parameter(
 }
 
 /// Assert the entry is DW_TAG_array_type, and get the DW_AT_count of the DW_TAG_subrange_type
-fn load_array_subrange_count(entry: &Die<'_, '_>) -> cu::Result<Option<u32>> {
+pub(crate) fn load_array_subrange_count(entry: &Die<'_, '_>) -> cu::Result<Option<u32>> {
     let offset = entry.goff();
     let mut count = None;
     let mut found_subrange = false;
@@ -806,6 +1233,10 @@ fn load_symbols_root(unit: &Unit, ctx: &mut LoadSymbolCtx) -> cu::Result<()> {
 
 fn load_symbols_recur(mut node: DieNode<'_, '_>, ctx: &mut LoadSymbolCtx) -> cu::Result<()> {
     let entry = node.entry();
+    if is_skipped(entry.goff(), &ctx.config) {
+        cu::warn!("skipping entry at {} in {}", entry.goff(), entry.unit());
+        return Ok(());
+    }
     match entry.tag() {
         DW_TAG_subprogram => {
             node = load_func_symbol_at(node, ctx)?;
@@ -825,13 +1256,14 @@ fn load_data_symbol_at<'a, 'b>(
     let entry = node.entry();
     let offset = entry.goff();
     let linkage_name = cu::check!(
-        entry.str_opt(DW_AT_linkage_name),
+        load_data_linkage_name(&entry),
         "failed to get linkage name for variable at {offset}"
     )?;
     let Some(linkage_name) = linkage_name else {
         // ignore variables without linkage name
         return Ok(node);
     };
+    let linkage_name = linkage_name.as_str();
 
     let loff = cu::check!(
         entry.loff_opt(DW_AT_type),
@@ -855,7 +1287,17 @@ fn load_data_symbol_at<'a, 'b>(
             )?
         }
     };
-    let symbol = SymbolInfo::new_data(linkage_name.to_string(), entry.to_global(loff));
+    let mut symbol = SymbolInfo::new_data(linkage_name.to_string(), entry.to_global(loff));
+    if ctx.config.extract.keep_qualifiers {
+        symbol.qualifiers = cu::check!(
+            dwarf_loader::load_type_qualifiers(entry.unit(), Some(loff)),
+            "failed to load qualifiers for data symbol at {offset}"
+        )?;
+    }
+    symbol.source = cu::check!(
+        load_symbol_source_loc(&entry, &mut ctx.source_files),
+        "failed to load source location for data symbol at {offset}"
+    )?;
     cu::check!(
         merge_symbol(linkage_name, symbol, ctx),
         "failed to merge data symbol at {offset}"
@@ -863,6 +1305,36 @@ fn load_data_symbol_at<'a, 'b>(
     Ok(node)
 }
 
+/// Get the linkage name of a variable. The definition of a static data member
+/// (`Class::s_instance`) is outside of the class, and usually only has a
+/// `DW_AT_specification` to the declaration in the class, which has the linkage name
+fn load_data_linkage_name(entry: &Die<'_, '_>) -> cu::Result<Option<String>> {
+    let offset = entry.goff();
+    let linkage_name = cu::check!(
+        entry.str_opt(DW_AT_linkage_name),
+        "failed to read linkage name for variable at {offset}"
+    )?;
+    if let Some(linkage_name) = linkage_name {
+        return Ok(Some(linkage_name.to_string()));
+    }
+    let specification = cu::check!(
+        entry.loff_opt(DW_AT_specification),
+        "failed to read specification for variable at {offset}"
+    )?;
+    let Some(specification) = specification else {
+        return Ok(None);
+    };
+    let spec = cu::check!(
+        entry.unit().entry_at(specification),
+        "failed to read specification entry for variable at {offset}"
+    )?;
+    let linkage_name = cu::check!(
+        spec.str_opt(DW_AT_linkage_name),
+        "failed to read linkage name from specification entry, for variable at {offset}"
+    )?;
+    Ok(linkage_name.map(|x| x.to_string()))
+}
+
 fn load_func_symbol_at<'a, 'b>(
     node: DieNode<'a, 'b>,
     ctx: &mut LoadSymbolCtx,
@@ -908,13 +1380,16 @@ fn load_func_symbol_at<'a, 'b>(
         super::load_func_retty(&entry),
         "failed to get return type for function at {offset}"
     )?;
-    let retty = match retty {
+    let retty_goff = match retty {
         None => Goff::prim(Prim::Void),
         Some(l) => entry.to_global(l),
     };
-    types.push(Tree::Base(retty));
+    types.push(Tree::Base(retty_goff));
 
+    let mut param_loffs = vec![];
     let mut param_names = vec![];
+    let mut explicit_param_count = 0;
+    let mut this_param = None;
     let mut template_args = vec![];
     let result = entry.for_each_child(|child| {
         let entry = child.entry();
@@ -941,7 +1416,18 @@ fn load_func_symbol_at<'a, 'b>(
                 let ty_loff = cu::check!(ty_loff, "missing parameter type at {offset}")?;
                 let ty = entry.to_global(ty_loff);
                 types.push(Tree::Base(ty));
+                param_loffs.push(ty_loff);
                 param_names.push(name.unwrap_or_default());
+                let is_artificial = cu::check!(
+                    super::load_func_param_is_artificial(&entry),
+                    "failed to check if function parameter is artificial at {offset}"
+                )?;
+                if !is_artificial {
+                    explicit_param_count += 1;
+                } else if param_loffs.len() == 1 {
+                    // the first artificial parameter is `this`
+                    this_param = Some(ty_loff);
+                }
             }
             // DW_TAG_variable => {
             //     let ty = cu::check!(entry.loff_opt(DW_AT_type), "failed to get function local variable type at {offset}")?;
@@ -961,8 +1447,26 @@ fn load_func_symbol_at<'a, 'b>(
         Ok(())
     });
     cu::check!(result, "failed to process function body at {offset}")?;
+    cu::check!(
+        check_func_signature(&entry, &linkage_name, explicit_param_count),
+        "failed to check signature for function at {offset}"
+    )?;
 
-    let symbol = SymbolInfo::new_func(linkage_name.clone(), types, param_names, template_args);
+    let mut symbol = SymbolInfo::new_func(linkage_name.clone(), types, param_names, template_args);
+    if ctx.config.extract.keep_qualifiers {
+        symbol.qualifiers = cu::check!(
+            dwarf_loader::load_func_qualifiers(entry.unit(), retty, &param_loffs),
+            "failed to load qualifiers for function at {offset}"
+        )?;
+    }
+    symbol.source = cu::check!(
+        load_symbol_source_loc(&entry, &mut ctx.source_files),
+        "failed to load source location for function at {offset}"
+    )?;
+    symbol.method = cu::check!(
+        dwarf_loader::load_func_method_info(&entry, this_param),
+        "failed to load method info for function at {offset}"
+    )?;
     cu::check!(
         merge_symbol(&linkage_name, symbol, ctx),
         "failed to merge function symbol at {offset}"
@@ -970,6 +1474,29 @@ fn load_func_symbol_at<'a, 'b>(
     Ok(node)
 }
 
+/// Cross-check the parameters against the signature in the name, if the producer
+/// spells the full signature in `DW_AT_name`. A mismatch means parameters
+/// are dropped or added when loading, which would otherwise go unnoticed
+fn check_func_signature(
+    entry: &Die<'_, '_>,
+    linkage_name: &str,
+    param_count: usize,
+) -> cu::Result<()> {
+    let Some(name) = super::load_func_name(entry)? else {
+        return Ok(());
+    };
+    let Some(expected) = dwarf_loader::signature_param_count(&name) else {
+        return Ok(());
+    };
+    if expected != param_count {
+        cu::warn!(
+            "function {linkage_name} at {} has {param_count} parameters, but its name `{name}` has {expected}",
+            entry.goff()
+        );
+    }
+    Ok(())
+}
+
 fn merge_symbol(
     linkage_name: &str,
     mut symbol: SymbolInfo,
@@ -994,6 +1521,46 @@ fn merge_symbol(
     Ok(())
 }
 
+/// Load where a type is declared in the source
+fn load_source_loc(
+    entry: &Die<'_, '_>,
+    files: &mut BTreeMap<u64, Option<ArcStr>>,
+) -> cu::Result<Option<SourceLoc>> {
+    let Some((file_index, line)) = entry.decl_file_line()? else {
+        return Ok(None);
+    };
+    make_source_loc(entry, file_index, line, files)
+}
+
+/// Load where a function or variable is declared in the source
+fn load_symbol_source_loc(
+    entry: &Die<'_, '_>,
+    files: &mut BTreeMap<u64, Option<ArcStr>>,
+) -> cu::Result<Option<SourceLoc>> {
+    let Some((file_index, line)) = dwarf_loader::load_decl_file_line(entry)? else {
+        return Ok(None);
+    };
+    make_source_loc(entry, file_index, line, files)
+}
+
+fn make_source_loc(
+    entry: &Die<'_, '_>,
+    file_index: u64,
+    line: u32,
+    files: &mut BTreeMap<u64, Option<ArcStr>>,
+) -> cu::Result<Option<SourceLoc>> {
+    let file = match files.get(&file_index) {
+        Some(file) => file.clone(),
+        None => {
+            let file = entry.unit().decl_file_path(file_index)?;
+            let file = file.map(|x| ArcStr::from(x.as_str()));
+            files.insert(file_index, file.clone());
+            file
+        }
+    };
+    Ok(file.map(|file| SourceLoc { file, line }))
+}
+
 fn make_ptr(goff: Goff) -> LType {
     LType::Tree(Tree::ptr(Tree::Base(goff)))
 }
@@ -1003,9 +1570,15 @@ struct LoadTypeCtx {
     config: Arc<Config>,
     types: GoffMap<LType>,
     nsmaps: NamespaceMaps,
+    degradation: Degradation,
+    /// Cache of file paths by index in the line program
+    source_files: BTreeMap<u64, Option<ArcStr>>,
 }
 
 struct LoadSymbolCtx {
+    config: Arc<Config>,
     loaded: BTreeMap<String, SymbolInfo>,
     symbol_list: Arc<SymbolList>,
+    /// Cache of file paths by index in the line program
+    source_files: BTreeMap<u64, Option<ArcStr>>,
 }
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use dejj_utils::{CanonicalNamePolicy, Config};
+use exstructs::algorithm::{FullQualPermutater, NamePolicy};
+use exstructs::{FullQualName, FullQualNameMap, Goff, GoffMap, HType, NameSeg};
+use tyyaml::{Document, SymbolDef, TypeDef};
+
+use crate::database::Database;
+use crate::export::ExportContext;
+
+/// Types and symbols in the database, in the data model of TyYAML
+#[derive(Debug, Default)]
+pub struct EmitModel {
+    /// Type definitions by name, including named function pointer types
+    pub types: BTreeMap<String, TypeDef>,
+    /// Symbols by link name
+    pub symbols: BTreeMap<String, SymbolDef>,
+    /// Name of the enclosing struct or union of nested types, by the name of
+    /// the nested type. Only types whose name is in the scope of the name of
+    /// the enclosing type are nested
+    pub parents: BTreeMap<String, String>,
+}
+
+impl EmitModel {
+    /// Convert the types and symbols in the database, naming the types
+    /// with their canonical fully-qualified names
+    pub fn from_database(db: &Database, policy: NamePolicy) -> cu::Result<Self> {
+        let names = cu::check!(
+            type_names(&db.types, policy),
+            "failed to compute type names"
+        )?;
+        let doc = exstructs::to_tyyaml_document(&db.types, &db.symbols, &db.typedefs, &names);
+
+        let mut parents = BTreeMap::new();
+        for (k, name) in &names {
+            if let Some(parent) = parent_name(db, &names, *k, name) {
+                parents.insert(name.clone(), parent.to_string());
+            }
+        }
+
+        Ok(Self {
+            types: doc.types,
+            symbols: doc.symbols,
+            parents,
+        })
+    }
+}
+
+/// Write the types (with sizes) and symbols in the database as TyYAML
+/// to `types.yaml` and `symbols.yaml` in the output directory. The files are
+/// split into parts if they are larger than the size budget of the format
+pub fn emit_tyyaml(db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+    let names = cu::check!(
+        type_names(&db.types, name_policy(ctx.config)),
+        "failed to compute type names"
+    )?;
+    let doc = exstructs::to_tyyaml_document(&db.types, &db.symbols, &db.typedefs, &names);
+    let types_path = ctx.output_dir.join(Document::TYPES_FILE);
+    let records = cu::check!(doc.type_records(), "failed to serialize types")?;
+    let types_files = ctx.write_split(&types_path, &records)?;
+    let symbols_path = ctx.output_dir.join(Document::SYMBOLS_FILE);
+    let records = cu::check!(doc.symbol_records(), "failed to serialize symbols")?;
+    let symbols_files = ctx.write_split(&symbols_path, &records)?;
+    cu::hint!(
+        "TyYAML saved to {} ({} files) and {} ({} files)",
+        types_path.try_to_rel().display(),
+        types_files.len(),
+        symbols_path.try_to_rel().display(),
+        symbols_files.len()
+    );
+    Ok(())
+}
+
+/// Pick the canonical name of each named type
+pub(crate) fn type_names(
+    types: &GoffMap<HType>,
+    policy: NamePolicy,
+) -> cu::Result<GoffMap<String>> {
+    let fullqual_names = FullQualNameMap::from_htypes(types)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names).with_policy(policy);
+    let mut output = GoffMap::default();
+    for (k, t) in types {
+        if matches!(t, HType::Prim(_)) {
+            continue;
+        }
+        if let Some(name) = permutater.canonical_name(*k)? {
+            output.insert(*k, name);
+        }
+    }
+    number_function_local_names(types, &mut output);
+    Ok(output)
+}
+
+/// Function-local types in different functions can have the same name, for example
+/// in functions without linkage names. Number the colliding names in Goff order,
+/// like `(function foo)::Local#2`, so the names are unique in the output
+fn number_function_local_names(types: &GoffMap<HType>, names: &mut GoffMap<String>) {
+    let mut by_name = BTreeMap::<&str, Vec<Goff>>::new();
+    for (k, name) in names.iter() {
+        by_name.entry(name).or_default().push(*k);
+    }
+    let mut renames = vec![];
+    for (name, goffs) in by_name {
+        if goffs.len() < 2 {
+            continue;
+        }
+        let local = goffs
+            .iter()
+            .filter(|k| types.get(k).is_some_and(HType::is_function_local))
+            .collect::<Vec<_>>();
+        // the first type keeps the name, unless a type outside of functions has it
+        let skip = if local.len() == goffs.len() { 1 } else { 0 };
+        for (i, k) in local.into_iter().enumerate().skip(skip) {
+            renames.push((*k, format!("{name}#{}", i + 1)));
+        }
+    }
+    names.extend(renames);
+}
+
+/// Get the policy for picking the canonical names of types from the config
+pub(crate) fn name_policy(config: &Config) -> NamePolicy {
+    match config.export.canonical_name {
+        CanonicalNamePolicy::Lexical => NamePolicy::Lexical,
+        CanonicalNamePolicy::PreferTypedef => NamePolicy::PreferTypedef,
+        CanonicalNamePolicy::PreferPrimitive => NamePolicy::PreferPrimitive,
+        CanonicalNamePolicy::Shortest => NamePolicy::Shortest,
+    }
+}
+
+/// Get the name of the struct or union that the type is nested in,
+/// from the namespaces of the fully-qualified names of the type
+fn parent_name<'a>(
+    db: &Database,
+    names: &'a GoffMap<String>,
+    k: Goff,
+    name: &str,
+) -> Option<&'a str> {
+    let fqnames = match db.types.get(&k)? {
+        HType::Prim(_) => return None,
+        HType::Enum(data) => &data.fqnames,
+        HType::Union(data) => &data.fqnames,
+        HType::Struct(data) => &data.fqnames,
+    };
+    for fqname in fqnames {
+        let base = match fqname {
+            FullQualName::Name(n) => &n.base,
+            FullQualName::Goff(n) => &n.base,
+        };
+        let Some(NameSeg::Type(parent_k, _)) = base.namespace().0.last() else {
+            continue;
+        };
+        if !matches!(
+            db.types.get(parent_k),
+            Some(HType::Struct(_) | HType::Union(_))
+        ) {
+            continue;
+        }
+        let Some(parent) = names.get(parent_k) else {
+            continue;
+        };
+        // the type could be named by a typedef outside of the parent
+        let is_in_scope = name
+            .strip_prefix(parent.as_str())
+            .is_some_and(|x| x.starts_with("::"));
+        if is_in_scope {
+            return Some(parent);
+        }
+    }
+    None
+}
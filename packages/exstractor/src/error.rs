@@ -0,0 +1,171 @@
+use std::path::PathBuf;
+
+use cu::pre::*;
+use dejj_utils::Config;
+
+/// Category of an extraction failure, with a stable code for automation.
+///
+/// The category is attached to the error chain as context, so the error
+/// still has the human-readable messages. Use [`ErrorKind::of`] to get it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The config file is missing or invalid
+    Config,
+    /// The build command failed
+    Build,
+    /// compile_commands.json cannot be loaded
+    Compdb,
+    /// A compilation unit in DWARF has no compile command
+    MissingCompileCommand,
+    /// The object file or the DWARF in it cannot be parsed
+    CorruptDwarf,
+    /// The symbol listing cannot be loaded
+    Symbols,
+    /// Stage0: loading types from DWARF failed
+    TypeLoad,
+    /// Stage1: reducing and parsing types failed
+    TypeReduce,
+    /// Stage2: linking and merging types across compilation units failed
+    TypeMerge,
+    /// Stage2: template instantiations have conflicting layouts across compilation units
+    TemplateConflict,
+    /// Stage3: resolving names failed
+    TypeResolve,
+    /// Stage3: the type optimizer failed
+    TypeOptimize,
+    /// Writing the outputs failed
+    Output,
+    /// The output directory is locked by another run
+    OutputLocked,
+}
+
+impl ErrorKind {
+    /// Stable code of the error kind
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Config => "E100",
+            Self::Build => "E200",
+            Self::Compdb => "E300",
+            Self::MissingCompileCommand => "E301",
+            Self::CorruptDwarf => "E302",
+            Self::Symbols => "E303",
+            Self::TypeLoad => "E400",
+            Self::TypeReduce => "E401",
+            Self::TypeMerge => "E402",
+            Self::TemplateConflict => "E403",
+            Self::TypeResolve => "E404",
+            Self::TypeOptimize => "E405",
+            Self::Output => "E500",
+            Self::OutputLocked => "E501",
+        }
+    }
+
+    /// Exit code of the process when failed with this kind of error.
+    ///
+    /// Other exit codes are 0 for success, 1 for uncategorized errors,
+    /// and [`EXIT_PARTIAL`](crate::EXIT_PARTIAL) for partial extraction
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Config => 2,
+            Self::Build => 3,
+            Self::Compdb | Self::MissingCompileCommand | Self::CorruptDwarf | Self::Symbols => 4,
+            Self::TypeLoad
+            | Self::TypeReduce
+            | Self::TypeMerge
+            | Self::TemplateConflict
+            | Self::TypeResolve
+            | Self::TypeOptimize => 5,
+            Self::Output | Self::OutputLocked => 6,
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Config => "invalid config",
+            Self::Build => "build failed",
+            Self::Compdb => "cannot load compile_commands.json",
+            Self::MissingCompileCommand => "missing compile command",
+            Self::CorruptDwarf => "corrupt or unsupported DWARF",
+            Self::Symbols => "cannot load symbols",
+            Self::TypeLoad => "failed to load types (stage0)",
+            Self::TypeReduce => "failed to reduce types (stage1)",
+            Self::TypeMerge => "failed to merge types (stage2)",
+            Self::TemplateConflict => "conflicting template instantiations (stage2)",
+            Self::TypeResolve => "failed to resolve type names (stage3)",
+            Self::TypeOptimize => "failed to optimize types (stage3)",
+            Self::Output => "failed to write outputs",
+            Self::OutputLocked => "output directory is locked by another run",
+        }
+    }
+
+    /// Get the kind of the error, if it was attached
+    pub fn of(error: &cu::Error) -> Option<Self> {
+        error.downcast_ref::<Self>().copied()
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.code(), self.describe())
+    }
+}
+
+pub(crate) trait ResultExt<T> {
+    /// Attach the error kind, unless the error already has one
+    fn error_kind(self, kind: ErrorKind) -> cu::Result<T>;
+}
+
+impl<T> ResultExt<T> for cu::Result<T> {
+    fn error_kind(self, kind: ErrorKind) -> cu::Result<T> {
+        self.map_err(|e| match ErrorKind::of(&e) {
+            Some(_) => e,
+            None => e.context(kind),
+        })
+    }
+}
+
+/// Report of a failed extraction, saved to `failure.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FailureReport {
+    /// None if the error was not categorized
+    pub kind: Option<ErrorKind>,
+    pub code: Option<String>,
+    /// The error messages, from outermost to innermost
+    pub messages: Vec<String>,
+}
+
+impl FailureReport {
+    pub fn new(error: &cu::Error) -> Self {
+        let kind = ErrorKind::of(error);
+        Self {
+            kind,
+            code: kind.map(|x| x.code().to_string()),
+            messages: error.chain().map(|e| e.to_string()).collect(),
+        }
+    }
+
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("failure.json")
+    }
+}
+
+/// A compilation unit that failed stage0 or stage1 even with the safest settings,
+/// and was skipped with `--keep-going`. Saved to `failures.json`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnitFailure {
+    /// Name of the compilation unit
+    pub unit: String,
+    /// The error. The kind is the stage that failed
+    #[serde(flatten)]
+    pub error: FailureReport,
+}
+
+impl UnitFailure {
+    pub fn new(unit: String, error: &cu::Error) -> Self {
+        Self {
+            unit,
+            error: FailureReport::new(error),
+        }
+    }
+}
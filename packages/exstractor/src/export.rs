@@ -0,0 +1,392 @@
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+use dejj_utils::{Config, ExportFormatConfig};
+use exstructs::algorithm::{self, FullQualPermutater};
+use exstructs::{FullQualNameMap, Goff, GoffSet, HType};
+use tyyaml::{Prim, Tree};
+
+use crate::compact_db::CompactDbExporter;
+use crate::compdb::Compdb;
+use crate::database::Database;
+use crate::emit;
+use crate::ghidra::GhidraExporter;
+use crate::hover::HoverData;
+use crate::ida::IdaExporter;
+use crate::template::TemplateExporter;
+
+/// Exporter of the extracted database to another format
+pub trait Exporter: Send + Sync {
+    /// Name of the format, used in `dejj export --format <name>`
+    /// and the config section `[export.<name>]`
+    fn name(&self) -> &'static str;
+    /// Extension of the exported file, without the dot
+    fn file_extension(&self) -> &'static str;
+    /// Export the database
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()>;
+}
+
+/// Inputs for an exporter, other than the database
+pub struct ExportContext<'a> {
+    pub config: &'a Config,
+    /// Options from the `[export.<name>]` section
+    pub options: ExportFormatConfig,
+    /// Directory for the exported files
+    pub output_dir: PathBuf,
+    /// Path of the exported file, for exporters that export to one file
+    pub output_path: PathBuf,
+}
+
+impl ExportContext<'_> {
+    /// Write the records to the file at path, where each record is a self-contained
+    /// piece of the output (for example, one type), in the order to write.
+    ///
+    /// If the total size is over `max-file-size` of the format, the records are split
+    /// into numbered parts `<stem>.part<n>.<ext>` instead, starting from 1. A record is
+    /// never split across parts, so each part is valid by itself if the format allows
+    /// concatenating records. Outputs from previous exports that are not overwritten
+    /// are removed, so there is no mix of split and unsplit files.
+    ///
+    /// Returns the paths written
+    pub fn write_split(&self, path: &Path, records: &[String]) -> cu::Result<Vec<PathBuf>> {
+        let total_size = records.iter().map(|x| x.len() as u64).sum::<u64>();
+        let (stem, extension) = split_file_name(path)?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let is_part = |file_name: &str| {
+            let Some(rest) = file_name.strip_prefix(stem) else {
+                return false;
+            };
+            let Some(rest) = rest.strip_prefix(".part") else {
+                return false;
+            };
+            let Some(n) = rest.strip_suffix(extension) else {
+                return false;
+            };
+            let n = n.strip_suffix('.').unwrap_or(n);
+            !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit())
+        };
+        // remove outputs of previous exports
+        if dir.exists() {
+            for entry in cu::fs::read_dir(dir)? {
+                let entry = entry?;
+                let file_name = entry.file_name().into_utf8()?;
+                if is_part(&file_name) {
+                    cu::fs::remove(entry.path())?;
+                }
+            }
+        }
+
+        let max_size = match self.options.max_file_size {
+            Some(max_size) if total_size > max_size => max_size,
+            _ => {
+                cu::fs::write(path, records.concat())?;
+                return Ok(vec![path.to_path_buf()]);
+            }
+        };
+        if path.exists() {
+            cu::fs::remove(path)?;
+        }
+        let mut paths = vec![];
+        let mut content = String::new();
+        for record in records {
+            if !content.is_empty() && (content.len() + record.len()) as u64 > max_size {
+                paths.push(write_part(dir, stem, extension, paths.len() + 1, &content)?);
+                content.clear();
+            }
+            content.push_str(record);
+        }
+        if !content.is_empty() {
+            paths.push(write_part(dir, stem, extension, paths.len() + 1, &content)?);
+        }
+        cu::info!(
+            "split {} into {} parts, because its size ({total_size} bytes) is over max-file-size ({max_size} bytes)",
+            path.try_to_rel().display(),
+            paths.len()
+        );
+        Ok(paths)
+    }
+}
+
+/// Split the file name into stem and extension (without the dot, could be empty)
+fn split_file_name(path: &Path) -> cu::Result<(&str, &str)> {
+    let file_name = cu::check!(path.file_name(), "invalid output path")?;
+    let file_name = cu::check!(file_name.to_str(), "output path is not UTF-8")?;
+    Ok(match file_name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, extension),
+        None => (file_name, ""),
+    })
+}
+
+fn write_part(
+    dir: &Path,
+    stem: &str,
+    extension: &str,
+    n: usize,
+    content: &str,
+) -> cu::Result<PathBuf> {
+    let file_name = if extension.is_empty() {
+        format!("{stem}.part{n}")
+    } else {
+        format!("{stem}.part{n}.{extension}")
+    };
+    let path = dir.join(file_name);
+    cu::fs::write(&path, content)?;
+    Ok(path)
+}
+
+/// Exporters by name
+pub struct ExporterRegistry {
+    exporters: Vec<Box<dyn Exporter>>,
+}
+
+impl Default for ExporterRegistry {
+    /// Create a registry with the built-in exporters
+    fn default() -> Self {
+        Self {
+            exporters: vec![
+                Box::new(HoverExporter),
+                Box::new(TyyamlExporter),
+                Box::new(TemplateExporter),
+                Box::new(CallGraphExporter),
+                Box::new(GhidraExporter),
+                Box::new(IdaExporter),
+                Box::new(CompactDbExporter),
+            ],
+        }
+    }
+}
+
+impl ExporterRegistry {
+    /// Add an exporter. Errors if an exporter with the same name is already registered
+    pub fn register(&mut self, exporter: impl Exporter + 'static) -> cu::Result<()> {
+        let name = exporter.name();
+        cu::ensure!(
+            self.exporters.iter().all(|x| x.name() != name),
+            "exporter {name} is already registered"
+        )?;
+        self.exporters.push(Box::new(exporter));
+        Ok(())
+    }
+
+    /// Get the exporter by name
+    pub fn get(&self, name: &str) -> cu::Result<&dyn Exporter> {
+        match self.exporters.iter().find(|x| x.name() == name) {
+            Some(exporter) => Ok(exporter.as_ref()),
+            None => {
+                let names = self.names().collect::<Vec<_>>().join(", ");
+                cu::bail!("unknown export format '{name}', available formats: {names}");
+            }
+        }
+    }
+
+    /// Names of all registered exporters
+    pub fn names(&self) -> impl Iterator<Item = &'static str> {
+        self.exporters.iter().map(|x| x.name())
+    }
+
+    /// Export the database with the exporter of the format
+    pub fn export(&self, db: &Database, config: &Config, format: &str) -> cu::Result<()> {
+        let exporter = self.get(format)?;
+        let options = config.export.format(format);
+        let output_dir = match &config.export.output_dir {
+            Some(dir) => dir.clone(),
+            None => config.paths.extract_output.clone(),
+        };
+        let file_name = match &options.file_name {
+            Some(name) => name.clone(),
+            None => format!("{format}.{}", exporter.file_extension()),
+        };
+        let output_path = output_dir.join(file_name);
+        cu::fs::make_dir(&output_dir)?;
+        let ctx = ExportContext {
+            config,
+            options,
+            output_dir,
+            output_path,
+        };
+        cu::check!(exporter.run(db, &ctx), "failed to export {format}")
+    }
+}
+
+/// Options for exporting an extracted database
+#[derive(Debug, Default)]
+pub struct ExportOptions {
+    /// Formats to export. Default is the formats to export on extract in the config
+    pub formats: Vec<String>,
+    /// Path to the database. Default is the database emitted by extract
+    pub input: Option<PathBuf>,
+    /// Only export these types, by fully-qualified name. Default is to export everything.
+    /// Symbols are not exported if roots are specified
+    pub roots: Vec<String>,
+    /// Also export types transitively referenced by the roots. Otherwise,
+    /// references to other types are replaced with byte arrays of the same size
+    pub closure: bool,
+}
+
+/// Export an extracted database to other formats, without re-running extraction
+pub fn export(config: &Config, options: ExportOptions) -> cu::Result<()> {
+    let registry = ExporterRegistry::default();
+    let formats = if options.formats.is_empty() {
+        config.export.on_extract.clone()
+    } else {
+        options.formats
+    };
+    // check all formats before loading the database
+    for format in &formats {
+        registry.get(format)?;
+    }
+    let input = options
+        .input
+        .unwrap_or_else(|| Database::default_path(config));
+    let mut db = Database::load(&input)?;
+    if options.roots.is_empty() {
+        cu::ensure!(!options.closure, "--closure requires --roots")?;
+    } else {
+        cu::check!(
+            select_roots(&mut db, &options.roots, options.closure),
+            "failed to select root types to export"
+        )?;
+    }
+    for format in &formats {
+        registry.export(&db, config, format)?;
+    }
+    Ok(())
+}
+
+/// Only keep the root types in the database, and the types they reference if closure is true
+fn select_roots(db: &mut Database, roots: &[String], closure: bool) -> cu::Result<()> {
+    let fullqual_names = FullQualNameMap::from_htypes(&db.types)?;
+    let permutater = FullQualPermutater::new(&fullqual_names);
+    let mut root_goffs = GoffSet::default();
+    let mut found = vec![false; roots.len()];
+    for k in db.types.keys().filter(|k| !k.is_prim()) {
+        for (root, is_found) in roots.iter().zip(&mut found) {
+            // only a few roots are given, no need to compute all permutations of every type
+            if permutater.matches_spelling(*k, root)? {
+                root_goffs.insert(*k);
+                *is_found = true;
+            }
+        }
+    }
+    for (root, is_found) in roots.iter().zip(found) {
+        cu::ensure!(is_found, "cannot find root type '{root}'")?;
+    }
+    let type_count = db.types.len();
+
+    db.symbols.clear();
+    db.typedefs.clear();
+    if closure {
+        algorithm::keep_referenced_from(&mut db.types, root_goffs)?;
+        cu::info!(
+            "exporting {} root types and their references ({} of {} types)",
+            roots.len(),
+            db.types.len(),
+            type_count
+        );
+        return Ok(());
+    }
+
+    // replace references to other types with byte arrays, so the roots are self-contained
+    let u8_goff = Goff::prim(Prim::U8);
+    let mut replacements = Vec::new();
+    for (k, t) in &db.types {
+        if k.is_prim() || root_goffs.contains(k) {
+            continue;
+        }
+        let replacement = match t.byte_size() {
+            Some(size) if size > 0 => Tree::Array(Box::new(Tree::Base(u8_goff)), size),
+            _ => Tree::Base(u8_goff),
+        };
+        replacements.push((*k, replacement));
+    }
+    db.types
+        .retain(|k, _| k.is_prim() || root_goffs.contains(k));
+    db.types.entry(u8_goff).or_insert(HType::Prim(Prim::U8));
+    for (k, replacement) in &replacements {
+        for t in db.types.values_mut() {
+            cu::check!(
+                t.replace(*k, replacement),
+                "failed to replace non-root type {k}"
+            )?;
+        }
+    }
+    cu::info!("exporting {} root types", root_goffs.len());
+    Ok(())
+}
+
+/// Editor hover data (`hover.json`), and compile flags for clangd (`compile_flags.txt`)
+struct HoverExporter;
+impl Exporter for HoverExporter {
+    fn name(&self) -> &'static str {
+        "hover"
+    }
+    fn file_extension(&self) -> &'static str {
+        "json"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        let hover = cu::check!(
+            HoverData::from_database(db, ctx.config),
+            "failed to compute editor hover data"
+        )?;
+        hover.save(&ctx.output_path)?;
+        cu::hint!(
+            "editor hover data saved to {}",
+            ctx.output_path.try_to_rel().display()
+        );
+        // the compile commands are not needed to export the database,
+        // so the flags are skipped if they are not available
+        let compdb = match Compdb::load(ctx.config) {
+            Ok(compdb) => compdb,
+            Err(e) => {
+                cu::warn!("not saving compile_flags.txt for clangd: {e:?}");
+                return Ok(());
+            }
+        };
+        let flags_path = ctx.output_dir.join("compile_flags.txt");
+        let mut flags = compdb.clangd_flags().join("\n");
+        flags.push('\n');
+        cu::fs::write(&flags_path, flags)?;
+        cu::hint!(
+            "clangd compile flags saved to {}",
+            flags_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
+
+/// TyYAML (`types.yaml` and `symbols.yaml`)
+struct TyyamlExporter;
+impl Exporter for TyyamlExporter {
+    fn name(&self) -> &'static str {
+        "tyyaml"
+    }
+    fn file_extension(&self) -> &'static str {
+        "yaml"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        emit::emit_tyyaml(db, ctx)
+    }
+}
+
+/// Static call graph (`callgraph.json`), as a list of calls sorted by caller address
+struct CallGraphExporter;
+impl Exporter for CallGraphExporter {
+    fn name(&self) -> &'static str {
+        "callgraph"
+    }
+    fn file_extension(&self) -> &'static str {
+        "json"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        if db.calls.is_empty() && !ctx.config.extract.call_graph {
+            cu::warn!("call graph is empty, enable extract.call-graph in the config to load it");
+        }
+        cu::fs::write_json_pretty(&ctx.output_path, &db.calls)?;
+        cu::hint!(
+            "call graph with {} calls saved to {}",
+            db.calls.len(),
+            ctx.output_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt::Write as _;
+
+use cu::pre::*;
+
+use crate::c_header;
+use crate::database::Database;
+use crate::export::{ExportContext, Exporter};
+
+/// Ghidra C header (`ghidra.h`) for `File > Parse C Source`, and a script
+/// (`ghidra_symbols.py`) to label the symbols in the program.
+///
+/// See the `c_header` module for how the types are converted to C
+pub struct GhidraExporter;
+impl Exporter for GhidraExporter {
+    fn name(&self) -> &'static str {
+        "ghidra"
+    }
+    fn file_extension(&self) -> &'static str {
+        "h"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        let header = cu::check!(
+            c_header::generate(db, ctx, "Parse in Ghidra with File > Parse C Source"),
+            "failed to generate C header for Ghidra"
+        )?;
+        cu::fs::write(&ctx.output_path, header)?;
+
+        let script_path = ctx.output_path.with_file_name(format!(
+            "{}_symbols.py",
+            ctx.output_path
+                .file_stem()
+                .and_then(|x| x.to_str())
+                .unwrap_or("ghidra")
+        ));
+        cu::fs::write(&script_path, symbols_script(db))?;
+        cu::hint!(
+            "Ghidra header saved to {}, and symbol script saved to {}",
+            ctx.output_path.try_to_rel().display(),
+            script_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
+
+/// Script to run in the Ghidra script manager. Addresses are relative to the image base
+fn symbols_script(db: &Database) -> String {
+    let mut out = String::new();
+    out.push_str("# Label symbols extracted by dejj\n");
+    out.push_str("# @category dejj\n");
+    out.push_str("from ghidra.program.model.symbol import SourceType\n\n");
+    out.push_str("SYMBOLS = [\n");
+    for symbol in db.symbols.values() {
+        let _ = writeln!(out, "    (0x{:x}, {:?}),", symbol.address, symbol.link_name);
+    }
+    out.push_str("]\n\n");
+    out.push_str("base = currentProgram.getImageBase()\n");
+    out.push_str("for (offset, name) in SYMBOLS:\n");
+    out.push_str("    createLabel(base.add(offset), name, True, SourceType.IMPORTED)\n");
+    out
+}
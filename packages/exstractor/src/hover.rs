@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{FullQualNameMap, Goff, HType, Member, SizeMap, SpecialMember};
+use tyyaml::Tree;
+
+use crate::database::Database;
+use crate::emit;
+
+/// Hover data for the editor extension, which maps type names and the
+/// source lines where types are declared to the actual layout of the type in the binary
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HoverData {
+    /// Layout of all named types
+    pub types: Vec<HoverType>,
+    /// Index into `types` for every spelling of every type name
+    pub names: BTreeMap<String, usize>,
+    /// Index into `types` by the source file and line where the type is declared
+    #[serde(default)]
+    pub lines: BTreeMap<String, BTreeMap<u32, usize>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HoverType {
+    /// Display name of the type
+    pub name: String,
+    pub kind: HoverTypeKind,
+    pub size: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<HoverMember>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enumerators: Vec<(String, i64)>,
+    /// (index, name) of virtual functions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vtable: Vec<(u32, String)>,
+    /// Declared location in the original source, as `file:line`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoverTypeKind {
+    Enum,
+    Union,
+    Struct,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HoverMember {
+    pub offset: u32,
+    /// None if the member type is unsized
+    pub size: Option<u32>,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// "base", "vfptr" or "bitfield"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special: Option<String>,
+    /// (name, bit_offset, bit_size) of bitfields in the member, if bitfields are preserved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bitfields: Vec<(Option<String>, u32, u32)>,
+}
+
+impl HoverData {
+    /// Path of the hover data emitted by extraction
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("hover.json")
+    }
+
+    pub fn from_database(db: &Database, config: &Config) -> cu::Result<Self> {
+        let sizes = db.sizes(config)?;
+        let fullqual_names = FullQualNameMap::from_htypes(&db.types)?;
+        let mut permutater =
+            FullQualPermutater::new(&fullqual_names).with_policy(emit::name_policy(config));
+        let mut output = Self::default();
+
+        for (k, t) in &db.types {
+            let (kind, size, source) = match t {
+                HType::Prim(_) => continue,
+                HType::Enum(data) => (HoverTypeKind::Enum, data.data.byte_size, &data.source),
+                HType::Union(data) => (HoverTypeKind::Union, data.data.byte_size, &data.source),
+                HType::Struct(data) => (HoverTypeKind::Struct, data.data.byte_size, &data.source),
+            };
+            // all permutated names can be hovered, but the canonical name is displayed
+            let names = permutater.permutated_fullqual_names(*k)?;
+            // anonymous types cannot be hovered
+            let Some(name) = permutater.canonical_name(*k)? else {
+                continue;
+            };
+            let mut hover_type = HoverType {
+                name,
+                kind,
+                size,
+                members: vec![],
+                enumerators: vec![],
+                vtable: vec![],
+                source: source.as_ref().map(|x| x.to_string()),
+            };
+            match t {
+                HType::Prim(_) => {}
+                HType::Enum(data) => {
+                    hover_type.enumerators = data
+                        .data
+                        .enumerators
+                        .iter()
+                        .map(|e| (e.name.to_string(), e.value))
+                        .collect();
+                }
+                HType::Union(data) => {
+                    hover_type.members =
+                        hover_members(&data.data.members, &sizes, &mut permutater)?;
+                }
+                HType::Struct(data) => {
+                    hover_type.members =
+                        hover_members(&data.data.members, &sizes, &mut permutater)?;
+                    hover_type.vtable = data
+                        .data
+                        .vtable
+                        .iter()
+                        .map(|(i, entry)| (*i, entry.name.to_string()))
+                        .collect();
+                }
+            }
+            let index = output.types.len();
+            output.types.push(hover_type);
+            for name in names.iter() {
+                output.names.insert(name.clone(), index);
+            }
+            // line 0 is unknown
+            if let Some(source) = source.as_ref().filter(|x| x.line != 0) {
+                output
+                    .lines
+                    .entry(source.file.to_string())
+                    .or_default()
+                    .entry(source.line)
+                    .or_insert(index);
+            }
+        }
+
+        Ok(output)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> cu::Result<()> {
+        let content = cu::check!(json::stringify(self), "failed to serialize hover data")?;
+        cu::fs::write(path, content)
+    }
+}
+
+fn hover_members(
+    members: &[Member],
+    sizes: &SizeMap,
+    permutater: &mut FullQualPermutater,
+) -> cu::Result<Vec<HoverMember>> {
+    let mut output = Vec::with_capacity(members.len());
+    for m in members {
+        let special = m.special.as_ref().map(|s| match s {
+            SpecialMember::Base => "base".to_string(),
+            SpecialMember::Vfptr => "vfptr".to_string(),
+            SpecialMember::Bitfield(_) | SpecialMember::BitfieldGroup(..) => "bitfield".to_string(),
+        });
+        let bitfields = match &m.special {
+            Some(SpecialMember::BitfieldGroup(_, bitfields)) => bitfields
+                .iter()
+                .map(|b| {
+                    (
+                        b.name.as_ref().map(|x| x.to_string()),
+                        b.bit_offset,
+                        b.bit_size,
+                    )
+                })
+                .collect(),
+            _ => vec![],
+        };
+        output.push(HoverMember {
+            offset: m.offset,
+            size: sizes.get_tree_optional(&m.ty),
+            name: m.name.as_ref().map(|x| x.to_string()),
+            ty: tree_display_name(&m.ty, permutater)?,
+            special,
+            bitfields,
+        });
+    }
+    Ok(output)
+}
+
+fn tree_display_name(tree: &Tree<Goff>, permutater: &mut FullQualPermutater) -> cu::Result<String> {
+    let name = cu::check!(
+        permutater.canonical_tree_name(tree),
+        "failed to compute display name for member type"
+    )?;
+    if let Some(name) = name {
+        return Ok(name);
+    }
+    // anonymous type, or composed from an anonymous type
+    match tree {
+        Tree::Base(k) => Ok(format!("[anonymous {k}]")),
+        _ => Ok("[anonymous]".to_string()),
+    }
+}
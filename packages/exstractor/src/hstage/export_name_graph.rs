@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use cu::pre::*;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{FullQualName, FullQualNameMap};
+
+use crate::stages::HStage;
+
+/// Name graph in a serializable form, for visualization
+#[derive(Debug, Serialize)]
+struct NameGraphExport {
+    /// Display name of each node
+    names: Vec<String>,
+    /// (derived, base) edges, as indices into names
+    derived: Vec<(usize, usize)>,
+}
+
+/// Save the name graph of the stage as JSON and DOT, so users can see which names
+/// were folded into which by the type optimizer
+pub fn save_name_graph(stage: &HStage, out_dir: &Path) -> cu::Result<()> {
+    let fullqual_names = FullQualNameMap::from_htypes(&stage.types)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    let names = stage
+        .name_graph
+        .names()
+        .iter()
+        .map(|n| display_name(n, &mut permutater))
+        .collect();
+    let export = NameGraphExport {
+        names,
+        derived: stage.name_graph.iter_derived_indices().collect(),
+    };
+
+    let json_path = out_dir.join("name_graph.json");
+    cu::fs::write_json_pretty(&json_path, &export)?;
+    let dot_path = out_dir.join("name_graph.dot");
+    cu::fs::write(&dot_path, export.to_dot())?;
+    cu::hint!(
+        "name graph saved to {} and {}",
+        json_path.try_to_rel().display(),
+        dot_path.try_to_rel().display()
+    );
+    Ok(())
+}
+
+impl NameGraphExport {
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph name_graph {\n    rankdir=LR;\n");
+        for (i, name) in self.names.iter().enumerate() {
+            // {:?} of str escapes quotes and backslashes
+            out.push_str(&format!("    n{i} [label={name:?}];\n"));
+        }
+        for (derived, base) in &self.derived {
+            out.push_str(&format!("    n{derived} -> n{base};\n"));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn display_name(name: &FullQualName, permutater: &mut FullQualPermutater) -> String {
+    let first = name
+        .permutated_fullqual(permutater)
+        .ok()
+        .and_then(|names| names.into_iter().next());
+    if let Some(first) = first {
+        return first;
+    }
+    // the name could reference types that are eliminated
+    match name {
+        FullQualName::Name(n) => n.base.to_string(),
+        FullQualName::Goff(n) => {
+            if n.templates.is_empty() {
+                return n.base.to_string();
+            }
+            let templates = n
+                .templates
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}<{}>", n.base, templates)
+        }
+    }
+}
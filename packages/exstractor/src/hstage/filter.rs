@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use dejj_utils::ExtractNameResolutionConfig;
+use exstructs::algorithm::{self, FullQualPermutater};
+use exstructs::{FullQualNameMap, Goff, GoffMap, GoffSet, HType, SymbolInfo};
+use tyyaml::{Prim, Tree};
+
+/// Drop types denied by the name resolution config from the output, and
+/// function-local types if `drop_function_local` is true.
+///
+/// A type is dropped if any permutation of its fully-qualified names matches a deny rule,
+/// and none matches an allow rule. References to dropped types are replaced with byte arrays
+/// of the same size
+pub fn run(
+    types: &mut GoffMap<HType>,
+    symbols: &mut BTreeMap<String, SymbolInfo>,
+    typedefs: &mut BTreeMap<String, Tree<Goff>>,
+    config: &ExtractNameResolutionConfig,
+    drop_function_local: bool,
+) -> cu::Result<()> {
+    if config.deny.is_empty() && !drop_function_local {
+        // save the cost of computing permutated names
+        return Ok(());
+    }
+    let fullqual_names = FullQualNameMap::from_htypes(types)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    // number of types dropped by each deny rule, and kept by each allow rule
+    let mut deny_counts = vec![0usize; config.deny.len()];
+    let mut allow_counts = vec![0usize; config.allow.len()];
+    let mut local_count = 0;
+    let mut dropped = GoffSet::default();
+    for (k, t) in types.iter().filter(|(k, _)| !k.is_prim()) {
+        if drop_function_local && t.is_function_local() {
+            cu::trace!("dropping function-local type {k} from output");
+            local_count += 1;
+            dropped.insert(*k);
+            continue;
+        }
+        if config.deny.is_empty() {
+            continue;
+        }
+        let names = permutater.permutated_fullqual_names(*k)?;
+        let denied_by = config
+            .deny
+            .iter()
+            .position(|r| names.iter().any(|name| r.is_match(name)));
+        let Some(denied_by) = denied_by else {
+            continue;
+        };
+        let allowed_by = config
+            .allow
+            .iter()
+            .position(|r| names.iter().any(|name| r.is_match(name)));
+        if let Some(allowed_by) = allowed_by {
+            allow_counts[allowed_by] += 1;
+            continue;
+        }
+        cu::trace!(
+            "dropping type {k} ({}) from output",
+            names.iter().cloned().collect::<Vec<_>>().join(", ")
+        );
+        deny_counts[denied_by] += 1;
+        dropped.insert(*k);
+    }
+
+    for (rule, count) in config.deny.iter().zip(deny_counts) {
+        cu::info!("name filter: deny rule '{rule}' dropped {count} types");
+    }
+    for (rule, count) in config.allow.iter().zip(allow_counts) {
+        cu::info!("name filter: allow rule '{rule}' kept {count} types");
+    }
+    if drop_function_local {
+        cu::info!("name filter: dropped {local_count} function-local types");
+    }
+    if dropped.is_empty() {
+        return Ok(());
+    }
+
+    let u8_goff = Goff::prim(Prim::U8);
+    types.entry(u8_goff).or_insert(HType::Prim(Prim::U8));
+    let mut replacements = Vec::with_capacity(dropped.len());
+    for k in &dropped {
+        let t = types.remove(k).unwrap();
+        let replacement = match t.byte_size() {
+            Some(size) if size > 0 => Tree::Array(Box::new(Tree::Base(u8_goff)), size),
+            _ => Tree::Base(u8_goff),
+        };
+        replacements.push((*k, replacement));
+    }
+    for (k, replacement) in &replacements {
+        for t in types.values_mut() {
+            cu::check!(
+                t.replace(*k, replacement),
+                "failed to replace dropped type {k} in types"
+            )?;
+        }
+        for (name, symbol) in symbols.iter_mut() {
+            cu::check!(
+                symbol.replace(*k, replacement),
+                "failed to replace dropped type {k} in symbol '{name}'"
+            )?;
+        }
+        for (name, tree) in typedefs.iter_mut() {
+            cu::check!(
+                algorithm::tree_replace(tree, *k, replacement),
+                "failed to replace dropped type {k} in typedef '{name}'"
+            )?;
+        }
+    }
+    cu::info!("name filter: dropped {} types from output", dropped.len());
+    Ok(())
+}
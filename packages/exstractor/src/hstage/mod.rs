@@ -1,23 +1,41 @@
 use std::sync::Arc;
 
 use cu::pre::*;
-use exstructs::{GoffMap, GoffSet, HType, HTypeData, MType, SizeMap, Struct};
+use dejj_utils::FunctionLocalTypesMode;
+use exstructs::algorithm;
+use exstructs::{GoffMap, HType, HTypeData, MType, Struct};
 
-use crate::stages::{HStage, MStage};
+use crate::stages::{self, HStage, MStage};
 
+mod export_name_graph;
+pub use export_name_graph::save_name_graph;
+mod filter;
 mod optimize;
+pub use optimize::AuditLog;
 mod split;
-// mod optimize_layout;
+mod verify;
+pub use verify::verify_layouts;
 
+/// Convert the linked MStage to HStage, without optimizing the layouts
 pub async fn from_mstage(stage: MStage) -> cu::Result<HStage> {
-    let stage = convert_from_mstage(stage)?;
+    let mut stage = convert_from_mstage(stage)?;
+    sweep(&mut stage)?;
+    Ok(stage)
+}
+
+/// Optimize (simplify) the type layouts in the stage
+pub async fn optimize(stage: HStage) -> cu::Result<HStage> {
     let mut stage = {
         cu::cli::set_thread_name("type-optimizer");
         let result = optimize::run(stage);
         cu::cli::reset_thread_name();
         result?
     };
+    sweep(&mut stage)?;
+    Ok(stage)
+}
 
+fn sweep(stage: &mut HStage) -> cu::Result<()> {
     if stage
         .config
         .extract
@@ -25,34 +43,20 @@ pub async fn from_mstage(stage: MStage) -> cu::Result<HStage> {
         .only_keep_referenced_from_symbols
     {
         // starting from types referenced by any symbols, only keep referenced types
-        let mut marked = GoffSet::default();
-        for symbol in stage.symbols.values() {
-            symbol.mark(&mut marked);
-        }
-        let mut newly_marked = GoffSet::default();
-        loop {
-            newly_marked.clear();
-            for k in &marked {
-                let t = cu::check!(
-                    stage.types.get(k),
-                    "unexpected unlinked type {k} while sweeping hstage"
-                )?;
-                t.mark(*k, &mut newly_marked);
-            }
-            let len_before = marked.len();
-            marked.extend(newly_marked.iter().copied());
-            if marked.len() == len_before {
-                break;
-            }
-        }
-        stage.types.retain(|k, _| marked.contains(k));
+        cu::check!(
+            algorithm::keep_referenced_from_symbols(
+                &mut stage.types,
+                &stage.symbols,
+                &stage.typedefs
+            ),
+            "failed to sweep hstage"
+        )?;
     }
-    Ok(stage)
+    Ok(())
 }
 
 fn convert_from_mstage(stage: MStage) -> cu::Result<HStage> {
     let mut types = GoffMap::default();
-    let mut sizes = GoffMap::default();
     for (k, t) in stage.types {
         let fqnames = t.fullqual_names();
         let t = match t {
@@ -60,43 +64,49 @@ fn convert_from_mstage(stage: MStage) -> cu::Result<HStage> {
             MType::Enum(data) => HType::Enum(HTypeData {
                 fqnames,
                 data: data.data,
+                source: data.source,
             }),
             MType::Union(data) => HType::Union(HTypeData {
                 fqnames,
                 data: data.data,
+                source: data.source,
             }),
             MType::Struct(data) => HType::Struct(HTypeData {
                 fqnames,
                 data: data.data,
+                source: data.source,
             }),
             MType::EnumDecl(_) | MType::UnionDecl(_) | MType::StructDecl(_) => {
                 HType::Struct(HTypeData {
                     fqnames,
-                    data: Struct::zst(),
+                    data: Struct::decl(),
+                    source: None,
                 })
             }
         };
-        let s = match &t {
-            HType::Prim(prim) => prim.byte_size(),
-            HType::Enum(data) => Some(data.data.byte_size),
-            HType::Union(data) => Some(data.data.byte_size),
-            HType::Struct(data) => Some(data.data.byte_size),
-        };
         types.insert(k, t);
-        sizes.insert(k, s);
     }
-    let sizes = SizeMap::new(
-        sizes,
-        stage.config.extract.pointer_size()?,
-        stage.config.extract.ptmd_size()?,
-        stage.config.extract.ptmf_size()?,
-    );
+    let mut symbols = stage.symbols;
+    let mut typedefs = stage.typedefs;
+    cu::check!(
+        filter::run(
+            &mut types,
+            &mut symbols,
+            &mut typedefs,
+            &stage.config.extract.name_resolution,
+            stage.config.extract.function_local_types == FunctionLocalTypesMode::Drop
+        ),
+        "failed to filter types by name"
+    )?;
+    let sizes = stages::size_map(&types, &stage.config)?;
 
     Ok(HStage {
         types,
         sizes: Arc::new(sizes),
         config: stage.config,
-        symbols: stage.symbols,
+        symbols,
+        typedefs,
         name_graph: Default::default(),
+        audit_log: Default::default(),
     })
 }
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use cu::pre::*;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{Goff, GoffSet, HType, HTypeData};
+use tyyaml::Tree;
+
+use crate::emit;
+use crate::hstage::optimize::{OptimizeContext, audit, util};
+use crate::stages::HStage;
+
+/// Keep one definition of the named structs and unions with identical layouts,
+/// and replace the others with typedefs of that definition.
+///
+/// Layouts are compared without the names, source locations and template arguments,
+/// so template instances that do not depend on the template arguments are aliased.
+/// This repeats until nothing changes, since aliasing a type can make the types
+/// that reference it identical
+pub fn run(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<()> {
+    let keep_distinct = cu::check!(
+        find_keep_distinct(stage),
+        "failed to match type-optimizer.keep-distinct"
+    )?;
+    // names are computed once before any change, since replacing the aliased
+    // types also replaces them in the names of other types
+    let names = emit::type_names(&stage.types, emit::name_policy(&stage.config))?;
+
+    let mut count = 0;
+    loop {
+        let mut canonical = HashMap::<HType, Goff>::new();
+        let mut aliases = vec![];
+        for (k, t) in &stage.types {
+            if ctx.excluded.contains(k) || keep_distinct.contains(k) {
+                continue;
+            }
+            let Some(layout) = layout_key(t) else {
+                continue;
+            };
+            match canonical.entry(layout) {
+                Entry::Occupied(e) => aliases.push((*k, *e.get())),
+                Entry::Vacant(e) => {
+                    e.insert(*k);
+                }
+            }
+        }
+        if aliases.is_empty() {
+            break;
+        }
+        for (alias_k, canonical_k) in aliases {
+            util::eliminate_unchecked(stage, alias_k, &Tree::Base(canonical_k))?;
+            let t = cu::check!(
+                stage.types.remove(&alias_k),
+                "unexpected: type {alias_k} was already removed"
+            )?;
+            let after = match stage.types.get(&canonical_k) {
+                Some(canonical_t) => format!("aliased to {}", audit::summarize(canonical_t)),
+                None => format!("aliased to {canonical_k}"),
+            };
+            stage.audit_log.record(alias_k, &t, after);
+            count += 1;
+            let (Some(name), Some(canonical_name)) = (names.get(&alias_k), names.get(&canonical_k))
+            else {
+                continue;
+            };
+            if name == canonical_name {
+                continue;
+            }
+            stage
+                .typedefs
+                .entry(name.clone())
+                .or_insert(Tree::Base(canonical_k));
+        }
+    }
+    cu::info!("aliased {count} types with identical layouts");
+    Ok(())
+}
+
+/// Get the data of the type to compare the layouts, None if the type
+/// should not be aliased. Empty types are not aliased, since they
+/// are usually tags that are distinct by name only
+fn layout_key(t: &HType) -> Option<HType> {
+    match t {
+        HType::Struct(data) => {
+            if data.fqnames.is_empty() || data.data.members.is_empty() {
+                return None;
+            }
+            let mut data = data.data.clone();
+            data.template_args.clear();
+            Some(HType::Struct(HTypeData {
+                fqnames: vec![],
+                data,
+                source: None,
+            }))
+        }
+        HType::Union(data) => {
+            if data.fqnames.is_empty() || data.data.members.is_empty() {
+                return None;
+            }
+            let mut data = data.data.clone();
+            data.template_args.clear();
+            Some(HType::Union(HTypeData {
+                fqnames: vec![],
+                data,
+                source: None,
+            }))
+        }
+        HType::Prim(_) | HType::Enum(_) => None,
+    }
+}
+
+/// Find types that are never aliased by config
+fn find_keep_distinct(stage: &HStage) -> cu::Result<GoffSet> {
+    let rules = &stage.config.extract.type_optimizer.keep_distinct;
+    let mut keep = GoffSet::default();
+    if rules.is_empty() {
+        // save the cost of computing permutated names
+        return Ok(keep);
+    }
+    let fullqual_names = util::compute_fqnames(stage)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    for regex in rules {
+        let goff_iter = stage.types.keys().filter(|k| !k.is_prim());
+        let matched = util::match_fqname(&mut permutater, regex, goff_iter)?;
+        for (k, name) in matched {
+            cu::debug!("keeping type {k} ({name}) distinct from identical layouts");
+            keep.insert(k);
+        }
+    }
+    Ok(keep)
+}
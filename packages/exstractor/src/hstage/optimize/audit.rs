@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use cu::pre::*;
+use exstructs::{FullQualName, Goff, HType};
+
+use crate::trace_type::{self, trace_type};
+
+/// Log of every change made by the type optimizer, so changes can be
+/// looked up after the fact without re-running with trace logging
+#[derive(Debug, Default, Clone)]
+pub struct AuditLog {
+    /// Name of the optimizer currently running
+    pass: &'static str,
+    entries: Vec<AuditEntry>,
+}
+
+/// One change made by the type optimizer
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// Name of the optimizer that made the change
+    pub pass: &'static str,
+    /// The type that was changed
+    pub goff: Goff,
+    /// Summary of the type before the change
+    pub before: String,
+    /// Summary of the change
+    pub after: String,
+}
+
+impl AuditLog {
+    /// Set the name of the optimizer that subsequent changes are recorded for
+    pub fn set_pass(&mut self, pass: &'static str) {
+        self.pass = pass;
+    }
+
+    /// Record a change to the type
+    pub fn record(&mut self, goff: Goff, before: &HType, after: impl Into<String>) {
+        let entry = AuditEntry {
+            pass: self.pass,
+            goff,
+            before: summarize(before),
+            after: after.into(),
+        };
+        if trace_type::matches_htype(goff, before) {
+            trace_type!("optimizer {}: {entry:#?}", self.pass);
+        } else {
+            cu::trace!("optimizer audit: {entry:?}");
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Save the log as JSON lines
+    pub fn save(&self, path: &Path) -> cu::Result<()> {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&json::stringify(entry)?);
+            out.push('\n');
+        }
+        cu::fs::write(path, out)
+    }
+}
+
+/// Summarize a type for the audit log, i.e. `union foo::Bar (8 bytes, 2 members)`
+pub fn summarize(t: &HType) -> String {
+    let (kind, fqnames, size, count) = match t {
+        HType::Prim(prim) => return format!("{prim:?}"),
+        HType::Enum(data) => (
+            "enum",
+            &data.fqnames,
+            data.data.byte_size,
+            data.data.enumerators.len(),
+        ),
+        HType::Union(data) => (
+            "union",
+            &data.fqnames,
+            data.data.byte_size,
+            data.data.members.len(),
+        ),
+        HType::Struct(data) => (
+            "struct",
+            &data.fqnames,
+            data.data.byte_size,
+            data.data.members.len(),
+        ),
+    };
+    let name = match fqnames.first() {
+        Some(FullQualName::Name(n)) => n.base.to_string(),
+        Some(FullQualName::Goff(n)) => n.base.to_string(),
+        None => "[anonymous]".to_string(),
+    };
+    let count_unit = if matches!(t, HType::Enum(_)) {
+        "enumerators"
+    } else {
+        "members"
+    };
+    format!("{kind} {name} ({size} bytes, {count} {count_unit})")
+}
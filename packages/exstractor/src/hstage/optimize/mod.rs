@@ -1,3 +1,6 @@
+mod alias;
+mod audit;
+pub use audit::AuditLog;
 mod util;
 pub use util::{OptimizeContext, Optimizer};
 mod run;
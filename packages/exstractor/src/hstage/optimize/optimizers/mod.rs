@@ -1,6 +1,7 @@
 use crate::hstage::optimize::Optimizer;
 use crate::hstage::optimize::util::make_optimizer;
 
+mod opt_collapse;
 mod opt_struct;
 mod opt_union;
 
@@ -10,8 +11,12 @@ pub static OPTIMIZERS: &[Optimizer] = &[
     // by another optimizer
     make_optimizer!(opt_union::pick_member),
     make_optimizer!(opt_struct::enumeratorize),
-    make_optimizer!(opt_struct::single_member),
-    make_optimizer!(opt_union::number_of_members),
-    make_optimizer!(opt_union::same_type_members),
-    // optimize_single_base_member_struct,
+    make_optimizer!(opt_collapse::collapse_to_public),
+    make_optimizer!(opt_struct::single_member, single_member_struct),
+    make_optimizer!(opt_union::empty, empty_union),
+    make_optimizer!(opt_union::single_member, single_member_union),
+    make_optimizer!(opt_union::two_members_by_size, two_member_union),
+    make_optimizer!(opt_union::same_type_members, same_type_union),
+    make_optimizer!(opt_union::two_members_by_usage, two_member_union_by_usage),
+    make_optimizer!(opt_struct::single_base_member, single_base_member_struct),
 ];
@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use cu::pre::*;
+use dejj_utils::ExtractTypeOptimizerCollapseInto;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{ArcStr, BaseClass, Goff, GoffSet, HType, Member, SpecialMember};
+use tyyaml::{Prim, Tree};
+
+use crate::hstage::optimize::{OptimizeContext, audit, util};
+use crate::stages::HStage;
+
+/// Collapse internal helper types into their owners or opaque blobs, based on config
+pub fn collapse_to_public(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+    // clone the config so we can re-borrow stage as mutable
+    let config = Arc::clone(&stage.config);
+    let rules = &config.extract.type_optimizer.collapse;
+    if rules.is_empty() {
+        // save the cost of computing permutated names
+        return Ok(false);
+    }
+
+    let fullqual_names = util::compute_fqnames(stage)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+
+    for rule in rules {
+        let error_prefix = format!("type-optimizer.collapse rule '{}'", rule.regex);
+        let goff_iter = stage.types.iter().filter_map(|(k, v)| {
+            if matches!(v, HType::Struct(_)) {
+                Some(k)
+            } else {
+                None
+            }
+        });
+        let matched = cu::check!(
+            util::match_fqname(&mut permutater, &rule.regex, goff_iter),
+            "{error_prefix} failed to match"
+        )?;
+        // one type could match with multiple permutated names
+        let matched = matched.into_iter().map(|(k, _)| k).collect::<GoffSet>();
+        for k in matched {
+            if ctx.excluded.contains(&k) {
+                continue;
+            }
+            let changed = match rule.into {
+                ExtractTypeOptimizerCollapseInto::Owner => cu::check!(
+                    collapse_into_owner(stage, k, ctx),
+                    "{error_prefix} failed to collapse {k} into owner"
+                )?,
+                ExtractTypeOptimizerCollapseInto::Opaque => cu::check!(
+                    collapse_into_opaque(stage, k),
+                    "{error_prefix} failed to collapse {k} into opaque"
+                )?,
+            };
+            if changed {
+                return Ok(true);
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// Inline the members of the helper type into one struct that has it as
+/// a base or a member. Return true if changed
+fn collapse_into_owner(
+    stage: &mut HStage,
+    helper_k: Goff,
+    ctx: &OptimizeContext,
+) -> cu::Result<bool> {
+    let helper = &stage
+        .types
+        .get(&helper_k)
+        .unwrap()
+        .as_struct_unchecked()
+        .data;
+    // types with vtable cannot be inlined, since the vfptr must be at offset 0
+    if !helper.vtable.is_empty()
+        || helper
+            .members
+            .iter()
+            .any(|m| matches!(m.special, Some(SpecialMember::Vfptr)))
+    {
+        return Ok(false);
+    }
+    let owner = stage.types.iter().find_map(|(k, t)| {
+        if *k == helper_k || ctx.excluded.contains(k) {
+            return None;
+        }
+        let HType::Struct(data) = t else {
+            return None;
+        };
+        let i = data
+            .data
+            .members
+            .iter()
+            .position(|m| m.ty == Tree::Base(helper_k))?;
+        Some((*k, i))
+    });
+    let Some((owner_k, i)) = owner else {
+        return Ok(false);
+    };
+    // must clone so we can re-borrow stage as mutable
+    let helper_members = helper.members.clone();
+    let helper_bases = helper.bases.clone();
+    let helper_t = stage.types.get(&helper_k).unwrap();
+    let owner_t = stage.types.get(&owner_k).unwrap();
+    let after = format!("inlined into owner {}", audit::summarize(owner_t));
+    stage.audit_log.record(helper_k, helper_t, after);
+
+    let owner = stage.types.get_mut(&owner_k).unwrap().as_struct_mut()?;
+    let member = owner.data.members.remove(i);
+    let inlined = helper_members.into_iter().map(|m| {
+        let name = match (&member.name, m.name) {
+            (Some(outer), Some(inner)) => Some(ArcStr::from(format!("{outer}_{inner}").as_str())),
+            (_, inner) => inner,
+        };
+        Member {
+            offset: member.offset + m.offset,
+            name,
+            ty: m.ty,
+            special: m.special,
+            qualifiers: m.qualifiers,
+            access: m.access,
+        }
+    });
+    owner.data.members.splice(i..i, inlined);
+    if member.is_base() {
+        // the bases of the helper become direct bases of the owner
+        if let Some(j) = owner.data.bases.iter().position(|b| b.ty == member.ty) {
+            let inlined = helper_bases.into_iter().map(|b| BaseClass {
+                offset: b.offset.map(|x| x + member.offset),
+                ..b
+            });
+            owner.data.bases.splice(j..=j, inlined);
+        }
+    }
+    cu::debug!("collapsed {helper_k} into owner {owner_k}");
+    Ok(true)
+}
+
+/// Replace the members of the type with a byte array. Return true if changed
+fn collapse_into_opaque(stage: &mut HStage, k: Goff) -> cu::Result<bool> {
+    let t = stage.types.get_mut(&k).unwrap();
+    let before = t.clone();
+    let data = &mut t.as_struct_mut()?.data;
+    if !data.vtable.is_empty() {
+        return Ok(false);
+    }
+    if !data.make_opaque() {
+        return Ok(false);
+    }
+    stage
+        .audit_log
+        .record(k, &before, "members replaced with opaque blob");
+    let u8_goff = Goff::prim(Prim::U8);
+    stage.types.entry(u8_goff).or_insert(HType::Prim(Prim::U8));
+    cu::debug!("collapsed {k} into opaque blob");
+    Ok(true)
+}
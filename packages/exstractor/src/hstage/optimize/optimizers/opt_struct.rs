@@ -1,9 +1,9 @@
 use cu::pre::*;
 use exstructs::algorithm::FullQualPermutater;
-use exstructs::{HType, HTypeData};
+use exstructs::{Goff, HType, HTypeData, Struct};
 use tyyaml::Tree;
 
-use crate::hstage::optimize::{OptimizeContext, util};
+use crate::hstage::optimize::{OptimizeContext, audit, util};
 use crate::stages::HStage;
 
 /// Eliminate structs with only one member
@@ -42,6 +42,63 @@ pub fn single_member(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bo
     Ok(false)
 }
 
+/// Inline the base class members into structs that only have the base class
+/// as member, but were not eliminated by `single_member` (for example, because
+/// they have their own virtual functions)
+pub fn single_base_member(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+    for (k, t) in &stage.types {
+        let HType::Struct(HTypeData { data, .. }) = t else {
+            continue;
+        };
+        let Some(base_k) = single_base(data) else {
+            continue;
+        };
+        let k = *k;
+        if base_k == k || ctx.excluded.contains(&k) || ctx.excluded.contains(&base_k) {
+            continue;
+        }
+        let Some(HType::Struct(base)) = stage.types.get(&base_k) else {
+            continue;
+        };
+        if base.data.byte_size != data.byte_size {
+            continue;
+        }
+        let after = format!(
+            "inlined members of base {}",
+            audit::summarize(stage.types.get(&base_k).unwrap())
+        );
+        cu::trace!("inlining base {base_k} into single-base-member struct {k}");
+        // must clone so we can re-borrow stage as mutable
+        let members = base.data.members.clone();
+        let base_bases = base.data.bases.clone();
+        let t = t.clone();
+        stage.audit_log.record(k, &t, after);
+        let data = &mut stage.types.get_mut(&k).unwrap().as_struct_mut()?.data;
+        data.members = members;
+        // the bases of the base become direct bases, at the same offsets
+        let base_ty = Tree::Base(base_k);
+        if let Some(i) = data.bases.iter().position(|b| b.ty == base_ty) {
+            data.bases.splice(i..=i, base_bases);
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Get the base class if the struct only has one member, which is the base class
+fn single_base(data: &Struct) -> Option<Goff> {
+    let [member] = data.members.as_slice() else {
+        return None;
+    };
+    if !member.is_base() || member.offset != 0 {
+        return None;
+    }
+    match member.ty {
+        Tree::Base(base_k) if !base_k.is_prim() => Some(base_k),
+        _ => None,
+    }
+}
+
 /// Eliminate struct/union into the associate Enum type
 pub fn enumeratorize(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
     let rules = &stage.config.extract.type_optimizer.enumeratorize;
@@ -108,3 +165,54 @@ pub fn enumeratorize(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bo
     }
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use exstructs::{Member, SpecialMember};
+
+    use super::*;
+
+    fn make_struct(members: Vec<Member>) -> Struct {
+        Struct {
+            byte_size: 8,
+            template_args: vec![],
+            members,
+            vtable: vec![],
+            bases: vec![],
+            is_decl: false,
+        }
+    }
+
+    fn make_member(goff: usize, special: Option<SpecialMember>) -> Member {
+        Member {
+            offset: 0,
+            name: None,
+            ty: Tree::Base(Goff(goff)),
+            special,
+            qualifiers: Default::default(),
+            access: None,
+        }
+    }
+
+    #[test]
+    fn test_single_base() {
+        let data = make_struct(vec![make_member(0x100, Some(SpecialMember::Base))]);
+        assert_eq!(single_base(&data), Some(Goff(0x100)));
+    }
+
+    #[test]
+    fn test_single_base_not_base() {
+        let data = make_struct(vec![make_member(0x100, None)]);
+        assert_eq!(single_base(&data), None);
+    }
+
+    #[test]
+    fn test_single_base_multiple_members() {
+        let data = make_struct(vec![
+            make_member(0x100, Some(SpecialMember::Base)),
+            make_member(0x200, None),
+        ]);
+        assert_eq!(single_base(&data), None);
+        assert_eq!(single_base(&make_struct(vec![])), None);
+    }
+}
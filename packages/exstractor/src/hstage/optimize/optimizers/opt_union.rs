@@ -1,91 +1,114 @@
 use cu::pre::*;
 use exstructs::algorithm::FullQualPermutater;
-use exstructs::{
-    FullQualName, FullQualNameMap, GoffMap, HType, HTypeData, NamespacedName,
-    NamespacedTemplatedGoffName, NamespacedTemplatedName, Struct,
-};
+use exstructs::{Goff, HType, HTypeData, Struct};
 use tyyaml::Tree;
 
 use crate::hstage::optimize::{OptimizeContext, util};
 use crate::stages::HStage;
 
-/// Based on number of union members, optimize the union
-pub fn number_of_members(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+/// Convert unions with no members to empty structs
+pub fn empty(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
     for (k, t) in &stage.types {
         let HType::Union(HTypeData { data, .. }) = t else {
             continue;
         };
         let k = *k;
-        match data.members.len() {
-            // Eliminate unions with fewer than 2 members
-            0 => {
-                // empty union is the same as an empty struct - a ZST (zero sized type, which has a
-                // sizeof() of 1
-                cu::ensure!(
-                    data.byte_size == 1,
-                    "expect empty union to be a ZST, but its size is {}",
-                    data.byte_size
-                )?;
-                cu::trace!("removing empty union {k}");
-                stage.types.entry(k).and_modify(|x| {
-                    let data = x.as_union_mut().unwrap();
-                    let fqnames = std::mem::take(&mut data.fqnames);
-                    let template_args = std::mem::take(&mut data.data.template_args);
-                    *x = HType::Struct(HTypeData {
-                        fqnames,
-                        data: Struct::zst_with_templates(template_args),
-                    });
-                });
-                return Ok(true);
-            }
-            1 => {
-                // a union with only one member is equivalent to that member
-                let member = &data.members[0];
-                if !util::check_eliminate(stage, k, &member.ty, ctx)? {
-                    continue;
-                }
-                cu::trace!("removing single-member union {k}");
-                // must clone so we can re-borrow stage as mutable
-                let member = member.clone();
-                util::eliminate_unchecked_and_give_names_to_base(stage, k, &member.ty)?;
-                return Ok(true);
-            }
-            2 => {
-                // 2 members, we optimize using the following heuristic:
-                // - if one member is a base type and the other is a composite type
-                // - if the base type has the same size as the union
-                // then, eliminate this union to be the base type
-                let member1 = &data.members[0];
-                let member2 = &data.members[1];
-                let member1_is_basety = matches!(member1.ty, Tree::Base(k) if !k.is_prim());
-                let member2_is_basety = matches!(member2.ty, Tree::Base(k) if !k.is_prim());
-                if member1_is_basety == member2_is_basety {
-                    continue;
-                }
+        if !data.members.is_empty() || ctx.excluded.contains(&k) {
+            continue;
+        }
+        // empty union is the same as an empty struct - a ZST (zero sized type, which has a
+        // sizeof() of 1
+        cu::ensure!(
+            data.byte_size == 1,
+            "expect empty union to be a ZST, but its size is {}",
+            data.byte_size
+        )?;
+        cu::trace!("removing empty union {k}");
+        stage
+            .audit_log
+            .record(k, t, "empty union converted to empty struct");
+        stage.types.entry(k).and_modify(|x| {
+            let data = x.as_union_mut().unwrap();
+            let fqnames = std::mem::take(&mut data.fqnames);
+            let template_args = std::mem::take(&mut data.data.template_args);
+            let source = data.source.take();
+            *x = HType::Struct(HTypeData {
+                fqnames,
+                data: Struct::zst_with_templates(template_args),
+                source,
+            });
+        });
+        return Ok(true);
+    }
+    Ok(false)
+}
 
-                let mut m = 2;
-                if member1_is_basety && stage.sizes.get_tree(&member1.ty)? == data.byte_size {
-                    m = 0;
-                } else if member2_is_basety && stage.sizes.get_tree(&member2.ty)? == data.byte_size
-                {
-                    m = 1;
-                }
-                if m == 2 {
-                    continue;
-                }
-                // eliminate to m
-                let member = &data.members[m];
-                if !util::check_eliminate(stage, k, &member.ty, ctx)? {
-                    continue;
-                }
-                cu::trace!("removing dual-member union {k} as member {m}");
-                // must clone so we can re-borrow stage as mutable
-                let member = member.clone();
-                util::eliminate_unchecked_and_give_names_to_base(stage, k, &member.ty)?;
-                return Ok(true);
+/// Eliminate unions with only one member, since it's equivalent to that member
+pub fn single_member(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+    for (k, t) in &stage.types {
+        let HType::Union(HTypeData { data, .. }) = t else {
+            continue;
+        };
+        if data.members.len() != 1 {
+            continue;
+        }
+        let k = *k;
+        let member = &data.members[0];
+        if !util::check_eliminate(stage, k, &member.ty, ctx)? {
+            continue;
+        }
+        cu::trace!("removing single-member union {k}");
+        // must clone so we can re-borrow stage as mutable
+        let member = member.clone();
+        util::eliminate_unchecked_and_give_names_to_base(stage, k, &member.ty)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// For unions with 2 members, eliminate the union to the member using the following heuristic:
+/// - if one member is a base type and the other is a composite type
+/// - if the base type has the same size as the union
+///
+/// then, eliminate this union to be the base type
+pub fn two_members_by_size(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+    for (k, t) in &stage.types {
+        let HType::Union(HTypeData { data, .. }) = t else {
+            continue;
+        };
+        if data.members.len() != 2 {
+            continue;
+        }
+        let k = *k;
+        let member1 = &data.members[0];
+        let member2 = &data.members[1];
+        let member1_is_basety = matches!(member1.ty, Tree::Base(k) if !k.is_prim());
+        let member2_is_basety = matches!(member2.ty, Tree::Base(k) if !k.is_prim());
+        if member1_is_basety == member2_is_basety {
+            continue;
+        }
+
+        let m = if member1_is_basety {
+            if stage.sizes.get_tree(&member1.ty)? != data.byte_size {
+                continue;
+            }
+            0
+        } else {
+            if stage.sizes.get_tree(&member2.ty)? != data.byte_size {
+                continue;
             }
-            _ => {}
+            1
+        };
+        // eliminate to m
+        let member = &data.members[m];
+        if !util::check_eliminate(stage, k, &member.ty, ctx)? {
+            continue;
         }
+        cu::trace!("removing dual-member union {k} as member {m}");
+        // must clone so we can re-borrow stage as mutable
+        let member = member.clone();
+        util::eliminate_unchecked_and_give_names_to_base(stage, k, &member.ty)?;
+        return Ok(true);
     }
     Ok(false)
 }
@@ -118,6 +141,107 @@ pub fn same_type_members(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Resul
     Ok(false)
 }
 
+/// For unions with 2 members of composite types that both have the same size as the union,
+/// pick the member by how the union is used: in the symbols and the other types that
+/// reference the union, if only one of the member types is also referenced
+pub fn two_members_by_usage(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
+    for (k, t) in &stage.types {
+        let HType::Union(HTypeData { data, .. }) = t else {
+            continue;
+        };
+        if data.members.len() != 2 {
+            continue;
+        }
+        let k = *k;
+        let (Tree::Base(goff1), Tree::Base(goff2)) = (&data.members[0].ty, &data.members[1].ty)
+        else {
+            continue;
+        };
+        let (goff1, goff2) = (*goff1, *goff2);
+        if goff1.is_prim() || goff2.is_prim() || goff1 == goff2 {
+            continue;
+        }
+        let (Some(size1), Some(size2)) = (
+            stage.sizes.get_optional(goff1),
+            stage.sizes.get_optional(goff2),
+        ) else {
+            continue;
+        };
+        if size1 != data.byte_size || size2 != data.byte_size {
+            continue;
+        }
+        let (symbol_uses1, symbol_uses2) = count_symbol_uses(stage, k, goff1, goff2);
+        let (type_uses1, type_uses2) = count_type_uses(stage, k, goff1, goff2);
+        let Some(m) = pick_by_usage((symbol_uses1, symbol_uses2), (type_uses1, type_uses2)) else {
+            continue;
+        };
+        let member = &data.members[m];
+        if !util::check_eliminate(stage, k, &member.ty, ctx)? {
+            continue;
+        }
+        let decision = format!(
+            "picked member {m} ({}) by usage: member 0 used with the union in {symbol_uses1} symbols and {type_uses1} types, member 1 used with the union in {symbol_uses2} symbols and {type_uses2} types",
+            member
+                .name
+                .as_ref()
+                .map(|s| s.as_ref())
+                .unwrap_or("[anonymous]"),
+        );
+        cu::debug!("union {k}: {decision}");
+        // must clone so we can re-borrow stage as mutable
+        let member = member.clone();
+        let t = t.clone();
+        stage.audit_log.record(k, &t, decision);
+        util::eliminate_unchecked_and_give_names_to_base(stage, k, &member.ty)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Pick the member to keep from the number of symbols and types (other than
+/// the union) that use each member type along with the union. None if it cannot be decided
+fn pick_by_usage(symbol_uses: (usize, usize), type_uses: (usize, usize)) -> Option<usize> {
+    match (symbol_uses, type_uses) {
+        // neither member is used
+        ((0, 0), (0, 0)) => None,
+        // not used in symbols, but only one is used in other types
+        ((0, 0), (_, 0)) => Some(0),
+        ((0, 0), (0, _)) => Some(1),
+        // only one is used in symbols, and the other is not used anywhere
+        ((_, 0), (_, 0)) => Some(0),
+        ((0, _), (0, _)) => Some(1),
+        // both members are used, cannot decide
+        _ => None,
+    }
+}
+
+/// Count the number of symbols that reference the union, and also reference each member type
+fn count_symbol_uses(stage: &HStage, union_k: Goff, goff1: Goff, goff2: Goff) -> (usize, usize) {
+    let mut uses = (0, 0);
+    for si in stage.symbols.values() {
+        if !si.contains_goff(union_k) {
+            continue;
+        }
+        uses.0 += si.contains_goff(goff1) as usize;
+        uses.1 += si.contains_goff(goff2) as usize;
+    }
+    uses
+}
+
+/// Count the number of types (other than the union) that have fields referencing
+/// the union, and also reference each member type
+fn count_type_uses(stage: &HStage, union_k: Goff, goff1: Goff, goff2: Goff) -> (usize, usize) {
+    let mut uses = (0, 0);
+    for (k, t) in &stage.types {
+        if *k == union_k || !t.contains_goff(union_k) {
+            continue;
+        }
+        uses.0 += t.contains_goff(goff1) as usize;
+        uses.1 += t.contains_goff(goff2) as usize;
+    }
+    uses
+}
+
 /// Pick union member based on config
 pub fn pick_member(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
     let rules = &stage.config.extract.type_optimizer.pick_union_member;
@@ -198,3 +322,30 @@ pub fn pick_member(stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool
 
     Ok(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_by_usage_unused() {
+        assert_eq!(pick_by_usage((0, 0), (0, 0)), None);
+    }
+
+    #[test]
+    fn test_pick_by_usage_types_only() {
+        assert_eq!(pick_by_usage((0, 0), (3, 0)), Some(0));
+        assert_eq!(pick_by_usage((0, 0), (0, 1)), Some(1));
+        assert_eq!(pick_by_usage((0, 0), (1, 1)), None);
+    }
+
+    #[test]
+    fn test_pick_by_usage_symbols() {
+        assert_eq!(pick_by_usage((2, 0), (0, 0)), Some(0));
+        assert_eq!(pick_by_usage((2, 0), (4, 0)), Some(0));
+        assert_eq!(pick_by_usage((0, 1), (0, 2)), Some(1));
+        // the other member is used in types
+        assert_eq!(pick_by_usage((2, 0), (0, 1)), None);
+        assert_eq!(pick_by_usage((1, 1), (0, 0)), None);
+    }
+}
@@ -1,6 +1,8 @@
-use std::sync::Arc;
+use cu::pre::*;
+use exstructs::GoffSet;
+use exstructs::algorithm::FullQualPermutater;
 
-use crate::hstage::optimize::{OPTIMIZERS, OptimizeContext};
+use crate::hstage::optimize::{OPTIMIZERS, OptimizeContext, alias, util};
 use crate::stages::HStage;
 
 /// Optimize (simplify) type layouts
@@ -13,35 +15,81 @@ pub fn run(mut stage: HStage) -> cu::Result<HStage> {
 
     let mut changed = true;
 
-    // the context only needs to be created once.
-    // as optimization happens, some marked data will no longer be relevant,
-    // but it's ok
     let mut ctx = OptimizeContext::default();
-    for (k, t) in &stage.types {
-        t.mark_non_eliminateable(*k, &mut ctx.non_eliminateable);
-    }
-    for si in stage.symbols.values() {
-        si.mark_non_eliminateable(&mut ctx.non_eliminateable);
-    }
+    ctx.mark(&stage);
+    ctx.excluded = cu::check!(
+        find_excluded(&stage),
+        "failed to match type-optimizer.exclude-types"
+    )?;
 
+    let passes = stage.config.extract.type_optimizer.passes;
     let mut next = 0;
     'outer: while changed {
         changed = false;
         for (i, optimizer) in OPTIMIZERS.iter().enumerate() {
+            if !optimizer.is_enabled(&passes) {
+                continue;
+            }
             if i >= next {
                 next = i + 1;
                 cu::info!("running optimizer: {}", optimizer.name);
                 cu::progress!(bar = next, "{}", optimizer.name);
             }
+            stage.audit_log.set_pass(optimizer.name);
             if optimizer.run(&mut stage, &ctx)? {
                 // after one optimization is made, re-start from the beginning
                 // all optimizations
                 changed = true;
+                ctx.mark(&stage);
                 continue 'outer;
             }
         }
     }
     bar.done();
 
+    if stage.config.extract.type_optimizer.alias_identical_layouts {
+        cu::info!("running optimizer: alias_identical_layouts");
+        stage.audit_log.set_pass("alias_identical_layouts");
+        cu::check!(
+            alias::run(&mut stage, &ctx),
+            "failed to alias types with identical layouts"
+        )?;
+    }
+
+    let audit_path = stage
+        .config
+        .paths
+        .extract_output
+        .join("optimizer_audit.jsonl");
+    match stage.audit_log.save(&audit_path) {
+        Ok(()) => cu::info!(
+            "type optimizer made {} changes, audit log saved to {}",
+            stage.audit_log.entries().len(),
+            audit_path.try_to_rel().display()
+        ),
+        Err(e) => cu::warn!("failed to save type optimizer audit log: {e:?}"),
+    }
+
     Ok(stage)
 }
+
+/// Find types excluded from optimization by config
+fn find_excluded(stage: &HStage) -> cu::Result<GoffSet> {
+    let rules = &stage.config.extract.type_optimizer.exclude_types;
+    let mut excluded = GoffSet::default();
+    if rules.is_empty() {
+        // save the cost of computing permutated names
+        return Ok(excluded);
+    }
+    let fullqual_names = util::compute_fqnames(stage)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    for regex in rules {
+        let goff_iter = stage.types.keys().filter(|k| !k.is_prim());
+        let matched = util::match_fqname(&mut permutater, regex, goff_iter)?;
+        for (k, name) in matched {
+            cu::debug!("excluding type {k} ({name}) from optimization");
+            excluded.insert(k);
+        }
+    }
+    Ok(excluded)
+}
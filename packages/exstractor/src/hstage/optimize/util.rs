@@ -1,11 +1,11 @@
 use cu::pre::*;
-use exstructs::algorithm::FullQualPermutater;
-use exstructs::{
-    FullQualName, FullQualNameMap, Goff, GoffMap, GoffSet, NamespacedName, NamespacedTemplatedName,
-};
+use dejj_utils::ExtractTypeOptimizerPasses;
+use exstructs::algorithm::{self, FullQualPermutater};
+use exstructs::{FullQualName, FullQualNameMap, Goff, GoffSet, HType};
 use regex::Regex;
 use tyyaml::Tree;
 
+use crate::hstage::optimize::audit;
 use crate::stages::HStage;
 
 /// Optimizatation function type
@@ -22,17 +22,34 @@ pub struct Optimizer {
     pub name: &'static str,
     /// Optimize function type
     pub f: fn(&mut HStage, &OptimizeContext) -> cu::Result<bool /* changed */>,
+    /// Flag in `type-optimizer.passes` that turns the optimizer on or off.
+    /// None if the optimizer always runs
+    pub flag: Option<fn(&ExtractTypeOptimizerPasses) -> bool>,
 }
 impl Optimizer {
     pub fn run(self, stage: &mut HStage, ctx: &OptimizeContext) -> cu::Result<bool> {
         (self.f)(stage, ctx)
     }
+    pub fn is_enabled(self, passes: &ExtractTypeOptimizerPasses) -> bool {
+        match self.flag {
+            None => true,
+            Some(flag) => flag(passes),
+        }
+    }
 }
 macro_rules! make_optimizer {
     ($fn:expr) => {
         $crate::hstage::optimize::Optimizer {
             name: stringify!($fn),
             f: $fn,
+            flag: None,
+        }
+    };
+    ($fn:expr, $flag:ident) => {
+        $crate::hstage::optimize::Optimizer {
+            name: stringify!($fn),
+            f: $fn,
+            flag: Some(|passes| passes.$flag),
         }
     };
 }
@@ -42,6 +59,36 @@ pub(crate) use make_optimizer;
 pub struct OptimizeContext {
     /// Type goffs that cannot be replaced with a tree
     pub non_eliminateable: GoffSet,
+    /// Type goffs that are excluded from optimization by config
+    pub excluded: GoffSet,
+    /// Type goffs used as the base of pointer-to-member types. They can only be
+    /// replaced by another struct or union
+    pub ptm_bases: GoffSet,
+}
+
+impl OptimizeContext {
+    /// Mark the types that cannot be eliminated in the current stage. This needs
+    /// to be done again after each change, since eliminating a PTM base
+    /// into another type makes that type a PTM base
+    pub fn mark(&mut self, stage: &HStage) {
+        self.non_eliminateable.clear();
+        self.ptm_bases.clear();
+        for (k, t) in &stage.types {
+            t.mark_non_eliminateable(*k, &mut self.non_eliminateable);
+            t.mark_ptm_bases(&mut self.ptm_bases);
+        }
+        for si in stage.symbols.values() {
+            // only PTM bases are marked for symbols
+            si.mark_non_eliminateable(&mut self.ptm_bases);
+        }
+        for tree in stage.typedefs.values() {
+            tree.for_each_ptm_base(|x| {
+                self.ptm_bases.insert(*x);
+            });
+        }
+        self.non_eliminateable
+            .extend(self.ptm_bases.iter().copied());
+    }
 }
 
 #[cu::context("failed to eliminate and merge with base (type={elim_k}, replace={replace:#?})")]
@@ -56,6 +103,14 @@ pub fn eliminate_unchecked_and_give_names_to_base(
         stage.types.remove(&elim_k),
         "unexpected: type {elim_k} was already removed"
     )?;
+    let after = match replace {
+        Tree::Base(k) => match stage.types.get(k) {
+            Some(base_t) => format!("eliminated into {}", audit::summarize(base_t)),
+            None => format!("eliminated into {k}"),
+        },
+        _ => format!("eliminated into {replace}"),
+    };
+    stage.audit_log.record(elim_k, &t, after);
     // give the names to inner type
     if let Tree::Base(member_goff) = &replace {
         let mut fqnames = t.into_fqnames()?;
@@ -76,6 +131,24 @@ pub fn check_eliminate(
     replace: &Tree<Goff>,
     ctx: &OptimizeContext,
 ) -> cu::Result<bool> {
+    if ctx.excluded.contains(&elim_k) {
+        return Ok(false);
+    }
+    if let Tree::Base(base_k) = replace {
+        // the base type will be given the names of the eliminated type
+        if ctx.excluded.contains(base_k) {
+            return Ok(false);
+        }
+        // PTM base must stay a struct/union
+        if ctx.ptm_bases.contains(&elim_k)
+            && !matches!(
+                stage.types.get(base_k),
+                Some(HType::Struct(_) | HType::Union(_))
+            )
+        {
+            return Ok(false);
+        }
+    }
     if !matches!(replace, Tree::Base(_)) {
         // replacing with a composite type
         if ctx.non_eliminateable.contains(&elim_k) {
@@ -103,6 +176,9 @@ pub fn check_eliminate(
             return Ok(true);
         }
     }
+    if stage.typedefs.values().any(|tree| tree.contains(&elim_k)) {
+        return Ok(true);
+    }
     Ok(false)
 }
 
@@ -132,6 +208,12 @@ pub fn eliminate_unchecked(
             "failed to replace type in symbol"
         )?;
     }
+    for (name, tree) in &mut stage.typedefs {
+        cu::check!(
+            algorithm::tree_replace(tree, elim_k, replace),
+            "failed to replace type in typedef '{name}'"
+        )?;
+    }
     Ok(())
 }
 
@@ -167,20 +249,7 @@ pub fn give_names_to_base(
 
 /// Compute fqnames for name-based type optimizer rules
 pub fn compute_fqnames(stage: &HStage) -> cu::Result<FullQualNameMap> {
-    let mut fullqual_names = GoffMap::default();
-    for (k, t) in &stage.types {
-        if let Some(prim) = k.to_prim() {
-            fullqual_names.insert(
-                *k,
-                vec![FullQualName::Name(NamespacedTemplatedName::new(
-                    NamespacedName::prim(prim),
-                ))],
-            );
-            continue;
-        };
-        fullqual_names.insert(*k, t.fqnames()?.to_vec());
-    }
-    Ok(fullqual_names.into())
+    FullQualNameMap::from_htypes(&stage.types)
 }
 
 pub fn match_unique_fqname<'a>(
@@ -208,9 +277,9 @@ pub fn match_fqname<'a>(
         let k = *k;
         let fullqual_names = permutater.permutated_fullqual_names(k)?;
 
-        for name in fullqual_names {
-            if regex.is_match(&name) {
-                matched.push((k, name));
+        for name in fullqual_names.iter() {
+            if regex.is_match(name) {
+                matched.push((k, name.clone()));
             }
         }
     }
@@ -1,343 +0,0 @@
-use std::collections::{BTreeMap, BTreeSet};
-
-use cu::pre::*;
-use exstructs::{
-    FullQualName, HType, HTypeData, algorithm,
-    Goff, GoffBuckets, GoffMap, GoffPair, GoffSet, MType, MTypeData, Struct
-};
-use tyyaml::Tree;
-
-use crate::stages::HStage;
-
-/// Optimize (simplify) type layouts
-pub fn run(stage: &mut HStage) -> cu::Result<()> {
-    let connected_components = algorithm::calc_connected_components(&stage.types)?;
-    cu::info!("there are {} connected components to optimize in the type graph", connected_components.len());
-
-
-    // let mut changed = true;
-    // let bar = cu::progress("stage2 -> stage3: optimizing layouts").spawn();
-    // let mut pass = 1;
-    // while changed {
-    //     cu::progress!(bar, "pass {pass}");
-    //     changed = false;
-    //     for optimize_fn in OPTIMIZERS {
-    //         let mut ctx = OptimizeContext::default();
-    //         for (k, t) in &stage.types {
-    //             t.mark_non_eliminateable(*k, &mut ctx.non_eliminateable);
-    //         }
-    //         for si in stage.symbols.values() {
-    //             si.mark_non_eliminateable(&mut ctx.non_eliminateable);
-    //         }
-    //         let output = optimize_fn(stage, &ctx)?;
-    //         if output.apply(stage)? {
-    //             changed = true;
-    //             break; // restart optimization passes if something was optimized
-    //         }
-    //     }
-    //     if changed {
-    //         let deduped = algorithm::dedupe(
-    //             std::mem::take(&mut stage.types),
-    //             GoffBuckets::default(),
-    //             &mut stage.symbols,
-    //             None,
-    //             |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
-    //         );
-    //         let deduped = cu::check!(deduped, "optimize_layout: dedupe failed")?;
-    //         stage.types = deduped;
-    //     }
-    //     pass += 1;
-    // }
-    // bar.done();
-    Ok(())
-}
-
-//////////////////////// TODO:
-// HStage needs to be mutable, just make the optimizer do one optimization at a time
-// and re-evaluate everything - this will be slow, but otherwise the dependency will be weird
-
-type ChangeFn = Box<dyn FnOnce(HType) -> cu::Result<HType>>;
-
-#[derive(Default)]
-struct OptimizeOutput {
-    /// If any changes have been applied
-    changed: bool, // /// Always rerun this optimization even if changed = false
-                   // force_rerun: bool,
-                   // /// Change one type to another data. This is applied first
-                   // changes: GoffMap<Vec<ChangeFn>>,
-                   // /// Eliminate the type by substituting all occurrence with a type tree.
-                   // /// This is applied first.
-                   // eliminations: Eliminations,
-                   // // /// Merge 2 types - the merge rules apply. This is applied last
-                   // // merges: Vec<GoffPair>,
-                   // /// Add `first` is a derived name of `second`
-                   // add_is_derived_names: Vec<(FullQualName, FullQualName)>,
-}
-impl OptimizeOutput {
-    // fn change(&mut self, k: Goff, change: HType) {
-    //     self.change_fn(k, move |_| Ok(change))
-    // }
-    // fn change_fn(&mut self, k: Goff, change_fn: impl FnOnce(HType) -> cu::Result<HType> + 'static) {
-    //     self.changes.entry(k).or_default().push(Box::new(change_fn))
-    // }
-    // fn will_change(&self, k: Goff) -> bool {
-    //     let Some(changes) = self.changes.get(&k) else {
-    //         return false;
-    //     };
-    //     !changes.is_empty()
-    // }
-    // /// Apply the optimizations, return true if anything changed
-    // fn apply(self, stage: &mut HStage) -> cu::Result<bool> {
-    //     cu::debug!(
-    //         "applying optimizations change={}, eliminations={}",
-    //         self.changes.len(),
-    //         self.eliminations.data.len()
-    //     );
-    //     // change the types
-    //     let mut changed = false;
-    //     for (k, changes) in self.changes {
-    //         let old = cu::check!(
-    //             stage.types.get_mut(&k),
-    //             "unlinked type {k} when trying to change"
-    //         )?;
-    //         let mut temp = old.clone();
-    //         for change_fn in changes {
-    //             temp = cu::check!(
-    //                 change_fn(temp),
-    //                 "error running change_fn during optimization"
-    //             )?;
-    //         }
-    //         if old != &temp {
-    //             changed = true;
-    //             *old = temp;
-    //         }
-    //     }
-    //     if !self.eliminations.data.is_empty() {
-    //         let bar = cu::progress("applying eliminations")
-    //             .total(self.eliminations.data.len())
-    //             .keep(false)
-    //             .spawn();
-    //
-    //         for (k, replacement) in &self.eliminations.data {
-    //             if replacement.contains(&k) {
-    //                 cu::bail!("the replacement recursively contains the type to replace");
-    //             }
-    //             for (j, t) in &mut stage.types {
-    //                 if j == k {
-    //                     continue;
-    //                 }
-    //                 changed |= cu::check!(
-    //                     t.replace(*k, replacement),
-    //                     "failed to replace {k} with {replacement:#?} (in type {j})"
-    //                 )?;
-    //             }
-    //             for si in stage.symbols.values_mut() {
-    //                 changed |= cu::check!(
-    //                     si.replace(*k, replacement),
-    //                     "failed to replace type in symbol"
-    //                 )?;
-    //             }
-    //             cu::progress!(bar += 1);
-    //         }
-    //     }
-    //     // remove types that are eliminated
-    //     for k in self.eliminations.data.keys() {
-    //         stage.types.remove(k);
-    //     }
-    //     // edit name graph
-    //     for (derived, base) in self.add_is_derived_names {
-    //         changed |= stage.name_graph.add_derived(&derived, &base)?;
-    //     }
-    //     Ok(changed || self.force_rerun)
-    // }
-}
-
-#[derive(Debug, Default)]
-struct Eliminations {
-    data: GoffMap<Tree<Goff>>,
-}
-
-impl Eliminations {
-    fn insert(&mut self, k: Goff, tree: Tree<Goff>, ctx: &OptimizeContext) -> cu::Result<()> {
-        if !matches!(tree, Tree::Base(_)) {
-            // replacing with a composite type
-            if ctx.non_eliminateable.contains(&k) {
-                // not eliminatable, ignore
-                return Ok(());
-            }
-        }
-        use std::collections::btree_map::Entry;
-        match self.data.entry(k) {
-            Entry::Vacant(e) => {
-                e.insert(tree);
-            }
-            Entry::Occupied(e) => {
-                if e.get() != &tree {
-                    cu::bail!(
-                        "conflicting elimination for {k}: new={tree:#?}, old={:#?}",
-                        e.get()
-                    );
-                }
-            }
-        }
-        Ok(())
-    }
-}
-
-// /// collapse the union if all members are the same type
-// fn optimize_single_type_union(stage: &MStage, ctx: &OptimizeContext) -> cu::Result<OptimizeOutput> {
-//     let mut output = OptimizeOutput::default();
-//     for (k, t) in &stage.types {
-//         let Type1::Union(_, data, _) = t else {
-//             continue;
-//         };
-//         let mut the_type = None;
-//         for member in &data.members {
-//             match &the_type {
-//                 None => {
-//                     the_type = Some(member.ty.clone());
-//                 }
-//                 Some(old) => {
-//                     if old != &member.ty {
-//                         continue;
-//                     }
-//                 }
-//             }
-//         }
-//         let Some(only_type) = the_type else {
-//             continue;
-//         };
-//         if !helper::can_eliminate(*k, t, &only_type, ctx) {
-//             continue;
-//         }
-//         output.eliminations.insert(*k, only_type)?;
-//     }
-//     Ok(output)
-// }
-
-// /// If a union has 2 members of the same size, pick one
-// // #[distributed_slice(OPTIMIZERS)]
-// fn optimize_two_members_union(compiler: &mut TypeCompilerUnit, _: bool) -> cu::Result<bool> {
-//     let mut changed = false;
-//     let mut to_check = vec![];
-//     for bucket in compiler.compiled.buckets() {
-//         let unit = &bucket.value;
-//         let Some(TypeUnitData::Union(data)) = &unit.data else {
-//             continue;
-//         };
-//         if data.members.len() != 2 {
-//             continue;
-//         }
-//         let bucket_key = bucket.canonical_key();
-//         let member_type = data.members[0].1;
-//         let member_anon = data.members[0].0.is_none();
-//         let member_type2 = data.members[1].1;
-//         let member_anon2 = data.members[1].0.is_none();
-//         to_check.push((bucket_key, member_type, member_type2, member_anon, member_anon2));
-//     }
-//
-//     for (bucket_key, member_type, member_type2, member_anon, member_anon2) in to_check {
-//         let member_size = compiler.resolve_size(member_type)?;
-//         let member_size2 = compiler.resolve_size(member_type2)?;
-//         let mut pick_member = 0;
-//         if member_size != member_size2 {
-//             // pick the larger member
-//             if member_size > member_size2 {
-//                 pick_member = 1;
-//             } else {
-//                 pick_member = 2;
-//             }
-//         }
-//         // pick the anonymous member
-//         if pick_member == 0 {
-//             if member_anon {
-//                 if !member_anon2 {
-//                     pick_member = 1;
-//                 }
-//             } else if member_anon2 {
-//                 pick_member = 2
-//             }
-//         }
-//         // pick the "more complex" member
-//         if pick_member == 0 {
-//             let complexity1 = compiler.resolve_complexity(member_type)?;
-//             let complexity2 = compiler.resolve_complexity(member_type2)?;
-//             if complexity1 > complexity2 {
-//                 pick_member = 1;
-//             } else if complexity1 < complexity2 {
-//                 pick_member = 2;
-//             } else {
-//                 cu::debug!("same complexity: {member_type} and {member_type2}: {complexity1}")
-//             }
-//         }
-//         if pick_member == 0 {
-//             continue;
-//         }
-//         if pick_member == 1 {
-//             if member_type != bucket_key {
-//                 let member_type_unit = compiler.compiled.get_unwrap(member_type)?;
-//                 if let Some(member_data) = &member_type_unit.value.data {
-//                     if !member_data.is_recursive_to(bucket_key) {
-//                         compiler.merges.push((bucket_key, member_type));
-//                         changed = true;
-//                     }
-//                 }
-//             }
-//             continue;
-//         }
-//         if member_type2 != bucket_key {
-//             let member_type_unit = compiler.compiled.get_unwrap(member_type2)?;
-//             if let Some(member_data) = &member_type_unit.value.data {
-//                 if !member_data.is_recursive_to(bucket_key) {
-//                     compiler.merges.push((bucket_key, member_type2));
-//                     changed = true;
-//                 }
-//             }
-//         }
-//     }
-//     Ok(changed)
-// }
-
-//
-// /// Inline the base struct members, if the derived class only has one field
-// /// that is the base class
-// // #[distributed_slice(OPTIMIZERS)]
-// fn optimize_single_base_member_struct(compiler: &mut TypeCompilerUnit, is_linking: bool) -> cu::Result<bool> {
-//     // if !is_linking {
-//     //     return Ok(false);
-//     // }
-//     let mut changed = false;
-//     for bucket in compiler.compiled.buckets() {
-//         let unit = &bucket.value;
-//         let Some(TypeUnitData::Struct(data)) = &unit.data else {
-//             continue;
-//         };
-//         if data.members.len() != 1 {
-//             continue;
-//         }
-//         let member = &data.members[0];
-//         if !member.is_base() {
-//             continue;
-//         }
-//         let bucket_key = bucket.canonical_key();
-//         let base_type = data.members[0].ty;
-//         let base_type_unit = compiler.compiled.get_unwrap(base_type)?;
-//         let Some(base_data) = &base_type_unit.value.data else {
-//             continue;
-//         };
-//         let members = if let TypeUnitData::Struct(base_data) = base_data {
-//             cu::ensure!(
-//                 base_data.byte_size == data.byte_size,
-//                 "unexpected single base member struct having different size than its base member"
-//             );
-//             base_data.members.clone()
-//         } else {
-//             continue;
-//         };
-//         let mut new_data = data.clone();
-//         new_data.members = members;
-//         compiler.changes.insert(bucket_key, TypeUnitData::Struct(new_data));
-//         changed = true;
-//     }
-//     Ok(changed)
-// }
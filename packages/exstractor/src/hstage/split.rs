@@ -32,17 +32,28 @@ pub fn run(mut stage: HStage) -> cu::Result<Vec<HStage>> {
             )?;
             split_symbols.insert(sym, info);
         }
+        // typedefs go with the component of the types they reference
+        let (split_typedefs, typedefs) = std::mem::take(&mut stage.typedefs)
+            .into_iter()
+            .partition(|(_, tree)| comp.types.iter().any(|k| tree.contains(k)));
+        stage.typedefs = typedefs;
         let split_stage = HStage {
             types: split_types,
             config: Arc::clone(&stage.config),
             symbols: split_symbols,
+            typedefs: split_typedefs,
             sizes: Arc::clone(&stage.sizes),
             name_graph: stage.name_graph.clone(),
+            audit_log: Default::default(),
         };
         split_stages.push(split_stage);
     }
     // there should be no symbols left
     cu::ensure!(stage.symbols.is_empty(), "{:?}", stage.symbols)?;
+    // typedefs left only reference primitives
+    if let Some(split_stage) = split_stages.first_mut() {
+        split_stage.typedefs.append(&mut stage.typedefs);
+    }
 
     // the remaining must be primitives
     for k in stage.types.keys() {
@@ -0,0 +1,181 @@
+use cu::pre::*;
+use exstructs::{Goff, GoffMap, HType, Member, SizeMap, SpecialMember, Struct};
+use tyyaml::Tree;
+
+use crate::emit;
+use crate::stages::{self, HStage};
+
+/// Layout of a struct that does not exactly fill its declared size,
+/// from recomputing the layout with the offsets and sizes of the members
+#[derive(Debug, Serialize)]
+pub struct LayoutIssue {
+    pub name: String,
+    pub goff: Goff,
+    /// Declared size of the struct
+    pub size: u32,
+    /// (offset, size) of the gaps between members
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub holes: Vec<(u32, u32)>,
+    /// Size of the gap between the end of the last member and the end of the struct
+    #[serde(skip_serializing_if = "is_zero")]
+    pub tail_padding: u32,
+    /// Descriptions of members that overlap the previous member
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub overlaps: Vec<String>,
+    /// End of the last member, if it is past the declared size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overflow: Option<u32>,
+}
+
+fn is_zero(x: &u32) -> bool {
+    *x == 0
+}
+
+impl LayoutIssue {
+    /// Overlaps and overflows are not possible in valid layouts,
+    /// while holes and padding are usually from alignment
+    pub fn is_error(&self) -> bool {
+        !self.overlaps.is_empty() || self.overflow.is_some()
+    }
+}
+
+/// Verify the layouts of the final structs, and save the structs with holes, overlapping
+/// members, tail padding or members past the declared size to `layout_report.json`
+pub fn verify_layouts(stage: &HStage) -> cu::Result<Vec<LayoutIssue>> {
+    let names = emit::type_names(&stage.types, emit::name_policy(&stage.config))?;
+    // the cached sizes could be stale after optimizing
+    let sizes = stages::size_map(&stage.types, &stage.config)?;
+    let mut issues = vec![];
+    for (k, t) in &stage.types {
+        let HType::Struct(data) = t else {
+            continue;
+        };
+        let name = names
+            .get(k)
+            .cloned()
+            .unwrap_or_else(|| exstructs::anonymous_type_name(*k));
+        if let Some(issue) = verify_struct(&stage.types, &sizes, *k, name, &data.data) {
+            issues.push(issue);
+        }
+    }
+
+    let path = stage.config.paths.extract_output.join("layout_report.json");
+    if issues.is_empty() {
+        // remove stale report from previous runs
+        cu::fs::remove(&path)?;
+        return Ok(issues);
+    }
+    cu::fs::write_json_pretty(&path, &issues)?;
+    cu::hint!(
+        "layouts of {} structs do not fill the declared size, report saved to {}",
+        issues.len(),
+        path.try_to_rel().display()
+    );
+    Ok(issues)
+}
+
+fn verify_struct(
+    types: &GoffMap<HType>,
+    sizes: &SizeMap,
+    k: Goff,
+    name: String,
+    data: &Struct,
+) -> Option<LayoutIssue> {
+    let mut issue = LayoutIssue {
+        name,
+        goff: k,
+        size: data.byte_size,
+        holes: vec![],
+        tail_padding: 0,
+        overlaps: vec![],
+        overflow: None,
+    };
+    let mut end = 0;
+    for (i, m) in data.members.iter().enumerate() {
+        // unsized members (i.e. flexible arrays) take no space
+        let mut size = member_size(types, sizes, m).unwrap_or_default();
+        if size == 0 {
+            continue;
+        }
+        if m.is_base() && has_virtual_bases(types, &m.ty, 0) {
+            // the base subobject does not contain the virtual bases of the base,
+            // so it could be smaller than the size of the base type
+            let next = data.members[i + 1..]
+                .iter()
+                .map(|x| x.offset)
+                .find(|x| *x > m.offset)
+                .unwrap_or(data.byte_size);
+            size = size.min(next.saturating_sub(m.offset));
+        }
+        if m.offset > end {
+            issue.holes.push((end, m.offset - end));
+        } else if m.offset < end {
+            let name = match &m.name {
+                Some(name) => name.to_string(),
+                None => "<unnamed>".to_string(),
+            };
+            issue.overlaps.push(format!(
+                "{name} at 0x{:x} overlaps the previous member ending at 0x{end:x}",
+                m.offset
+            ));
+        }
+        end = end.max(m.offset + size);
+    }
+    if end > data.byte_size {
+        issue.overflow = Some(end);
+    } else if end > 0 && !has_virtual_bases_in(types, data, 0) {
+        // empty structs have a size of 1, which is not padding,
+        // and the virtual bases are stored after the members
+        issue.tail_padding = data.byte_size - end;
+    }
+    let is_filled = issue.holes.is_empty() && issue.tail_padding == 0 && !issue.is_error();
+    if is_filled {
+        return None;
+    }
+    if issue.is_error() {
+        cu::debug!("bad layout of struct {} ({k}): {issue:?}", issue.name);
+    }
+    Some(issue)
+}
+
+/// If the type is a struct with virtual bases, directly or through its bases
+fn has_virtual_bases(types: &GoffMap<HType>, ty: &Tree<Goff>, depth: usize) -> bool {
+    let Tree::Base(k) = ty else {
+        return false;
+    };
+    match types.get(k) {
+        Some(HType::Struct(data)) => has_virtual_bases_in(types, &data.data, depth),
+        _ => false,
+    }
+}
+
+fn has_virtual_bases_in(types: &GoffMap<HType>, data: &Struct, depth: usize) -> bool {
+    if data.has_virtual_bases() {
+        return true;
+    }
+    // the depth limit is only to stop on bad data with self-inheritance
+    depth < 1000
+        && data
+            .bases
+            .iter()
+            .any(|b| has_virtual_bases(types, &b.ty, depth + 1))
+}
+
+/// Size the member takes in the layout
+fn member_size(types: &GoffMap<HType>, sizes: &SizeMap, m: &Member) -> Option<u32> {
+    match &m.special {
+        Some(SpecialMember::Bitfield(size) | SpecialMember::BitfieldGroup(size, _)) => Some(*size),
+        Some(SpecialMember::Base) => {
+            // empty bases take no space (empty base optimization)
+            if let Tree::Base(base) = &m.ty {
+                if let Some(HType::Struct(base)) = types.get(base) {
+                    if base.data.members.is_empty() {
+                        return Some(0);
+                    }
+                }
+            }
+            sizes.get_tree_optional(&m.ty)
+        }
+        _ => sizes.get_tree_optional(&m.ty),
+    }
+}
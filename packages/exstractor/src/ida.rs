@@ -0,0 +1,60 @@
+use std::fmt::Write as _;
+
+use cu::pre::*;
+
+use crate::c_header;
+use crate::database::Database;
+use crate::export::{ExportContext, Exporter};
+
+/// IDAPython script (`ida.py`) that parses the types as C declarations into
+/// the local type library, and names the symbols in the database.
+///
+/// Run it with `File > Script file`. See the `c_header` module for how
+/// the types are converted to C
+pub struct IdaExporter;
+impl Exporter for IdaExporter {
+    fn name(&self) -> &'static str {
+        "ida"
+    }
+    fn file_extension(&self) -> &'static str {
+        "py"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        let header = cu::check!(
+            c_header::generate(db, ctx, "Parsed by the IDAPython script"),
+            "failed to generate C header for IDA"
+        )?;
+        // the declarations are embedded in a raw string
+        cu::ensure!(
+            !header.contains("\"\"\""),
+            "generated C header cannot be embedded in the script"
+        )?;
+        cu::fs::write(&ctx.output_path, ida_script(db, &header))?;
+        cu::hint!(
+            "IDA script saved to {}",
+            ctx.output_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
+
+/// Script to run in IDA. Addresses are relative to the image base
+fn ida_script(db: &Database, header: &str) -> String {
+    let mut out = String::new();
+    out.push_str("# Import types and symbols extracted by dejj\n");
+    out.push_str("import idaapi\n");
+    out.push_str("import idc\n\n");
+    let _ = writeln!(out, "DECLS = r\"\"\"\n{header}\"\"\"\n");
+    out.push_str("SYMBOLS = [\n");
+    for symbol in db.symbols.values() {
+        let _ = writeln!(out, "    (0x{:x}, {:?}),", symbol.address, symbol.link_name);
+    }
+    out.push_str("]\n\n");
+    out.push_str("errors = idc.parse_decls(DECLS, idc.PT_SILENT)\n");
+    out.push_str("if errors:\n");
+    out.push_str("    print(\"dejj: failed to parse %d declarations\" % errors)\n");
+    out.push_str("base = idaapi.get_imagebase()\n");
+    out.push_str("for (offset, name) in SYMBOLS:\n");
+    out.push_str("    idc.set_name(base + offset, name, idc.SN_NOWARN | idc.SN_NOCHECK)\n");
+    out
+}
@@ -1,11 +1,55 @@
 pub mod dwarf;
 mod run;
-pub use run::run;
+pub use run::{ExtractOptions, run};
+mod pipeline;
+pub use pipeline::{Pipeline, PipelineOutput};
+mod error;
+pub use error::{ErrorKind, FailureReport, UnitFailure};
+mod summary;
+pub use summary::*;
+mod stats;
+pub use stats::*;
+mod lock;
+pub use lock::OutputLock;
+mod database;
+pub use database::{CallEdge, Database, LineTable};
+mod demangled_index;
+pub use demangled_index::{DemangledIndex, DemangledSymbol};
+mod hover;
+pub use hover::*;
+mod emit;
+pub use emit::*;
+mod export;
+pub use export::{ExportContext, ExportOptions, Exporter, ExporterRegistry, export};
+mod symbol_listing;
+pub use symbol_listing::{SymbolListing, SymbolListingEntry};
+mod shrink;
+pub use shrink::{ShrinkOptions, shrink};
+mod check;
+pub use check::check;
+mod clean;
+pub use clean::{CleanOptions, clean};
+mod diff;
+pub use diff::{DiffOptions, DiffReport, SymbolDiff, TypeDiff, diff};
+mod query;
+pub use query::{QueryOptions, query};
+mod merge_db;
+pub use merge_db::{MergeDbOptions, merge_db};
+mod repro;
+pub use repro::{ReproOptions, repro};
 
+mod c_header;
+mod compact_db;
+mod compdb;
+mod degrade;
 mod dwarf_loader;
+mod ghidra;
 mod hstage;
+mod ida;
 mod lstage;
 mod mstage;
 
 mod stage_cache;
-mod stages;
+pub mod stages;
+mod template;
+mod trace_type;
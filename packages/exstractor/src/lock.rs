@@ -0,0 +1,157 @@
+use std::collections::BTreeSet;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cu::pre::*;
+use dejj_utils::Config;
+
+/// Lock of the output directory, held for the whole run, so concurrent runs
+/// with the same output directory don't corrupt the caches.
+///
+/// The lock file is removed when the lock is dropped
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+/// Lock files held by this process. The pid in the lock file can't tell
+/// if the lock is still held by this process, or left by an earlier process
+/// that had the same pid
+static HELD: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Content of the lock file, to tell who is holding the lock
+#[derive(Debug, Serialize, Deserialize)]
+struct LockInfo {
+    pid: u32,
+    /// Host name of the machine running the process, since the output
+    /// directory could be shared between machines
+    #[serde(default)]
+    host: String,
+    /// Unix timestamp in seconds
+    started: u64,
+}
+
+impl OutputLock {
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("dejj.lock")
+    }
+
+    /// Lock the output directory. Stale lock files left by processes that no longer
+    /// exist are removed. With force_unlock, the existing lock file is always removed,
+    /// unless the lock is held by this process
+    pub fn acquire(config: &Config, force_unlock: bool) -> cu::Result<Self> {
+        cu::fs::make_dir(&config.paths.extract_output)?;
+        let path = Self::default_path(config);
+        let mut held = HELD.lock().unwrap_or_else(|e| e.into_inner());
+        if held.contains(&path) {
+            cu::bail!(
+                "output directory is already locked by this process ({})",
+                path.try_to_rel().display()
+            );
+        }
+        if force_unlock && path.exists() {
+            cu::warn!("force removing lock file {}", path.try_to_rel().display());
+            cu::fs::remove(&path)?;
+        }
+        // at most one retry after removing a stale lock
+        for _ in 0..2 {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path);
+            match file {
+                Ok(mut file) => {
+                    let info = LockInfo {
+                        pid: std::process::id(),
+                        host: host_name(),
+                        started: now_secs(),
+                    };
+                    let json = cu::check!(json::stringify(&info), "failed to serialize lock info")?;
+                    cu::check!(
+                        file.write_all(json.as_bytes()),
+                        "failed to write lock file {}",
+                        path.display()
+                    )?;
+                    cu::debug!("locked output directory with {}", path.display());
+                    held.insert(path.clone());
+                    return Ok(Self { path });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let Some(info) = read_lock_info(&path) else {
+                        // could be in the middle of being written by the other process
+                        cu::bail!(
+                            "output directory is locked by another run ({} exists), if no other run is active, rerun with --force-unlock",
+                            path.try_to_rel().display()
+                        );
+                    };
+                    if is_stale(&info) {
+                        cu::warn!(
+                            "removing stale lock file from process {} on host {:?}",
+                            info.pid,
+                            info.host
+                        );
+                        cu::fs::remove(&path)?;
+                        continue;
+                    }
+                    let elapsed = now_secs().saturating_sub(info.started);
+                    cu::bail!(
+                        "output directory is locked by another run (pid {}, started {elapsed}s ago), if no other run is active, rerun with --force-unlock",
+                        info.pid
+                    );
+                }
+                Err(e) => {
+                    cu::rethrow!(e, "failed to create lock file {}", path.display());
+                }
+            }
+        }
+        cu::bail!(
+            "failed to lock output directory, {} keeps being created by another run",
+            path.try_to_rel().display()
+        );
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        HELD.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.path);
+        if let Err(e) = cu::fs::remove(&self.path) {
+            cu::warn!("failed to remove lock file: {e:?}");
+        }
+    }
+}
+
+fn read_lock_info(path: &Path) -> Option<LockInfo> {
+    let content = std::fs::read_to_string(path).ok()?;
+    json::parse(&content).ok()
+}
+
+/// Check if the lock is left by a process on another host, or a process that
+/// no longer exists. The process is only checked where /proc is available,
+/// otherwise a lock from the same host is never considered stale
+fn is_stale(info: &LockInfo) -> bool {
+    let host = host_name();
+    if !info.host.is_empty() && !host.is_empty() && info.host != host {
+        return true;
+    }
+    let proc = Path::new("/proc");
+    proc.exists() && !proc.join(info.pid.to_string()).exists()
+}
+
+/// Host name of this machine, empty if unknown
+fn host_name() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_default();
+    name.trim().to_string()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
@@ -45,6 +45,7 @@ pub fn run(stage: &mut LStage) -> cu::Result<()> {
         new_map,
         buckets,
         &mut stage.symbols,
+        &mut stage.typedefs,
         Some(&mut stage.ns),
         |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
     );
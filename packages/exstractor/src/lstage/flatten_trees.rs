@@ -54,6 +54,7 @@ pub fn run(stage: &mut LStage) -> cu::Result<()> {
                         LType::Union(LTypeData {
                             name: data.name.clone(),
                             data: copy,
+                            source: data.source.clone(),
                         }),
                     ));
                 }
@@ -96,12 +97,23 @@ pub fn run(stage: &mut LStage) -> cu::Result<()> {
                         changed = true;
                     }
                 }
+                for base in &mut copy.bases {
+                    let flattened = cu::check!(
+                        flatten_by_tree(&base.ty, &stage.types, 0),
+                        "failed to flatten struct base class for {goff}"
+                    )?;
+                    if let Some(flattened) = flattened {
+                        base.ty = flattened;
+                        changed = true;
+                    }
+                }
                 if changed {
                     changes.push((
                         *goff,
                         LType::Struct(LTypeData {
                             name: data.name.clone(),
                             data: copy,
+                            source: data.source.clone(),
                         }),
                     ));
                 }
@@ -143,10 +155,24 @@ pub fn run(stage: &mut LStage) -> cu::Result<()> {
     }
     stage.symbols.extend(changes);
 
+    // flatten types in typedefs
+    let mut changes = vec![];
+    for (name, tree) in &stage.typedefs {
+        let flattened = cu::check!(
+            flatten_by_tree(tree, &stage.types, 0),
+            "failed to flatten type for typedef '{name}'"
+        )?;
+        if let Some(flattened) = flattened {
+            changes.push((name.clone(), flattened));
+        }
+    }
+    stage.typedefs.extend(changes);
+
     let deduped = algorithm::dedupe(
         std::mem::take(&mut stage.types),
         GoffBuckets::default(),
         &mut stage.symbols,
+        &mut stage.typedefs,
         Some(&mut stage.ns),
         |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
     );
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use exstructs::LType;
+
+use crate::stages::LStage;
+
+/// Hoist enumerators of anonymous enums into the constants table
+/// (fully-qualified name -> value), so the values survive even when
+/// the enum type itself is eliminated
+pub fn hoist_anonymous_enums(stage: &LStage, constants: &mut BTreeMap<String, i64>) {
+    for (k, t) in &stage.types {
+        let LType::Enum(data) = t else {
+            continue;
+        };
+        if data.name.is_some() {
+            continue;
+        }
+        let namespace = match stage.ns.qualifiers.get(k) {
+            None => String::new(),
+            Some(ns) => match ns.to_cpp_typedef_source() {
+                Ok(x) => x,
+                Err(_) => {
+                    // local enums in functions cannot be referenced from outside
+                    cu::trace!("skipping constants of function-local anonymous enum {k}");
+                    continue;
+                }
+            },
+        };
+        for e in &data.data.enumerators {
+            let name = if namespace.is_empty() {
+                e.name.to_string()
+            } else {
+                format!("{namespace}::{}", e.name)
+            };
+            insert_constant(constants, name, e.value, &stage.name);
+        }
+    }
+}
+
+/// Hoist object-like macros that are integer literals into the constants table.
+/// Reserved names (like `__GNUC__`) are skipped, since they are mostly predefined
+/// by the compiler
+pub fn hoist_macros(
+    macros: &BTreeMap<String, String>,
+    constants: &mut BTreeMap<String, i64>,
+    source: &str,
+) {
+    for (name, value) in macros {
+        if is_reserved_name(name) {
+            continue;
+        }
+        let Some(value) = parse_int_literal(value) else {
+            continue;
+        };
+        insert_constant(constants, name.clone(), value, source);
+    }
+}
+
+fn is_reserved_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some('_'), Some(c)) => c == '_' || c.is_ascii_uppercase(),
+        _ => false,
+    }
+}
+
+/// Parse a C++ integer literal, optionally negated and in parentheses,
+/// like `0x10`, `-1`, `(1024u)` or `1'000'000`
+fn parse_int_literal(value: &str) -> Option<i64> {
+    let mut value = value.trim();
+    while let Some(inner) = value.strip_prefix('(').and_then(|x| x.strip_suffix(')')) {
+        value = inner.trim();
+    }
+    if let Some(inner) = value.strip_prefix('-') {
+        return parse_int_literal(inner)?.checked_neg();
+    }
+    let value = value.trim_end_matches(['u', 'U', 'l', 'L', 'z', 'Z']);
+    let value = value.replace('\'', "");
+    let (digits, radix) = if let Some(x) = value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        (x, 16)
+    } else if let Some(x) = value.strip_prefix("0b").or(value.strip_prefix("0B")) {
+        (x, 2)
+    } else if value.len() > 1 && value.starts_with('0') {
+        (&value[1..], 8)
+    } else {
+        (value.as_str(), 10)
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    // unsigned values larger than i64::MAX are stored as if they are u64,
+    // same as enumerators
+    let value = u64::from_str_radix(digits, radix).ok()?;
+    Some(value as i64)
+}
+
+/// Merge constants hoisted from another compilation unit
+pub fn merge_constants(
+    constants: &mut BTreeMap<String, i64>,
+    other: BTreeMap<String, i64>,
+    source: &str,
+) {
+    for (name, value) in other {
+        insert_constant(constants, name, value, source);
+    }
+}
+
+fn insert_constant(constants: &mut BTreeMap<String, i64>, name: String, value: i64, source: &str) {
+    match constants.get(&name) {
+        Some(existing) if *existing != value => {
+            cu::warn!(
+                "constant {name} has conflicting values {existing} and {value} (in {source}), keeping the first one"
+            );
+        }
+        Some(_) => {}
+        None => {
+            constants.insert(name, value);
+        }
+    }
+}
@@ -1,19 +1,45 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
 use cu::pre::*;
+use dejj_utils::{Config, TypeParserBackend};
 use exstructs::algorithm;
 use exstructs::{Enum, GoffBuckets, GoffMap, GoffSet, LType, MType, MTypeData, MTypeDecl};
-use llvmutils::{CompileCommand, NameParser};
+use llvmutils::{ClangBatcher, CompileCommand, NameParser, NameParserBackend, TypeNameCache};
 
+use crate::degrade::Degradation;
 use crate::stage_cache::L2mCache;
 use crate::stages::{LStage, MStage};
+use crate::trace_type::{self, trace_type};
 
 mod clean_typedefs;
 mod flatten_trees;
+mod hoist_constants;
+pub use hoist_constants::{hoist_anonymous_enums, hoist_macros, merge_constants};
+mod name_anonymous_enums;
+mod resolve_enum_signedness;
 mod resolve_enum_sizes;
 
+/// Directory of the clang AST cache for parsing type names
+pub fn type_parse_cache_dir(config: &Config) -> PathBuf {
+    config.paths.extract_output.join("clang-type-parse")
+}
+
+/// The compile command for parsing the type names of a unit
+#[derive(Clone)]
+pub struct ParseCommand {
+    pub command: CompileCommand,
+    /// Set if the clang invocations are batched with other units
+    pub batcher: Option<Arc<ClangBatcher>>,
+    /// Names parsed by all units
+    pub name_cache: Option<Arc<TypeNameCache>>,
+}
+
 pub async fn to_mstage(
     stage: LStage,
-    command: CompileCommand,
+    command: ParseCommand,
     cache: &L2mCache,
+    degradation: Degradation,
 ) -> cu::Result<MStage> {
     cu::trace!("converting lstage to mstage: {}", stage.name);
     let cached_mstage = cu::check!(
@@ -24,17 +50,28 @@ pub async fn to_mstage(
     if let Some(x) = cached_mstage {
         return Ok(x);
     }
-    let mstage = to_mstage_internal(stage, command).await?;
-    // save cache
-    cu::check!(
-        cache.set(&mstage),
-        "failed to save l2mcache for {}",
-        mstage.name
-    )?;
+    let mstage = to_mstage_internal(stage, command, degradation).await?;
+    // save cache, unless some features were degraded, so the full
+    // feature set can be tried again in the next run
+    if degradation == Degradation::default() {
+        cu::check!(
+            cache.set(&mstage),
+            "failed to save l2mcache for {}",
+            mstage.name
+        )?;
+    }
     Ok(mstage)
 }
 
-async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::Result<MStage> {
+async fn to_mstage_internal(
+    mut stage: LStage,
+    command: ParseCommand,
+    degradation: Degradation,
+) -> cu::Result<MStage> {
+    cu::check!(
+        resolve_enum_signedness::run(&mut stage),
+        "stage1: resolve_enum_signedness failed"
+    )?;
     cu::check!(
         resolve_enum_sizes::run(&mut stage),
         "stage1: resolve_enum_sizes failed"
@@ -48,16 +85,36 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
         "stage1: flatten_trees failed"
     )?;
 
+    let backend = match stage.config.extract.type_parser.backend {
+        TypeParserBackend::Clang => NameParserBackend::Clang,
+        TypeParserBackend::Builtin => NameParserBackend::Builtin,
+        TypeParserBackend::Auto => NameParserBackend::Auto,
+    };
     let name_parser = NameParser {
-        output_dir: stage.config.paths.extract_output.join("clang-type-parse"),
+        backend,
+        output_dir: type_parse_cache_dir(&stage.config),
         system_header_paths: stage.config.paths.system_header_paths.clone(),
         char_repr: stage.config.extract.char_repr,
         wchar_repr: stage.config.extract.wchar_repr,
+        allow_unknown_template_args: stage.config.extract.type_parser.allow_unknown_template_args
+            || degradation.lenient_names,
+        batcher: command.batcher,
+        name_cache: command.name_cache,
     };
     let mut names = cu::check!(
-        name_parser.parse(command, &stage.ns, &stage.types).await,
+        name_parser
+            .parse(command.command, &stage.ns, &stage.types)
+            .await,
         "stage1: name parse failed"
     )?;
+    if trace_type::is_enabled() {
+        for (k, name) in &names {
+            let base = name.base.to_string();
+            if trace_type::matches(*k, std::iter::once(base.as_str())) {
+                trace_type!("name parsed ({}): {k} = {name:#?}", stage.name);
+            }
+        }
+    }
 
     // GC types to ensure trees are all GC-ed
     // note GC must be after parsing names, since some types could be referenced
@@ -66,6 +123,7 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
     for symbol in stage.symbols.values() {
         symbol.mark(&mut marked);
     }
+    algorithm::mark_typedefs(&stage.typedefs, &mut marked);
     // also mark the parsed name
     for name in names.values() {
         name.mark(&mut marked);
@@ -127,6 +185,9 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
                 let Ok(byte_size) = data.data.byte_size_or_base else {
                     cu::bail!("unexpected did not resolve enum byte size: {k}");
                 };
+                let Some(is_signed) = data.data.is_signed else {
+                    cu::bail!("unexpected did not resolve enum signedness: {k}");
+                };
                 let enumerators = data.data.enumerators.clone();
                 types.insert(
                     *k,
@@ -134,9 +195,11 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
                         name: data.name.clone(),
                         data: Enum {
                             byte_size,
+                            is_signed,
                             enumerators,
                         },
                         decl_names: vec![],
+                        source: data.source.clone(),
                     }),
                 );
             }
@@ -160,6 +223,7 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
                         name: data.name.clone(),
                         data: data.data.clone(),
                         decl_names: vec![],
+                        source: data.source.clone(),
                     }),
                 );
             }
@@ -183,6 +247,7 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
                         name: data.name.clone(),
                         data: data.data.clone(),
                         decl_names: vec![],
+                        source: data.source.clone(),
                     }),
                 );
             }
@@ -209,6 +274,9 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
             | MType::StructDecl(MTypeDecl { typedef_names, .. }) => *typedef_names = names,
         }
     }
+    if stage.config.extract.name_anonymous_enums {
+        name_anonymous_enums::run(&stage, &mut types);
+    }
     for (k, g) in dupes {
         types.insert(k, types.get(&g).unwrap().clone());
     }
@@ -216,6 +284,7 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
         types,
         GoffBuckets::default(),
         &mut stage.symbols,
+        &mut stage.typedefs,
         None,
         |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
     );
@@ -228,5 +297,6 @@ async fn to_mstage_internal(mut stage: LStage, command: CompileCommand) -> cu::R
         types: deduped,
         config: stage.config,
         symbols: stage.symbols,
+        typedefs: stage.typedefs,
     })
 }
@@ -0,0 +1,43 @@
+use exstructs::{GoffMap, MType, MTypeData, NameSeg, NamespacedName, NamespacedTemplatedName};
+
+use crate::stages::LStage;
+
+/// Give anonymous enums without a typedef a synthesized decl name, from the
+/// enclosing scope and the first enumerator (e.g. `Parent::AnonEnum_First`).
+///
+/// Unscoped enumerators are unique in the enclosing scope, so the name is
+/// deterministic and the same enum from different compilation units gets
+/// the same name when merging
+pub fn run(stage: &LStage, types: &mut GoffMap<MType>) {
+    for (k, t) in types.iter_mut() {
+        let MType::Enum(MTypeData {
+            name: None,
+            data,
+            decl_names,
+            ..
+        }) = t
+        else {
+            continue;
+        };
+        if !decl_names.is_empty() {
+            continue;
+        }
+        let Some(first) = data.enumerators.first() else {
+            continue;
+        };
+        let namespace = stage.ns.qualifiers.get(k).cloned().unwrap_or_default();
+        if namespace
+            .0
+            .iter()
+            .any(|seg| matches!(seg, NameSeg::Subprogram(_, _, _)))
+        {
+            // local enums in functions cannot be referenced from outside
+            cu::trace!("not naming function-local anonymous enum {k}");
+            continue;
+        }
+        let basename = format!("AnonEnum_{}", first.name);
+        let name = NamespacedName::namespaced(&namespace, &basename);
+        cu::trace!("naming anonymous enum {k} as {name}");
+        decl_names.push(NamespacedTemplatedName::new(name));
+    }
+}
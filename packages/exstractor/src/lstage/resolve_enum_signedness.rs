@@ -0,0 +1,76 @@
+use cu::pre::*;
+use exstructs::{Enumerator, Goff, GoffMap, GoffSet, LType};
+use tyyaml::Prim;
+
+use crate::stages::LStage;
+
+/// Resolve if the underlying type of each enum is signed.
+///
+/// This must run before `resolve_enum_sizes`, since that pass replaces the
+/// base type with the size.
+pub fn run(stage: &mut LStage) -> cu::Result<()> {
+    let mut resolved = GoffMap::new();
+    for (goff, data) in &stage.types {
+        let LType::Enum(data) = data else { continue };
+        if data.data.is_signed.is_some() {
+            continue;
+        }
+        let is_signed = match data.data.byte_size_or_base {
+            Err(base) => {
+                let mut visited = GoffSet::new();
+                cu::check!(
+                    resolve_base_signedness(base, stage, &mut visited),
+                    "failed to resolve signedness for enum base type {goff} -> {base}"
+                )?
+            }
+            Ok(_) => infer_from_enumerators(&data.data.enumerators),
+        };
+        resolved.insert(*goff, is_signed);
+    }
+    for (goff, is_signed) in resolved {
+        let Some(LType::Enum(data)) = stage.types.get_mut(&goff) else {
+            continue;
+        };
+        data.data.is_signed = Some(is_signed);
+    }
+    Ok(())
+}
+
+/// Follow the base type of the enum down to a primitive
+fn resolve_base_signedness(goff: Goff, stage: &LStage, visited: &mut GoffSet) -> cu::Result<bool> {
+    if let Some(prim) = goff.to_prim() {
+        return Ok(is_signed_prim(prim));
+    }
+    cu::ensure!(
+        visited.insert(goff),
+        "unexpected recursive enum base type {goff}"
+    )?;
+    let data = cu::check!(stage.types.get(&goff), "unexpected unlinked type {goff}")?;
+    match data {
+        LType::Prim(prim) => Ok(is_signed_prim(*prim)),
+        LType::Typedef { target, .. } => resolve_base_signedness(*target, stage, visited),
+        LType::Alias(inner) => resolve_base_signedness(*inner, stage, visited),
+        LType::Enum(data) => match (data.data.is_signed, data.data.byte_size_or_base) {
+            (Some(is_signed), _) => Ok(is_signed),
+            (None, Err(base)) => resolve_base_signedness(base, stage, visited),
+            (None, Ok(_)) => Ok(infer_from_enumerators(&data.data.enumerators)),
+        },
+        _ => cu::bail!("unexpected non-integral enum base type {goff}"),
+    }
+}
+
+fn is_signed_prim(prim: Prim) -> bool {
+    matches!(
+        prim,
+        Prim::I8 | Prim::I16 | Prim::I32 | Prim::I64 | Prim::I128
+    )
+}
+
+/// Infer the signedness from the enumerator values when DWARF does not
+/// have the base type.
+///
+/// Note that unsigned 64-bit values above `i64::MAX` are also stored as negative,
+/// these are treated as signed as well since there's no way to tell them apart
+fn infer_from_enumerators(enumerators: &[Enumerator]) -> bool {
+    enumerators.iter().any(|e| e.value < 0)
+}
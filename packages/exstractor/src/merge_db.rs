@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+use exstructs::algorithm::{self, MapGoff};
+use exstructs::{
+    FullQualName, Goff, GoffBuckets, GoffMap, GoffMapFn, HType, StableIdMap, SymbolInfo,
+};
+
+use crate::database::{Database, LineTable};
+use crate::demangled_index::DemangledIndex;
+
+/// Options for combining databases from multiple extractions
+#[derive(Debug)]
+pub struct MergeDbOptions {
+    /// Databases to combine, or the output directories that contain them.
+    /// When the same symbol is in multiple inputs, the first input is kept
+    pub inputs: Vec<PathBuf>,
+    /// Offset added to the addresses of each input (in hex), in the same order as
+    /// the inputs. Missing offsets are 0
+    pub offsets: Vec<String>,
+    /// Path to save the combined database
+    pub output: PathBuf,
+}
+
+/// Combine databases from multiple extractions, for example a program and a module
+/// loaded by it, into one database.
+///
+/// Types with the same fully-qualified names are linked, and other types are deduped
+/// if they are equal after linking. Symbols are combined by link name
+pub fn merge_db(options: MergeDbOptions) -> cu::Result<()> {
+    cu::ensure!(
+        options.inputs.len() >= 2,
+        "need at least 2 databases to merge"
+    )?;
+    cu::ensure!(
+        options.offsets.len() <= options.inputs.len(),
+        "there are more offsets than inputs"
+    )?;
+    let mut databases = Vec::with_capacity(options.inputs.len());
+    for (i, path) in options.inputs.iter().enumerate() {
+        let offset = match options.offsets.get(i) {
+            Some(offset) => parse_offset(offset)?,
+            None => 0,
+        };
+        let db = load_database(path)?;
+        databases.push((path.as_path(), db, offset));
+    }
+    let merged = merge_databases(databases)?;
+    merged.save(&options.output)?;
+    cu::hint!(
+        "merged database saved to {} ({} types, {} symbols)",
+        options.output.try_to_rel().display(),
+        merged.types.len(),
+        merged.symbols.len()
+    );
+    Ok(())
+}
+
+fn load_database(path: &Path) -> cu::Result<Database> {
+    let path = if path.is_dir() {
+        path.join("database.json")
+    } else {
+        path.to_path_buf()
+    };
+    Database::load(&path)
+}
+
+fn parse_offset(s: &str) -> cu::Result<u32> {
+    let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+    let offset = cu::check!(
+        u32::from_str_radix(hex, 16),
+        "invalid offset `{s}`, expecting a hex number"
+    )?;
+    Ok(offset)
+}
+
+fn merge_databases(databases: Vec<(&Path, Database, u32)>) -> cu::Result<Database> {
+    let mut types = GoffMap::default();
+    let mut symbols = BTreeMap::<String, SymbolInfo>::new();
+    let mut typedefs = BTreeMap::new();
+    let mut constants = BTreeMap::new();
+    let mut calls = vec![];
+    let mut line_rows = vec![];
+    let mut line_files = vec![];
+    let mut demangled = BTreeMap::new();
+    // type names from the previous inputs, to link the types by name
+    let mut names = BTreeMap::<Vec<FullQualName>, Goff>::new();
+    let mut buckets = GoffBuckets::default();
+    let mut next_goff = 1;
+    let mut symbol_conflicts = 0;
+
+    for (path, db, offset) in databases {
+        cu::debug!(
+            "merging {} at offset 0x{offset:08x}",
+            path.try_to_rel().display()
+        );
+        // Goffs are only unique within one database
+        let goff_base = next_goff;
+        let shift_goff = move |k: Goff| -> cu::Result<Goff> {
+            if k.is_prim() {
+                return Ok(k);
+            }
+            let shifted = Goff(k.0 + goff_base);
+            cu::ensure!(
+                !shifted.is_prim(),
+                "too many types to merge, the type offsets overflowed"
+            )?;
+            Ok(shifted)
+        };
+        let shift_address = |address: u32| -> cu::Result<u32> {
+            let relocated = cu::check!(
+                address.checked_add(offset),
+                "address 0x{address:08x} overflowed after adding the offset 0x{offset:08x}"
+            )?;
+            Ok(relocated)
+        };
+
+        for (k, mut t) in db.types {
+            let k = shift_goff(k)?;
+            t.map_goff(shift_goff)?;
+            if !matches!(t, HType::Prim(_)) {
+                let fqnames = t.fqnames()?;
+                // only names without type offsets are comparable across databases
+                let is_linkable = !fqnames.is_empty()
+                    && fqnames.iter().all(|x| matches!(x, FullQualName::Name(_)));
+                if is_linkable {
+                    if let Some(linked) = names.get(fqnames) {
+                        buckets.merge(*linked, k)?;
+                    } else {
+                        names.insert(fqnames.to_vec(), k);
+                    }
+                }
+            }
+            if !k.is_prim() {
+                next_goff = next_goff.max(k.0 + 1);
+            }
+            types.insert(k, t);
+        }
+
+        let f: GoffMapFn = Box::new(shift_goff);
+        for (name, mut symbol) in db.symbols {
+            symbol.map_goff(&f)?;
+            symbol.address = shift_address(symbol.address)?;
+            match symbols.get_mut(&name) {
+                None => {
+                    symbols.insert(name, symbol);
+                }
+                Some(existing) => {
+                    if let Err(e) = existing.link(&symbol) {
+                        cu::debug!("keeping the first definition of symbol {name}: {e:?}");
+                        symbol_conflicts += 1;
+                    }
+                }
+            }
+        }
+
+        // same as the symbols, the first input is kept
+        for (name, mut tree) in db.typedefs {
+            tree.map_goff(&f)?;
+            typedefs.entry(name).or_insert(tree);
+        }
+        for (name, mut symbol) in db.demangled.symbols {
+            symbol.address = shift_address(symbol.address)?;
+            demangled.entry(name).or_insert(symbol);
+        }
+        for (name, value) in db.constants {
+            constants.entry(name).or_insert(value);
+        }
+        for mut call in db.calls {
+            call.caller_address = shift_address(call.caller_address)?;
+            call.return_address = call.return_address.map(shift_address).transpose()?;
+            calls.push(call);
+        }
+        let file_base = line_files.len() as u32;
+        line_files.extend(db.lines.files);
+        for (address, file, line) in db.lines.rows {
+            line_rows.push((shift_address(address)?, file + file_base, line));
+        }
+    }
+    if symbol_conflicts > 0 {
+        cu::warn!(
+            "{symbol_conflicts} symbols are in multiple inputs with different addresses or parameters, the definitions from the first input are kept (see debug logs)"
+        );
+    }
+
+    let types = cu::check!(
+        algorithm::merging_dedupe(
+            types,
+            buckets,
+            &mut symbols,
+            &mut typedefs,
+            None,
+            |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
+            |t1, _| {
+                let name = t1.fqnames().ok().and_then(|x| x.first());
+                cu::bail!("type {name:?} is defined differently in the inputs and cannot be merged")
+            },
+        ),
+        "failed to link types in the databases"
+    )?;
+
+    calls.sort();
+    line_rows.sort();
+    Ok(Database {
+        stable_ids: StableIdMap::new(&types),
+        types,
+        symbols,
+        typedefs,
+        constants,
+        calls,
+        lines: LineTable {
+            files: line_files,
+            rows: line_rows,
+        },
+        demangled: DemangledIndex::from_symbols(demangled),
+    })
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeSet;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{FullQualName, Goff, GoffMap, GoffSet, MType, NamespacedTemplatedGoffName};
+
+use super::conflict::UnitNames;
+
+/// Name shared by types whose own names are different, which are
+/// merged only because a typedef in one of them has the same name.
+///
+/// Merging by the type's own name is confident, since the name is unique
+/// in a program. Typedef names are not, for example, `typedef Foo Handle;`
+/// and `typedef Bar Handle;` could be in different compilation units
+#[derive(Debug, Serialize)]
+pub struct AmbiguousName {
+    /// The shared name
+    pub name: String,
+    /// Types with the name, which are merged
+    pub candidates: Vec<AmbiguousCandidate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmbiguousCandidate {
+    pub offset: String,
+    pub unit: String,
+    /// Own names of the type, excluding typedef names
+    pub names: Vec<String>,
+}
+
+/// Own (non-typedef) names of types, for checking if a name match is confident
+pub struct OwnNames<'a> {
+    types: &'a GoffMap<MType>,
+    cache: GoffMap<BTreeSet<String>>,
+}
+
+impl<'a> OwnNames<'a> {
+    pub fn new(types: &'a GoffMap<MType>) -> Self {
+        Self {
+            types,
+            cache: Default::default(),
+        }
+    }
+
+    /// Get the permutated own names of the type. Empty if the type is
+    /// anonymous, and is only named by typedefs
+    fn get(
+        &mut self,
+        k: Goff,
+        permutater: &mut FullQualPermutater,
+    ) -> cu::Result<&BTreeSet<String>> {
+        if !self.cache.contains_key(&k) {
+            let names = match own_name(self.types.get(&k)) {
+                Some(name) => name.permutated_fullqual(permutater)?,
+                None => Default::default(),
+            };
+            self.cache.insert(k, names);
+        }
+        Ok(self.cache.get(&k).unwrap())
+    }
+
+    /// Check if the types sharing the name have different own names.
+    ///
+    /// Types are grouped if their own names overlap. Anonymous types
+    /// can be grouped with any type. The name is ambiguous if there are
+    /// more than one group
+    pub fn check(
+        &mut self,
+        name: &str,
+        goffs: &GoffSet,
+        permutater: &mut FullQualPermutater,
+        unit_names: &UnitNames,
+    ) -> cu::Result<Option<AmbiguousName>> {
+        let mut groups: Vec<BTreeSet<String>> = vec![];
+        let mut candidates = vec![];
+        for k in goffs {
+            let names = self.get(*k, permutater)?;
+            if names.is_empty() {
+                continue;
+            }
+            candidates.push(AmbiguousCandidate {
+                offset: k.to_string(),
+                unit: unit_names.lookup(*k),
+                names: names.iter().cloned().collect(),
+            });
+            let mut merged = names.clone();
+            groups.retain(|group| {
+                if group.is_disjoint(&merged) {
+                    return true;
+                }
+                merged.extend(group.iter().cloned());
+                false
+            });
+            groups.push(merged);
+        }
+        if groups.len() <= 1 {
+            return Ok(None);
+        }
+        Ok(Some(AmbiguousName {
+            name: name.to_string(),
+            candidates,
+        }))
+    }
+}
+
+/// The type's own name, which is the first fully-qualified name
+/// if the type is not anonymous
+fn own_name(t: Option<&MType>) -> Option<FullQualName> {
+    let (name, template_args) = match t? {
+        MType::Prim(_) => return None,
+        MType::Enum(data) => (data.name.as_ref()?, vec![]),
+        MType::Union(data) => (data.name.as_ref()?, data.data.template_args.clone()),
+        MType::Struct(data) => (data.name.as_ref()?, data.data.template_args.clone()),
+        MType::EnumDecl(decl) | MType::UnionDecl(decl) | MType::StructDecl(decl) => {
+            return Some(FullQualName::Name(decl.name.clone()));
+        }
+    };
+    Some(FullQualName::Goff(NamespacedTemplatedGoffName {
+        base: name.clone(),
+        templates: template_args,
+    }))
+}
+
+/// Save the ambiguous name report, or remove the stale report if there are none
+pub fn save_ambiguous_report(config: &Config, names: &[AmbiguousName]) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("ambiguous_names.json");
+    if names.is_empty() {
+        cu::fs::remove(&path)?;
+        return Ok(());
+    }
+    for name in names {
+        let candidates = name
+            .candidates
+            .iter()
+            .map(|c| c.names.first().map(|x| x.as_str()).unwrap_or_default())
+            .collect::<Vec<_>>();
+        cu::warn!(
+            "ambiguous name {} is shared by: {}",
+            name.name,
+            candidates.join(", ")
+        );
+    }
+    cu::fs::write_json_pretty(&path, &names)?;
+    cu::hint!(
+        "ambiguous name report saved to {}",
+        path.try_to_rel().display()
+    );
+    Ok(())
+}
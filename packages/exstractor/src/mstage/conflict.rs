@@ -0,0 +1,345 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{Enum, Goff, MType, Member, NamespacedName};
+
+/// Template instantiation whose layout differs between compilation units
+#[derive(Debug, Serialize)]
+pub struct TemplateConflict {
+    /// Name of the instantiation, including template args
+    pub name: String,
+    /// Compilation units the conflicting definitions are from
+    pub units: Vec<String>,
+    /// Offsets of the conflicting definitions
+    pub offsets: Vec<String>,
+    /// Human-readable differences between the 2 definitions
+    pub differences: Vec<String>,
+    /// The merge error
+    pub error: String,
+}
+
+/// Types with the same name that cannot be merged
+#[derive(Debug, Serialize)]
+pub struct MergeConflict {
+    /// The name the types are merged by
+    pub name: String,
+    /// The 2 conflicting types
+    pub types: Vec<ConflictingType>,
+    /// Human-readable differences between the 2 types
+    pub differences: Vec<String>,
+    /// The merge error
+    pub error: String,
+    /// New name of the second type, if both types are kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed: Option<String>,
+}
+
+impl MergeConflict {
+    pub fn new(
+        name: String,
+        types: [(Goff, &MType); 2],
+        error: String,
+        unit_names: &UnitNames,
+        permutater: &mut FullQualPermutater,
+    ) -> Self {
+        let [(k1, t1), (k2, t2)] = types;
+        Self {
+            name,
+            types: vec![
+                ConflictingType::new(k1, t1, unit_names, permutater),
+                ConflictingType::new(k2, t2, unit_names, permutater),
+            ],
+            differences: diff_layout(t1, t2, permutater),
+            error,
+            renamed: None,
+        }
+    }
+}
+
+/// One side of a [`MergeConflict`]
+#[derive(Debug, Serialize)]
+pub struct ConflictingType {
+    pub offset: String,
+    /// Compilation unit the type is from
+    pub unit: String,
+    /// Fully-qualified names of the type
+    pub names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+impl ConflictingType {
+    fn new(
+        k: Goff,
+        t: &MType,
+        unit_names: &UnitNames,
+        permutater: &mut FullQualPermutater,
+    ) -> Self {
+        let source = match t {
+            MType::Enum(data) => data.source.as_ref(),
+            MType::Union(data) => data.source.as_ref(),
+            MType::Struct(data) => data.source.as_ref(),
+            _ => None,
+        };
+        Self {
+            offset: k.to_string(),
+            unit: unit_names.lookup(k),
+            names: permutater
+                .permutated_fullqual_names(k)
+                .map(|names| names.iter().cloned().collect())
+                .unwrap_or_default(),
+            source: source.map(|x| x.to_string()),
+        }
+    }
+}
+
+/// Name of the compilation units by unit offset, for finding
+/// which CU a type is from
+#[derive(Debug, Default)]
+pub struct UnitNames(BTreeMap<usize, String>);
+
+impl UnitNames {
+    pub fn insert(&mut self, offset: usize, name: String) {
+        self.0.insert(offset, name);
+    }
+
+    /// Get the name of the unit that contains the DIE at the offset
+    pub fn lookup(&self, goff: Goff) -> String {
+        match self.0.range(..=goff.0).next_back() {
+            Some((_, name)) => name.clone(),
+            None => format!("[unknown unit for {goff}]"),
+        }
+    }
+}
+
+/// Returns true if the layout conflict between the types with this name should be reported
+/// instead of failing the merge directly
+pub fn is_reportable(name: &str, t1: &MType, t2: &MType) -> bool {
+    if !name.contains('<') {
+        return false;
+    }
+    matches!(
+        (t1, t2),
+        (MType::Struct(_), MType::Struct(_)) | (MType::Union(_), MType::Union(_))
+    )
+}
+
+/// Describe the differences between the layout of 2 definitions of the same type
+pub fn diff_layout(t1: &MType, t2: &MType, permutater: &mut FullQualPermutater) -> Vec<String> {
+    let mut differences = vec![];
+    let (size1, members1, size2, members2) = match (t1, t2) {
+        (MType::Enum(a), MType::Enum(b)) => return diff_enum(&a.data, &b.data),
+        (MType::Struct(a), MType::Struct(b)) => (
+            a.data.byte_size,
+            &a.data.members,
+            b.data.byte_size,
+            &b.data.members,
+        ),
+        (MType::Union(a), MType::Union(b)) => (
+            a.data.byte_size,
+            &a.data.members,
+            b.data.byte_size,
+            &b.data.members,
+        ),
+        _ => {
+            if std::mem::discriminant(t1) != std::mem::discriminant(t2) {
+                differences.push(format!("kind: {} != {}", kind_name(t1), kind_name(t2)));
+            }
+            return differences;
+        }
+    };
+    if size1 != size2 {
+        differences.push(format!("size: 0x{size1:x} != 0x{size2:x}"));
+    }
+    if members1.len() != members2.len() {
+        differences.push(format!(
+            "member count: {} != {}",
+            members1.len(),
+            members2.len()
+        ));
+    }
+    let len = members1.len().max(members2.len());
+    for i in 0..len {
+        let a = members1.get(i).map(|m| describe_member(m, permutater));
+        let b = members2.get(i).map(|m| describe_member(m, permutater));
+        if a == b {
+            continue;
+        }
+        let a = a.unwrap_or_else(|| "<none>".to_string());
+        let b = b.unwrap_or_else(|| "<none>".to_string());
+        differences.push(format!("member {i}: {a} != {b}"));
+    }
+    differences
+}
+
+fn diff_enum(a: &Enum, b: &Enum) -> Vec<String> {
+    let mut differences = vec![];
+    if a.byte_size != b.byte_size {
+        differences.push(format!("size: 0x{:x} != 0x{:x}", a.byte_size, b.byte_size));
+    }
+    if a.is_signed != b.is_signed {
+        differences.push(format!("signed: {} != {}", a.is_signed, b.is_signed));
+    }
+    let len = a.enumerators.len().max(b.enumerators.len());
+    for i in 0..len {
+        let x = a
+            .enumerators
+            .get(i)
+            .map(|e| format!("{} = {}", e.name, e.value));
+        let y = b
+            .enumerators
+            .get(i)
+            .map(|e| format!("{} = {}", e.name, e.value));
+        if x == y {
+            continue;
+        }
+        let x = x.unwrap_or_else(|| "<none>".to_string());
+        let y = y.unwrap_or_else(|| "<none>".to_string());
+        differences.push(format!("enumerator {i}: {x} != {y}"));
+    }
+    differences
+}
+
+fn kind_name(t: &MType) -> &'static str {
+    match t {
+        MType::Prim(_) => "primitive",
+        MType::Enum(_) => "enum",
+        MType::EnumDecl(_) => "enum declaration",
+        MType::Union(_) => "union",
+        MType::UnionDecl(_) => "union declaration",
+        MType::Struct(_) => "struct",
+        MType::StructDecl(_) => "struct declaration",
+    }
+}
+
+fn describe_member(member: &Member, permutater: &mut FullQualPermutater) -> String {
+    let name = member
+        .name
+        .as_ref()
+        .map(|s| s.as_ref())
+        .unwrap_or("<anonymous>");
+    let ty = member
+        .ty
+        .clone()
+        .map(|k| type_name(k, permutater))
+        .to_string();
+    format!("0x{:x}: {name}: {ty}", member.offset)
+}
+
+/// Type names are compared instead of offsets, since the offsets are
+/// always different for types from different CUs
+pub fn type_name(k: Goff, permutater: &mut FullQualPermutater) -> String {
+    if let Some(p) = k.to_prim() {
+        return p.to_string();
+    }
+    permutater
+        .permutated_fullqual_names(k)
+        .ok()
+        .and_then(|names| names.first().cloned())
+        .unwrap_or_else(|| format!("[anonymous {k}]"))
+}
+
+/// Print the names with the most conflicts, to help target the cleanup
+pub fn print_top_conflicts(conflicts: &[TemplateConflict]) {
+    const TOP: usize = 20;
+    if conflicts.is_empty() {
+        return;
+    }
+    let mut counts = BTreeMap::<&str, usize>::new();
+    for conflict in conflicts {
+        *counts.entry(&conflict.name).or_default() += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    // stable sort keeps the names in order for the same count
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    cu::info!(
+        "top {} of {} conflicting names:",
+        counts.len().min(TOP),
+        counts.len()
+    );
+    for (name, count) in counts.into_iter().take(TOP) {
+        cu::info!("  {count:>5} {name}");
+    }
+}
+
+/// Save the conflict report, or remove the stale report if there are no conflicts
+pub fn save_conflict_report(config: &Config, conflicts: &[TemplateConflict]) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("template_conflicts.json");
+    if conflicts.is_empty() {
+        cu::fs::remove(&path)?;
+        return Ok(());
+    }
+    for conflict in conflicts {
+        cu::warn!(
+            "conflicting layouts for {} in: {}",
+            conflict.name,
+            conflict.units.join(", ")
+        );
+    }
+    cu::fs::write_json_pretty(&path, &conflicts)?;
+    cu::hint!(
+        "template conflict report saved to {}",
+        path.try_to_rel().display()
+    );
+    Ok(())
+}
+
+/// Rename the type so it's no longer merged by name with the type it conflicts with.
+///
+/// All names of the type are suffixed with the offset. Returns the new name,
+/// or None if the type is anonymous
+pub fn disambiguate(t: &mut MType, k: Goff) -> Option<String> {
+    let (name, decl_names) = match t {
+        MType::Enum(data) => (&mut data.name, &mut data.decl_names),
+        MType::Union(data) => (&mut data.name, &mut data.decl_names),
+        MType::Struct(data) => (&mut data.name, &mut data.decl_names),
+        _ => return None,
+    };
+    let rename = |name: &NamespacedName| {
+        let basename = format!("{}__{:x}", name.basename(), k.0);
+        NamespacedName::namespaced(name.namespace(), &basename)
+    };
+    for decl_name in decl_names.iter_mut() {
+        decl_name.base = rename(&decl_name.base);
+    }
+    match name {
+        Some(name) => {
+            *name = rename(name);
+            Some(name.to_string())
+        }
+        None => decl_names.first().map(|x| x.base.to_string()),
+    }
+}
+
+/// Save the merge conflict report, or remove the stale report if there are no conflicts
+pub fn save_merge_conflict_report(config: &Config, conflicts: &[MergeConflict]) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("merge_conflicts.json");
+    if conflicts.is_empty() {
+        cu::fs::remove(&path)?;
+        return Ok(());
+    }
+    for conflict in conflicts {
+        let units = conflict
+            .types
+            .iter()
+            .map(|t| t.unit.as_str())
+            .collect::<Vec<_>>();
+        match &conflict.renamed {
+            Some(renamed) => cu::warn!(
+                "cannot merge {} in: {}, kept both as {renamed}",
+                conflict.name,
+                units.join(", ")
+            ),
+            None => cu::warn!("cannot merge {} in: {}", conflict.name, units.join(", ")),
+        }
+    }
+    cu::fs::write_json_pretty(&path, &conflicts)?;
+    cu::hint!(
+        "merge conflict report saved to {}",
+        path.try_to_rel().display()
+    );
+    Ok(())
+}
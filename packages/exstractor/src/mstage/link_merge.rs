@@ -1,26 +1,88 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use cu::pre::*;
-use exstructs::algorithm::{self, FullQualPermutater, merge::MergeTask};
-use exstructs::{FullQualNameMap, GoffBuckets, GoffMap, GoffPair, GoffSet, MType};
+use dejj_utils::{MergeConflictMode, VtableMergeMode};
+use exstructs::algorithm::merge::{MergeOptions, MergeTask};
+use exstructs::algorithm::{self, FullQualPermutater};
+use exstructs::{FullQualNameMap, Goff, GoffBuckets, GoffMap, GoffPair, GoffSet, MType};
 
 use crate::stages::MStage;
+use crate::trace_type::{self, trace_type};
+
+use super::ambiguous::{AmbiguousName, OwnNames};
+use super::conflict::{self, MergeConflict, TemplateConflict, UnitNames};
+
+pub enum LinkMergeOutput {
+    /// The merged stage, the names that are ambiguous in the merge,
+    /// the number of permutated names of types whose names are truncated,
+    /// and the conflicting types that are kept separately
+    Merged(
+        MStage,
+        Vec<AmbiguousName>,
+        BTreeMap<String, usize>,
+        Vec<MergeConflict>,
+    ),
+    /// Template instantiations have different layouts in different CUs,
+    /// or types with the same name cannot be merged
+    Conflict(Vec<TemplateConflict>, Vec<MergeConflict>),
+}
 
 /// Link the 2 stages, and merge types that are duplicated
-pub fn link_merge(a: MStage, b: MStage) -> cu::Result<MStage> {
+pub fn link_merge(a: MStage, b: MStage, unit_names: &UnitNames) -> cu::Result<LinkMergeOutput> {
     let mut merged = a.link(b)?;
-    cu::check!(process_merges(&mut merged), "merged merge_by_name failed")?;
-    Ok(merged)
+    let mut ambiguous = vec![];
+    let mut truncated = BTreeMap::new();
+    let mut merge_conflicts = vec![];
+    let conflicts = cu::check!(
+        process_merges(
+            &mut merged,
+            unit_names,
+            &mut ambiguous,
+            &mut truncated,
+            &mut merge_conflicts
+        ),
+        "merged merge_by_name failed"
+    )?;
+    let keep_both = merged.config.extract.merge_conflicts == MergeConflictMode::KeepBoth;
+    if !conflicts.is_empty() || (!keep_both && !merge_conflicts.is_empty()) {
+        return Ok(LinkMergeOutput::Conflict(conflicts, merge_conflicts));
+    }
+    Ok(LinkMergeOutput::Merged(
+        merged,
+        ambiguous,
+        truncated,
+        merge_conflicts,
+    ))
 }
 
-/// Merge types that have the same name
-fn process_merges(stage: &mut MStage) -> cu::Result<()> {
+/// Merge types that have the same name.
+///
+/// Returns the template instantiations with conflicting layouts, in which case
+/// the merge is not performed. Other types with the same name that cannot be merged
+/// are added to `merge_conflicts`, and the merge is only performed if both types are kept
+/// with `extract.merge-conflicts`. Names shared by types with different own names
+/// are added to `ambiguous`, but are still merged. Types with more permutated names than
+/// the limit in the config are added to `truncated`
+fn process_merges(
+    stage: &mut MStage,
+    unit_names: &UnitNames,
+    ambiguous: &mut Vec<AmbiguousName>,
+    truncated: &mut BTreeMap<String, usize>,
+    merge_conflicts: &mut Vec<MergeConflict>,
+) -> cu::Result<Vec<TemplateConflict>> {
     let mut fullqual_names = GoffMap::default();
     for (k, t) in &stage.types {
         fullqual_names.insert(*k, t.fullqual_names());
     }
     let fullqual_names = FullQualNameMap::from(fullqual_names);
-    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    let mut permutater = FullQualPermutater::new(&fullqual_names)
+        .with_limit(stage.config.extract.max_name_permutations);
+    let merge_options = MergeOptions {
+        lenient_vtable: stage.config.extract.vtable_merge == VtableMergeMode::Lenient,
+    };
+    let keep_both = stage.config.extract.merge_conflicts == MergeConflictMode::KeepBoth;
+    // types kept separately because of conflicts, to the index of the conflict
+    let mut split = BTreeMap::<Goff, usize>::new();
 
     let mut name2goffs_enum = BTreeMap::<String, GoffSet>::new();
     let mut name2goffs_union = BTreeMap::<String, GoffSet>::new();
@@ -37,8 +99,28 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
             permutater.permutated_fullqual_names(k),
             "failed to permutate names for type {k}"
         )?;
-        for name in names {
-            map.entry(name).or_default().insert(k);
+        for name in names.iter() {
+            map.entry(name.clone()).or_default().insert(k);
+        }
+    }
+    for (k, count) in permutater.truncated_types().clone() {
+        if let Some(name) = permutater.permutated_fullqual_names(k)?.first() {
+            truncated.insert(name.clone(), count);
+        }
+    }
+    {
+        let mut own_names = OwnNames::new(&stage.types);
+        for (name, goffs) in name2goffs_enum
+            .iter()
+            .chain(name2goffs_union.iter())
+            .chain(name2goffs_struct.iter())
+        {
+            if goffs.len() < 2 {
+                continue;
+            }
+            if let Some(x) = own_names.check(name, goffs, &mut permutater, unit_names)? {
+                ambiguous.push(x);
+            }
         }
     }
 
@@ -57,24 +139,61 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
             }
         }
         let mut merge_tasks = BTreeMap::default();
+        let mut conflicts = vec![];
+        let mut conflicting = BTreeSet::new();
         for (k1, k2, merging_name) in to_merge {
             let key = GoffPair::from((k1, k2));
-            if merge_tasks.contains_key(&key) {
+            if merge_tasks.contains_key(&key) || conflicting.contains(&key) {
                 continue;
             }
+            if trace_type::matches_name(merging_name) {
+                trace_type!("merge attempted by name {merging_name}: {k1} and {k2}");
+            }
             let mut task = MergeTask::new(k1, k2);
             let t1 = stage.types.get(&k1).unwrap();
             let t2 = stage.types.get(&k2).unwrap();
-            if let Err(e) = t1.add_merge_deps(t2, &mut task) {
-                let k1_names = fullqual_names.get(k1)?;
-                let k2_names = fullqual_names.get(k2)?;
-                cu::rethrow!(
-                    e,
-                    "failed to add merge deps for {k1} and {k2}\n- merging_name={merging_name}, k1_names={k1_names:#?}, k2_names={k2_names:#?}"
-                );
+            if let Err(e) = t1.add_merge_deps(t2, &mut task, merge_options) {
+                // with keep-both, template conflicts are kept separately like other types
+                if !keep_both && conflict::is_reportable(merging_name, t1, t2) {
+                    conflicts.push(TemplateConflict {
+                        name: merging_name.clone(),
+                        units: vec![unit_names.lookup(k1), unit_names.lookup(k2)],
+                        offsets: vec![k1.to_string(), k2.to_string()],
+                        differences: conflict::diff_layout(t1, t2, &mut permutater),
+                        error: format!("{e:?}"),
+                    });
+                    // mark as processed so the same pair is not reported twice
+                    merge_tasks.insert(key, task);
+                    continue;
+                }
+                if trace_type::matches_name(merging_name) {
+                    trace_type!("cannot merge {k1} and {k2} by name {merging_name}: {e:?}");
+                }
+                let (k1, k2) = key.to_pair();
+                split.entry(k2).or_insert(merge_conflicts.len());
+                merge_conflicts.push(MergeConflict::new(
+                    merging_name.clone(),
+                    [(k1, t1), (k2, t2)],
+                    format!("{e:?}"),
+                    unit_names,
+                    &mut permutater,
+                ));
+                conflicting.insert(key);
+                continue;
             }
             merge_tasks.insert(key, task);
         }
+        if !conflicts.is_empty() || (!keep_both && !merge_conflicts.is_empty()) {
+            return Ok(conflicts);
+        }
+        split_dependents(
+            stage,
+            &mut merge_tasks,
+            &mut split,
+            merge_conflicts,
+            unit_names,
+            &mut permutater,
+        );
         // detect orphan deps (deps that aren't in merge tasks), and merge them if possible
         // orphan deps can happen if a type is anonymous and does not have a typedef,
         // for example, an anonymous member
@@ -91,7 +210,16 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
             for task in merge_tasks.values() {
                 task.track_deps(&mut depmap);
             }
-            let all_deps = depmap.values().flatten().copied().collect::<BTreeSet<_>>();
+            // deps on the split types are removed by split_dependents below
+            let all_deps = depmap
+                .values()
+                .flatten()
+                .copied()
+                .filter(|pair| {
+                    let (k1, k2) = pair.to_pair();
+                    !split.contains_key(&k1) && !split.contains_key(&k2)
+                })
+                .collect::<BTreeSet<_>>();
             let keys = depmap.keys().copied().collect();
             let mut real_orphan_deps = BTreeSet::default();
             for orphan_dep in all_deps.difference(&keys) {
@@ -105,7 +233,7 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
                     let t1 = stage.types.get(&k1).unwrap();
                     let t2 = stage.types.get(&k2).unwrap();
                     cu::check!(
-                        t1.add_merge_deps(t2, &mut task),
+                        t1.add_merge_deps(t2, &mut task, merge_options),
                         "failed to add merge deps (from orphan deps) for {k1} and {k2}"
                     )?;
                     merge_tasks.insert((k1, k2).into(), task);
@@ -154,10 +282,25 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
                 cu::bail!("{error_string}\n{len} orphan deps found");
             }
         }
+        // orphan deps could depend on the split types
+        split_dependents(
+            stage,
+            &mut merge_tasks,
+            &mut split,
+            merge_conflicts,
+            unit_names,
+            &mut permutater,
+        );
 
         merge_tasks.into_values().collect::<Vec<_>>()
     };
 
+    for (k, i) in split {
+        if let Some(t) = stage.types.get_mut(&k) {
+            merge_conflicts[i].renamed = conflict::disambiguate(t, k);
+        }
+    }
+
     let mut buckets = GoffBuckets::default();
     loop {
         let len_before = merge_tasks.len();
@@ -167,7 +310,7 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
                 merge_tasks.push(task);
                 continue;
             }
-            task.execute(&mut stage.types, &mut buckets)?;
+            task.execute(&mut stage.types, &mut buckets, merge_options)?;
         }
         if len_before == merge_tasks.len() {
             break;
@@ -220,7 +363,7 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
                     merge_tasks.push(task);
                     continue;
                 }
-                task.execute(&mut stage.types, &mut buckets)?;
+                task.execute(&mut stage.types, &mut buckets, merge_options)?;
             }
             if len_before == merge_tasks.len() {
                 break;
@@ -236,12 +379,68 @@ fn process_merges(stage: &mut MStage) -> cu::Result<()> {
         std::mem::take(&mut stage.types),
         buckets,
         &mut stage.symbols,
+        &mut stage.typedefs,
         None,
         |data, buckets| data.map_goff(|k| Ok(buckets.primary_fallback(k))),
-        |t1, t2| t1.merge_data(t2),
+        |t1, t2| t1.merge_data(t2, merge_options),
     );
     let deduped = cu::check!(deduped, "link_merge: dedupe failed")?;
     stage.types = deduped;
 
-    Ok(())
+    Ok(vec![])
+}
+
+/// Remove the merges that involve the types kept separately because of conflicts.
+///
+/// Merges that depend on the split types cannot happen either, so the second type
+/// of those merges is also split and reported, until no more merges are affected
+fn split_dependents(
+    stage: &MStage,
+    merge_tasks: &mut BTreeMap<GoffPair, MergeTask>,
+    split: &mut BTreeMap<Goff, usize>,
+    merge_conflicts: &mut Vec<MergeConflict>,
+    unit_names: &UnitNames,
+    permutater: &mut FullQualPermutater,
+) {
+    if split.is_empty() {
+        return;
+    }
+    let is_split = |split: &BTreeMap<Goff, usize>, pair: &GoffPair| {
+        let (k1, k2) = pair.to_pair();
+        split.contains_key(&k1) || split.contains_key(&k2)
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut blocked = vec![];
+        for (key, task) in merge_tasks.iter() {
+            if is_split(split, key) {
+                blocked.push((*key, None));
+                continue;
+            }
+            if let Some(dep) = task.deps().iter().find(|dep| is_split(split, dep)) {
+                blocked.push((*key, Some(*dep)));
+            }
+        }
+        for (key, dep) in blocked {
+            merge_tasks.remove(&key);
+            let Some(dep) = dep else {
+                continue;
+            };
+            changed = true;
+            let (k1, k2) = key.to_pair();
+            let (dep1, dep2) = dep.to_pair();
+            let t1 = stage.types.get(&k1).unwrap();
+            let t2 = stage.types.get(&k2).unwrap();
+            split.entry(k2).or_insert(merge_conflicts.len());
+            let name = conflict::type_name(k1, permutater);
+            merge_conflicts.push(MergeConflict::new(
+                name,
+                [(k1, t1), (k2, t2)],
+                format!("depends on {dep1} and {dep2}, which cannot be merged"),
+                unit_names,
+                permutater,
+            ));
+        }
+    }
 }
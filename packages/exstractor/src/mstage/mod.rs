@@ -1,11 +1,31 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+
+use dejj_utils::MergeConflictMode;
 use exstructs::{GoffSet, MType, algorithm};
 
+use crate::error::{ErrorKind, ResultExt};
 use crate::stages::MStage;
 
+mod ambiguous;
+mod conflict;
+use conflict::UnitNames;
 mod link_merge;
+use link_merge::LinkMergeOutput;
 
-pub async fn link_mstages(mut stages: Vec<MStage>) -> cu::Result<MStage> {
+pub async fn link_mstages(stages: Vec<MStage>) -> cu::Result<MStage> {
     cu::ensure!(!stages.is_empty(), "no CUs to merge")?;
+    let config = Arc::clone(&stages[0].config);
+    let mut unit_names = UnitNames::default();
+    for stage in &stages {
+        unit_names.insert(stage.offset, stage.name.clone());
+    }
+    let unit_names = Arc::new(unit_names);
+    let mut stages = stages
+        .into_iter()
+        .map(QueuedStage::new)
+        .collect::<BinaryHeap<_>>();
     let stage = {
         let total = stages.len() - 1;
         let bar = cu::progress("stage1 -> stage2: merging types")
@@ -14,26 +34,94 @@ pub async fn link_mstages(mut stages: Vec<MStage>) -> cu::Result<MStage> {
             .spawn();
         let pool = cu::co::pool(-1);
         let mut handles = Vec::with_capacity(total / 2 + 1);
-        while let Some(handle) = spawn_task(&mut stages, &pool) {
+        while let Some(handle) = spawn_task(&mut stages, &pool, &unit_names) {
             handles.push(handle);
         }
 
+        let mut conflicts = vec![];
+        let mut merge_conflicts = vec![];
+        // set when a merge fails, new merges are not started after that
+        let mut failed = false;
+        let mut ambiguous = vec![];
+        let mut truncated = BTreeMap::new();
+        let mut merge_count = 0;
         let mut set = cu::co::set(handles);
         while let Some(result) = set.next().await {
-            let merged = result??;
-            cu::progress!(bar += 1);
-            stages.push(merged);
-            if let Some(handle) = spawn_task(&mut stages, &pool) {
+            let merged = match result?? {
+                LinkMergeOutput::Merged(merged, names, truncated_names, kept) => {
+                    ambiguous.extend(names);
+                    merge_conflicts.extend(kept);
+                    // the same type is truncated again in later merges, keep the largest count
+                    for (name, count) in truncated_names {
+                        let entry = truncated.entry(name).or_default();
+                        *entry = count.max(*entry);
+                    }
+                    merged
+                }
+                LinkMergeOutput::Conflict(c, m) => {
+                    // let the running merges finish to report as many conflicts as possible
+                    conflicts.extend(c);
+                    merge_conflicts.extend(m);
+                    failed = true;
+                    cu::progress!(
+                        bar,
+                        "{merge_count} merged, {} conflicts",
+                        conflicts.len() + merge_conflicts.len()
+                    );
+                    continue;
+                }
+            };
+            merge_count += 1;
+            cu::progress!(
+                bar += 1,
+                "{merge_count} merged, {} conflicts",
+                conflicts.len() + merge_conflicts.len()
+            );
+            stages.push(QueuedStage::new(merged));
+            if failed {
+                continue;
+            }
+            if let Some(handle) = spawn_task(&mut stages, &pool, &unit_names) {
                 set.add(handle);
             }
         }
+        drop(bar);
+        warn_truncated(&truncated);
+        conflict::print_top_conflicts(&conflicts);
+        conflict::save_conflict_report(&config, &conflicts)?;
+        merge_conflicts.sort_by(|a, b| a.name.cmp(&b.name));
+        conflict::save_merge_conflict_report(&config, &merge_conflicts)?;
+        cu::ensure!(
+            conflicts.is_empty(),
+            "{} template instantiations have conflicting layouts across compilation units",
+            conflicts.len()
+        )
+        .error_kind(ErrorKind::TemplateConflict)?;
+        if config.extract.merge_conflicts == MergeConflictMode::Fail {
+            cu::ensure!(
+                merge_conflicts.is_empty(),
+                "{} types with the same name cannot be merged, see merge_conflicts.json",
+                merge_conflicts.len()
+            )
+            .error_kind(ErrorKind::TypeMerge)?;
+        }
+        ambiguous::save_ambiguous_report(&config, &ambiguous)?;
+        if config.extract.fail_on_ambiguous_names {
+            cu::ensure!(
+                ambiguous.is_empty(),
+                "{} names are shared by types with different names, see ambiguous_names.json",
+                ambiguous.len()
+            )
+            .error_kind(ErrorKind::TypeMerge)?;
+        }
 
-        let mut stage = stages.into_iter().next().unwrap();
+        let mut stage = stages.pop().unwrap().stage;
 
         let mut marked = GoffSet::default();
         for symbol in stage.symbols.values() {
             symbol.mark(&mut marked);
         }
+        algorithm::mark_typedefs(&stage.typedefs, &mut marked);
         algorithm::mark_and_sweep(marked, &mut stage.types, MType::mark);
         stage
     };
@@ -41,15 +129,69 @@ pub async fn link_mstages(mut stages: Vec<MStage>) -> cu::Result<MStage> {
     Ok(stage)
 }
 
+/// Warn about the types with too many permutated names, which are truncated when linking
+fn warn_truncated(truncated: &BTreeMap<String, usize>) {
+    if truncated.is_empty() {
+        return;
+    }
+    let mut message = format!(
+        "names of {} types are truncated to extract.max-name-permutations:",
+        truncated.len()
+    );
+    for (name, count) in truncated {
+        message += &format!("\n- {name} ({count} names)");
+    }
+    cu::warn!("{message}");
+}
+
+/// Stage waiting to be merged. The smallest stage is the greatest,
+/// so it's popped first from the [`BinaryHeap`]
+struct QueuedStage {
+    size: usize,
+    stage: MStage,
+}
+
+impl QueuedStage {
+    fn new(stage: MStage) -> Self {
+        Self {
+            size: stage.types.len() + stage.symbols.len(),
+            stage,
+        }
+    }
+}
+
+impl PartialEq for QueuedStage {
+    fn eq(&self, other: &Self) -> bool {
+        self.size == other.size
+    }
+}
+impl Eq for QueuedStage {}
+impl PartialOrd for QueuedStage {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for QueuedStage {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.size.cmp(&self.size)
+    }
+}
+
+/// Spawn a task to merge the 2 smallest stages.
+///
+/// Merging the smallest stages first keeps the stages similar in size,
+/// so one big merge doesn't run alone at the end while the other threads are idle
 fn spawn_task(
-    stages: &mut Vec<MStage>,
+    stages: &mut BinaryHeap<QueuedStage>,
     pool: &cu::co::Pool,
-) -> Option<cu::co::Handle<cu::Result<MStage>>> {
+    unit_names: &Arc<UnitNames>,
+) -> Option<cu::co::Handle<cu::Result<LinkMergeOutput>>> {
     if stages.len() <= 1 {
         return None;
     }
-    let unit_a = stages.pop().unwrap();
-    let unit_b = stages.pop().unwrap();
-    let handle = pool.spawn(async move { link_merge::link_merge(unit_a, unit_b) });
+    let unit_a = stages.pop().unwrap().stage;
+    let unit_b = stages.pop().unwrap().stage;
+    let unit_names = Arc::clone(unit_names);
+    let handle = pool.spawn(async move { link_merge::link_merge(unit_a, unit_b, &unit_names) });
     Some(handle)
 }
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::{GoffMap, HType, SizeMap, SymbolInfo};
+
+use crate::database::Database;
+use crate::error::{ErrorKind, ResultExt};
+use crate::lock::OutputLock;
+use crate::run::{self, ExtractOptions};
+use crate::stats::RunStats;
+use crate::summary::RunSummary;
+
+/// Builder for running the extraction programmatically, with the final
+/// artifacts returned in memory instead of saved to the output directory.
+///
+/// The output directory is still used for caches and reports
+/// (for example, the l2mcache and `degraded_units.json`), and is locked while running.
+///
+/// ```ignore
+/// let output = Pipeline::new(config)
+///     .elf("build/main.elf")
+///     .skip_build(true)
+///     .run()?;
+/// let size = output.sizes.get(goff)?;
+/// ```
+pub struct Pipeline {
+    config: Config,
+    options: ExtractOptions,
+}
+
+/// Final artifacts of the extraction
+pub struct PipelineOutput {
+    pub database: Database,
+    /// Size of every type in the database
+    pub sizes: SizeMap,
+    pub summary: RunSummary,
+    /// Timing and counts of each stage
+    pub stats: RunStats,
+}
+
+impl PipelineOutput {
+    pub fn types(&self) -> &GoffMap<HType> {
+        &self.database.types
+    }
+
+    /// Symbols by link name
+    pub fn symbols(&self) -> &BTreeMap<String, SymbolInfo> {
+        &self.database.symbols
+    }
+}
+
+impl Pipeline {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            options: ExtractOptions::default(),
+        }
+    }
+
+    /// Override the path to the ELF (or other object file with DWARF)
+    pub fn elf(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.paths.elf = path.into();
+        self
+    }
+
+    /// Override the path to compile_commands.json
+    pub fn compdb(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.paths.compdb = path.into();
+        self
+    }
+
+    /// Override the directory for caches and reports
+    pub fn output_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.paths.extract_output = path.into();
+        self
+    }
+
+    /// Do not run the build command before extracting
+    pub fn skip_build(mut self, skip: bool) -> Self {
+        self.options.skip_build = skip;
+        self
+    }
+
+    /// Skip the type optimizer, and keep the layouts as-is in DWARF
+    pub fn no_optimize(mut self, no_optimize: bool) -> Self {
+        self.options.no_optimize = no_optimize;
+        self
+    }
+
+    /// Remove the lock file of the output directory, even if it's not stale
+    pub fn force_unlock(mut self, force: bool) -> Self {
+        self.options.force_unlock = force;
+        self
+    }
+
+    /// Log everything that happens to one type, by goff or name
+    pub fn trace_type(mut self, spec: impl Into<String>) -> Self {
+        self.options.trace_type = Some(spec.into());
+        self
+    }
+
+    /// Run the extraction
+    pub fn run(self) -> cu::Result<PipelineOutput> {
+        let _lock = OutputLock::acquire(&self.config, self.options.force_unlock)
+            .error_kind(ErrorKind::OutputLocked)?;
+        let config = Arc::new(self.config);
+        let mut summary = RunSummary::default();
+        let mut stats = RunStats::default();
+        let database = run::extract_database(&config, &self.options, &mut summary, &mut stats)?;
+        let database = cu::check!(database, "no database extracted")?;
+        let sizes = cu::check!(database.sizes(&config), "failed to compute type sizes")?;
+        summary.finish(&Ok(()));
+        Ok(PipelineOutput {
+            database,
+            sizes,
+            summary,
+            stats,
+        })
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::algorithm::FullQualPermutater;
+use exstructs::{FullQualNameMap, Goff, HType, SizeMap};
+use tyyaml::{Document, Prim, Tree};
+
+use crate::database::Database;
+use crate::hover::{HoverData, HoverType, HoverTypeKind};
+
+/// Max number of candidates to print for fuzzy matches
+const MAX_MATCHES: usize = 20;
+
+/// Options for querying an extracted database
+#[derive(Debug, Default)]
+pub struct QueryOptions {
+    /// Path to the database, or a directory with TyYAML output.
+    /// Default is the database emitted by extract
+    pub input: Option<PathBuf>,
+    /// Queries to run, for example `layout uking::ui::PauseMenuDataMgr`.
+    /// If empty, queries are read from stdin interactively
+    pub queries: Vec<String>,
+}
+
+/// Query an extracted database, for example to print the layout of a type or
+/// look up the symbol at an address. The `help` query lists the available queries
+pub fn query(config: &Config, options: QueryOptions) -> cu::Result<()> {
+    let input = options
+        .input
+        .unwrap_or_else(|| Database::default_path(config));
+    let db = if input.is_dir() {
+        let doc = Document::load(&input)?;
+        cu::check!(
+            Database::from_tyyaml(&doc),
+            "failed to convert TyYAML in {}",
+            input.try_to_rel().display()
+        )?
+    } else {
+        Database::load(&input)?
+    };
+    let engine = cu::check!(
+        QueryEngine::new(&db, config),
+        "failed to index database {}",
+        input.try_to_rel().display()
+    )?;
+    if !options.queries.is_empty() {
+        for q in &options.queries {
+            engine.run(q)?;
+        }
+        return Ok(());
+    }
+
+    cu::hint!("type `help` for available queries, or an empty line to exit");
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        stdout.flush()?;
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        let q = line.trim();
+        if q.is_empty() {
+            break;
+        }
+        // errors in interactive mode should not end the session
+        if let Err(e) = engine.run(q) {
+            cu::error!("{e:?}");
+        }
+    }
+    Ok(())
+}
+
+static QUERY_HELP: &str = "\
+find <pattern>     fuzzy search type names
+layout <name>      print the layout of a type, with offsets and padding
+refs <name>        list symbols whose type references the type
+addr <address>     look up the symbol at or before an address (hex)
+sym <name>         look up the address of a symbol by its demangled name
+sizeof <type>      print the size and alignment of a type, which can have
+                   pointer and array suffixes, like `Foo*[4]`
+help               print this message";
+
+struct QueryEngine<'a> {
+    db: &'a Database,
+    hover: HoverData,
+    sizes: SizeMap,
+    /// Goff of the type, by every spelling of the type name
+    goffs: BTreeMap<String, Goff>,
+    /// (address, link name) of the symbols, sorted by address
+    addresses: Vec<(u32, &'a str)>,
+}
+
+impl<'a> QueryEngine<'a> {
+    fn new(db: &'a Database, config: &Config) -> cu::Result<Self> {
+        let hover = HoverData::from_database(db, config)?;
+        let sizes = db.sizes(config)?;
+        let fullqual_names = FullQualNameMap::from_htypes(&db.types)?;
+        let mut permutater = FullQualPermutater::new(&fullqual_names);
+        let mut goffs = BTreeMap::new();
+        for (k, t) in &db.types {
+            if matches!(t, HType::Prim(_)) {
+                continue;
+            }
+            for name in permutater.permutated_fullqual_names(*k)?.iter() {
+                goffs.insert(name.clone(), *k);
+            }
+        }
+        let mut addresses = db
+            .symbols
+            .values()
+            .map(|s| (s.address, s.link_name.as_str()))
+            .collect::<Vec<_>>();
+        addresses.sort_unstable();
+        Ok(Self {
+            db,
+            hover,
+            sizes,
+            goffs,
+            addresses,
+        })
+    }
+
+    fn run(&self, query: &str) -> cu::Result<()> {
+        let (command, arg) = match query.split_once(char::is_whitespace) {
+            Some((command, arg)) => (command, arg.trim()),
+            None => (query, ""),
+        };
+        match command {
+            "help" => println!("{QUERY_HELP}"),
+            "find" => self.find(arg),
+            "layout" => self.layout(arg)?,
+            "refs" => self.refs(arg)?,
+            "addr" => self.addr(arg)?,
+            "sym" => self.sym(arg)?,
+            "sizeof" => self.sizeof(arg)?,
+            _ => cu::bail!("unknown query `{command}`, type `help` for available queries"),
+        }
+        Ok(())
+    }
+
+    fn find(&self, pattern: &str) {
+        let matches = self.fuzzy_find(pattern);
+        if matches.is_empty() {
+            println!("no type matches `{pattern}`");
+            return;
+        }
+        for (_, name) in matches.iter().take(MAX_MATCHES) {
+            println!("{name}");
+        }
+        if matches.len() > MAX_MATCHES {
+            println!("... and {} more", matches.len() - MAX_MATCHES);
+        }
+    }
+
+    fn layout(&self, name: &str) -> cu::Result<()> {
+        let t = &self.hover.types[self.resolve(name)?];
+        let kind = match t.kind {
+            HoverTypeKind::Enum => "enum",
+            HoverTypeKind::Union => "union",
+            HoverTypeKind::Struct => "struct",
+        };
+        println!("{kind} {} (size 0x{:x})", t.name, t.size);
+        if let Some(source) = &t.source {
+            println!("  defined at {source}");
+        }
+        match t.kind {
+            HoverTypeKind::Enum => {
+                for (name, value) in &t.enumerators {
+                    println!("  {name} = {value}");
+                }
+            }
+            HoverTypeKind::Union => {
+                for m in &t.members {
+                    let name = m.name.as_deref().unwrap_or("");
+                    println!("  {} {name}", m.ty);
+                }
+            }
+            HoverTypeKind::Struct => print_struct_layout(t),
+        }
+        for (i, name) in &t.vtable {
+            println!("  vtable[{i}] {name}");
+        }
+        Ok(())
+    }
+
+    fn refs(&self, name: &str) -> cu::Result<()> {
+        let k = self.goffs[&self.hover.types[self.resolve(name)?].name];
+        let mut count = 0;
+        for symbol in self.db.symbols.values() {
+            if !symbol.ty.contains(&k) {
+                continue;
+            }
+            println!("0x{:08x} {}", symbol.address, symbol.link_name);
+            count += 1;
+        }
+        if count == 0 {
+            println!("no symbol references the type");
+        }
+        Ok(())
+    }
+
+    fn addr(&self, address: &str) -> cu::Result<()> {
+        let hex = address.trim_start_matches("0x").trim_start_matches("0X");
+        let address = cu::check!(
+            u32::from_str_radix(hex, 16),
+            "invalid address `{address}`, expecting a hex number"
+        )?;
+        let i = self.addresses.partition_point(|x| x.0 <= address);
+        let Some((start, name)) = i.checked_sub(1).map(|i| self.addresses[i]) else {
+            println!("no symbol at or before 0x{address:08x}");
+            return Ok(());
+        };
+        println!("0x{start:08x}+0x{:x} {name}", address - start);
+        if let Some(symbol) = self.db.demangled.symbols.get(name) {
+            println!("  {}", symbol.demangled);
+        }
+        if let Some((file, line)) = self.db.lines.lookup(address) {
+            println!("  at {file}:{line}");
+        }
+        Ok(())
+    }
+
+    fn sym(&self, name: &str) -> cu::Result<()> {
+        cu::ensure!(!name.is_empty(), "missing symbol name")?;
+        let index = &self.db.demangled;
+        cu::ensure!(
+            !index.is_empty(),
+            "the database does not have demangled names, set extract.demangled-index in the config and extract again"
+        )?;
+        let addresses = index.lookup(name);
+        if !addresses.is_empty() {
+            for address in addresses {
+                println!("0x{address:08x} {name}");
+            }
+            return Ok(());
+        }
+        let mut matches = index
+            .addresses
+            .iter()
+            .filter_map(|(demangled, addresses)| {
+                Some((fuzzy_score(name, demangled)?, demangled.as_str(), addresses))
+            })
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            println!("no symbol matches `{name}`");
+            return Ok(());
+        }
+        matches.sort_unstable();
+        for (_, demangled, addresses) in matches.iter().take(MAX_MATCHES) {
+            for address in addresses.iter() {
+                println!("0x{address:08x} {demangled}");
+            }
+        }
+        if matches.len() > MAX_MATCHES {
+            println!("... and {} more", matches.len() - MAX_MATCHES);
+        }
+        Ok(())
+    }
+
+    fn sizeof(&self, expr: &str) -> cu::Result<()> {
+        cu::ensure!(!expr.is_empty(), "missing type")?;
+        let tree = self.resolve_tree(expr)?;
+        let size = cu::check!(
+            self.sizes.size_of(&tree),
+            "failed to get the size of `{expr}`"
+        )?;
+        let align = cu::check!(
+            self.sizes.align_of(&tree),
+            "failed to get the alignment of `{expr}`"
+        )?;
+        println!("sizeof({expr}) = 0x{size:x} ({size})");
+        println!("alignof({expr}) = {align}");
+        Ok(())
+    }
+
+    /// Resolve a type name with pointer and array suffixes to a type tree,
+    /// for example `Foo*[4]` is an array of 4 pointers to `Foo`
+    fn resolve_tree(&self, expr: &str) -> cu::Result<Tree<Goff>> {
+        let expr = expr.trim();
+        if let Some(pointee) = expr.strip_suffix('*') {
+            return Ok(Tree::Ptr(Box::new(self.resolve_tree(pointee)?)));
+        }
+        if let Some(rest) = expr.strip_suffix(']') {
+            if let Some((elem, len)) = rest.rsplit_once('[') {
+                let len = len.trim();
+                let len = cu::check!(
+                    len.parse::<u32>(),
+                    "invalid array length `{len}` in `{expr}`"
+                )?;
+                return Ok(Tree::Array(Box::new(self.resolve_tree(elem)?), len));
+            }
+        }
+        if let Some(prim) = Prim::from_str(expr) {
+            return Ok(Tree::Base(Goff::prim(prim)));
+        }
+        let name = &self.hover.types[self.resolve(expr)?].name;
+        Ok(Tree::Base(self.goffs[name]))
+    }
+
+    /// Resolve a type name to an index into the hover types. If there is no exact
+    /// match, use the best fuzzy match if all of them are spellings of the same type
+    fn resolve(&self, name: &str) -> cu::Result<usize> {
+        let names = &self.hover.names;
+        cu::ensure!(!name.is_empty(), "missing type name")?;
+        if let Some(i) = names.get(name) {
+            return Ok(*i);
+        }
+        let matches = self.fuzzy_find(name);
+        let Some((best, _)) = matches.first() else {
+            cu::bail!("no type matches `{name}`");
+        };
+        let best = matches
+            .iter()
+            .filter(|(category, _)| category == best)
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+        let first = names[best[0]];
+        if best.iter().all(|x| names[*x] == first) {
+            return Ok(first);
+        }
+        for name in best.iter().take(MAX_MATCHES) {
+            cu::info!("candidate: {name}");
+        }
+        cu::bail!("`{name}` matches {} types, be more specific", best.len())
+    }
+
+    /// Type names matching the pattern with the category of the match,
+    /// the best matches first. See [`fuzzy_score`]
+    fn fuzzy_find(&self, pattern: &str) -> Vec<(u8, &str)> {
+        let mut matches = self
+            .hover
+            .names
+            .keys()
+            .filter_map(|name| Some((fuzzy_score(pattern, name)?, name.as_str())))
+            .collect::<Vec<_>>();
+        matches.sort_unstable();
+        matches
+            .into_iter()
+            .map(|((category, _), name)| (category, name))
+            .collect()
+    }
+}
+
+/// Print the members of a struct with their offsets, and the padding
+/// between the members and at the end
+fn print_struct_layout(t: &HoverType) {
+    let mut end = 0;
+    for m in &t.members {
+        if m.offset > end {
+            println!("  0x{end:04x}  [padding 0x{:x}]", m.offset - end);
+        }
+        let name = match (&m.name, &m.special) {
+            (Some(name), _) => name.as_str(),
+            (None, Some(special)) => special.as_str(),
+            (None, None) => "",
+        };
+        match m.size {
+            Some(size) => {
+                println!("  0x{:04x}  {} {name} (size 0x{size:x})", m.offset, m.ty);
+                end = end.max(m.offset + size);
+            }
+            None => {
+                println!("  0x{:04x}  {} {name} (unsized)", m.offset, m.ty);
+                end = end.max(m.offset);
+            }
+        }
+        for (name, bit_offset, bit_size) in &m.bitfields {
+            let name = name.as_deref().unwrap_or("");
+            println!("          {name} : {bit_size} (bit {bit_offset})");
+        }
+    }
+    if t.size > end {
+        println!("  0x{end:04x}  [padding 0x{:x}]", t.size - end);
+    }
+}
+
+/// Score how well the name matches the pattern, lower is better. None if the
+/// characters of the pattern are not in the name in order (case-insensitive).
+///
+/// Exact matches are the best, then names ending with the pattern
+/// (i.e. the pattern is not qualified), then names containing the pattern,
+/// then the rest. Shorter names are preferred within each category
+fn fuzzy_score(pattern: &str, name: &str) -> Option<(u8, usize)> {
+    let pattern = pattern.to_lowercase();
+    let lower = name.to_lowercase();
+    let category = if lower == pattern {
+        0
+    } else if lower.ends_with(&format!("::{pattern}")) {
+        1
+    } else if lower.contains(&pattern) {
+        2
+    } else {
+        let mut chars = lower.chars();
+        if !pattern.chars().all(|c| chars.any(|x| x == c)) {
+            return None;
+        }
+        3
+    };
+    Some((category, name.len()))
+}
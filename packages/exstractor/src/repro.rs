@@ -0,0 +1,346 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::Goff;
+use gimli::constants::DW_AT_linkage_name;
+
+use crate::compdb::Compdb;
+use crate::dwarf::{ArcBuf, Container, Dwarf, SplitDwarfPaths, Unit};
+
+/// Options for creating a repro bundle
+#[derive(Debug)]
+pub struct ReproOptions {
+    /// Name of the compilation unit. Could be the end of the path
+    /// if it is unique, for example `PauseMenuDataMgr.cpp`
+    pub cu: String,
+    /// Offset of the type in the original DWARF, for example `0x0001f3a0`
+    pub ty: Option<String>,
+    /// Path to the output tarball. Default is `repro.tar` in the extract output
+    pub output: Option<PathBuf>,
+}
+
+/// Sections copied as a whole into the bundle, since the entries in the unit
+/// reference them by section offsets. .debug_info is sliced to the unit
+const COPIED_SECTIONS: &[&str] = &[
+    ".debug_abbrev",
+    ".debug_addr",
+    ".debug_line",
+    ".debug_line_str",
+    ".debug_loc",
+    ".debug_loclists",
+    ".debug_macinfo",
+    ".debug_macro",
+    ".debug_ranges",
+    ".debug_rnglists",
+    ".debug_str",
+    ".debug_str_offsets",
+];
+
+/// Manifest of the repro bundle
+#[derive(Debug, Serialize)]
+struct ReproManifest {
+    /// Name of the compilation unit
+    unit: String,
+    /// Offset of the unit in the original .debug_info
+    original_offset: Goff,
+    /// Offset of the type in the original DWARF
+    #[serde(skip_serializing_if = "Option::is_none")]
+    original_type: Option<Goff>,
+    /// Offset of the type in the DWARF in the bundle
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r#type: Option<Goff>,
+    /// Number of symbols in the listing
+    symbols: usize,
+}
+
+/// Create a self-contained bundle that reproduces the extraction of one compilation unit.
+///
+/// The bundle has the DWARF of the unit in a minimal ELF, the compile command,
+/// the config with the paths replaced, and the symbols defined in the unit,
+/// so it can be attached to an issue without the original program
+pub fn repro(config_path: impl AsRef<Path>, options: ReproOptions) -> cu::Result<()> {
+    let config_path = config_path.as_ref();
+    let config = Config::load(config_path)?;
+    let ty = match &options.ty {
+        None => None,
+        Some(ty) => Some(parse_goff(ty)?),
+    };
+
+    let compile_commands = Compdb::load(&config)?;
+    let bytes = ArcBuf::map(&config.paths.elf)?;
+    let split_paths = SplitDwarfPaths::for_object_file(&config.paths.elf);
+    let dwarf = Dwarf::try_parse(bytes.clone(), split_paths)?;
+    let unit = find_unit(&dwarf, &options.cu)?;
+    cu::ensure!(
+        !unit.is_split(),
+        "{unit} is loaded from split DWARF, which is not supported in repro bundles"
+    )?;
+    let range = unit.debug_info_range();
+    let new_ty = match ty {
+        None => None,
+        Some(ty) => {
+            cu::ensure!(
+                range.contains(&ty.0),
+                "type {ty} is not in {unit}, which is at 0x{:08x}..0x{:08x}",
+                range.start,
+                range.end
+            )?;
+            Some(Goff(ty.0 - range.start))
+        }
+    };
+    let command = cu::check!(
+        compile_commands.get(&unit.name)?,
+        "cannot find compile command for {}",
+        unit.name
+    )?;
+
+    let elf = {
+        let container = Container::parse(bytes.bytes())?;
+        let debug_info = cu::check!(
+            container.section_data(".debug_info")?,
+            "missing .debug_info section"
+        )?;
+        // the unit is moved to the start of .debug_info. Offsets local to the unit
+        // are unchanged, but references to other units (DW_FORM_ref_addr) are broken
+        let mut copied = vec![];
+        for name in COPIED_SECTIONS {
+            if let Some(data) = container.section_data(name)? {
+                copied.push((*name, data));
+            }
+        }
+        let mut sections = vec![(".debug_info", &debug_info[range.clone()])];
+        sections.extend(copied.iter().map(|(name, data)| (*name, data.as_ref())));
+        write_elf(&sections)
+    };
+    let symbols = unit_symbols(&config, &bytes, &unit)?;
+    let symbol_count = symbols.lines().count();
+    let compdb = {
+        #[derive(Serialize)]
+        struct Entry<'a> {
+            directory: &'a str,
+            file: &'a str,
+            command: String,
+        }
+        let entry = Entry {
+            directory: ".",
+            file: &unit.name,
+            // the first word is the compiler, which is removed when parsing
+            command: format!("cc {}", shell_words::join(&command.command)),
+        };
+        json::stringify_pretty(&[entry])?
+    };
+    let config_content = sanitize_config(&cu::fs::read_string(config_path)?);
+    let manifest = ReproManifest {
+        unit: unit.name.clone(),
+        original_offset: Goff(range.start),
+        original_type: ty,
+        r#type: new_ty,
+        symbols: symbol_count,
+    };
+    let manifest = json::stringify_pretty(&manifest)?;
+
+    let output = match options.output {
+        Some(x) => x,
+        None => config.paths.extract_output.join("repro.tar"),
+    };
+    let files: [(&str, &[u8]); 5] = [
+        ("repro/repro.json", manifest.as_bytes()),
+        ("repro/dejj.toml", config_content.as_bytes()),
+        ("repro/compile_commands.json", compdb.as_bytes()),
+        ("repro/symbols.txt", symbols.as_bytes()),
+        ("repro/repro.elf", &elf),
+    ];
+    cu::check!(
+        write_tar(&output, &files),
+        "failed to write repro bundle to {}",
+        output.try_to_rel().display()
+    )?;
+    cu::info!(
+        "bundled {unit} ({} bytes of .debug_info, {symbol_count} symbols)",
+        range.len()
+    );
+    if let (Some(ty), Some(new_ty)) = (ty, new_ty) {
+        cu::info!("type {ty} is at {new_ty} in the bundle");
+    }
+    cu::hint!(
+        "repro bundle saved to {}. The compile command is not sanitized, check it before sharing",
+        output.try_to_rel().display()
+    );
+    Ok(())
+}
+
+fn parse_goff(s: &str) -> cu::Result<Goff> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    let offset = cu::check!(
+        usize::from_str_radix(hex, 16),
+        "invalid type offset {s}, expected a hex offset like 0x0001f3a0"
+    )?;
+    Ok(Goff(offset))
+}
+
+/// Find the unit by name, or by the end of the name if it is unique
+fn find_unit(dwarf: &Arc<Dwarf>, name: &str) -> cu::Result<Unit> {
+    let mut matches = vec![];
+    let mut iter = Dwarf::iter_units(dwarf);
+    while let Some(unit) = iter.next_unit()? {
+        if unit.name == name {
+            return Ok(unit);
+        }
+        if unit.name.ends_with(name) {
+            matches.push(unit);
+        }
+    }
+    match matches.len() {
+        0 => cu::bail!("cannot find compilation unit {name}"),
+        1 => Ok(matches.pop().unwrap()),
+        n => {
+            for unit in &matches {
+                cu::info!("- {}", unit.name);
+            }
+            cu::bail!("{n} compilation units match {name}, use the full name")
+        }
+    }
+}
+
+/// Get the listed symbols with linkage names in the unit, in the format of `nm`
+fn unit_symbols(config: &Config, bytes: &ArcBuf, unit: &Unit) -> cu::Result<String> {
+    let mut linkage_names = BTreeSet::new();
+    let mut cursor = unit.cursor();
+    while let Some(entry) = cursor.next_entry()? {
+        if let Some(name) = entry.str_opt(DW_AT_linkage_name)? {
+            linkage_names.insert(name.to_string());
+        }
+    }
+    let source = crate::run::symbol_source(config, Some(bytes.bytes()))?;
+    let symbols = source.load()?;
+    let mut output = String::new();
+    for (map, kind) in [(&symbols.funcs, "T"), (&symbols.data, "D")] {
+        for (name, address) in map {
+            if linkage_names.contains(name) {
+                output += &format!("{address:016x} {kind} {name}\n");
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Keep the config except the paths, which are replaced with the files in the bundle
+fn sanitize_config(content: &str) -> String {
+    let mut output = String::from(
+        r#"# paths are replaced by dejj repro
+[paths]
+build-dir = "."
+elf = "repro.elf"
+extract-output = "output"
+compdb = "compile_commands.json"
+system-header-paths = []
+
+[paths.symbols]
+source = "nm"
+path = "symbols.txt"
+base-address = 0
+"#,
+    );
+    let mut in_paths = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            let table = trimmed.trim_start_matches('[').trim_end_matches(']').trim();
+            in_paths = table == "paths" || table.starts_with("paths.");
+        }
+        if in_paths {
+            continue;
+        }
+        // the export output is a local path
+        if trimmed.starts_with("output-dir") {
+            continue;
+        }
+        output.push_str(line);
+        output.push('\n');
+    }
+    output
+}
+
+/// Write a minimal little-endian ELF64 with only the sections
+fn write_elf(sections: &[(&str, &[u8])]) -> Vec<u8> {
+    const EHDR_SIZE: usize = 64;
+    const SHDR_SIZE: usize = 64;
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_STRTAB: u32 = 3;
+
+    // section name string table, starting with the empty name
+    let mut shstrtab = vec![0u8];
+    let mut name_offsets = Vec::with_capacity(sections.len());
+    for (name, _) in sections {
+        name_offsets.push(shstrtab.len() as u32);
+        shstrtab.extend_from_slice(name.as_bytes());
+        shstrtab.push(0);
+    }
+    let shstrtab_name = shstrtab.len() as u32;
+    shstrtab.extend_from_slice(b".shstrtab\0");
+
+    let mut data = vec![0u8; EHDR_SIZE];
+    // (name, type, offset, size)
+    let mut headers = vec![(0, 0, 0, 0)];
+    for ((_, section), name) in sections.iter().zip(name_offsets) {
+        headers.push((name, SHT_PROGBITS, data.len(), section.len()));
+        data.extend_from_slice(section);
+    }
+    headers.push((shstrtab_name, SHT_STRTAB, data.len(), shstrtab.len()));
+    data.extend_from_slice(&shstrtab);
+    data.resize(data.len().next_multiple_of(8), 0);
+    let shoff = data.len();
+
+    for (name, sh_type, offset, size) in &headers {
+        data.extend_from_slice(&name.to_le_bytes());
+        data.extend_from_slice(&sh_type.to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes()); // flags
+        data.extend_from_slice(&0u64.to_le_bytes()); // addr
+        data.extend_from_slice(&(*offset as u64).to_le_bytes());
+        data.extend_from_slice(&(*size as u64).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes()); // link
+        data.extend_from_slice(&0u32.to_le_bytes()); // info
+        data.extend_from_slice(&1u64.to_le_bytes()); // addralign
+        data.extend_from_slice(&0u64.to_le_bytes()); // entsize
+    }
+
+    let mut ehdr = Vec::with_capacity(EHDR_SIZE);
+    // magic, 64-bit, little-endian, version 1
+    ehdr.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    ehdr.extend_from_slice(&[0; 8]);
+    ehdr.extend_from_slice(&1u16.to_le_bytes()); // ET_REL
+    ehdr.extend_from_slice(&0u16.to_le_bytes()); // EM_NONE
+    ehdr.extend_from_slice(&1u32.to_le_bytes()); // version
+    ehdr.extend_from_slice(&0u64.to_le_bytes()); // entry
+    ehdr.extend_from_slice(&0u64.to_le_bytes()); // phoff
+    ehdr.extend_from_slice(&(shoff as u64).to_le_bytes());
+    ehdr.extend_from_slice(&0u32.to_le_bytes()); // flags
+    ehdr.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend_from_slice(&0u16.to_le_bytes()); // phentsize
+    ehdr.extend_from_slice(&0u16.to_le_bytes()); // phnum
+    ehdr.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+    ehdr.extend_from_slice(&(headers.len() as u16).to_le_bytes());
+    ehdr.extend_from_slice(&((headers.len() - 1) as u16).to_le_bytes()); // shstrndx
+    data[..EHDR_SIZE].copy_from_slice(&ehdr);
+    data
+}
+
+fn write_tar(path: &Path, files: &[(&str, &[u8])]) -> cu::Result<()> {
+    if let Some(parent) = path.parent() {
+        cu::fs::make_dir(parent)?;
+    }
+    let file = std::fs::File::create(path)?;
+    let mut builder = tar::Builder::new(file);
+    for (name, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data)?;
+    }
+    builder.into_inner()?;
+    Ok(())
+}
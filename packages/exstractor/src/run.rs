@@ -1,46 +1,178 @@
 use std::collections::BTreeMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
 use cu::pre::*;
-use dejj_utils::Config;
-use llvmutils::Demangler;
+use dejj_utils::{AddressRangeSymbolSource, Config, LineTableMode, SymbolSource};
+use exstructs::{GoffMap, LType};
+use llvmutils::{ClangBatcher, Demangler, TypeNameCache};
 use symlist::SymbolList;
 
-use crate::dwarf::Dwarf;
-use crate::dwarf_loader;
+use crate::compdb::Compdb;
+use crate::database::{CallEdge, Database, LineTable};
+use crate::degrade::Degradation;
+use crate::demangled_index::DemangledIndex;
+use crate::dwarf::{ArcBuf, Dwarf, ElfSymbolSource, SplitDwarfPaths, Unit, elf_base_address};
+use crate::dwarf_loader::{self, FunctionFrame, InlineSite, InlinedCall, LStageTimes, LineRow};
+use crate::error::{ErrorKind, FailureReport, ResultExt, UnitFailure};
+use crate::export::ExporterRegistry;
 use crate::hstage;
-use crate::lstage;
+use crate::lock::OutputLock;
+use crate::lstage::{self, ParseCommand};
 use crate::mstage;
 use crate::stage_cache::L2mCache;
-use crate::stages::StageInfo;
+use crate::stages::{LStage, MStage, StageInfo};
+use crate::stats::{RunStats, UnitStats};
+use crate::summary::RunSummary;
+use crate::symbol_listing;
+use crate::trace_type;
 
-pub fn run(config: Config) -> cu::Result<()> {
-    cu::fs::make_dir(&config.paths.extract_output)?;
-    // build the project to generate the ELF
-    // usually this should be fast since the build is incremental
-    cu::check!(
-        build_project(&config),
-        "failed to execute build command, please ensure the decomp project is in a clean state."
-    )?;
+/// Options for extraction that are not from the config file
+#[derive(Debug, Default)]
+pub struct ExtractOptions {
+    /// Skip the type optimizer, and emit the layouts as-is in DWARF
+    pub no_optimize: bool,
+    /// Goff or name of a type to log verbosely through all stages
+    pub trace_type: Option<String>,
+    /// Only load symbols in stage0 and emit the symbol listing,
+    /// skipping type merging and layouts
+    pub symbols_only: bool,
+    /// Do not run the build command, and use the existing ELF and compile_commands.json
+    pub skip_build: bool,
+    /// Remove the lock file of the output directory, even if it's not stale
+    pub force_unlock: bool,
+    /// Save timing and counts of each stage to this path as JSON
+    pub stats_out: Option<PathBuf>,
+    /// Save the final stage3 types in debug format to this path.
+    /// This is very large for real programs
+    pub dump_hstage: Option<PathBuf>,
+    /// Skip compilation units that fail stage0 or stage1 even with the safest settings,
+    /// and continue with the rest. The failures are saved to `failures.json`
+    pub keep_going: bool,
+}
+
+/// Run the extraction. If it fails, the error is categorized with an [`ErrorKind`],
+/// and the failure report is saved to the output directory.
+///
+/// The run summary is saved to the output directory regardless of the result,
+/// unless the output directory is locked by another run.
+/// If the extraction finished, the summary is returned to check if it's partial
+pub fn run(config: Config, options: ExtractOptions) -> cu::Result<RunSummary> {
+    // don't touch anything in the output directory until locked
+    let _lock =
+        OutputLock::acquire(&config, options.force_unlock).error_kind(ErrorKind::OutputLocked)?;
+    let failure_path = FailureReport::default_path(&config);
+    let summary_path = RunSummary::default_path(&config);
+    let stats_out = options.stats_out.clone();
+    let mut summary = RunSummary::default();
+    let mut stats = RunStats::default();
+    let result = run_internal(config, options, &mut summary, &mut stats);
+    summary.finish(&result);
+    if let Some(path) = stats_out {
+        // saved even if failed, for diagnosing the stages that ran
+        match cu::fs::write_json_pretty(&path, &stats) {
+            Ok(()) => cu::hint!("run stats saved to {}", path.try_to_rel().display()),
+            Err(e) => cu::warn!("failed to save run stats: {e:?}"),
+        }
+    }
+    match &summary.failure {
+        None => {
+            // remove stale report from previous runs
+            if let Err(e) = cu::fs::remove(&failure_path) {
+                cu::warn!("failed to remove stale failure report: {e:?}");
+            }
+        }
+        Some(report) => match cu::fs::write_json_pretty(&failure_path, report) {
+            Ok(()) => cu::hint!(
+                "failure report saved to {}",
+                failure_path.try_to_rel().display()
+            ),
+            Err(e) => cu::warn!("failed to save failure report: {e:?}"),
+        },
+    }
+    match cu::fs::write_json_pretty(&summary_path, &summary) {
+        Ok(()) => cu::hint!(
+            "run summary saved to {}",
+            summary_path.try_to_rel().display()
+        ),
+        Err(e) => cu::warn!("failed to save run summary: {e:?}"),
+    }
+    result.map(|_| summary)
+}
 
+fn run_internal(
+    config: Config,
+    options: ExtractOptions,
+    summary: &mut RunSummary,
+    stats: &mut RunStats,
+) -> cu::Result<()> {
     let config = Arc::new(config);
+    let Some(database) = extract_database(&config, &options, summary, stats)? else {
+        // symbols only
+        return Ok(());
+    };
+    let start = Instant::now();
+    let database_path = Database::default_path(&config);
+    database
+        .save(&database_path)
+        .error_kind(ErrorKind::Output)?;
+    cu::hint!("database saved to {}", database_path.try_to_rel().display());
+
+    let exporters = ExporterRegistry::default();
+    for format in &config.export.on_extract {
+        exporters
+            .export(&database, &config, format)
+            .error_kind(ErrorKind::Output)?;
+    }
+    stats.record_stage("export", start);
+
+    Ok(())
+}
+
+/// Run the extraction up to the final database, without saving it.
+/// Returns None if only the symbol listing is extracted.
+///
+/// Caches and reports are still written to the output directory
+pub(crate) fn extract_database(
+    config: &Arc<Config>,
+    options: &ExtractOptions,
+    summary: &mut RunSummary,
+    stats: &mut RunStats,
+) -> cu::Result<Option<Database>> {
+    if let Some(spec) = &options.trace_type {
+        trace_type::init(spec);
+    }
+    cu::fs::make_dir(&config.paths.extract_output).error_kind(ErrorKind::Output)?;
+    let clang_invocations = llvmutils::clang_invocation_count();
+    let start = Instant::now();
+    if options.skip_build {
+        cu::info!("skipping build command");
+    } else {
+        // build the project to generate the ELF
+        // usually this should be fast since the build is incremental
+        cu::check!(
+            build_project(config),
+            "failed to execute build command, please ensure the decomp project is in a clean state."
+        )
+        .error_kind(ErrorKind::Build)?;
+        stats.record_stage("build", start);
+    }
+    let config = Arc::clone(config);
 
     // parse the compile_commands.json file generated by building the project (cmake)
-    let compile_commands = llvmutils::parse_compdb(&config.paths.compdb)?;
-    let demangler_cache = config.paths.extract_output.join("demangler_cache.json");
+    let start = Instant::now();
+    let compile_commands = Compdb::load(&config).error_kind(ErrorKind::Compdb)?;
+    let demangler_cache = config.paths.extract_output.join("demangler_cache.jsonl");
     let demangler = Arc::new(Demangler::try_new(demangler_cache)?);
+    let bytes = ArcBuf::map(&config.paths.elf).error_kind(ErrorKind::CorruptDwarf)?;
     let symbol_list = {
         let config = Arc::clone(&config);
         let demangler = Arc::clone(&demangler);
-        let mut symbol_list = SymbolList::default();
-        symbol_list.load_data(&config.paths.data_csv)?;
-        let symbol_list = cu::co::run(async move {
-            symbol_list
-                .load_func(&config.paths.functions_csv, demangler)
-                .await?;
-            cu::Ok(symbol_list)
-        })?;
+        let bytes = bytes.clone();
+        let symbol_list =
+            cu::co::run(async move { load_symbol_list(&config, bytes.bytes(), demangler).await })
+                .error_kind(ErrorKind::Symbols)?;
         cu::info!("loaded {} symbols from listing", symbol_list.len());
         symbol_list
     };
@@ -48,132 +180,296 @@ pub fn run(config: Config) -> cu::Result<()> {
     if let Err(e) = demangler.flush_cache() {
         cu::warn!("failed to flush demangler cache: {e:?}");
     }
+    stats.record_stage("symbols", start);
 
     // parse DWARF
-    let bytes: Arc<[u8]> = cu::fs::read(&config.paths.elf)?.into();
-    let dwarf = Dwarf::try_parse(bytes)?;
+    let start = Instant::now();
+    let split_paths = SplitDwarfPaths::for_object_file(&config.paths.elf);
+    let dwarf = Dwarf::try_parse(bytes, split_paths).error_kind(ErrorKind::CorruptDwarf)?;
 
     let units = {
         let mut units = Vec::new();
         let mut iter = Dwarf::iter_units(&dwarf);
         while let Some(unit) = iter
             .next_unit()
-            .context("error while collecting units from DWARF")?
+            .context("error while collecting units from DWARF")
+            .error_kind(ErrorKind::CorruptDwarf)?
         {
             units.push(unit);
         }
         cu::info!("found {} compilation units", units.len());
+        let units = filter_units(&config, units);
+        summary.counts.units = units.len();
+        check_unit_metadata(&config, &units, summary);
         units
     };
+    stats.record_stage("dwarf", start);
 
-    let stages = {
-        let config = Arc::clone(&config);
+    if options.symbols_only {
+        summary.counts.symbols =
+            symbol_listing::extract_symbols_only(units, &config, &symbol_list, &demangler)
+                .error_kind(ErrorKind::TypeLoad)?;
+        return Ok(None);
+    }
+
+    // each unit is streamed through stage0 and stage1 in one task, so at most
+    // one stage0 per worker is in memory at any time
+    let start = Instant::now();
+    let keep_going = options.keep_going;
+    let batcher = match config.extract.type_parser.batch_size {
+        0 | 1 => None,
+        size => Some(Arc::new(ClangBatcher::new(size))),
+    };
+    let name_cache = open_type_name_cache(&config).map(Arc::new);
+    let name_cache1 = name_cache.clone();
+    let (stages, constants, calls, lines, save_cache_task) = {
+        let cache = Arc::new(L2mCache::open(&config)?);
+        let config1 = Arc::clone(&config);
         let symbol_list = Arc::clone(&symbol_list);
-        let stages = cu::co::run(async move {
-            let bar = cu::progress("stage0: loading types")
+        let (outputs, mut failures, save_cache_task) = cu::co::run(async move {
+            let bar = cu::progress("stage0 -> stage1: loading and reducing types")
                 .total(units.len())
                 .spawn();
             let mut handles = Vec::with_capacity(units.len());
             let pool = cu::co::pool(-1);
             let mut output = Vec::with_capacity(units.len());
+            let mut failures = Vec::new();
 
             for unit in units {
-                let config = Arc::clone(&config);
+                let name = unit.name.to_string();
+                let command = compile_commands.get(&name).and_then(|command| {
+                    cu::check!(command, "cannot find compile command for {name}")
+                });
+                let command = command.error_kind(ErrorKind::MissingCompileCommand);
+                let command = match command {
+                    Ok(command) => command,
+                    Err(e) if keep_going => {
+                        cu::warn!("skipping {name}: {e:?}");
+                        failures.push(UnitFailure::new(name, &e));
+                        cu::progress!(bar += 1);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                let command = ParseCommand {
+                    command,
+                    batcher: batcher.clone(),
+                    name_cache: name_cache1.clone(),
+                };
+                let cache = Arc::clone(&cache);
+                let config = Arc::clone(&config1);
                 let symbol_list = Arc::clone(&symbol_list);
                 let handle = pool.spawn(async move {
-                    let ns = dwarf_loader::load_namespaces(&unit)?;
-                    let stage0 = dwarf_loader::load_lstage(&unit, config, ns, symbol_list)?;
-                    cu::Ok(stage0)
+                    let result = process_unit(&unit, command, &cache, &config, &symbol_list).await;
+                    (name, result)
                 });
                 handles.push(handle);
             }
 
             let mut set = cu::co::set(handles);
             while let Some(result) = set.next().await {
-                let stage = result??;
-                cu::progress!(bar += 1, "{}", stage.name);
-                output.push(stage);
-            }
-            output.sort_unstable_by_key(|x| x.offset);
-            cu::Ok(output)
-        })?;
-
-        let mut info = StageInfo::new(0);
-        stages.iter().for_each(|x| info.add_lstage(x));
-        info.print();
-
-        stages
-    };
-
-    if config.extract.debug.lstage {
-        let types = stages
-            .iter()
-            .flat_map(|x| &x.types)
-            .collect::<BTreeMap<_, _>>();
-        save_debug(types, &config.paths.extract_output, "lstage");
-    }
-
-    let (stages, save_cache_task) = {
-        let compile_commands = compile_commands.clone();
-        let cache = L2mCache::open(&config)?;
-        let cache = Arc::new(cache);
-        let cache1 = Arc::clone(&cache);
-
-        let (stages, save_cache_task) = cu::co::run(async move {
-            let bar = cu::progress("stage0 -> stage1: reducing types")
-                .total(stages.len())
-                .spawn();
-            let mut handles = Vec::with_capacity(stages.len());
-            let pool = cu::co::pool(-1);
-            let mut output = Vec::with_capacity(stages.len());
-
-            for stage in stages {
-                let name = &stage.name;
-                let command = cu::check!(
-                    compile_commands.get(name),
-                    "cannot find compile command for {name}"
-                )?;
-                let command = command.clone();
-                let cache = Arc::clone(&cache);
-                let handle =
-                    pool.spawn(async move { lstage::to_mstage(stage, command, &cache).await });
-                handles.push(handle);
-            }
-
-            let mut set = cu::co::set(handles);
-            while let Some(result) = set.next().await {
-                let stage = result??;
-                cu::progress!(bar += 1, "{}", stage.name);
-                output.push(stage);
+                let (name, result) = result?;
+                match result {
+                    Ok(unit_output) => output.push(unit_output),
+                    Err(e) if keep_going => {
+                        cu::warn!("skipping {name}: {e:?}");
+                        failures.push(UnitFailure::new(name.clone(), &e));
+                    }
+                    Err(e) => return Err(e),
+                }
+                cu::progress!(bar += 1, "{name}");
             }
             drop(bar);
-            output.sort_unstable_by_key(|x| x.offset);
+            output.sort_unstable_by_key(|x| x.mstage.offset);
 
             let save_cache_task = cu::co::spawn(async move { cache.save() });
 
-            cu::Ok((output, save_cache_task))
+            cu::Ok((output, failures, save_cache_task))
         })?;
+        if let Some(name_cache) = &name_cache {
+            if let Err(e) = name_cache.flush_cache() {
+                cu::warn!("failed to flush type name cache: {e:?}");
+            }
+        }
+        failures.sort_by(|a, b| a.unit.cmp(&b.unit));
+        for failure in &failures {
+            if let Some(kind) = failure.error.kind {
+                summary.add_failure(kind);
+            }
+        }
+        save_unit_failures(&config, &failures).error_kind(ErrorKind::Output)?;
+        summary.counts.failed_units = failures.len();
+        if !failures.is_empty() {
+            summary.warnings.push(format!(
+                "{} compilation units failed and were skipped",
+                failures.len()
+            ));
+        }
 
+        let mut info = StageInfo::new(0);
+        let mut degraded = BTreeMap::new();
+        // anonymous enums could be eliminated in later stages,
+        // so the values are hoisted in stage0
+        let mut constants = BTreeMap::new();
+        let mut calls = Vec::new();
+        let mut frames = Vec::new();
+        let mut inlined_calls = Vec::new();
+        let mut line_rows = Vec::new();
+        let mut lstage_types = BTreeMap::new();
+        let mut stages = Vec::with_capacity(outputs.len());
+        for output in outputs {
+            info.merge(&output.lstage_info);
+            if output.load_level > 0 {
+                summary.add_failure(ErrorKind::TypeLoad);
+            }
+            if output.level > output.load_level {
+                summary.add_failure(ErrorKind::TypeReduce);
+            }
+            if output.level > 0 {
+                degraded.insert(
+                    output.mstage.name.clone(),
+                    Degradation::LEVELS[output.level].describe(),
+                );
+            }
+            lstage::merge_constants(&mut constants, output.constants, &output.mstage.name);
+            calls.extend(output.calls);
+            frames.extend(output.frames);
+            inlined_calls.extend(output.inlined_calls);
+            line_rows.extend(output.line_rows);
+            if let Some(types) = output.lstage_types {
+                lstage_types.extend(types);
+            }
+            stats.units.push(UnitStats {
+                name: output.mstage.name.clone(),
+                millis: output.millis,
+                type_load_millis: output.load_times.types_millis,
+                symbol_load_millis: output.load_times.symbols_millis,
+                lstage_types: output.lstage_type_count,
+                mstage_types: output.mstage.types.len(),
+                cache_hit: output.mstage.is_cache_hit,
+                degradation_level: output.level,
+            });
+            stages.push(output.mstage);
+        }
+        stats.record_counts(info);
+        cu::info!("hoisted {} constants", constants.len());
+        if config.extract.call_graph {
+            // inline functions are defined in multiple units
+            calls.sort_unstable();
+            calls.dedup();
+            cu::info!("loaded {} direct calls", calls.len());
+        }
+        if config.extract.frame_layouts {
+            // inline functions are defined in multiple units
+            frames.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+            frames.dedup_by(|a, b| a.name == b.name);
+            save_frames(&config, &frames).error_kind(ErrorKind::Output)?;
+        }
+        if config.extract.inline_report {
+            // inline functions are defined in multiple units
+            inlined_calls.sort_unstable();
+            inlined_calls.dedup();
+            save_inline_report(&config, inlined_calls).error_kind(ErrorKind::Output)?;
+        }
+        let lines = LineTable::from_rows(line_rows);
+        if config.extract.line_table != LineTableMode::Off {
+            cu::info!(
+                "loaded {} line rows in {} files",
+                lines.rows.len(),
+                lines.files.len()
+            );
+        }
+        if config.extract.debug.lstage {
+            save_debug(&lstage_types, &config.paths.extract_output, "lstage");
+        }
+        save_degraded_units(&config, &degraded).error_kind(ErrorKind::Output)?;
+        summary.counts.degraded_units = degraded.len();
+        if !degraded.is_empty() {
+            summary.warnings.push(format!(
+                "{} compilation units needed degraded settings to load",
+                degraded.len()
+            ));
+        }
+
+        for stage in &stages {
+            trace_type::trace_mstage(stage, &format!("stage1 reduced ({})", stage.name));
+        }
         let cache_hit_count = stages.iter().filter(|x| x.is_cache_hit).count();
         cu::info!(
             "l2mcache hit {} of {} compilation units",
             cache_hit_count,
             stages.len()
         );
-        (stages, save_cache_task)
+        (stages, constants, calls, lines, save_cache_task)
     };
+    stats.record_stage("stage0-1", start);
 
-    let stage = cu::co::run(async move { mstage::link_mstages(stages).await })?;
-    StageInfo::mstage2(&stage).print();
+    let start = Instant::now();
+    stats.merge.input_types = stages.iter().map(|x| x.types.len()).sum();
+    let stage = cu::co::run(async move { mstage::link_mstages(stages).await })
+        .error_kind(ErrorKind::TypeMerge)?;
+    stats.record_stage("stage2", start);
+    stats.merge.linked_types = stage.types.len();
+    stats.merge.deduped_types = stats
+        .merge
+        .input_types
+        .saturating_sub(stats.merge.linked_types);
+    stats.record_counts(StageInfo::mstage2(&stage));
+    trace_type::trace_mstage(&stage, "stage2 linked");
     if config.extract.debug.mstage {
         save_debug(&stage.types, &config.paths.extract_output, "mstage");
     }
 
-    let stage = cu::co::run(async move { hstage::from_mstage(stage).await })?;
-    StageInfo::hstage3(&stage).print();
+    let start = Instant::now();
+    let stage = cu::co::run(async move { hstage::from_mstage(stage).await })
+        .error_kind(ErrorKind::TypeResolve)?;
+    stats.record_stage("stage3-convert", start);
+    stats.merge.hstage_types = stage.types.len();
+    trace_type::trace_hstage(&stage, "stage3 converted");
+    let stage = if options.no_optimize {
+        cu::info!("skipping type optimizer");
+        stage
+    } else {
+        // keep the unoptimized layouts for consumers that want
+        // the layouts as-is in DWARF
+        let raw_database_path = Database::raw_path(&config);
+        Database::from_hstage(&stage, &constants, &calls, &lines)
+            .save(&raw_database_path)
+            .error_kind(ErrorKind::Output)?;
+        cu::hint!(
+            "unoptimized database saved to {}",
+            raw_database_path.try_to_rel().display()
+        );
+        let start = Instant::now();
+        let stage = cu::co::run(async move { hstage::optimize(stage).await })
+            .error_kind(ErrorKind::TypeOptimize)?;
+        stats.record_stage("stage3-optimize", start);
+        stage
+    };
+    stats.record_counts(StageInfo::hstage3(&stage));
+    trace_type::trace_hstage(&stage, "stage3 final");
     if config.extract.debug.hstage {
         save_debug(&stage.types, &config.paths.extract_output, "hstage");
     }
+    if let Some(path) = &options.dump_hstage {
+        save_debug_to(&stage.types, path, "hstage");
+    }
+    match hstage::verify_layouts(&stage) {
+        Ok(issues) => {
+            let errors = issues.iter().filter(|x| x.is_error()).count();
+            if errors > 0 {
+                summary.warn(format!(
+                    "{errors} structs have overlapping members or members past the declared size"
+                ));
+            }
+        }
+        Err(e) => cu::warn!("failed to verify struct layouts: {e:?}"),
+    }
+    if config.extract.debug.name_graph {
+        if let Err(e) = hstage::save_name_graph(&stage, &config.paths.extract_output) {
+            cu::warn!("failed to save name graph: {e:?}");
+        }
+    }
 
     cu::co::run(async move {
         if let Err(e) = save_cache_task.co_join().await.flatten() {
@@ -181,11 +477,413 @@ pub fn run(config: Config) -> cu::Result<()> {
         }
     });
 
-    cu::hint!("todo");
+    let mut database = Database::from_hstage(&stage, &constants, &calls, &lines);
+    if config.extract.demangled_index {
+        database.demangled = cu::check!(
+            DemangledIndex::build(&database.symbols, &demangler),
+            "failed to build the demangled name index"
+        )
+        .error_kind(ErrorKind::Symbols)?;
+        if let Err(e) = demangler.flush_cache() {
+            cu::warn!("failed to flush demangler cache: {e:?}");
+        }
+    }
+    stats.merge.final_types = database.types.len();
+    stats.clang_invocations = llvmutils::clang_invocation_count() - clang_invocations;
+    summary.counts.types = database.types.len();
+    summary.counts.symbols = database.symbols.len();
+    summary.counts.constants = database.constants.len();
+    Ok(Some(database))
+}
 
+/// Only keep the units matching the unit-include and unit-exclude regexes in the config
+fn filter_units(config: &Config, units: Vec<Unit>) -> Vec<Unit> {
+    let include = &config.extract.unit_include;
+    let exclude = &config.extract.unit_exclude;
+    if include.is_empty() && exclude.is_empty() {
+        return units;
+    }
+    let total = units.len();
+    let units = units
+        .into_iter()
+        .filter(|unit| {
+            if !include.is_empty() && !include.iter().any(|r| r.is_match(&unit.name)) {
+                return false;
+            }
+            !exclude.iter().any(|r| r.is_match(&unit.name))
+        })
+        .collect::<Vec<_>>();
+    cu::info!(
+        "skipped {} of {total} compilation units by unit-include and unit-exclude",
+        total - units.len()
+    );
+    units
+}
+
+/// Output of streaming one unit through stage0 and stage1
+struct UnitOutput {
+    mstage: MStage,
+    /// Degradation level needed to load stage0 of the unit
+    load_level: usize,
+    /// Degradation level needed to process the unit
+    level: usize,
+    lstage_info: StageInfo,
+    /// Constants hoisted from anonymous enums and macros in stage0
+    constants: BTreeMap<String, i64>,
+    /// Direct calls from functions in the unit, if the call graph is enabled
+    calls: Vec<CallEdge>,
+    /// Stack frames of functions in the unit, if frame layouts are enabled
+    frames: Vec<FunctionFrame>,
+    /// Functions inlined into functions in the unit, if the inline report is enabled
+    inlined_calls: Vec<InlinedCall>,
+    /// Line table rows of functions in the unit, if the line table is enabled
+    line_rows: Vec<LineRow>,
+    /// Stage0 types, only kept if the lstage debug output is enabled
+    lstage_types: Option<GoffMap<LType>>,
+    /// Number of types loaded in stage0
+    lstage_type_count: usize,
+    /// Time to process the unit
+    millis: u64,
+    /// Time to load the types and symbols in stage0, with the level used
+    load_times: LStageTimes,
+}
+
+async fn process_unit(
+    unit: &Unit,
+    command: ParseCommand,
+    cache: &L2mCache,
+    config: &Arc<Config>,
+    symbol_list: &Arc<SymbolList>,
+) -> cu::Result<UnitOutput> {
+    let start = Instant::now();
+    // batches don't wait for this unit after it's processed
+    let _batch_guard = command.batcher.as_ref().map(|b| b.enter());
+    let (stage, load_level, load_times) =
+        load_lstage_with_retry(unit, config, symbol_list, 0).error_kind(ErrorKind::TypeLoad)?;
+    trace_type::trace_lstage(&stage, "stage0 loaded");
+    let mut lstage_info = StageInfo::new(0);
+    lstage_info.add_lstage(&stage);
+    let mut constants = BTreeMap::new();
+    lstage::hoist_anonymous_enums(&stage, &mut constants);
+    if config.extract.macro_constants {
+        match unit.object_macros() {
+            Ok(macros) => lstage::hoist_macros(&macros, &mut constants, &stage.name),
+            Err(e) => cu::warn!("failed to read macros of {unit}: {e:?}"),
+        }
+    }
+    let calls = if config.extract.call_graph {
+        match dwarf_loader::load_call_graph(unit, symbol_list) {
+            Ok(calls) => calls,
+            Err(e) => {
+                cu::warn!("failed to load call graph of {unit}: {e:?}");
+                vec![]
+            }
+        }
+    } else {
+        vec![]
+    };
+    let frames = if config.extract.frame_layouts {
+        match dwarf_loader::load_frames(unit, &stage.ns, symbol_list) {
+            Ok(frames) => frames,
+            Err(e) => {
+                cu::warn!("failed to load frame layouts of {unit}: {e:?}");
+                vec![]
+            }
+        }
+    } else {
+        vec![]
+    };
+    let inlined_calls = if config.extract.inline_report {
+        match dwarf_loader::load_inlined_calls(unit, symbol_list) {
+            Ok(calls) => calls,
+            Err(e) => {
+                cu::warn!("failed to load inlined subroutines of {unit}: {e:?}");
+                vec![]
+            }
+        }
+    } else {
+        vec![]
+    };
+    let line_rows =
+        match dwarf_loader::load_line_table(unit, symbol_list, config.extract.line_table) {
+            Ok(rows) => rows,
+            Err(e) => {
+                cu::warn!("failed to load line table of {unit}: {e:?}");
+                vec![]
+            }
+        };
+    let lstage_types = if config.extract.debug.lstage {
+        Some(stage.types.clone())
+    } else {
+        None
+    };
+    let lstage_type_count = stage.types.len();
+    let (mstage, level) =
+        to_mstage_with_retry(stage, load_level, unit, command, cache, config, symbol_list)
+            .await
+            .error_kind(ErrorKind::TypeReduce)?;
+    Ok(UnitOutput {
+        mstage,
+        load_level,
+        level,
+        lstage_info,
+        constants,
+        calls,
+        frames,
+        inlined_calls,
+        line_rows,
+        lstage_types,
+        lstage_type_count,
+        millis: start.elapsed().as_millis() as u64,
+        load_times,
+    })
+}
+
+/// Load stage0 of the unit, starting from the degradation level,
+/// and retry with safer settings if it fails. Returns the stage, the level used,
+/// and the time to load the stage
+pub(crate) fn load_lstage_with_retry(
+    unit: &Unit,
+    config: &Arc<Config>,
+    symbol_list: &Arc<SymbolList>,
+    start_level: usize,
+) -> cu::Result<(LStage, usize, LStageTimes)> {
+    let ns = dwarf_loader::load_namespaces(unit, config)?;
+    let mut level = start_level;
+    loop {
+        let result = dwarf_loader::load_lstage(
+            unit,
+            Arc::clone(config),
+            ns.clone(),
+            Arc::clone(symbol_list),
+            Degradation::LEVELS[level],
+        );
+        match result {
+            Ok((stage, times)) => return Ok((stage, level, times)),
+            Err(e) if level + 1 < Degradation::LEVELS.len() => {
+                level += 1;
+                cu::warn!(
+                    "stage0 failed for {unit}, retrying with {:?}: {e:?}",
+                    Degradation::LEVELS[level].describe()
+                );
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Reduce the stage to stage1. If it fails, reload stage0 from the unit with
+/// safer settings and try again. Returns the stage and the degradation level used
+async fn to_mstage_with_retry(
+    mut stage: LStage,
+    mut level: usize,
+    unit: &Unit,
+    command: ParseCommand,
+    cache: &L2mCache,
+    config: &Arc<Config>,
+    symbol_list: &Arc<SymbolList>,
+) -> cu::Result<(MStage, usize)> {
+    loop {
+        let result =
+            lstage::to_mstage(stage, command.clone(), cache, Degradation::LEVELS[level]).await;
+        match result {
+            Ok(stage) => return Ok((stage, level)),
+            Err(e) if level + 1 < Degradation::LEVELS.len() => {
+                cu::warn!(
+                    "stage1 failed for {unit}, retrying with {:?}: {e:?}",
+                    Degradation::LEVELS[level + 1].describe()
+                );
+                (stage, level, _) = load_lstage_with_retry(unit, config, symbol_list, level + 1)
+                    .error_kind(ErrorKind::TypeLoad)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Save which features were degraded for which units to `degraded_units.json`
+fn save_degraded_units(
+    config: &Config,
+    degraded: &BTreeMap<String, Vec<&'static str>>,
+) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("degraded_units.json");
+    if degraded.is_empty() {
+        // remove stale report from previous runs
+        cu::fs::remove(&path)?;
+        return Ok(());
+    }
+    cu::warn!(
+        "{} compilation units needed degraded settings to load",
+        degraded.len()
+    );
+    cu::fs::write_json_pretty(&path, degraded)?;
+    cu::hint!("degraded units saved to {}", path.try_to_rel().display());
+    Ok(())
+}
+
+/// Open the cache of parsed type names. The cache is cleared when the ELF
+/// is rebuilt, since the names could resolve to different types
+fn open_type_name_cache(config: &Config) -> Option<TypeNameCache> {
+    let path = config.paths.extract_output.join("type_name_cache.jsonl");
+    let mtime = std::fs::metadata(&config.paths.elf)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let stamp = format!("{}:{mtime}", config.paths.elf.display());
+    match TypeNameCache::try_new(path, &stamp) {
+        Ok(x) => Some(x),
+        Err(e) => {
+            cu::warn!("failed to open type name cache: {e:?}");
+            None
+        }
+    }
+}
+
+/// Save the compilation units skipped with `--keep-going` to `failures.json`
+fn save_unit_failures(config: &Config, failures: &[UnitFailure]) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("failures.json");
+    if failures.is_empty() {
+        // remove stale report from previous runs
+        cu::fs::remove(&path)?;
+        return Ok(());
+    }
+    cu::warn!(
+        "{} compilation units failed and were skipped",
+        failures.len()
+    );
+    cu::fs::write_json_pretty(&path, &failures)?;
+    cu::hint!("failed units saved to {}", path.try_to_rel().display());
+    Ok(())
+}
+
+fn save_frames(config: &Config, frames: &[FunctionFrame]) -> cu::Result<()> {
+    let path = config.paths.extract_output.join("frames.json");
+    cu::fs::write_json_pretty(&path, &frames)?;
+    cu::hint!(
+        "{} frame layouts saved to {}",
+        frames.len(),
+        path.try_to_rel().display()
+    );
+    Ok(())
+}
+
+/// Save the inlined calls grouped by the link name of the inlined function
+fn save_inline_report(config: &Config, calls: Vec<InlinedCall>) -> cu::Result<()> {
+    let mut report = BTreeMap::<String, Vec<InlineSite>>::new();
+    for call in calls {
+        report.entry(call.inlined).or_default().push(call.site);
+    }
+    let path = config.paths.extract_output.join("inlined-into.json");
+    cu::fs::write_json_pretty(&path, &report)?;
+    cu::hint!(
+        "{} inlined functions saved to {}",
+        report.len(),
+        path.try_to_rel().display()
+    );
     Ok(())
 }
 
+/// Report mixed DWARF versions, and units whose address size does not
+/// match the configured pointer width
+fn check_unit_metadata(config: &Config, units: &[Unit], summary: &mut RunSummary) {
+    let mut versions = BTreeMap::<u16, usize>::new();
+    for unit in units {
+        *versions.entry(unit.version).or_default() += 1;
+        if unit.address_size as u32 * 8 != config.extract.pointer_width as u32 {
+            summary.warn(format!(
+                "{unit} has address size {} but config.extract.pointer-width is {}, produced by: {}",
+                unit.address_size,
+                config.extract.pointer_width,
+                unit.producer.as_deref().unwrap_or("<unknown>")
+            ));
+        }
+    }
+    if versions.len() > 1 {
+        cu::debug!("found mixed DWARF versions (version => unit count): {versions:?}");
+    }
+}
+
+async fn load_symbol_list(
+    config: &Config,
+    elf_bytes: &[u8],
+    demangler: Arc<Demangler>,
+) -> cu::Result<SymbolList> {
+    let source = symbol_source(config, Some(elf_bytes))?;
+    let mut symbol_list = SymbolList::new(config.extract.check_virtual_bases);
+    symbol_list.load(source.as_ref(), demangler).await?;
+    save_fabricated_symbols(config, &symbol_list)?;
+    Ok(symbol_list)
+}
+
+/// Save the fabricated ctor/dtor symbols for auditing
+fn save_fabricated_symbols(config: &Config, symbol_list: &SymbolList) -> cu::Result<()> {
+    #[derive(Serialize)]
+    struct Entry<'a> {
+        name: &'a str,
+        address: u32,
+        from: &'a str,
+    }
+    let path = config.paths.extract_output.join("fabricated_symbols.json");
+    let entries = symbol_list
+        .fabricated()
+        .iter()
+        .map(|(name, f)| Entry {
+            name,
+            address: f.address,
+            from: &f.from,
+        })
+        .collect::<Vec<_>>();
+    cu::fs::write_json_pretty(&path, &entries)?;
+    cu::debug!(
+        "{} fabricated ctor/dtor symbols saved to {}",
+        entries.len(),
+        path.try_to_rel().display()
+    );
+    Ok(())
+}
+
+/// Get the source of the symbol listing in the config
+pub(crate) fn symbol_source<'a>(
+    config: &'a Config,
+    elf_bytes: Option<&'a [u8]>,
+) -> cu::Result<Box<dyn SymbolSource + 'a>> {
+    let base_address = if config.paths.symbols.detect_base_address {
+        let bytes = cu::check!(
+            elf_bytes,
+            "cannot detect the base address since the ELF is not readable"
+        )?;
+        cu::check!(
+            elf_base_address(bytes),
+            "failed to detect the base address from the ELF"
+        )?
+    } else {
+        config.paths.symbols.base_address
+    };
+    let source = match config.paths.symbol_file_source(base_address)? {
+        Some(source) => source,
+        None => {
+            let bytes = cu::check!(
+                elf_bytes,
+                "cannot load symbols since the ELF is not readable"
+            )?;
+            Box::new(ElfSymbolSource {
+                bytes,
+                relocator: config.paths.symbol_relocator(base_address),
+            })
+        }
+    };
+    let ranges = &config.extract.symbol_address_ranges;
+    if ranges.is_empty() {
+        return Ok(source);
+    }
+    Ok(Box::new(AddressRangeSymbolSource {
+        inner: source,
+        ranges,
+    }))
+}
+
 fn build_project(config: &Config) -> cu::Result<()> {
     // unwrap: config is validated
     let build_bin = config.extract.build_command.first().unwrap();
@@ -208,11 +906,14 @@ fn build_project(config: &Config) -> cu::Result<()> {
 }
 
 fn save_debug(t: impl std::fmt::Debug, out_dir: &Path, name: &str) {
+    save_debug_to(t, &out_dir.join(format!("{name}.rs")), name)
+}
+
+fn save_debug_to(t: impl std::fmt::Debug, out_path: &Path, name: &str) {
     let debug_info = format!(
         "/* The .rs extension is only for syntax highlighting and the macro is to suppress syntax errors */ {name}!{{{t:#?}}}",
     );
-    let out_path = out_dir.join(format!("{name}.rs"));
-    match cu::fs::write(&out_path, debug_info) {
+    match cu::fs::write(out_path, debug_info) {
         Ok(()) => cu::hint!(
             "{} debug info saved to {}",
             name,
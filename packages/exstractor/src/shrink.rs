@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::algorithm::MapGoff;
+use exstructs::algorithm::{self, FullQualPermutater};
+use exstructs::{FullQualNameMap, Goff, GoffMap, GoffMapFn, GoffSet, HType};
+use regex::Regex;
+use tyyaml::Prim;
+
+use crate::database::Database;
+
+/// Options for shrinking an extracted database into a lite database
+#[derive(Debug, Default)]
+pub struct ShrinkOptions {
+    /// Path to the input database. Default is the database emitted by extraction
+    pub input: Option<PathBuf>,
+    /// Path to the output database. Default is `database.lite.json` next to the input
+    pub output: Option<PathBuf>,
+    /// Keep declaration-only structs (declared but never defined) instead of replacing them with `u8`
+    pub keep_decls: bool,
+    /// Structs with a name matching any of these are made opaque
+    pub strip: Vec<String>,
+    /// Template instantiations larger than this many bytes are made opaque
+    pub template_size_threshold: Option<u32>,
+    /// Only keep types that are referenced from symbols
+    pub gc: bool,
+}
+
+/// Shrink the extracted database for editor tooling, without re-running extraction
+pub fn shrink(config: &Config, options: ShrinkOptions) -> cu::Result<()> {
+    let input = options
+        .input
+        .unwrap_or_else(|| Database::default_path(config));
+    let output = match options.output {
+        Some(x) => x,
+        None => input.with_extension("lite.json"),
+    };
+    let strip = options
+        .strip
+        .iter()
+        .map(|x| cu::check!(Regex::new(x), "invalid strip regex: {x}"))
+        .collect::<cu::Result<Vec<_>>>()?;
+
+    let mut db = Database::load(&input)?;
+    let type_count = db.types.len();
+
+    if !options.keep_decls {
+        let count = cu::check!(drop_decls(&mut db), "failed to drop declaration-only types")?;
+        cu::info!("dropped {count} declaration-only types");
+    }
+
+    let opaque = cu::check!(
+        find_opaque(&db, &strip, options.template_size_threshold),
+        "failed to find types to strip"
+    )?;
+    let mut count = 0;
+    for k in &opaque {
+        if let HType::Struct(data) = db.types.get_mut(k).unwrap() {
+            count += data.data.make_opaque() as usize;
+        }
+    }
+    if count > 0 {
+        let u8_goff = Goff::prim(Prim::U8);
+        db.types.entry(u8_goff).or_insert(HType::Prim(Prim::U8));
+    }
+    cu::info!("stripped {count} types into opaque blobs");
+
+    if options.gc {
+        cu::check!(
+            algorithm::keep_referenced_from_symbols(&mut db.types, &db.symbols, &db.typedefs),
+            "failed to remove unreferenced types"
+        )?;
+    }
+
+    db.save(&output)?;
+    cu::info!(
+        "shrunk database from {} to {} types",
+        type_count,
+        db.types.len()
+    );
+    cu::hint!("lite database saved to {}", output.try_to_rel().display());
+    Ok(())
+}
+
+/// Remove the structs that are declared but never defined (see [`exstructs::Struct::is_decl`]),
+/// and replace the references to them with `u8`. Returns the number of types dropped
+fn drop_decls(db: &mut Database) -> cu::Result<usize> {
+    let u8_goff = Goff::prim(Prim::U8);
+    let replacements = db
+        .types
+        .iter()
+        .filter_map(|(k, t)| match t {
+            HType::Struct(data) if data.data.is_decl => Some((*k, u8_goff)),
+            _ => None,
+        })
+        .collect::<GoffMap<_>>();
+    if replacements.is_empty() {
+        return Ok(0);
+    }
+    db.types.retain(|k, _| !replacements.contains_key(k));
+    db.types.entry(u8_goff).or_insert(HType::Prim(Prim::U8));
+    let f: GoffMapFn = Box::new(|k| Ok(replacements.get(&k).copied().unwrap_or(k)));
+    for (k, t) in &mut db.types {
+        cu::check!(t.map_goff(&f), "failed to replace declarations in type {k}")?;
+    }
+    for (name, symbol) in &mut db.symbols {
+        cu::check!(
+            symbol.map_goff(&f),
+            "failed to replace declarations in symbol '{name}'"
+        )?;
+    }
+    for (name, tree) in &mut db.typedefs {
+        cu::check!(
+            tree.map_goff(&f),
+            "failed to replace declarations in typedef '{name}'"
+        )?;
+    }
+    Ok(replacements.len())
+}
+
+/// Find structs to make opaque, either because its name is stripped, or because
+/// it's a large template instantiation
+fn find_opaque(
+    db: &Database,
+    strip: &[Regex],
+    template_size_threshold: Option<u32>,
+) -> cu::Result<GoffSet> {
+    let fullqual_names = FullQualNameMap::from_htypes(&db.types)?;
+    let mut permutater = FullQualPermutater::new(&fullqual_names);
+    let mut opaque = GoffSet::default();
+    for (k, t) in &db.types {
+        let HType::Struct(data) = t else {
+            continue;
+        };
+        // types with vtable cannot be opaque, since the vfptr must be kept
+        if !data.data.vtable.is_empty() {
+            continue;
+        }
+        let is_large_template = match template_size_threshold {
+            Some(threshold) => {
+                !data.data.template_args.is_empty() && data.data.byte_size > threshold
+            }
+            None => false,
+        };
+        if is_large_template {
+            opaque.insert(*k);
+            continue;
+        }
+        if strip.is_empty() {
+            continue;
+        }
+        for name in permutater.permutated_fullqual_names(*k)?.iter() {
+            if strip.iter().any(|r| r.is_match(name)) {
+                opaque.insert(*k);
+                break;
+            }
+        }
+    }
+    Ok(opaque)
+}
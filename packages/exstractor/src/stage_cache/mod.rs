@@ -12,6 +12,7 @@ use exstructs::{
     Goff, GoffMap, GoffMapFn, GoffSet, LType, MType, NamespaceMaps, SymbolInfo, algorithm::MapGoff,
 };
 use rkyv::rancor;
+use tyyaml::Tree;
 
 use crate::stages::{LStage, MStage};
 
@@ -274,6 +275,7 @@ struct MStageCacheData {
     pub config_hash: u64,
     pub normalized_types: GoffMap<MType>,
     pub normalized_symbols: BTreeMap<String, SymbolInfo>,
+    pub normalized_typedefs: BTreeMap<String, Tree<Goff>>,
 }
 
 impl MStageCacheData {
@@ -281,10 +283,12 @@ impl MStageCacheData {
         // normalize Goffs to indices everywhere
         let normalized_types = convert(&stage.types, &goffs, goff2index)?;
         let normalized_symbols = convert_nongoff(&stage.symbols, &goffs, goff2index)?;
+        let normalized_typedefs = convert_nongoff(&stage.typedefs, goffs, goff2index)?;
         Ok(Self {
             config_hash: stage.config.hash,
             normalized_types,
             normalized_symbols,
+            normalized_typedefs,
         })
     }
     pub fn to_mstage(&self, stage: &LStage, goffs: &[Goff]) -> cu::Result<MStage> {
@@ -293,6 +297,7 @@ impl MStageCacheData {
         let config = Arc::clone(&stage.config);
         let types = convert(&self.normalized_types, goffs, index2goff)?;
         let symbols = convert_nongoff(&self.normalized_symbols, goffs, index2goff)?;
+        let typedefs = convert_nongoff(&self.normalized_typedefs, goffs, index2goff)?;
         Ok(MStage {
             is_cache_hit: true,
             offset,
@@ -300,6 +305,7 @@ impl MStageCacheData {
             types,
             config,
             symbols,
+            typedefs,
         })
     }
 }
@@ -311,6 +317,7 @@ struct LStageCacheData {
     pub normalized_types: GoffMap<LType>,
     pub normalized_namespaces: NamespaceMaps,
     pub normalized_symbols: BTreeMap<String, SymbolInfo>,
+    pub normalized_typedefs: BTreeMap<String, Tree<Goff>>,
 }
 
 impl LStageCacheData {
@@ -321,6 +328,7 @@ impl LStageCacheData {
         let normalized_ns_namespaces = convert(&stage.ns.namespaces, &goffs, goff2index)?;
         let normalized_ns_by_src = convert_nongoff(&stage.ns.by_src, &goffs, goff2index)?;
         let normalized_symbols = convert_nongoff(&stage.symbols, &goffs, goff2index)?;
+        let normalized_typedefs = convert_nongoff(&stage.typedefs, goffs, goff2index)?;
         Ok(Self {
             config_hash: stage.config.hash,
             normalized_types,
@@ -328,8 +336,10 @@ impl LStageCacheData {
                 qualifiers: normalized_ns_qualifiers,
                 namespaces: normalized_ns_namespaces,
                 by_src: normalized_ns_by_src,
+                abi_tags: stage.ns.abi_tags.clone(),
             },
             normalized_symbols,
+            normalized_typedefs,
         })
     }
 }
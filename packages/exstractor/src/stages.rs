@@ -4,9 +4,15 @@ use std::sync::Arc;
 use cu::pre::*;
 
 use dejj_utils::Config;
-use exstructs::{GoffMap, HType, LType, MType, NameGraph, NamespaceMaps, SizeMap, SymbolInfo};
+use exstructs::{
+    Goff, GoffMap, HType, LType, MType, NameGraph, NamespaceMaps, SizeMap, SymbolInfo,
+};
+use tyyaml::Tree;
 
-#[derive(Default)]
+pub use crate::hstage::AuditLog;
+
+/// Type and symbol counts of a stage
+#[derive(Debug, Default, Serialize)]
 pub struct StageInfo {
     stage_num: usize,
     enum_count: usize,
@@ -78,6 +84,19 @@ impl StageInfo {
         self.add_symbols(&stage.symbols)
     }
 
+    /// Add the counts from another info
+    pub fn merge(&mut self, other: &Self) {
+        self.enum_count += other.enum_count;
+        self.enum_decl_count += other.enum_decl_count;
+        self.union_count += other.union_count;
+        self.union_decl_count += other.union_decl_count;
+        self.struct_count += other.struct_count;
+        self.struct_decl_count += other.struct_decl_count;
+        self.other_count += other.other_count;
+        self.data_count += other.data_count;
+        self.func_count += other.func_count;
+    }
+
     fn add_symbols(&mut self, symbols: &BTreeMap<String, SymbolInfo>) {
         for si in symbols.values() {
             if si.is_data() {
@@ -179,10 +198,58 @@ pub struct HStage {
     pub types: GoffMap<HType>,
     pub config: Arc<Config>,
     pub symbols: BTreeMap<String, SymbolInfo>,
+    /// Named typedefs that are kept as type trees, by name
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
     /// Size of each type, cached for convenience
     pub sizes: Arc<SizeMap>,
     /// Relationship of the names
     pub name_graph: NameGraph,
+    /// Changes made by the type optimizer
+    pub audit_log: AuditLog,
+}
+
+impl HStage {
+    /// Create the stage from its serialized data, re-computing the sizes
+    pub fn from_data(data: HStageData, config: Arc<Config>) -> cu::Result<Self> {
+        let sizes = size_map(&data.types, &config)?;
+        Ok(Self {
+            types: data.types,
+            config,
+            symbols: data.symbols,
+            typedefs: data.typedefs,
+            sizes: Arc::new(sizes),
+            name_graph: Default::default(),
+            audit_log: Default::default(),
+        })
+    }
+
+    /// Clone the serializable data of the stage
+    pub fn to_data(&self) -> HStageData {
+        HStageData {
+            types: self.types.clone(),
+            symbols: self.symbols.clone(),
+            typedefs: self.typedefs.clone(),
+        }
+    }
+}
+
+/// Serializable data of [`HStage`]. The config and caches derived from it are not included
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HStageData {
+    pub types: GoffMap<HType>,
+    pub symbols: BTreeMap<String, SymbolInfo>,
+    #[serde(default)]
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
+}
+
+/// Compute the size and alignment of every type in a high-level type map
+pub(crate) fn size_map(types: &GoffMap<HType>, config: &Config) -> cu::Result<SizeMap> {
+    Ok(SizeMap::from_types(
+        types,
+        config.extract.pointer_size()?,
+        config.extract.ptmd_size()?,
+        config.extract.ptmf_size()?,
+    ))
 }
 
 /// Mid-level (M) type stage
@@ -193,6 +260,8 @@ pub struct MStage {
     pub types: GoffMap<MType>,
     pub config: Arc<Config>,
     pub symbols: BTreeMap<String, SymbolInfo>,
+    /// Named typedefs that are kept as type trees, by name
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
 }
 
 impl MStage {
@@ -210,6 +279,10 @@ impl MStage {
                 self.symbols.insert(s.link_name.to_string(), s);
             }
         }
+        // the types are deduped after linking, so keeping either tree is fine
+        for (name, tree) in other.typedefs {
+            self.typedefs.entry(name).or_insert(tree);
+        }
         Ok(Self {
             is_cache_hit: false,
             offset: 0,
@@ -217,8 +290,44 @@ impl MStage {
             types: self.types,
             config: self.config,
             symbols: self.symbols,
+            typedefs: self.typedefs,
         })
     }
+
+    /// Create the stage from its serialized data
+    pub fn from_data(data: MStageData, config: Arc<Config>) -> Self {
+        Self {
+            is_cache_hit: false,
+            offset: data.offset,
+            name: data.name,
+            types: data.types,
+            config,
+            symbols: data.symbols,
+            typedefs: data.typedefs,
+        }
+    }
+
+    /// Clone the serializable data of the stage
+    pub fn to_data(&self) -> MStageData {
+        MStageData {
+            offset: self.offset,
+            name: self.name.clone(),
+            types: self.types.clone(),
+            symbols: self.symbols.clone(),
+            typedefs: self.typedefs.clone(),
+        }
+    }
+}
+
+/// Serializable data of [`MStage`], without the config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MStageData {
+    pub offset: usize,
+    pub name: String,
+    pub types: GoffMap<MType>,
+    pub symbols: BTreeMap<String, SymbolInfo>,
+    #[serde(default)]
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
 }
 
 /// Low-level (L) type stage
@@ -229,4 +338,45 @@ pub struct LStage {
     pub config: Arc<Config>,
     pub ns: NamespaceMaps,
     pub symbols: BTreeMap<String, SymbolInfo>,
+    /// Named typedefs that are kept as type trees, by name
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
+}
+
+impl LStage {
+    /// Create the stage from its serialized data
+    pub fn from_data(data: LStageData, config: Arc<Config>) -> Self {
+        Self {
+            offset: data.offset,
+            name: data.name,
+            types: data.types,
+            config,
+            ns: data.ns,
+            symbols: data.symbols,
+            typedefs: data.typedefs,
+        }
+    }
+
+    /// Clone the serializable data of the stage
+    pub fn to_data(&self) -> LStageData {
+        LStageData {
+            offset: self.offset,
+            name: self.name.clone(),
+            types: self.types.clone(),
+            ns: self.ns.clone(),
+            symbols: self.symbols.clone(),
+            typedefs: self.typedefs.clone(),
+        }
+    }
+}
+
+/// Serializable data of [`LStage`], without the config
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LStageData {
+    pub offset: usize,
+    pub name: String,
+    pub types: GoffMap<LType>,
+    pub ns: NamespaceMaps,
+    pub symbols: BTreeMap<String, SymbolInfo>,
+    #[serde(default)]
+    pub typedefs: BTreeMap<String, Tree<Goff>>,
 }
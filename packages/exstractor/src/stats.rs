@@ -0,0 +1,74 @@
+use std::time::Instant;
+
+use cu::pre::*;
+
+use crate::stages::StageInfo;
+
+/// Timing and counts of an extract run, for finding which stages and
+/// compilation units take the most time. Saved with `--stats-out`
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    /// Time of each stage, in the order they finished
+    pub stages: Vec<StageStats>,
+    /// Stats of each compilation unit in stage0 and stage1, by unit offset
+    pub units: Vec<UnitStats>,
+    /// Stats of linking the units in stage2 and the conversion after
+    pub merge: MergeStats,
+    /// Number of times clang is invoked to parse type names (i.e. AST cache misses)
+    pub clang_invocations: usize,
+    /// Type and symbol counts after stage0 (all units), stage2 and stage3
+    pub counts: Vec<StageInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StageStats {
+    pub name: &'static str,
+    pub millis: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnitStats {
+    pub name: String,
+    /// Time to stream the unit through stage0 and stage1
+    pub millis: u64,
+    /// Time to load the types in stage0. Symbols are loaded at the same time
+    pub type_load_millis: u64,
+    /// Time to load the symbols in stage0
+    pub symbol_load_millis: u64,
+    /// Number of types loaded in stage0
+    pub lstage_types: usize,
+    /// Number of types after reducing in stage1
+    pub mstage_types: usize,
+    /// If stage1 is loaded from the l2mcache
+    pub cache_hit: bool,
+    /// Degradation level used to process the unit, 0 is not degraded
+    pub degradation_level: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MergeStats {
+    /// Total number of types in all units before linking
+    pub input_types: usize,
+    /// Number of types after linking and merging the units
+    pub linked_types: usize,
+    /// Number of types deduplicated by linking
+    pub deduped_types: usize,
+    /// Number of types after converting to stage3, before optimizing
+    pub hstage_types: usize,
+    /// Number of types in the final database
+    pub final_types: usize,
+}
+
+impl RunStats {
+    /// Record the time of a stage that started at start
+    pub(crate) fn record_stage(&mut self, name: &'static str, start: Instant) {
+        let millis = start.elapsed().as_millis() as u64;
+        cu::debug!("{name} took {millis}ms");
+        self.stages.push(StageStats { name, millis });
+    }
+    /// Print the type and symbol counts of a stage, and keep them in the stats
+    pub(crate) fn record_counts(&mut self, info: StageInfo) {
+        info.print();
+        self.counts.push(info);
+    }
+}
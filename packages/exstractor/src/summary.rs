@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use cu::pre::*;
+use dejj_utils::Config;
+
+use crate::error::{ErrorKind, FailureReport};
+
+/// Exit code of a successful extraction
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit code of an extraction that finished, but some units needed degraded settings,
+/// or failed and were skipped with `--keep-going`.
+/// The outputs are written, but might be missing some data
+pub const EXIT_PARTIAL: i32 = 7;
+
+/// Machine-readable summary of an extract run, always saved to `run_summary.json`
+/// (unless the config cannot be loaded)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub status: RunStatus,
+    /// Exit code of the process. See [`ErrorKind::exit_code`] for codes of failures
+    pub exit_code: i32,
+    pub counts: RunCounts,
+    /// Warnings that affect the outputs
+    pub warnings: Vec<String>,
+    /// Number of failures by category, including failures that were
+    /// recovered from by retrying with degraded settings
+    pub failure_categories: BTreeMap<ErrorKind, usize>,
+    /// The error if the run failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<FailureReport>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunStatus {
+    #[default]
+    Success,
+    /// Outputs are written, but some units needed degraded settings or were skipped
+    Partial,
+    Failed,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunCounts {
+    /// Number of compilation units in DWARF
+    pub units: usize,
+    /// Number of compilation units that needed degraded settings
+    pub degraded_units: usize,
+    /// Number of compilation units that failed and were skipped with `--keep-going`
+    #[serde(default)]
+    pub failed_units: usize,
+    /// Number of types in the output database
+    pub types: usize,
+    /// Number of symbols in the output database (or listing, for `--symbols-only`)
+    pub symbols: usize,
+    /// Number of constants hoisted from anonymous enums and macros
+    pub constants: usize,
+}
+
+impl RunSummary {
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("run_summary.json")
+    }
+
+    pub(crate) fn warn(&mut self, message: String) {
+        cu::warn!("{message}");
+        self.warnings.push(message);
+    }
+
+    pub(crate) fn add_failure(&mut self, kind: ErrorKind) {
+        *self.failure_categories.entry(kind).or_default() += 1;
+    }
+
+    /// Set the status and exit code from the result of the run
+    pub(crate) fn finish(&mut self, result: &cu::Result<()>) {
+        match result {
+            Ok(()) => {
+                if self.counts.degraded_units > 0 || self.counts.failed_units > 0 {
+                    self.status = RunStatus::Partial;
+                    self.exit_code = EXIT_PARTIAL;
+                } else {
+                    self.status = RunStatus::Success;
+                    self.exit_code = EXIT_SUCCESS;
+                }
+            }
+            Err(e) => {
+                let report = FailureReport::new(e);
+                if let Some(kind) = report.kind {
+                    self.add_failure(kind);
+                }
+                self.status = RunStatus::Failed;
+                // uncategorized errors exit with 1
+                self.exit_code = report.kind.map(ErrorKind::exit_code).unwrap_or(1);
+                self.failure = Some(report);
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cu::pre::*;
+use dejj_utils::Config;
+use exstructs::{Goff, GoffMap, LType};
+use llvmutils::Demangler;
+use symlist::SymbolList;
+use tyyaml::Tree;
+
+use crate::dwarf::Unit;
+use crate::stages::LStage;
+
+/// Listing of function and data symbols, without the type layouts
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SymbolListing {
+    /// Symbols by link name
+    pub symbols: BTreeMap<String, SymbolListingEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolListingEntry {
+    pub address: u32,
+    pub is_func: bool,
+    pub demangled: String,
+    /// C++-like spelling of the type, as seen in the compilation unit
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub param_names: Vec<String>,
+}
+
+impl SymbolListing {
+    /// Path of the listing emitted by `extract --symbols-only`
+    pub fn default_path(config: &Config) -> PathBuf {
+        config.paths.extract_output.join("symbols.json")
+    }
+
+    /// Add symbols from a stage0. Symbols already in the listing are kept
+    fn add_lstage(&mut self, stage: &LStage, demangler: &Demangler) -> cu::Result<()> {
+        for symbol in stage.symbols.values() {
+            if self.symbols.contains_key(&symbol.link_name) {
+                continue;
+            }
+            let demangled = cu::check!(
+                demangler.demangle(&symbol.link_name),
+                "failed to demangle {}",
+                symbol.link_name
+            )?;
+            let entry = SymbolListingEntry {
+                address: symbol.address,
+                is_func: symbol.is_func(),
+                demangled,
+                ty: tree_display_name(&symbol.ty, &stage.types, 0),
+                param_names: symbol.param_names.clone(),
+            };
+            self.symbols.insert(symbol.link_name.clone(), entry);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> cu::Result<()> {
+        cu::fs::write_json_pretty(path, self)
+    }
+}
+
+/// Run stage0 on all units and only emit the symbol listing.
+/// Returns the number of symbols listed
+pub(crate) fn extract_symbols_only(
+    units: Vec<Unit>,
+    config: &Arc<Config>,
+    symbol_list: &Arc<SymbolList>,
+    demangler: &Arc<Demangler>,
+) -> cu::Result<usize> {
+    let stages = {
+        let config = Arc::clone(config);
+        let symbol_list = Arc::clone(symbol_list);
+        cu::co::run(async move {
+            let bar = cu::progress("stage0: loading symbols")
+                .total(units.len())
+                .spawn();
+            let mut handles = Vec::with_capacity(units.len());
+            let pool = cu::co::pool(-1);
+            let mut output = Vec::with_capacity(units.len());
+            for unit in units {
+                let config = Arc::clone(&config);
+                let symbol_list = Arc::clone(&symbol_list);
+                let handle = pool.spawn(async move {
+                    let (stage, ..) =
+                        crate::run::load_lstage_with_retry(&unit, &config, &symbol_list, 0)?;
+                    cu::Ok(stage)
+                });
+                handles.push(handle);
+            }
+            let mut set = cu::co::set(handles);
+            while let Some(result) = set.next().await {
+                let stage = result??;
+                cu::progress!(bar += 1, "{}", stage.name);
+                output.push(stage);
+            }
+            // keep the first one by offset for symbols in multiple units
+            output.sort_unstable_by_key(|x| x.offset);
+            cu::Ok(output)
+        })?
+    };
+
+    let mut listing = SymbolListing::default();
+    for stage in &stages {
+        cu::check!(
+            listing.add_lstage(stage, demangler),
+            "failed to list symbols for {}",
+            stage.name
+        )?;
+    }
+    if let Err(e) = demangler.flush_cache() {
+        cu::warn!("failed to flush demangler cache: {e:?}");
+    }
+
+    let path = SymbolListing::default_path(config);
+    listing.save(&path)?;
+    cu::info!("listed {} symbols", listing.symbols.len());
+    cu::hint!("symbol listing saved to {}", path.try_to_rel().display());
+    Ok(listing.symbols.len())
+}
+
+fn tree_display_name(tree: &Tree<Goff>, types: &GoffMap<LType>, depth: usize) -> String {
+    tree.clone()
+        .map(|k| type_display_name(k, types, depth + 1))
+        .to_string()
+}
+
+/// Best-effort name of a type in stage0, before names are parsed
+fn type_display_name(k: Goff, types: &GoffMap<LType>, depth: usize) -> String {
+    if depth > 1000 {
+        return format!("[recursive {k}]");
+    }
+    let Some(t) = types.get(&k) else {
+        return format!("[unknown {k}]");
+    };
+    let name = match t {
+        LType::Prim(p) => return p.to_string(),
+        LType::Typedef { name, .. } => Some(name),
+        LType::Enum(data) => data.name.as_ref(),
+        LType::Union(data) => data.name.as_ref(),
+        LType::Struct(data) => data.name.as_ref(),
+        LType::EnumDecl(decl) | LType::UnionDecl(decl) | LType::StructDecl(decl) => {
+            Some(&decl.name_with_tpl)
+        }
+        LType::Tree(tree) => return tree_display_name(tree, types, depth),
+        LType::Alias(inner) => return type_display_name(*inner, types, depth + 1),
+    };
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("[anonymous {k}]"),
+    }
+}
@@ -0,0 +1,321 @@
+//! Export with a user-provided [minijinja](https://docs.rs/minijinja) template.
+//!
+//! The template is set with `template` in `[export.template]`, and is rendered
+//! with the following variables:
+//!
+//! - `types`: list of type definitions, sorted by name
+//!   - `name`: primary fully-qualified name of the type
+//!   - `parent`: name of the struct or union the type is nested in, `none` for
+//!     types at namespace scope
+//!   - `basename`: the name without the scope of `parent`, same as `name` if not nested
+//!   - `nested`: names of the types nested in this type, sorted by name. To emit
+//!     nested classes inside their parents, skip types with a `parent` at the top level
+//!     and look up the nested types with `types_by_name`
+//!   - `kind`: `"enum"`, `"union"`, `"struct"` or `"typedef"` (named function pointer type)
+//!   - `size`: size in bytes, `none` for typedefs
+//!   - `signed`: if the underlying type of an enum is signed, `false` for other kinds
+//!   - `enumerators`: list of `{ name, value }`, empty unless the type is an enum
+//!   - `members`: list of `{ offset, name, type, special, bitfields, access }`, empty for
+//!     enums and typedefs. `name` is `none` for anonymous members, `special` is `"base"`,
+//!     `"vfptr"`, `"bitfield"` or `none`. `bitfields` is a list of
+//!     `{ name, bit_offset, bit_size }` if `extract.preserve-bitfields` is enabled and
+//!     the member is a group of bitfields, empty otherwise. `access` is `"public"`,
+//!     `"protected"`, `"private"`, or `none` if not recorded in the debug info
+//!   - `vtable`: list of `{ index, name, type, access }`, empty unless the type is a struct
+//!     with virtual functions
+//!   - `bases`: list of `{ offset, virtual, vbase_offset, access, type }` of the direct
+//!     base classes, empty unless the type is a struct. `offset` is `none` for virtual bases.
+//!     `vbase_offset` is where the offset of a virtual base is stored in the vtable, `none`
+//!     if not virtual or unknown. Non-virtual bases are also in `members` with `special`
+//!     of `"base"`
+//!   - `type`: the aliased type for typedefs, `none` otherwise
+//!   - `source`: declared location in the original source as `"file:line"`,
+//!     `none` if unknown or for typedefs
+//! - `types_by_name`: the same types, as a map from `name` to the type
+//! - `symbols`: list of symbols, sorted by link name
+//!   - `name`: link name of the symbol
+//!   - `address`: address of the symbol
+//!   - `type`: type of the symbol
+//!   - `params`: names of the parameters if the symbol is a function
+//!   - `source`: declared location in the original source as `"file:line"`, `none` if unknown
+//!
+//! All `type` fields are strings in C++-like syntax, the same as the types in TyYAML.
+//! For example, `int*`, `ns::Foo[4]` or `void (*)(int, float)`
+
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use tyyaml::{Access, MemberDef, TypeDef};
+
+use crate::database::Database;
+use crate::emit::{self, EmitModel};
+use crate::export::{ExportContext, Exporter};
+
+/// Custom format rendered from a user-provided template
+pub struct TemplateExporter;
+impl Exporter for TemplateExporter {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+    fn file_extension(&self) -> &'static str {
+        "txt"
+    }
+    fn run(&self, db: &Database, ctx: &ExportContext) -> cu::Result<()> {
+        let Some(template_path) = &ctx.options.template else {
+            cu::bail!("template format requires export.template.template to be set in the config");
+        };
+        let source = cu::fs::read_string(template_path)?;
+        let model = EmitModel::from_database(db, emit::name_policy(ctx.config))?;
+        let context = TemplateContext::new(&model);
+
+        let mut env = minijinja::Environment::new();
+        env.set_keep_trailing_newline(true);
+        let name = template_path.display().to_string();
+        cu::check!(
+            env.add_template_owned(name.clone(), source),
+            "failed to parse template {}",
+            template_path.try_to_rel().display()
+        )?;
+        let template = env.get_template(&name)?;
+        let output = cu::check!(
+            template.render(&context),
+            "failed to render template {}",
+            template_path.try_to_rel().display()
+        )?;
+        cu::fs::write(&ctx.output_path, output)?;
+        cu::hint!(
+            "rendered template {} to {}",
+            template_path.try_to_rel().display(),
+            ctx.output_path.try_to_rel().display()
+        );
+        Ok(())
+    }
+}
+
+/// Variables passed to the template, see the module doc for the data model
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    types: Vec<TemplateType<'a>>,
+    types_by_name: BTreeMap<&'a str, TemplateType<'a>>,
+    symbols: Vec<TemplateSymbol<'a>>,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateType<'a> {
+    name: &'a str,
+    parent: Option<&'a str>,
+    basename: &'a str,
+    nested: Vec<&'a str>,
+    kind: &'static str,
+    size: Option<u32>,
+    signed: bool,
+    enumerators: Vec<TemplateEnumerator<'a>>,
+    members: Vec<TemplateMember<'a>>,
+    vtable: Vec<TemplateVfunc<'a>>,
+    bases: Vec<TemplateBase>,
+    #[serde(rename = "type")]
+    ty: Option<String>,
+    source: Option<&'a str>,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateEnumerator<'a> {
+    name: &'a str,
+    value: i64,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateMember<'a> {
+    offset: u32,
+    name: Option<&'a str>,
+    #[serde(rename = "type")]
+    ty: String,
+    special: Option<&'a str>,
+    bitfields: Vec<TemplateBitfield<'a>>,
+    access: Option<Access>,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateBitfield<'a> {
+    name: Option<&'a str>,
+    bit_offset: u32,
+    bit_size: u32,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateVfunc<'a> {
+    index: u32,
+    name: &'a str,
+    #[serde(rename = "type")]
+    ty: String,
+    access: Option<Access>,
+}
+
+#[derive(Clone, Serialize)]
+struct TemplateBase {
+    offset: Option<u32>,
+    #[serde(rename = "virtual")]
+    is_virtual: bool,
+    vbase_offset: Option<i64>,
+    access: Option<Access>,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Serialize)]
+struct TemplateSymbol<'a> {
+    name: &'a str,
+    address: u32,
+    #[serde(rename = "type")]
+    ty: String,
+    params: &'a [String],
+    source: Option<&'a str>,
+}
+
+impl<'a> TemplateContext<'a> {
+    fn new(model: &'a EmitModel) -> Self {
+        let mut nested = BTreeMap::<&str, Vec<&str>>::new();
+        for (name, parent) in &model.parents {
+            nested.entry(parent).or_default().push(name);
+        }
+        let types = model
+            .types
+            .iter()
+            .map(|(name, t)| {
+                let mut output = TemplateType::new(name, t);
+                if let Some(parent) = model.parents.get(name) {
+                    output.parent = Some(parent);
+                    // checked when creating the model
+                    output.basename = &name[parent.len() + 2..];
+                }
+                output.nested = nested.remove(name.as_str()).unwrap_or_default();
+                output
+            })
+            .collect::<Vec<_>>();
+        let types_by_name = types.iter().map(|t| (t.name, t.clone())).collect();
+        let symbols = model
+            .symbols
+            .iter()
+            .map(|(name, s)| TemplateSymbol {
+                name,
+                address: s.address,
+                ty: s.ty.to_string(),
+                params: &s.params,
+                source: s.source.as_deref(),
+            })
+            .collect();
+        Self {
+            types,
+            types_by_name,
+            symbols,
+        }
+    }
+}
+
+impl<'a> TemplateType<'a> {
+    fn new(name: &'a str, t: &'a TypeDef) -> Self {
+        let mut output = Self {
+            name,
+            parent: None,
+            basename: name,
+            nested: vec![],
+            kind: "",
+            size: None,
+            signed: false,
+            enumerators: vec![],
+            members: vec![],
+            vtable: vec![],
+            bases: vec![],
+            ty: None,
+            source: None,
+        };
+        match t {
+            TypeDef::Enum {
+                size,
+                signed,
+                enumerators,
+                source,
+            } => {
+                output.kind = "enum";
+                output.source = source.as_deref();
+                output.size = Some(*size);
+                output.signed = *signed;
+                output.enumerators = enumerators
+                    .iter()
+                    .map(|e| TemplateEnumerator {
+                        name: &e.name,
+                        value: e.value,
+                    })
+                    .collect();
+            }
+            TypeDef::Union {
+                size,
+                members,
+                source,
+            } => {
+                output.kind = "union";
+                output.source = source.as_deref();
+                output.size = Some(*size);
+                output.members = template_members(members);
+            }
+            TypeDef::Struct {
+                size,
+                members,
+                vtable,
+                bases,
+                source,
+            } => {
+                output.kind = "struct";
+                output.source = source.as_deref();
+                output.size = Some(*size);
+                output.members = template_members(members);
+                output.vtable = vtable
+                    .iter()
+                    .map(|v| TemplateVfunc {
+                        index: v.index,
+                        name: &v.name,
+                        ty: v.ty.to_string(),
+                        access: v.access,
+                    })
+                    .collect();
+                output.bases = bases
+                    .iter()
+                    .map(|b| TemplateBase {
+                        offset: b.offset,
+                        is_virtual: b.is_virtual,
+                        vbase_offset: b.vbase_offset,
+                        access: b.access,
+                        ty: b.ty.to_string(),
+                    })
+                    .collect();
+            }
+            TypeDef::Typedef { ty } => {
+                output.kind = "typedef";
+                output.ty = Some(ty.to_string());
+            }
+        }
+        output
+    }
+}
+
+fn template_members(members: &[MemberDef]) -> Vec<TemplateMember<'_>> {
+    members
+        .iter()
+        .map(|m| TemplateMember {
+            offset: m.offset,
+            name: m.name.as_deref(),
+            ty: m.ty.to_string(),
+            special: m.special.as_deref(),
+            bitfields: m
+                .bitfields
+                .iter()
+                .map(|b| TemplateBitfield {
+                    name: b.name.as_deref(),
+                    bit_offset: b.bit_offset,
+                    bit_size: b.bit_size,
+                })
+                .collect(),
+            access: m.access,
+        })
+        .collect()
+}
@@ -0,0 +1,125 @@
+//! Verbose logging of one type through all stages, enabled by `--trace-type`
+
+use std::sync::OnceLock;
+
+use exstructs::{FullQualName, Goff, HType, LType};
+
+use crate::stages::{HStage, LStage, MStage};
+
+static TRACE_TYPE: OnceLock<TraceType> = OnceLock::new();
+
+enum TraceType {
+    Goff(Goff),
+    Name(String),
+}
+
+/// Enable tracing for the type, specified either as a goff (i.e. `0x1234`)
+/// or a fully-qualified name. A name without template args traces all instantiations
+pub fn init(spec: &str) {
+    let spec = spec.trim();
+    let trace_type = match cu::parse::<usize>(spec) {
+        Ok(x) => TraceType::Goff(Goff(x)),
+        Err(_) => TraceType::Name(spec.to_string()),
+    };
+    if TRACE_TYPE.set(trace_type).is_err() {
+        cu::warn!("--trace-type is already set, ignoring {spec}");
+    }
+}
+
+pub fn is_enabled() -> bool {
+    TRACE_TYPE.get().is_some()
+}
+
+/// Check if the goff or any of the names matches the traced type
+pub fn matches<'a>(k: Goff, mut names: impl Iterator<Item = &'a str>) -> bool {
+    match TRACE_TYPE.get() {
+        None => false,
+        Some(TraceType::Goff(goff)) => *goff == k,
+        Some(TraceType::Name(_)) => names.any(matches_name),
+    }
+}
+
+/// Check if the name matches the traced type
+pub fn matches_name(name: &str) -> bool {
+    let Some(TraceType::Name(target)) = TRACE_TYPE.get() else {
+        return false;
+    };
+    match name.strip_prefix(target.as_str()) {
+        Some(rest) => rest.is_empty() || rest.starts_with('<'),
+        None => false,
+    }
+}
+
+/// Log a message about the traced type
+macro_rules! trace_type {
+    ($($tt:tt)*) => {
+        if $crate::trace_type::is_enabled() {
+            cu::info!("[trace-type] {}", format!($($tt)*));
+        }
+    };
+}
+pub(crate) use trace_type;
+
+pub fn trace_lstage(stage: &LStage, when: &str) {
+    if !is_enabled() {
+        return;
+    }
+    for (k, t) in &stage.types {
+        let name = match t {
+            LType::Typedef { name, .. } => Some(name.to_string()),
+            LType::Enum(data) => data.name.as_ref().map(|x| x.to_string()),
+            LType::Union(data) => data.name.as_ref().map(|x| x.to_string()),
+            LType::Struct(data) => data.name.as_ref().map(|x| x.to_string()),
+            LType::EnumDecl(decl) | LType::UnionDecl(decl) | LType::StructDecl(decl) => {
+                Some(decl.name_with_tpl.to_string())
+            }
+            _ => None,
+        };
+        if matches(*k, name.as_deref().into_iter()) {
+            trace_type!("{when} ({}): {k} = {t:#?}", stage.name);
+        }
+    }
+}
+
+pub fn trace_mstage(stage: &MStage, when: &str) {
+    if !is_enabled() {
+        return;
+    }
+    for (k, t) in &stage.types {
+        let names = t
+            .fullqual_names()
+            .iter()
+            .map(fqname_base)
+            .collect::<Vec<_>>();
+        if matches(*k, names.iter().map(|x| x.as_str())) {
+            trace_type!("{when}: {k} = {t:#?}");
+        }
+    }
+}
+
+pub fn trace_hstage(stage: &HStage, when: &str) {
+    if !is_enabled() {
+        return;
+    }
+    for (k, t) in &stage.types {
+        if matches_htype(*k, t) {
+            trace_type!("{when}: {k} = {t:#?}");
+        }
+    }
+}
+
+/// Check if the type at HStage matches the traced type
+pub fn matches_htype(k: Goff, t: &HType) -> bool {
+    let names = match t.fqnames() {
+        Ok(names) => names.iter().map(fqname_base).collect::<Vec<_>>(),
+        Err(_) => vec![],
+    };
+    matches(k, names.iter().map(|x| x.as_str()))
+}
+
+fn fqname_base(name: &FullQualName) -> String {
+    match name {
+        FullQualName::Name(n) => n.base.to_string(),
+        FullQualName::Goff(n) => n.base.to_string(),
+    }
+}
@@ -47,6 +47,11 @@ impl Struct {
                 return true;
             }
         }
+        for base in &self.bases {
+            if base.ty.contains(&k) {
+                return true;
+            }
+        }
         false
     }
 }
@@ -3,24 +3,34 @@ use std::hash::{Hash, Hasher};
 
 use cu::pre::*;
 use fxhash::FxHasher;
+use tyyaml::Tree;
 
 use crate::algorithm::MapGoff;
-use crate::{GoffBuckets, GoffMap, GoffMapFn, NamespaceMaps, SymbolInfo};
+use crate::{Goff, GoffBuckets, GoffMap, GoffMapFn, NamespaceMaps, SymbolInfo};
 
 pub fn dedupe<T: Eq + Hash + std::fmt::Debug, FMap: Fn(&mut T, &GoffBuckets) -> cu::Result<()>>(
     map: GoffMap<T>,
     buckets: GoffBuckets,
     symbols: &mut BTreeMap<String, SymbolInfo>,
+    typedefs: &mut BTreeMap<String, Tree<Goff>>,
     namespace: Option<&mut NamespaceMaps>,
     mapper: FMap,
 ) -> cu::Result<GoffMap<T>> {
-    merging_dedupe(map, buckets, symbols, namespace, mapper, |a, b| {
-        cu::bail!(
-            "the data are not equal after mapping, please check the mapper implementation.\na={:#?}, b={:#?}. If this is expected, a merger must be provided to do dedupe-time merging.",
-            a,
-            b
-        );
-    })
+    merging_dedupe(
+        map,
+        buckets,
+        symbols,
+        typedefs,
+        namespace,
+        mapper,
+        |a, b| {
+            cu::bail!(
+                "the data are not equal after mapping, please check the mapper implementation.\na={:#?}, b={:#?}. If this is expected, a merger must be provided to do dedupe-time merging.",
+                a,
+                b
+            );
+        },
+    )
 }
 
 /// Dedupe goffs that map to the same type data
@@ -40,6 +50,8 @@ pub fn merging_dedupe<
     mut buckets: GoffBuckets,
     // symbol data to be modified as merge happens
     symbols: &mut BTreeMap<String, SymbolInfo>,
+    // typedef data to be modified as merge happens
+    typedefs: &mut BTreeMap<String, Tree<Goff>>,
     // namespace data to be modified as merge happens
     namespace: Option<&mut NamespaceMaps>,
     // mapper to get the primary key for a given type
@@ -116,6 +128,9 @@ pub fn merging_dedupe<
         for symbol in symbols.values_mut() {
             cu::check!(symbol.map_goff(&f), "symbol mapping failed when deduping")?;
         }
+        for tree in typedefs.values_mut() {
+            cu::check!(tree.map_goff(&f), "typedef mapping failed when deduping")?;
+        }
 
         if let Some(ns) = namespace {
             for n in ns.qualifiers.values_mut() {
@@ -0,0 +1,94 @@
+use cu::pre::*;
+use tyyaml::Tree;
+
+use crate::{ArcStr, Goff, GoffMap, HType, Member};
+
+/// Inline the members of base classes (recursively) into the derived structs,
+/// for outputs without inheritance (like C). Offsets of all members are kept the same.
+///
+/// Members inlined from a base are prefixed with the name of the base member,
+/// or `base{i}` if the base member is unnamed
+pub fn flatten_bases(types: &mut GoffMap<HType>) -> cu::Result<()> {
+    let mut flattened = GoffMap::default();
+    for (k, t) in types.iter() {
+        if matches!(t, HType::Struct(_)) {
+            cu::check!(
+                flattened_members(*k, types, &mut flattened, 0),
+                "failed to flatten bases of struct {k}"
+            )?;
+        }
+    }
+    for (k, members) in flattened {
+        // unwrap: only structs are flattened
+        let data = &mut types.get_mut(&k).unwrap().as_struct_mut()?.data;
+        data.members = members;
+        data.bases.clear();
+    }
+    Ok(())
+}
+
+fn flattened_members(
+    k: Goff,
+    types: &GoffMap<HType>,
+    flattened: &mut GoffMap<Vec<Member>>,
+    depth: usize,
+) -> cu::Result<Vec<Member>> {
+    if let Some(members) = flattened.get(&k) {
+        return Ok(members.clone());
+    }
+    if depth > 1000 {
+        cu::bail!("max base class depth limit reached");
+    }
+    let t = cu::check!(types.get(&k), "unexpected unlinked type {k}")?;
+    let HType::Struct(data) = t else {
+        cu::bail!("expected type {k} to be a struct");
+    };
+    let mut output = Vec::with_capacity(data.data.members.len());
+    let mut base_index = 0;
+    for member in &data.data.members {
+        if !member.is_base() {
+            output.push(member.clone());
+            continue;
+        }
+        let prefix = match &member.name {
+            Some(name) => name.to_string(),
+            None => format!("base{base_index}"),
+        };
+        base_index += 1;
+        let base_k = match &member.ty {
+            Tree::Base(base_k) if matches!(types.get(base_k), Some(HType::Struct(_))) => *base_k,
+            // the base struct could be optimized into another type,
+            // in which case it's kept as a regular member
+            _ => {
+                output.push(Member {
+                    offset: member.offset,
+                    name: Some(ArcStr::from(prefix.as_str())),
+                    ty: member.ty.clone(),
+                    special: None,
+                    qualifiers: member.qualifiers.clone(),
+                    access: member.access,
+                });
+                continue;
+            }
+        };
+        let base_members = cu::check!(
+            flattened_members(base_k, types, flattened, depth + 1),
+            "failed to flatten base class {base_k}"
+        )?;
+        for inner in base_members {
+            let name = inner
+                .name
+                .map(|name| ArcStr::from(format!("{prefix}_{name}").as_str()));
+            output.push(Member {
+                offset: member.offset + inner.offset,
+                name,
+                ty: inner.ty,
+                special: inner.special,
+                qualifiers: inner.qualifiers,
+                access: inner.access,
+            });
+        }
+    }
+    flattened.insert(k, output.clone());
+    Ok(output)
+}
@@ -1,10 +1,30 @@
 use std::collections::BTreeSet;
 
 use crate::{
-    Enum, FullQualName, HType, MType, MTypeData, MTypeDecl, NamespacedName,
-    NamespacedTemplatedGoffName, NamespacedTemplatedName, Struct, Union,
+    Enum, FullQualName, FullQualNameMap, GoffMap, HType, MType, MTypeData, MTypeDecl,
+    NamespacedName, NamespacedTemplatedGoffName, NamespacedTemplatedName, Struct, Union,
 };
 
+impl FullQualNameMap {
+    /// Compute the fqnames of all types, including primitives
+    pub fn from_htypes(types: &GoffMap<HType>) -> cu::Result<Self> {
+        let mut fullqual_names = GoffMap::default();
+        for (k, t) in types {
+            if let Some(prim) = k.to_prim() {
+                fullqual_names.insert(
+                    *k,
+                    vec![FullQualName::Name(NamespacedTemplatedName::new(
+                        NamespacedName::prim(prim),
+                    ))],
+                );
+                continue;
+            };
+            fullqual_names.insert(*k, t.fqnames()?.to_vec());
+        }
+        Ok(Self(fullqual_names))
+    }
+}
+
 impl HType {
     pub fn fqnames(&self) -> cu::Result<&[FullQualName]> {
         match self {
@@ -14,6 +34,21 @@ impl HType {
             HType::Struct(data) => Ok(&data.fqnames),
         }
     }
+    /// Check if the type is defined in a function, i.e. all names
+    /// of the type are qualified by a function
+    pub fn is_function_local(&self) -> bool {
+        let Ok(fqnames) = self.fqnames() else {
+            return false;
+        };
+        !fqnames.is_empty()
+            && fqnames.iter().all(|name| {
+                let base = match name {
+                    FullQualName::Name(n) => &n.base,
+                    FullQualName::Goff(n) => &n.base,
+                };
+                base.namespace().contains_subprogram()
+            })
+    }
     pub fn into_fqnames(self) -> cu::Result<Vec<FullQualName>> {
         match self {
             HType::Prim(_) => cu::bail!("expected HTYPE to have fqnames, but it's a primitive"),
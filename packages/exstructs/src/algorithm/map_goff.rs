@@ -1,6 +1,7 @@
 //! Maps goffs in a data structure through a mapping function
 
 use cu::pre::*;
+use tyyaml::Tree;
 
 use crate::{
     Enum, EnumUndeterminedSize, FullQualName, Goff, GoffMapFn, HType, HTypeData, LType, LTypeData,
@@ -216,6 +217,15 @@ impl MapGoff for Struct {
         for member in &mut self.members {
             cu::check!(member.map_goff(f), "failed to map struct members")?;
         }
+        for base in &mut self.bases {
+            cu::check!(
+                base.ty.for_each_mut(|r| {
+                    *r = f(*r)?;
+                    cu::Ok(())
+                }),
+                "failed to map struct base classes"
+            )?;
+        }
         Ok(())
     }
 }
@@ -248,6 +258,18 @@ impl VtableEntry {
     }
 }
 
+impl MapGoff for Tree<Goff> {
+    fn map_goff(&mut self, f: &GoffMapFn) -> cu::Result<()> {
+        cu::check!(
+            self.for_each_mut(|r| {
+                *r = f(*r)?;
+                cu::Ok(())
+            }),
+            "failed to map type tree"
+        )
+    }
+}
+
 impl MapGoff for SymbolInfo {
     fn map_goff(&mut self, f: &GoffMapFn) -> cu::Result<()> {
         cu::check!(
@@ -184,6 +184,12 @@ impl Mark for Struct {
                 Ok(())
             });
         }
+        for base in &self.bases {
+            let _: Result<_, _> = base.ty.for_each(|goff| {
+                marked.insert(*goff);
+                Ok(())
+            });
+        }
     }
 }
 
@@ -286,7 +292,7 @@ impl NameSeg {
                 marked.insert(*goff);
             }
             NameSeg::Name(_) => {}
-            NameSeg::Anonymous => {}
+            NameSeg::Anonymous | NameSeg::AnonymousNamespace(_) => {}
         }
     }
 }
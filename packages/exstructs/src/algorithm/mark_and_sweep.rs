@@ -1,4 +1,9 @@
-use crate::{Goff, GoffMap, GoffSet};
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use tyyaml::Tree;
+
+use crate::{Goff, GoffMap, GoffSet, HType, SymbolInfo};
 
 /// Remove keys in `map` that is not referenced by `marker` or `always_marked`
 pub fn mark_and_sweep<T, F: Fn(&T, Goff, &mut GoffSet)>(
@@ -18,3 +23,47 @@ pub fn mark_and_sweep<T, F: Fn(&T, Goff, &mut GoffSet)>(
         }
     }
 }
+
+/// Remove types that are not (transitively) referenced by any symbol or typedef
+pub fn keep_referenced_from_symbols(
+    types: &mut GoffMap<HType>,
+    symbols: &BTreeMap<String, SymbolInfo>,
+    typedefs: &BTreeMap<String, Tree<Goff>>,
+) -> cu::Result<()> {
+    let mut marked = GoffSet::default();
+    for symbol in symbols.values() {
+        symbol.mark(&mut marked);
+    }
+    mark_typedefs(typedefs, &mut marked);
+    keep_referenced_from(types, marked)
+}
+
+/// Remove types that are not the roots or (transitively) referenced by the roots
+pub fn keep_referenced_from(types: &mut GoffMap<HType>, roots: GoffSet) -> cu::Result<()> {
+    let mut marked = roots;
+    let mut newly_marked = GoffSet::default();
+    loop {
+        newly_marked.clear();
+        for k in &marked {
+            let t = cu::check!(types.get(k), "unexpected unlinked type {k} while sweeping")?;
+            t.mark(*k, &mut newly_marked);
+        }
+        let len_before = marked.len();
+        marked.extend(newly_marked.iter().copied());
+        if marked.len() == len_before {
+            break;
+        }
+    }
+    types.retain(|k, _| marked.contains(k));
+    Ok(())
+}
+
+/// Mark the types referenced by the typedef trees
+pub fn mark_typedefs(typedefs: &BTreeMap<String, Tree<Goff>>, marked: &mut GoffSet) {
+    for tree in typedefs.values() {
+        let _: Result<_, _> = tree.for_each(|goff| {
+            marked.insert(*goff);
+            Ok(())
+        });
+    }
+}
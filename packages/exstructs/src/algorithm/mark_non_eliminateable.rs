@@ -3,6 +3,9 @@
 //! - A PTM base type must be a struct/union, so it cannot be eliminated
 //! - A struct/class with vtable cannot be eliminated
 //! - A type that directly references itself cannot be eliminated
+//!
+//! PTM bases are also marked separately with `mark_ptm_bases`, since they can only
+//! be replaced by another struct/union
 
 use crate::{
     Goff, GoffSet, HType, MType, Member, Struct, SymbolInfo, TemplateArg, Union, VtableEntry,
@@ -23,6 +26,22 @@ impl HType {
     }
 }
 
+impl HType {
+    /// Mark types used as base of pointer-to-member types
+    pub fn mark_ptm_bases(&self, marked: &mut GoffSet) {
+        match self {
+            Self::Prim(_) => {}
+            Self::Enum(_) => {}
+            Self::Union(data) => {
+                data.data.mark_ptm_bases(marked);
+            }
+            Self::Struct(data) => {
+                data.data.mark_ptm_bases(marked);
+            }
+        }
+    }
+}
+
 impl MType {
     pub fn mark_non_eliminateable(&self, self_goff: Goff, marked: &mut GoffSet) {
         match self {
@@ -48,6 +67,10 @@ impl Union {
         if self.contains_goff(self_goff) {
             marked.insert(self_goff);
         }
+        self.mark_ptm_bases(marked);
+    }
+    /// Mark types used as base of pointer-to-member types
+    pub fn mark_ptm_bases(&self, marked: &mut GoffSet) {
         for targ in &self.template_args {
             targ.mark_non_eliminateable(marked);
         }
@@ -67,6 +90,14 @@ impl Struct {
         if !self.vtable.is_empty() {
             marked.insert(self_goff);
         }
+        // types with virtual bases need the vptr to locate the bases
+        if self.has_virtual_bases() {
+            marked.insert(self_goff);
+        }
+        self.mark_ptm_bases(marked);
+    }
+    /// Mark types used as base of pointer-to-member types
+    pub fn mark_ptm_bases(&self, marked: &mut GoffSet) {
         for targ in &self.template_args {
             targ.mark_non_eliminateable(marked);
         }
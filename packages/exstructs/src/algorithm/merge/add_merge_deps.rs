@@ -4,11 +4,16 @@ use tyyaml::Tree;
 
 use cu::pre::*;
 
-use crate::algorithm::merge::MergeTask;
-use crate::{Goff, MType, Member, Struct, TemplateArg, Union, VtableEntry};
+use crate::algorithm::merge::{MergeOptions, MergeTask};
+use crate::{BaseClass, Goff, MType, Member, Struct, TemplateArg, Union, VtableEntry};
 
 impl MType {
-    pub fn add_merge_deps(&self, other: &Self, task: &mut MergeTask) -> cu::Result<()> {
+    pub fn add_merge_deps(
+        &self,
+        other: &Self,
+        task: &mut MergeTask,
+        options: MergeOptions,
+    ) -> cu::Result<()> {
         match (self, other) {
             (MType::Prim(a), MType::Prim(b)) => {
                 cu::ensure!(a == b)?;
@@ -32,7 +37,7 @@ impl MType {
             (MType::UnionDecl(_), MType::UnionDecl(_)) => {}
 
             (MType::Struct(a), MType::Struct(b)) => {
-                a.data.add_merge_deps(&b.data, task)?;
+                a.data.add_merge_deps(&b.data, task, options)?;
             }
             (MType::Struct(_), MType::StructDecl(_)) => {}
             (MType::StructDecl(_), MType::Struct(_)) => {}
@@ -78,7 +83,12 @@ impl Union {
 }
 
 impl Struct {
-    pub fn add_merge_deps(&self, other: &Self, task: &mut MergeTask) -> cu::Result<()> {
+    pub fn add_merge_deps(
+        &self,
+        other: &Self,
+        task: &mut MergeTask,
+        options: MergeOptions,
+    ) -> cu::Result<()> {
         cu::ensure!(
             self.byte_size == other.byte_size,
             "structs of different sizes cannot be merged (0x{:x} != 0x{:x})",
@@ -101,7 +111,7 @@ impl Struct {
             if entry.is_dtor() {
                 if let Some((_, other_entry)) = other.vtable.iter().find(|(_, e)| e.is_dtor()) {
                     cu::check!(
-                        entry.add_merge_deps(other_entry, task),
+                        entry.add_merge_deps(other_entry, task, options),
                         "add_merge_deps failed for vtable dtor entry, {entry:#?}"
                     )?;
                 }
@@ -111,7 +121,7 @@ impl Struct {
                 other.vtable.iter().find(|(x, oe)| !oe.is_dtor() && x == i)
             {
                 cu::check!(
-                    entry.add_merge_deps(other_entry, task),
+                    entry.add_merge_deps(other_entry, task, options),
                     "add_merge_deps failed for vtable entry i={i}, a={entry:#?}, b={other_entry:#?}"
                 )?;
             }
@@ -128,10 +138,45 @@ impl Struct {
             )?;
         }
 
+        cu::ensure!(
+            self.bases.len() == other.bases.len(),
+            "structs of different base class count cannot be merged"
+        )?;
+        for (a, b) in std::iter::zip(&self.bases, &other.bases) {
+            cu::check!(
+                a.add_merge_deps(b, task),
+                "add_merge_deps failed for struct base classes"
+            )?;
+        }
+
         Ok(())
     }
 }
 
+impl BaseClass {
+    pub fn add_merge_deps(&self, other: &Self, task: &mut MergeTask) -> cu::Result<()> {
+        cu::ensure!(
+            self.offset == other.offset,
+            "base classes of different offsets cannot be merged"
+        )?;
+        cu::ensure!(
+            self.is_virtual == other.is_virtual,
+            "virtual and non-virtual base classes cannot be merged"
+        )?;
+        // the location could be unrecognized in some units
+        if let (Some(a), Some(b)) = (self.vbase_offset, other.vbase_offset) {
+            cu::ensure!(
+                a == b,
+                "virtual base classes with different vbase offsets cannot be merged"
+            )?;
+        }
+        cu::check!(
+            tree_add_merge_deps(&self.ty, &other.ty, task),
+            "add_merge_deps failed for base class"
+        )
+    }
+}
+
 impl Member {
     pub fn add_merge_deps(&self, other: &Self, task: &mut MergeTask) -> cu::Result<()> {
         cu::ensure!(
@@ -154,7 +199,26 @@ impl Member {
 }
 
 impl VtableEntry {
-    pub fn add_merge_deps(&self, other: &Self, task: &mut MergeTask) -> cu::Result<()> {
+    pub fn add_merge_deps(
+        &self,
+        other: &Self,
+        task: &mut MergeTask,
+        options: MergeOptions,
+    ) -> cu::Result<()> {
+        if options.lenient_vtable {
+            // different functions in the slot, the first one is kept when merging
+            if self.name != other.name || self.function_types.len() != other.function_types.len() {
+                return Ok(());
+            }
+            // skip the return type, which could be covariant
+            for (a, b) in std::iter::zip(&self.function_types, &other.function_types).skip(1) {
+                cu::check!(
+                    tree_add_merge_deps(a, b, task),
+                    "add_merge_deps failed for vtable parameter types"
+                )?;
+            }
+            return Ok(());
+        }
         cu::ensure!(
             self.name == other.name,
             "vtable entries of different names cannot be merged"
@@ -186,6 +250,12 @@ impl TemplateArg<Goff> {
                 tree_add_merge_deps(a, b, task)?;
             }
             (TemplateArg::StaticConst, TemplateArg::StaticConst) => {}
+            (TemplateArg::Unknown(a), TemplateArg::Unknown(b)) => {
+                cu::ensure!(
+                    a == b,
+                    "unknown template arg of different spelling cannot be merged"
+                )?;
+            }
             _ => {
                 cu::bail!("different template args cannot be merged");
             }
@@ -2,11 +2,15 @@ use std::collections::BTreeSet;
 
 use cu::pre::*;
 
-use crate::{MType, MTypeData, MTypeDecl, NamespacedName, NamespacedTemplatedName, Struct};
+use crate::algorithm::merge::MergeOptions;
+use crate::{
+    Access, BaseClass, MType, MTypeData, MTypeDecl, Member, NamespacedName,
+    NamespacedTemplatedName, Struct, Union,
+};
 
 impl MType {
     /// Create a merged type data
-    pub fn merge_data(&self, other: &Self) -> cu::Result<Self> {
+    pub fn merge_data(&self, other: &Self, options: MergeOptions) -> cu::Result<Self> {
         fn select_name(
             a: &Option<NamespacedName>,
             b: &Option<NamespacedName>,
@@ -33,6 +37,7 @@ impl MType {
                     name,
                     data: a.data.clone(),
                     decl_names: decl_names.into_iter().collect(),
+                    source: a.source.clone().or_else(|| b.source.clone()),
                 };
                 Ok(MType::Enum(data))
             }
@@ -51,7 +56,7 @@ impl MType {
                 decl_names.extend(a.decl_names.clone());
                 decl_names.extend(b.decl_names.clone());
                 let data = cu::check!(
-                    a.data.merge_data(&b.data),
+                    a.data.merge_data(&b.data, options),
                     "failed to get merged struct data"
                 )?;
                 let name = select_name(&a.name, &b.name);
@@ -59,6 +64,7 @@ impl MType {
                     name,
                     data,
                     decl_names: decl_names.into_iter().collect(),
+                    source: a.source.clone().or_else(|| b.source.clone()),
                 };
                 Ok(MType::Struct(data))
             }
@@ -81,8 +87,12 @@ impl MType {
                 let name = select_name(&a.name, &b.name);
                 let data = MTypeData {
                     name,
-                    data: a.data.clone(),
+                    data: Union {
+                        members: merge_members(&a.data.members, &b.data.members),
+                        ..a.data.clone()
+                    },
                     decl_names: decl_names.into_iter().collect(),
+                    source: a.source.clone().or_else(|| b.source.clone()),
                 };
                 Ok(MType::Union(data))
             }
@@ -105,6 +115,7 @@ impl<T: Clone> MTypeData<T> {
             name: self.name.clone(),
             data: self.data.clone(),
             decl_names: decl_names.into_iter().collect(),
+            source: self.source.clone(),
         }
     }
 }
@@ -137,32 +148,42 @@ impl MTypeDecl {
 
 impl Struct {
     /// Create a merged type data
-    pub fn merge_data(&self, other: &Self) -> cu::Result<Self> {
+    pub fn merge_data(&self, other: &Self, options: MergeOptions) -> cu::Result<Self> {
         // merge vtables
         let mut new_vtable = self.vtable.clone();
         for (i, other_entry) in &other.vtable {
             if other_entry.is_dtor() {
-                if let Some((_, self_entry)) = self.vtable.iter().find(|(_, e)| e.is_dtor()) {
+                if let Some(j) = self.vtable.iter().position(|(_, e)| e.is_dtor()) {
+                    let self_entry = &mut new_vtable[j].1;
                     cu::ensure!(
-                        other_entry.name == self_entry.name,
+                        options.lenient_vtable || other_entry.name == self_entry.name,
                         "cannot merge vtable dtor entries of different names: {:?} and {:?}",
                         other_entry.name,
                         self_entry.name
                     )?;
+                    self_entry.access = merge_access(self_entry.access, other_entry.access, || {
+                        format!("virtual function {}", self_entry.name)
+                    });
                 } else {
                     new_vtable.push((*i, other_entry.clone()));
                 }
                 continue;
             }
-            if let Some((_, self_entry)) =
-                self.vtable.iter().find(|(j, se)| !se.is_dtor() && i == j)
+            if let Some(j) = self
+                .vtable
+                .iter()
+                .position(|(j, se)| !se.is_dtor() && i == j)
             {
+                let self_entry = &mut new_vtable[j].1;
                 cu::ensure!(
-                    other_entry.name == self_entry.name,
+                    options.lenient_vtable || other_entry.name == self_entry.name,
                     "cannot merge vtable entries of different names, at index {i}: {:?} and {:?}",
                     other_entry.name,
                     self_entry.name
                 )?;
+                self_entry.access = merge_access(self_entry.access, other_entry.access, || {
+                    format!("virtual function {}", self_entry.name)
+                });
             } else {
                 new_vtable.push((*i, other_entry.clone()));
             }
@@ -172,7 +193,56 @@ impl Struct {
             template_args: self.template_args.clone(),
             byte_size: self.byte_size,
             vtable: new_vtable,
-            members: self.members.clone(),
+            members: merge_members(&self.members, &other.members),
+            bases: merge_bases(&self.bases, &other.bases),
+            is_decl: self.is_decl && other.is_decl,
         })
     }
 }
+
+/// Merge the access specifiers of the members. The members are checked
+/// to be the same otherwise when adding the merge dependencies
+fn merge_members(a: &[Member], b: &[Member]) -> Vec<Member> {
+    let mut members = a.to_vec();
+    for (m, other) in std::iter::zip(&mut members, b) {
+        m.access = merge_access(m.access, other.access, || match &m.name {
+            Some(name) => format!("member {name}"),
+            None => format!("member at offset {}", m.offset),
+        });
+    }
+    members
+}
+
+/// Merge the access specifiers and virtual base offsets of the base classes.
+/// The base classes are checked to be the same otherwise when adding the merge dependencies
+fn merge_bases(a: &[BaseClass], b: &[BaseClass]) -> Vec<BaseClass> {
+    let mut bases = a.to_vec();
+    for (base, other) in std::iter::zip(&mut bases, b) {
+        base.vbase_offset = base.vbase_offset.or(other.vbase_offset);
+        base.access = merge_access(base.access, other.access, || match base.offset {
+            Some(offset) => format!("base class at offset {offset}"),
+            None => "virtual base class".to_string(),
+        });
+    }
+    bases
+}
+
+/// Merge the access specifiers of the same member in different compilation units.
+/// If they are different, the most permissive one is used
+fn merge_access(
+    a: Option<Access>,
+    b: Option<Access>,
+    describe: impl FnOnce() -> String,
+) -> Option<Access> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => {
+            let merged = a.most_permissive(b);
+            cu::warn!(
+                "{} has different access specifiers {a} and {b}, using {merged}",
+                describe()
+            );
+            Some(merged)
+        }
+        (a, b) => a.or(b),
+    }
+}
@@ -4,6 +4,14 @@ use cu::pre::*;
 
 use crate::{Goff, GoffBuckets, GoffMap, GoffPair, MType};
 
+/// Options for checking and merging types
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MergeOptions {
+    /// Allow vtable slots with different functions (keeping the first one),
+    /// and vtable functions with different return types
+    pub lenient_vtable: bool,
+}
+
 /// Tracks dependency for merging types. After merging all deps, the merge can happen
 #[derive(Debug)]
 pub struct MergeTask {
@@ -17,6 +25,10 @@ impl MergeTask {
             merge: (k1, k2).into(),
         }
     }
+    /// The pairs of types that need to be merged before this merge
+    pub fn deps(&self) -> &[GoffPair] {
+        &self.deps
+    }
     pub fn add_dep(&mut self, k1: Goff, k2: Goff) {
         if k1 == k2 || self.merge == (k1, k2).into() {
             // dep trivially satisfied
@@ -50,11 +62,19 @@ impl MergeTask {
             .extend(self.deps.iter().copied())
     }
     /// Execute the merge
-    pub fn execute(&self, types: &mut GoffMap<MType>, buckets: &mut GoffBuckets) -> cu::Result<()> {
+    pub fn execute(
+        &self,
+        types: &mut GoffMap<MType>,
+        buckets: &mut GoffBuckets,
+        options: MergeOptions,
+    ) -> cu::Result<()> {
         let (k1, k2) = self.merge.to_pair();
         let t1 = types.get(&k1).unwrap();
         let t2 = types.get(&k2).unwrap();
-        let merged = cu::check!(t1.merge_data(t2), "failed to merge types {k1} and {k2}")?;
+        let merged = cu::check!(
+            t1.merge_data(t2, options),
+            "failed to merge types {k1} and {k2}"
+        )?;
         types.insert(k1, merged.clone());
         types.insert(k2, merged);
         cu::check!(
@@ -4,8 +4,12 @@ mod mark_and_sweep;
 pub use mark_and_sweep::*;
 mod permute;
 pub use permute::*;
+mod permute_canonical;
+pub use permute_canonical::NamePolicy;
 mod connected_components;
 pub use connected_components::*;
+mod flatten_bases;
+pub use flatten_bases::*;
 
 pub mod merge;
 
@@ -16,4 +20,6 @@ mod map_goff;
 pub use map_goff::MapGoff;
 mod mark;
 mod mark_non_eliminateable;
+mod permute_match;
 mod replace;
+pub use replace::tree_replace;
@@ -1,16 +1,25 @@
+use std::borrow::Borrow;
 use std::collections::BTreeSet;
+use std::sync::Arc;
 
 use cu::pre::*;
 use tyyaml::Tree;
 
+use crate::algorithm::NamePolicy;
 use crate::{
     FullQualName, FullQualNameMap, Goff, GoffMap, NameSeg, Namespace, NamespacedName,
     NamespacedTemplatedGoffName, NamespacedTemplatedName, TemplateArg,
 };
 
 pub struct FullQualPermutater<'a> {
-    names: &'a FullQualNameMap,
-    cache: GoffMap<BTreeSet<String>>,
+    pub(super) names: &'a FullQualNameMap,
+    pub(super) cache: GoffMap<Arc<BTreeSet<String>>>,
+    /// Max number of names to keep for each type, 0 for no limit
+    limit: usize,
+    /// Number of names before truncating, for types that have more names than the limit
+    pub(super) truncated: GoffMap<usize>,
+    pub(super) policy: NamePolicy,
+    pub(super) canonical: GoffMap<Option<String>>,
 }
 
 impl<'a> FullQualPermutater<'a> {
@@ -18,13 +27,33 @@ impl<'a> FullQualPermutater<'a> {
         Self {
             names,
             cache: Default::default(),
+            limit: 0,
+            truncated: Default::default(),
+            policy: Default::default(),
+            canonical: Default::default(),
         }
     }
 }
 impl FullQualPermutater<'_> {
-    pub fn permutated_fullqual_names(&mut self, goff: Goff) -> cu::Result<BTreeSet<String>> {
+    /// Limit the number of permutated names of each type. 0 means no limit.
+    ///
+    /// Deeply nested templates can have thousands of names from the combinations of
+    /// typedefs in the template args. The lexicographically first names are kept,
+    /// so the result is deterministic, and the primary name is never dropped
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Get the types whose names were truncated by the limit, and the number of names
+    /// they have before truncating
+    pub fn truncated_types(&self) -> &GoffMap<usize> {
+        &self.truncated
+    }
+
+    pub fn permutated_fullqual_names(&mut self, goff: Goff) -> cu::Result<Arc<BTreeSet<String>>> {
         if let Some(x) = self.cache.get(&goff) {
-            return Ok(x.clone());
+            return Ok(Arc::clone(x));
         }
         let mut output = BTreeSet::new();
         let names = cu::check!(
@@ -32,7 +61,7 @@ impl FullQualPermutater<'_> {
             "did not resolve structured name for type {goff}"
         )?;
         if names.is_empty() {
-            return Ok(output);
+            return Ok(Default::default());
         }
         // insert empty set into the map, since there can be self-referencing names
         // for example
@@ -47,12 +76,25 @@ impl FullQualPermutater<'_> {
         if output.is_empty() {
             // do not cache and discard this attempt if empty
             self.cache.remove(&goff);
-            return Ok(output);
+            return Ok(Default::default());
+        }
+        if self.limit != 0 && output.len() > self.limit {
+            self.truncated.insert(goff, output.len());
+            output = output.into_iter().take(self.limit).collect();
         }
-        self.cache.insert(goff, output.clone());
+        let output = Arc::new(output);
+        self.cache.insert(goff, Arc::clone(&output));
 
         Ok(output)
     }
+
+    /// Get all permutated names of a type tree
+    pub fn permutated_tree_names(
+        &mut self,
+        tree: &Tree<Goff>,
+    ) -> cu::Result<Arc<BTreeSet<String>>> {
+        tree_goff_permutated_fullqual(tree, self)
+    }
 }
 
 impl FullQualName {
@@ -132,12 +174,14 @@ impl TemplateArg<Goff> {
     pub fn permutated_fullqual(
         &self,
         permutater: &mut FullQualPermutater,
-    ) -> cu::Result<BTreeSet<String>> {
-        match self {
-            TemplateArg::Const(x) => Ok(std::iter::once(x.to_string()).collect()),
-            TemplateArg::Type(tree) => tree_goff_permutated_fullqual(tree, permutater),
-            TemplateArg::StaticConst => Ok(std::iter::once("[static]".to_string()).collect()),
-        }
+    ) -> cu::Result<Arc<BTreeSet<String>>> {
+        let output = match self {
+            TemplateArg::Const(x) => std::iter::once(x.to_string()).collect(),
+            TemplateArg::Type(tree) => return tree_goff_permutated_fullqual(tree, permutater),
+            TemplateArg::StaticConst => std::iter::once("[static]".to_string()).collect(),
+            TemplateArg::Unknown(s) => std::iter::once(s.clone()).collect(),
+        };
+        Ok(Arc::new(output))
     }
 }
 
@@ -150,6 +194,7 @@ impl TemplateArg<NamespacedTemplatedName> {
             TemplateArg::Const(x) => Ok(std::iter::once(x.to_string()).collect()),
             TemplateArg::Type(tree) => tree_name_permutated_fullqual(tree, permutater),
             TemplateArg::StaticConst => Ok(std::iter::once("[static]".to_string()).collect()),
+            TemplateArg::Unknown(s) => Ok(std::iter::once(s.clone()).collect()),
         }
     }
 }
@@ -157,18 +202,15 @@ impl TemplateArg<NamespacedTemplatedName> {
 fn tree_goff_permutated_fullqual(
     tree: &Tree<Goff>,
     permutater: &mut FullQualPermutater,
-) -> cu::Result<BTreeSet<String>> {
-    match tree {
-        Tree::Base(k) => permutater.permutated_fullqual_names(*k),
+) -> cu::Result<Arc<BTreeSet<String>>> {
+    let output = match tree {
+        Tree::Base(k) => return permutater.permutated_fullqual_names(*k),
         Tree::Array(base, len) => {
             let base_names = cu::check!(
                 tree_goff_permutated_fullqual(base, permutater),
                 "failed to compute array base permutations"
             )?;
-            Ok(base_names
-                .into_iter()
-                .map(|x| format!("{x}[{len}]"))
-                .collect())
+            base_names.iter().map(|x| format!("{x}[{len}]")).collect()
         }
         Tree::Ptr(pointee) => {
             if let Tree::Sub(args) = pointee.as_ref() {
@@ -185,13 +227,13 @@ fn tree_goff_permutated_fullqual(
                     let n = format!("{}(*)({})", arg_names[0], arg_names[1..].join(", "));
                     output.insert(n);
                 }
-                Ok(output)
+                output
             } else {
                 let base_names = cu::check!(
                     tree_goff_permutated_fullqual(pointee, permutater),
                     "failed to compute pointee permutations"
                 )?;
-                Ok(base_names.into_iter().map(|x| format!("{x}*")).collect())
+                base_names.iter().map(|x| format!("{x}*")).collect()
             }
         }
         Tree::Sub(args) => {
@@ -208,7 +250,7 @@ fn tree_goff_permutated_fullqual(
                 let n = format!("{}({})", arg_names[0], arg_names[1..].join(", "));
                 output.insert(n);
             }
-            Ok(output)
+            output
         }
         Tree::Ptmd(base, pointee) => {
             let base_names = cu::check!(
@@ -220,12 +262,12 @@ fn tree_goff_permutated_fullqual(
                 "failed to compute ptmd pointee permutations"
             )?;
             let mut output = BTreeSet::default();
-            for base_n in base_names {
-                for pointee_n in &pointee_names {
+            for base_n in base_names.iter() {
+                for pointee_n in pointee_names.iter() {
                     output.insert(format!("{pointee_n} {base_n}::*"));
                 }
             }
-            Ok(output)
+            output
         }
         Tree::Ptmf(base, args) => {
             let base_names = cu::check!(
@@ -243,15 +285,16 @@ fn tree_goff_permutated_fullqual(
             let arg_names = permute(&inner_names);
 
             let mut output = BTreeSet::default();
-            for base_n in base_names {
+            for base_n in base_names.iter() {
                 for arg_n in &arg_names {
                     let retty = &arg_n[0];
                     output.insert(format!("{retty} ({base_n}::*)({})", arg_n[1..].join(", ")));
                 }
             }
-            Ok(output)
+            output
         }
-    }
+    };
+    Ok(Arc::new(output))
 }
 
 fn tree_name_permutated_fullqual(
@@ -387,7 +430,7 @@ impl Namespace {
                 }
                 NameSeg::Type(k, _) => {
                     // the type repr contains the namespace, so we can discard the previous
-                    output = permutater.permutated_fullqual_names(*k)?;
+                    output = Arc::unwrap_or_clone(permutater.permutated_fullqual_names(*k)?);
                     // if the type returns empty names, it means the type is being resolved
                     // recursively, so we discard this name by returning empty
                     if output.is_empty() {
@@ -405,20 +448,32 @@ impl Namespace {
                     }
                 }
                 NameSeg::Anonymous => {}
+                NameSeg::AnonymousNamespace(_) => {
+                    let s = n.to_string();
+                    if output.is_empty() {
+                        output = std::iter::once(s).collect();
+                    } else {
+                        output = output.into_iter().map(|x| format!("{x}::{s}")).collect();
+                    }
+                }
             }
         }
         Ok(output)
     }
 }
 
-fn permute(input: &[BTreeSet<String>]) -> Vec<Vec<String>> {
+fn permute<S: Borrow<BTreeSet<String>>>(input: &[S]) -> Vec<Vec<String>> {
     match input.len() {
         0 => vec![],
-        1 => input[0].iter().map(|x| vec![x.to_string()]).collect(),
+        1 => input[0]
+            .borrow()
+            .iter()
+            .map(|x| vec![x.to_string()])
+            .collect(),
         len => {
             let recur_output = permute(&input[..len - 1]);
             let mut output = Vec::with_capacity(recur_output.len() * len);
-            for last in input.last().unwrap() {
+            for last in input.last().unwrap().borrow() {
                 for prev in &recur_output {
                     output.push(
                         prev.iter()
@@ -0,0 +1,284 @@
+use cu::pre::*;
+use tyyaml::Tree;
+
+use crate::algorithm::FullQualPermutater;
+use crate::{
+    FullQualName, Goff, NameSeg, Namespace, NamespacedName, NamespacedTemplatedGoffName,
+    NamespacedTemplatedName, TemplateArg,
+};
+
+/// Policy for picking one canonical name out of all permutated names of a type
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// The lexicographically first permutated name
+    #[default]
+    Lexical,
+    /// Prefer names spelled in the source (i.e. typedefs and declarations),
+    /// for example `Foo<uint64_t>` over `Foo<u64>`
+    PreferTypedef,
+    /// Prefer names with the template args resolved to the types,
+    /// for example `Foo<u64>` over `Foo<uint64_t>`
+    PreferPrimitive,
+    /// The shortest permutated name
+    Shortest,
+}
+
+impl FullQualPermutater<'_> {
+    /// Set the policy for [`canonical_name`](Self::canonical_name)
+    pub fn with_policy(mut self, policy: NamePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Get the canonical name of the type according to the policy. None if the type is anonymous.
+    ///
+    /// Except for the lexical policy, one name is picked at each choice while the name
+    /// is built, so the combinatorial set of all names is never materialized
+    pub fn canonical_name(&mut self, goff: Goff) -> cu::Result<Option<String>> {
+        if self.policy == NamePolicy::Lexical {
+            return Ok(self.permutated_fullqual_names(goff)?.first().cloned());
+        }
+        if let Some(x) = self.canonical.get(&goff) {
+            return Ok(x.clone());
+        }
+        let map = self.names;
+        let names = cu::check!(
+            map.get(goff),
+            "did not resolve structured name for type {goff}"
+        )?;
+        // same as the permutated names, self-referencing names are discarded
+        self.canonical.insert(goff, None);
+        let prefer_goff = self.policy == NamePolicy::PreferPrimitive;
+        let preferred = names
+            .iter()
+            .filter(|n| matches!(n, FullQualName::Goff(_)) == prefer_goff);
+        let others = names
+            .iter()
+            .filter(|n| matches!(n, FullQualName::Goff(_)) != prefer_goff);
+        let mut output: Option<String> = None;
+        for n in preferred.chain(others) {
+            let Some(name) = n.canonical_name(self)? else {
+                continue;
+            };
+            if self.policy != NamePolicy::Shortest {
+                output = Some(name);
+                break;
+            }
+            let is_shorter = match &output {
+                None => true,
+                Some(x) => (name.len(), &name) < (x.len(), x),
+            };
+            if is_shorter {
+                output = Some(name);
+            }
+        }
+        if output.is_none() {
+            // do not cache and discard this attempt if empty
+            self.canonical.remove(&goff);
+            return Ok(None);
+        }
+        self.canonical.insert(goff, output.clone());
+        Ok(output)
+    }
+
+    /// Get the canonical name of a type tree according to the policy
+    pub fn canonical_tree_name(&mut self, tree: &Tree<Goff>) -> cu::Result<Option<String>> {
+        if self.policy == NamePolicy::Lexical {
+            return Ok(self.permutated_tree_names(tree)?.first().cloned());
+        }
+        tree_canonical(tree, self, &mut |k, p| p.canonical_name(*k))
+    }
+}
+
+impl FullQualName {
+    fn canonical_name(&self, permutater: &mut FullQualPermutater) -> cu::Result<Option<String>> {
+        match self {
+            Self::Name(name) => name.canonical_name(permutater),
+            Self::Goff(name) => name.canonical_name(permutater),
+        }
+    }
+}
+
+impl NamespacedTemplatedGoffName {
+    fn canonical_name(&self, permutater: &mut FullQualPermutater) -> cu::Result<Option<String>> {
+        let Some(base) = self.base.canonical_name(permutater)? else {
+            return Ok(None);
+        };
+        if self.templates.is_empty() {
+            return Ok(Some(base));
+        }
+        let mut args = Vec::with_capacity(self.templates.len());
+        for t in &self.templates {
+            let arg = match t {
+                TemplateArg::Const(x) => Some(x.to_string()),
+                TemplateArg::Type(tree) => {
+                    tree_canonical(tree, permutater, &mut |k, p| p.canonical_name(*k))?
+                }
+                TemplateArg::StaticConst => Some("[static]".to_string()),
+                TemplateArg::Unknown(s) => Some(s.clone()),
+            };
+            let Some(arg) = arg else {
+                return Ok(None);
+            };
+            args.push(arg);
+        }
+        Ok(Some(format!("{base}<{}>", args.join(", "))))
+    }
+}
+
+impl NamespacedTemplatedName {
+    fn canonical_name(&self, permutater: &mut FullQualPermutater) -> cu::Result<Option<String>> {
+        let Some(base) = self.base.canonical_name(permutater)? else {
+            return Ok(None);
+        };
+        if self.templates.is_empty() {
+            return Ok(Some(base));
+        }
+        let mut args = Vec::with_capacity(self.templates.len());
+        for t in &self.templates {
+            let arg = match t {
+                TemplateArg::Const(x) => Some(x.to_string()),
+                TemplateArg::Type(tree) => {
+                    tree_canonical(tree, permutater, &mut |n, p| n.canonical_name(p))?
+                }
+                TemplateArg::StaticConst => Some("[static]".to_string()),
+                TemplateArg::Unknown(s) => Some(s.clone()),
+            };
+            let Some(arg) = arg else {
+                return Ok(None);
+            };
+            args.push(arg);
+        }
+        Ok(Some(format!("{base}<{}>", args.join(", "))))
+    }
+}
+
+/// Build the canonical name of a type tree, in the same format as the permutated tree names
+fn tree_canonical<T, F>(
+    tree: &Tree<T>,
+    permutater: &mut FullQualPermutater,
+    base: &mut F,
+) -> cu::Result<Option<String>>
+where
+    F: FnMut(&T, &mut FullQualPermutater) -> cu::Result<Option<String>>,
+{
+    let name = match tree {
+        Tree::Base(k) => return base(k, permutater),
+        Tree::Array(elem, len) => {
+            let Some(elem) = tree_canonical(elem, permutater, base)? else {
+                return Ok(None);
+            };
+            format!("{elem}[{len}]")
+        }
+        Tree::Ptr(pointee) => {
+            if let Tree::Sub(args) = pointee.as_ref() {
+                let Some(args) = args_canonical(args, permutater, base)? else {
+                    return Ok(None);
+                };
+                format!("{}(*)({})", args[0], args[1..].join(", "))
+            } else {
+                let Some(pointee) = tree_canonical(pointee, permutater, base)? else {
+                    return Ok(None);
+                };
+                format!("{pointee}*")
+            }
+        }
+        Tree::Sub(args) => {
+            let Some(args) = args_canonical(args, permutater, base)? else {
+                return Ok(None);
+            };
+            format!("{}({})", args[0], args[1..].join(", "))
+        }
+        Tree::Ptmd(class, pointee) => {
+            let Some(class) = base(class, permutater)? else {
+                return Ok(None);
+            };
+            let Some(pointee) = tree_canonical(pointee, permutater, base)? else {
+                return Ok(None);
+            };
+            format!("{pointee} {class}::*")
+        }
+        Tree::Ptmf(class, args) => {
+            let Some(class) = base(class, permutater)? else {
+                return Ok(None);
+            };
+            let Some(args) = args_canonical(args, permutater, base)? else {
+                return Ok(None);
+            };
+            format!("{} ({class}::*)({})", args[0], args[1..].join(", "))
+        }
+    };
+    Ok(Some(name))
+}
+
+/// Canonical names of the return type and the parameters of a subroutine.
+/// None if any of them does not have a name, or there is no return type
+fn args_canonical<T, F>(
+    args: &[Tree<T>],
+    permutater: &mut FullQualPermutater,
+    base: &mut F,
+) -> cu::Result<Option<Vec<String>>>
+where
+    F: FnMut(&T, &mut FullQualPermutater) -> cu::Result<Option<String>>,
+{
+    if args.is_empty() {
+        return Ok(None);
+    }
+    let mut output = Vec::with_capacity(args.len());
+    for a in args {
+        let Some(name) = tree_canonical(a, permutater, base)? else {
+            return Ok(None);
+        };
+        output.push(name);
+    }
+    Ok(Some(output))
+}
+
+impl NamespacedName {
+    fn canonical_name(&self, permutater: &mut FullQualPermutater) -> cu::Result<Option<String>> {
+        if self.0.is_empty() {
+            return Ok(Some(self.basename().to_string()));
+        }
+        let namespace = self.0.canonical_name(permutater)?;
+        Ok(namespace.map(|x| format!("{x}::{}", self.1)))
+    }
+}
+
+impl Namespace {
+    fn canonical_name(&self, permutater: &mut FullQualPermutater) -> cu::Result<Option<String>> {
+        let mut output: Option<String> = None;
+        for n in &self.0 {
+            match n {
+                NameSeg::Name(s) => {
+                    output = Some(match output {
+                        None => s.to_string(),
+                        Some(x) => format!("{x}::{s}"),
+                    });
+                }
+                NameSeg::Type(k, _) => {
+                    // the type repr contains the namespace, so we can discard the previous
+                    output = permutater.canonical_name(*k)?;
+                    if output.is_none() {
+                        return Ok(None);
+                    }
+                }
+                NameSeg::Subprogram(_, name, is_linkage_name) => {
+                    if *is_linkage_name {
+                        output = Some(name.to_string());
+                    } else {
+                        output = output.map(|x| format!("{x}::(function {name})"));
+                    }
+                }
+                NameSeg::Anonymous => {}
+                NameSeg::AnonymousNamespace(_) => {
+                    let s = n.to_string();
+                    output = Some(match output {
+                        None => s,
+                        Some(x) => format!("{x}::{s}"),
+                    });
+                }
+            }
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use tyyaml::Tree;
+
+use crate::algorithm::FullQualPermutater;
+use crate::{
+    FullQualName, FullQualNameMap, Goff, NameSeg, Namespace, NamespacedName,
+    NamespacedTemplatedGoffName, NamespacedTemplatedName, TemplateArg,
+};
+
+impl FullQualPermutater<'_> {
+    /// Check if the spelling is one of the permutated fully-qualified names of the type,
+    /// i.e. if [`permutated_fullqual_names`](Self::permutated_fullqual_names) contains it.
+    ///
+    /// The permutations are walked lazily against the spelling, and a branch is pruned as soon
+    /// as it stops being a prefix of the spelling, so the combinatorial set of names
+    /// is never materialized
+    pub fn matches_spelling(&self, goff: Goff, spelling: &str) -> cu::Result<bool> {
+        // truncated names could be missing the spelling
+        let cached = self
+            .cache
+            .get(&goff)
+            .filter(|x| !x.is_empty() && !self.truncated.contains_key(&goff));
+        if let Some(names) = cached {
+            return Ok(names.contains(spelling));
+        }
+        let mut matcher = SpellingMatcher {
+            names: self.names,
+            spelling,
+            memo: Default::default(),
+        };
+        let ends = matcher.goff(goff, 0)?;
+        Ok(ends.contains(&spelling.len()))
+    }
+}
+
+/// Matcher of permutated names against a spelling. Each step takes the positions
+/// in the spelling where the previous part of the name could end, and returns
+/// the positions where the current part could end
+struct SpellingMatcher<'a, 'b> {
+    names: &'a FullQualNameMap,
+    spelling: &'b str,
+    /// End positions of the names of a type starting at a position
+    memo: BTreeMap<(Goff, usize), Vec<usize>>,
+}
+
+impl SpellingMatcher<'_, '_> {
+    fn literal(&self, starts: &[usize], lit: &str) -> Vec<usize> {
+        starts
+            .iter()
+            .filter(|x| self.spelling[**x..].starts_with(lit))
+            .map(|x| x + lit.len())
+            .collect()
+    }
+
+    fn each(
+        &mut self,
+        starts: &[usize],
+        mut f: impl FnMut(&mut Self, usize) -> cu::Result<Vec<usize>>,
+    ) -> cu::Result<Vec<usize>> {
+        let mut ends = vec![];
+        for start in starts {
+            ends.extend(f(self, *start)?);
+        }
+        ends.sort_unstable();
+        ends.dedup();
+        Ok(ends)
+    }
+
+    fn goff(&mut self, goff: Goff, start: usize) -> cu::Result<Vec<usize>> {
+        if let Some(ends) = self.memo.get(&(goff, start)) {
+            return Ok(ends.clone());
+        }
+        let names = cu::check!(
+            self.names.get(goff),
+            "did not resolve structured name for type {goff}"
+        )?;
+        // same as the permutater, self-referencing names have no permutation
+        self.memo.insert((goff, start), vec![]);
+        let mut ends = vec![];
+        for n in names {
+            ends.extend(match n {
+                FullQualName::Name(n) => self.templated_name(n, start)?,
+                FullQualName::Goff(n) => self.templated_goff_name(n, start)?,
+            });
+        }
+        ends.sort_unstable();
+        ends.dedup();
+        self.memo.insert((goff, start), ends.clone());
+        Ok(ends)
+    }
+
+    fn templated_goff_name(
+        &mut self,
+        name: &NamespacedTemplatedGoffName,
+        start: usize,
+    ) -> cu::Result<Vec<usize>> {
+        let mut ends = self.namespaced_name(&name.base, start)?;
+        if name.templates.is_empty() {
+            return Ok(ends);
+        }
+        ends = self.literal(&ends, "<");
+        for (i, t) in name.templates.iter().enumerate() {
+            if i > 0 {
+                ends = self.literal(&ends, ", ");
+            }
+            ends = self.each(&ends, |m, start| m.goff_template_arg(t, start))?;
+        }
+        Ok(self.literal(&ends, ">"))
+    }
+
+    fn templated_name(
+        &mut self,
+        name: &NamespacedTemplatedName,
+        start: usize,
+    ) -> cu::Result<Vec<usize>> {
+        let mut ends = self.namespaced_name(&name.base, start)?;
+        if name.templates.is_empty() {
+            return Ok(ends);
+        }
+        ends = self.literal(&ends, "<");
+        for (i, t) in name.templates.iter().enumerate() {
+            if i > 0 {
+                ends = self.literal(&ends, ", ");
+            }
+            ends = self.each(&ends, |m, start| m.name_template_arg(t, start))?;
+        }
+        Ok(self.literal(&ends, ">"))
+    }
+
+    fn goff_template_arg(
+        &mut self,
+        arg: &TemplateArg<Goff>,
+        start: usize,
+    ) -> cu::Result<Vec<usize>> {
+        match arg {
+            TemplateArg::Const(x) => Ok(self.literal(&[start], &x.to_string())),
+            TemplateArg::Type(tree) => self.tree(tree, start, &mut |m, k, start| m.goff(*k, start)),
+            TemplateArg::StaticConst => Ok(self.literal(&[start], "[static]")),
+            TemplateArg::Unknown(s) => Ok(self.literal(&[start], s)),
+        }
+    }
+
+    fn name_template_arg(
+        &mut self,
+        arg: &TemplateArg<NamespacedTemplatedName>,
+        start: usize,
+    ) -> cu::Result<Vec<usize>> {
+        match arg {
+            TemplateArg::Const(x) => Ok(self.literal(&[start], &x.to_string())),
+            TemplateArg::Type(tree) => {
+                self.tree(tree, start, &mut |m, n, start| m.templated_name(n, start))
+            }
+            TemplateArg::StaticConst => Ok(self.literal(&[start], "[static]")),
+            TemplateArg::Unknown(s) => Ok(self.literal(&[start], s)),
+        }
+    }
+
+    /// Match a type tree, in the same format as the permutated tree names
+    fn tree<T, F>(&mut self, tree: &Tree<T>, start: usize, base: &mut F) -> cu::Result<Vec<usize>>
+    where
+        F: FnMut(&mut Self, &T, usize) -> cu::Result<Vec<usize>>,
+    {
+        match tree {
+            Tree::Base(k) => base(self, k, start),
+            Tree::Array(elem, len) => {
+                let ends = self.tree(elem, start, base)?;
+                Ok(self.literal(&ends, &format!("[{len}]")))
+            }
+            Tree::Ptr(pointee) => match pointee.as_ref() {
+                Tree::Sub(args) => self.subroutine(args, start, "(*)(", base),
+                pointee => {
+                    let ends = self.tree(pointee, start, base)?;
+                    Ok(self.literal(&ends, "*"))
+                }
+            },
+            Tree::Sub(args) => self.subroutine(args, start, "(", base),
+            Tree::Ptmd(class, pointee) => {
+                let ends = self.tree(pointee, start, base)?;
+                let ends = self.literal(&ends, " ");
+                let ends = self.each(&ends, |m, start| base(m, class, start))?;
+                Ok(self.literal(&ends, "::*"))
+            }
+            Tree::Ptmf(class, args) => {
+                let Some((retty, params)) = args.split_first() else {
+                    return Ok(vec![]);
+                };
+                let ends = self.tree(retty, start, base)?;
+                let ends = self.literal(&ends, " (");
+                let ends = self.each(&ends, |m, start| base(m, class, start))?;
+                let ends = self.literal(&ends, "::*)(");
+                let ends = self.params(params, &ends, base)?;
+                Ok(self.literal(&ends, ")"))
+            }
+        }
+    }
+
+    /// Match `retty{open}params)`
+    fn subroutine<T, F>(
+        &mut self,
+        args: &[Tree<T>],
+        start: usize,
+        open: &str,
+        base: &mut F,
+    ) -> cu::Result<Vec<usize>>
+    where
+        F: FnMut(&mut Self, &T, usize) -> cu::Result<Vec<usize>>,
+    {
+        let Some((retty, params)) = args.split_first() else {
+            return Ok(vec![]);
+        };
+        let ends = self.tree(retty, start, base)?;
+        let ends = self.literal(&ends, open);
+        let ends = self.params(params, &ends, base)?;
+        Ok(self.literal(&ends, ")"))
+    }
+
+    /// Match the parameters separated by `, `
+    fn params<T, F>(
+        &mut self,
+        params: &[Tree<T>],
+        starts: &[usize],
+        base: &mut F,
+    ) -> cu::Result<Vec<usize>>
+    where
+        F: FnMut(&mut Self, &T, usize) -> cu::Result<Vec<usize>>,
+    {
+        let mut ends = starts.to_vec();
+        for (i, p) in params.iter().enumerate() {
+            if i > 0 {
+                ends = self.literal(&ends, ", ");
+            }
+            ends = self.each(&ends, |m, start| m.tree(p, start, base))?;
+        }
+        Ok(ends)
+    }
+
+    fn namespaced_name(&mut self, name: &NamespacedName, start: usize) -> cu::Result<Vec<usize>> {
+        if name.0.is_empty() {
+            return Ok(self.literal(&[start], name.basename()));
+        }
+        let ends = self.namespace(&name.0, start)?;
+        let ends = self.literal(&ends, "::");
+        Ok(self.literal(&ends, name.basename()))
+    }
+
+    /// Match the namespace, in the same way as the permutated namespaces are built.
+    /// None means nothing is matched yet, which is different from no match
+    fn namespace(&mut self, namespace: &Namespace, start: usize) -> cu::Result<Vec<usize>> {
+        let mut ends: Option<Vec<usize>> = None;
+        for n in &namespace.0 {
+            match n {
+                NameSeg::Name(s) => {
+                    ends = Some(match &ends {
+                        None => self.literal(&[start], s.as_ref()),
+                        Some(ends) => {
+                            let ends = self.literal(ends, "::");
+                            self.literal(&ends, s.as_ref())
+                        }
+                    });
+                }
+                NameSeg::Type(k, _) => {
+                    // the type repr contains the namespace, so we can discard the previous
+                    let type_ends = self.goff(*k, start)?;
+                    if type_ends.is_empty() {
+                        return Ok(type_ends);
+                    }
+                    ends = Some(type_ends);
+                }
+                NameSeg::Subprogram(_, name, is_linkage_name) => {
+                    if *is_linkage_name {
+                        ends = Some(self.literal(&[start], name.as_ref()));
+                    } else if let Some(x) = &ends {
+                        ends = Some(self.literal(x, &format!("::(function {name})")));
+                    }
+                }
+                NameSeg::Anonymous => {}
+                NameSeg::AnonymousNamespace(_) => {
+                    let s = n.to_string();
+                    ends = Some(match &ends {
+                        None => self.literal(&[start], &s),
+                        Some(ends) => {
+                            let ends = self.literal(ends, "::");
+                            self.literal(&ends, &s)
+                        }
+                    });
+                }
+            }
+        }
+        Ok(ends.unwrap_or_default())
+    }
+}
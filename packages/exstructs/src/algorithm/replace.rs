@@ -78,6 +78,12 @@ impl Struct {
                 "failed to replace type in struct member"
             )?;
         }
+        for base in &mut self.bases {
+            changed |= cu::check!(
+                tree_replace(&mut base.ty, k, replacement),
+                "failed to replace type in struct base class"
+            )?;
+        }
         for targ in &mut self.template_args {
             changed |= cu::check!(
                 targ.replace(k, replacement),
@@ -183,7 +189,7 @@ impl NameSeg {
                 *goff = *replacement;
                 Ok(true)
             }
-            NameSeg::Anonymous => Ok(false),
+            NameSeg::Anonymous | NameSeg::AnonymousNamespace(_) => Ok(false),
         }
     }
 }
@@ -212,7 +218,8 @@ impl TemplateArg<NamespacedTemplatedName> {
     }
 }
 
-fn tree_replace(tree: &mut Tree<Goff>, k: Goff, replacement: &Tree<Goff>) -> cu::Result<bool> {
+/// Replace `k` in the tree, returns if the tree is changed
+pub fn tree_replace(tree: &mut Tree<Goff>, k: Goff, replacement: &Tree<Goff>) -> cu::Result<bool> {
     let result = tree.to_replaced(|x| {
         if x == &k {
             Some(replacement.clone())
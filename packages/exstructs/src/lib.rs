@@ -15,7 +15,15 @@ mod structure;
 pub use structure::*;
 mod symbol;
 pub use symbol::*;
+mod source_loc;
+pub use source_loc::*;
+mod qualifier;
+pub use qualifier::*;
 mod size;
 pub use size::*;
+mod stable_id;
+pub use stable_id::*;
 mod name_graph;
 pub use name_graph::*;
+mod tyyaml_doc;
+pub use tyyaml_doc::*;
@@ -42,6 +42,16 @@ impl NameGraph {
         Ok(())
     }
 
+    /// All names in the graph. The index of the name is used by [`Self::iter_derived_indices`]
+    pub fn names(&self) -> &[FullQualName] {
+        &self.names
+    }
+
+    /// Iterate the (derived, base) edges as indices into [`Self::names`]
+    pub fn iter_derived_indices(&self) -> impl Iterator<Item = (usize, usize)> {
+        self.is_derived.iter()
+    }
+
     pub fn iter_derived(&self) -> impl Iterator<Item = (&FullQualName, &FullQualName)> {
         self.is_derived
             .iter()
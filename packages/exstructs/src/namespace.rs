@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 
 use cu::pre::*;
@@ -10,7 +11,7 @@ mod imp {
 
     /// Data for all namespaces
     #[derive(
-        PartialEq, Serialize, Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+        Clone, PartialEq, Serialize, Deserialize, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
     )]
     #[rkyv(derive(PartialEq))]
     #[rkyv(compare(PartialEq))]
@@ -21,6 +22,10 @@ mod imp {
         pub namespaces: GoffMap<Namespace>,
         /// Source string to namespace
         pub by_src: BTreeMap<String, Namespace>,
+        /// ABI tags removed from the names of types and namespaces,
+        /// by the source string of the name without the tags
+        #[serde(default)]
+        pub abi_tags: BTreeMap<String, Vec<String>>,
     }
 
     #[rustfmt::skip]
@@ -70,6 +75,12 @@ mod imp {
         #[display("[anonymous]")]
         #[debug("[anonymous]")]
         Anonymous,
+
+        /// Anonymous namespace qualified by the name of the compilation unit,
+        /// so types in it are not merged with types in other units
+        #[display("(anonymous namespace in {})", _0)]
+        #[debug("(anonymous namespace in {})", _0)]
+        AnonymousNamespace(ArcStr),
     }
 }
 
@@ -123,13 +134,21 @@ impl Namespace {
         self.0.is_empty()
     }
     pub fn contains_anonymous(&self) -> bool {
-        self.0.iter().any(|x| x == &NameSeg::Anonymous)
+        self.0
+            .iter()
+            .any(|x| matches!(x, NameSeg::Anonymous | NameSeg::AnonymousNamespace(_)))
     }
     pub fn contains_offsets(&self) -> bool {
         self.0
             .iter()
             .any(|x| matches!(x, NameSeg::Type(_, _) | NameSeg::Subprogram(_, _, _)))
     }
+    /// Check if the namespace is in a function, i.e. the name is of a function-local type
+    pub fn contains_subprogram(&self) -> bool {
+        self.0
+            .iter()
+            .any(|x| matches!(x, NameSeg::Subprogram(_, _, _)))
+    }
     pub fn source_segs_equal(&self, other: &Self) -> bool {
         if self.0.len() != other.0.len() {
             return false;
@@ -163,7 +182,7 @@ impl NameSeg {
             NameSeg::Subprogram(_, _, _) => {
                 cu::bail!("to_cpp_source does not support subprogram as namespace");
             }
-            NameSeg::Anonymous => Ok(None),
+            NameSeg::Anonymous | NameSeg::AnonymousNamespace(_) => Ok(None),
         }
     }
     pub fn source_segs_equal(&self, other: &Self) -> bool {
@@ -172,17 +191,42 @@ impl NameSeg {
             (NameSeg::Type(_, a), NameSeg::Type(_, b)) => a == b,
             (NameSeg::Subprogram(a, _, _), NameSeg::Subprogram(b, _, _)) => a == b,
             (NameSeg::Anonymous, NameSeg::Anonymous) => true,
+            (NameSeg::AnonymousNamespace(a), NameSeg::AnonymousNamespace(b)) => a == b,
             _ => false,
         }
     }
     pub fn to_string_without_anonymous(&self) -> Option<String> {
-        if let NameSeg::Anonymous = self {
+        if let NameSeg::Anonymous | NameSeg::AnonymousNamespace(_) = self {
             return None;
         }
         Some(self.to_string())
     }
 }
 
+/// Remove the ABI tags (like `[abi:cxx11]`) from the name. The tags are in the
+/// DWARF names of types with `__attribute__((abi_tag))`, but not in the names
+/// printed by clang, so they are removed for comparing names.
+///
+/// Returns the name without the tags, and the tags
+pub fn split_abi_tags(name: &str) -> (Cow<'_, str>, Vec<String>) {
+    if !name.contains("[abi:") {
+        return (Cow::Borrowed(name), vec![]);
+    }
+    let mut output = String::with_capacity(name.len());
+    let mut tags = vec![];
+    let mut rest = name;
+    while let Some(start) = rest.find("[abi:") {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        tags.push(rest[start + 5..start + len].to_string());
+        rest = &rest[start + len + 1..];
+    }
+    output.push_str(rest);
+    (Cow::Owned(output), tags)
+}
+
 #[rustfmt::skip]
 mod __detail {
     use super::*;
@@ -0,0 +1,71 @@
+use cu::pre::*;
+use tyyaml::Tree;
+
+mod imp {
+    use super::*;
+    /// cv-qualifiers of each node of a type tree, in pre-order of the nodes
+    /// (the base type of pointer-to-members is not a node).
+    ///
+    /// Each node is a bit set of [`Qualifiers::CONST`] and [`Qualifiers::VOLATILE`].
+    /// Empty if none of the nodes are qualified, or if qualifiers are not kept
+    #[rustfmt::skip]
+    #[derive(
+        Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+        rkyv::Archive, rkyv::Serialize, rkyv::Deserialize
+    )]
+    #[rkyv(derive(PartialEq))]
+    #[rkyv(compare(PartialEq))]
+    #[serde(transparent)]
+    pub struct Qualifiers(pub Vec<u8>);
+}
+pub use imp::Qualifiers;
+
+impl Qualifiers {
+    pub const CONST: u8 = 1;
+    pub const VOLATILE: u8 = 2;
+
+    /// Create from the qualifiers of each node, dropping them if no node is qualified
+    pub fn new(nodes: Vec<u8>) -> Self {
+        if nodes.iter().all(|x| *x == 0) {
+            return Self::default();
+        }
+        Self(nodes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the qualifiers of the nodes of the tree. The qualifiers are empty if
+    /// they don't match the shape of the tree, for example if the type was replaced
+    /// by an optimizer after the qualifiers are loaded
+    pub fn for_tree<T>(&self, tree: &Tree<T>) -> &[u8] {
+        if self.0.len() != tree_node_count(tree) {
+            return &[];
+        }
+        &self.0
+    }
+
+    /// Get the C spelling of the qualifier bits, with a trailing space if not empty
+    pub fn to_c_prefix(bits: u8) -> &'static str {
+        match (bits & Self::CONST != 0, bits & Self::VOLATILE != 0) {
+            (false, false) => "",
+            (true, false) => "const ",
+            (false, true) => "volatile ",
+            (true, true) => "const volatile ",
+        }
+    }
+}
+
+/// Number of nodes in the tree, in the same order as [`Qualifiers`]
+pub fn tree_node_count<T>(tree: &Tree<T>) -> usize {
+    match tree {
+        Tree::Base(_) => 1,
+        Tree::Array(inner, _) | Tree::Ptr(inner) | Tree::Ptmd(_, inner) => {
+            1 + tree_node_count(inner)
+        }
+        Tree::Sub(types) | Tree::Ptmf(_, types) => {
+            1 + types.iter().map(tree_node_count).sum::<usize>()
+        }
+    }
+}
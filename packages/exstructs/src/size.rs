@@ -1,11 +1,13 @@
 use cu::pre::*;
 use tyyaml::Tree;
 
-use crate::{Goff, GoffMap};
+use crate::{Goff, GoffMap, GoffSet, HType};
 
 const UNSIZED: u32 = u32::MAX;
 pub struct SizeMap {
     map: GoffMap<u32>,
+    /// Alignment of the types, only known if created with [`SizeMap::from_types`]
+    align_map: GoffMap<u32>,
     pointer_size: u32,
     ptmd_size: u32,
     ptmf_size: u32,
@@ -24,11 +26,40 @@ impl SizeMap {
             .collect();
         Self {
             map,
+            align_map: Default::default(),
             pointer_size,
             ptmd_size,
             ptmf_size,
         }
     }
+
+    /// Compute the size and alignment of every type.
+    ///
+    /// The alignment of structs and unions is the largest alignment of the members,
+    /// reduced until it divides the size for packed types. Alignment specified
+    /// in the source (i.e. `alignas`) is not in the types, and is not accounted for
+    pub fn from_types(
+        types: &GoffMap<HType>,
+        pointer_size: u32,
+        ptmd_size: u32,
+        ptmf_size: u32,
+    ) -> Self {
+        let sizes = types.iter().map(|(k, t)| (*k, t.byte_size())).collect();
+        let mut size_map = Self::new(sizes, pointer_size, ptmd_size, ptmf_size);
+        let mut ctx = AlignCtx {
+            types,
+            sizes: &size_map,
+            aligns: GoffMap::default(),
+            visiting: GoffSet::default(),
+        };
+        for k in types.keys() {
+            ctx.align_of_goff(*k);
+        }
+        let aligns = ctx.aligns;
+        size_map.align_map = aligns;
+        size_map
+    }
+
     pub fn get(&self, k: Goff) -> cu::Result<u32> {
         cu::check!(self.get_optional(k), "unexpected unsized type goff {k}")
     }
@@ -49,4 +80,204 @@ impl SizeMap {
             self.get_optional(*x)
         })
     }
+
+    /// Get `sizeof` of the type tree. The error names the part of the tree
+    /// that does not have a size
+    pub fn size_of(&self, tree: &Tree<Goff>) -> cu::Result<u32> {
+        match tree {
+            Tree::Base(k) => cu::check!(
+                self.get_optional(*k),
+                "type {k} is unsized or not in the size map"
+            ),
+            Tree::Array(elem, len) => {
+                let elem_size = cu::check!(
+                    self.size_of(elem),
+                    "failed to get the element size of array of {len} elements"
+                )?;
+                cu::check!(
+                    elem_size.checked_mul(*len),
+                    "size of array of {len} elements of size 0x{elem_size:x} overflowed"
+                )
+            }
+            Tree::Ptr(_) => Ok(self.pointer_size),
+            Tree::Sub(_) => {
+                cu::bail!("function types do not have a size, only pointers to functions do")
+            }
+            Tree::Ptmd(..) => Ok(self.ptmd_size),
+            Tree::Ptmf(..) => Ok(self.ptmf_size),
+        }
+    }
+
+    /// Get `alignof` of the type tree. The error names the part of the tree
+    /// that does not have an alignment
+    pub fn align_of(&self, tree: &Tree<Goff>) -> cu::Result<u32> {
+        match tree {
+            Tree::Base(k) => cu::check!(
+                self.get_align_optional(*k),
+                "type {k} is unsized or its alignment is not computed"
+            ),
+            Tree::Array(elem, len) => cu::check!(
+                self.align_of(elem),
+                "failed to get the element alignment of array of {len} elements"
+            ),
+            Tree::Ptr(_) => Ok(self.pointer_size),
+            Tree::Sub(_) => {
+                cu::bail!("function types do not have an alignment, only pointers to functions do")
+            }
+            Tree::Ptmd(..) => Ok(self.ptmd_size),
+            // member function pointers are a function pointer and an adjustment
+            Tree::Ptmf(..) => Ok(self.pointer_size),
+        }
+    }
+
+    /// Get the alignment of a type, None if unsized or unknown
+    pub fn get_align_optional(&self, k: Goff) -> Option<u32> {
+        if let Some(prim) = k.to_prim() {
+            return prim.byte_size();
+        }
+        match *self.align_map.get(&k)? {
+            UNSIZED => None,
+            x => Some(x),
+        }
+    }
+}
+
+struct AlignCtx<'a> {
+    types: &'a GoffMap<HType>,
+    sizes: &'a SizeMap,
+    aligns: GoffMap<u32>,
+    /// Types being computed, to stop on (invalid) recursive types
+    visiting: GoffSet,
+}
+
+impl AlignCtx<'_> {
+    fn align_of_goff(&mut self, k: Goff) -> Option<u32> {
+        if let Some(prim) = k.to_prim() {
+            return prim.byte_size();
+        }
+        if let Some(align) = self.aligns.get(&k) {
+            return (*align != UNSIZED).then_some(*align);
+        }
+        if !self.visiting.insert(k) {
+            return None;
+        }
+        let align = self.compute(k);
+        self.visiting.remove(&k);
+        self.aligns.insert(k, align.unwrap_or(UNSIZED));
+        align
+    }
+
+    fn compute(&mut self, k: Goff) -> Option<u32> {
+        let (byte_size, members) = match self.types.get(&k)? {
+            HType::Prim(prim) => return prim.byte_size(),
+            HType::Enum(data) => return Some(data.data.byte_size),
+            HType::Union(data) => (data.data.byte_size, &data.data.members),
+            HType::Struct(data) => (data.data.byte_size, &data.data.members),
+        };
+        let mut align = 1;
+        for m in members {
+            align = align.max(self.align_of_tree(&m.ty)?);
+        }
+        while align > 1 && byte_size % align != 0 {
+            align /= 2;
+        }
+        Some(align)
+    }
+
+    fn align_of_tree(&mut self, tree: &Tree<Goff>) -> Option<u32> {
+        match tree {
+            Tree::Base(k) => self.align_of_goff(*k),
+            Tree::Array(elem, _) => self.align_of_tree(elem),
+            Tree::Ptr(_) | Tree::Ptmf(..) => Some(self.sizes.pointer_size),
+            Tree::Sub(_) => None,
+            Tree::Ptmd(..) => Some(self.sizes.ptmd_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tyyaml::Prim;
+
+    use super::*;
+    use crate::{HTypeData, Member, Struct};
+
+    fn member(offset: u32, ty: Tree<Goff>) -> Member {
+        Member {
+            offset,
+            name: None,
+            ty,
+            special: None,
+            qualifiers: Default::default(),
+            access: None,
+        }
+    }
+
+    fn make_struct(byte_size: u32, members: Vec<Member>) -> HType {
+        HType::Struct(HTypeData {
+            fqnames: vec![],
+            data: Struct {
+                byte_size,
+                template_args: vec![],
+                members,
+                vtable: vec![],
+                bases: vec![],
+                is_decl: false,
+            },
+            source: None,
+        })
+    }
+
+    fn size_map() -> SizeMap {
+        let u8_ = Tree::Base(Goff::prim(Prim::U8));
+        let u32_ = Tree::Base(Goff::prim(Prim::U32));
+        let mut types = GoffMap::default();
+        // struct { u8; u32; }
+        types.insert(
+            Goff(1),
+            make_struct(8, vec![member(0, u8_.clone()), member(4, u32_.clone())]),
+        );
+        // packed struct { u8; u32; }
+        types.insert(
+            Goff(2),
+            make_struct(5, vec![member(0, u8_), member(1, u32_)]),
+        );
+        // struct { Goff(1)[2]; void*; }
+        types.insert(
+            Goff(3),
+            make_struct(
+                24,
+                vec![
+                    member(0, Tree::Array(Box::new(Tree::Base(Goff(1))), 2)),
+                    member(16, Tree::Ptr(Box::new(Tree::Base(Goff::prim(Prim::Void))))),
+                ],
+            ),
+        );
+        SizeMap::from_types(&types, 8, 8, 16)
+    }
+
+    #[test]
+    fn test_size_of() -> cu::Result<()> {
+        let sizes = size_map();
+        let array = Tree::Array(Box::new(Tree::Base(Goff(1))), 3);
+        assert_eq!(sizes.size_of(&array)?, 24);
+        let ptmf = Tree::Ptmf(Goff(1), vec![Tree::Base(Goff::prim(Prim::Void))]);
+        assert_eq!(sizes.size_of(&ptmf)?, 16);
+        let func = Tree::Sub(vec![Tree::Base(Goff::prim(Prim::Void))]);
+        assert_eq!(sizes.size_of(&Tree::Ptr(Box::new(func.clone())))?, 8);
+        assert!(sizes.size_of(&func).is_err());
+        assert!(sizes.size_of(&Tree::Base(Goff(4))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_align_of() -> cu::Result<()> {
+        let sizes = size_map();
+        assert_eq!(sizes.align_of(&Tree::Base(Goff(1)))?, 4);
+        assert_eq!(sizes.align_of(&Tree::Base(Goff(2)))?, 1);
+        assert_eq!(sizes.align_of(&Tree::Base(Goff(3)))?, 8);
+        let ptmf = Tree::Ptmf(Goff(1), vec![Tree::Base(Goff::prim(Prim::Void))]);
+        assert_eq!(sizes.align_of(&ptmf)?, 8);
+        Ok(())
+    }
 }
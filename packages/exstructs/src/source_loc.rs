@@ -0,0 +1,48 @@
+use std::hash::{Hash, Hasher};
+
+use cu::pre::*;
+
+use crate::ArcStr;
+
+mod imp {
+    use super::*;
+    /// Location in the original source where a type or symbol is declared,
+    /// from `DW_AT_decl_file` and `DW_AT_decl_line`
+    ///
+    /// The location is only informational. All locations compare and hash
+    /// equal, so that the same type seen at different paths (for example,
+    /// a header included with different relative paths) still merges
+    #[rustfmt::skip]
+    #[derive(
+        Debug, Clone, Serialize, Deserialize, Display,
+        rkyv::Archive, rkyv::Serialize, rkyv::Deserialize
+    )]
+    #[display("{}:{}", file, line)]
+    pub struct SourceLoc {
+        /// Path of the source file, as recorded in the line program
+        pub file: ArcStr,
+        /// Line number, 0 if unknown
+        pub line: u32,
+    }
+}
+pub use imp::{ArchivedSourceLoc, SourceLoc};
+
+impl PartialEq for SourceLoc {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl Eq for SourceLoc {}
+impl Hash for SourceLoc {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+impl PartialEq for ArchivedSourceLoc {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl PartialEq<SourceLoc> for ArchivedSourceLoc {
+    fn eq(&self, _: &SourceLoc) -> bool {
+        true
+    }
+}
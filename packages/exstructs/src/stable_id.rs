@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+
+use cu::pre::*;
+use fxhash::FxHasher;
+use tyyaml::{Prim, Tree, TreeRepr};
+
+use crate::{
+    FullQualName, Goff, GoffMap, HType, Member, NameSeg, NamespacedName, NamespacedTemplatedName,
+    TemplateArg,
+};
+
+/// Identifier of a type that is stable across extraction runs
+///
+/// Unlike a Goff, which is an offset into the DWARF of one build, the stable ID
+/// is a hash of the fully-qualified name and the layout of the type. The same type
+/// has the same ID in different builds, as long as its name and layout do not change
+#[rustfmt::skip]
+#[derive(DebugCustom, Display, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[display("0x{:016x}", self.0)]
+#[debug("0x{:016x}", self.0)]
+pub struct StableId(pub u64);
+
+impl Serialize for StableId {
+    fn serialize<S: serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for StableId {
+    fn deserialize<D: serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        return de.deserialize_str(Visitor);
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = StableId;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a hex integer literal")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                match cu::parse::<u64>(v) {
+                    Ok(x) => Ok(StableId(x)),
+                    Err(e) => Err(serde::de::Error::custom(format!(
+                        "failed to parse StableId: {e}"
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+/// Stable IDs of the types in one extraction run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StableIdMap {
+    ids: GoffMap<StableId>,
+}
+
+impl StableIdMap {
+    /// Assign stable IDs to the types.
+    ///
+    /// References to other types in the layout are hashed by the name, size and kind
+    /// of the referenced type, so recursive types don't need special handling.
+    /// If two types still have the same hash (i.e. they have the same name and layout),
+    /// the next free IDs are assigned in the order of the Goffs
+    pub fn new(types: &GoffMap<HType>) -> Self {
+        let shallow = types
+            .iter()
+            .map(|(goff, ty)| (*goff, shallow_hash(ty)))
+            .collect::<GoffMap<_>>();
+        let mut hashes = types
+            .iter()
+            .map(|(goff, ty)| (layout_hash(ty, &shallow), *goff))
+            .collect::<Vec<_>>();
+        hashes.sort_unstable();
+
+        let mut used = BTreeSet::new();
+        let mut ids = GoffMap::new();
+        for (hash, goff) in hashes {
+            let mut id = hash;
+            while !used.insert(id) {
+                id = id.wrapping_add(1);
+            }
+            ids.insert(goff, StableId(id));
+        }
+        Self { ids }
+    }
+
+    /// Get the stable ID of the type. Primitive types always have an ID,
+    /// even if they are not in the types
+    pub fn get(&self, goff: Goff) -> Option<StableId> {
+        if let Some(id) = self.ids.get(&goff) {
+            return Some(*id);
+        }
+        goff.to_prim().map(|p| StableId(prim_hash(p)))
+    }
+
+    /// Iterate the types as (Goff, StableId), ordered by Goff
+    pub fn iter(&self) -> impl Iterator<Item = (Goff, StableId)> + '_ {
+        self.ids.iter().map(|(goff, id)| (*goff, *id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+fn prim_hash(prim: Prim) -> u64 {
+    let mut h = FxHasher::default();
+    "prim".hash(&mut h);
+    prim.to_str().hash(&mut h);
+    h.finish()
+}
+
+/// Hash of the kind, size and primary name of the type
+fn shallow_hash(ty: &HType) -> u64 {
+    let (kind, byte_size, fqnames) = match ty {
+        HType::Prim(prim) => return prim_hash(*prim),
+        HType::Enum(data) => ("enum", data.data.byte_size, &data.fqnames),
+        HType::Union(data) => ("union", data.data.byte_size, &data.fqnames),
+        HType::Struct(data) => ("struct", data.data.byte_size, &data.fqnames),
+    };
+    let mut h = FxHasher::default();
+    kind.hash(&mut h);
+    byte_size.hash(&mut h);
+    match fqnames.first() {
+        None => "[anonymous]".hash(&mut h),
+        Some(name) => hash_fqname(name, &mut h),
+    }
+    h.finish()
+}
+
+/// Hash of the shallow hash and the layout of the type
+fn layout_hash(ty: &HType, shallow: &GoffMap<u64>) -> u64 {
+    let mut h = FxHasher::default();
+    shallow_hash(ty).hash(&mut h);
+    match ty {
+        HType::Prim(_) => {}
+        HType::Enum(data) => {
+            for e in &data.data.enumerators {
+                e.name.hash(&mut h);
+                e.value.hash(&mut h);
+            }
+        }
+        HType::Union(data) => {
+            hash_template_args(&data.data.template_args, shallow, &mut h);
+            hash_members(&data.data.members, shallow, &mut h);
+        }
+        HType::Struct(data) => {
+            hash_template_args(&data.data.template_args, shallow, &mut h);
+            hash_members(&data.data.members, shallow, &mut h);
+            for (index, entry) in &data.data.vtable {
+                index.hash(&mut h);
+                entry.name.hash(&mut h);
+                for tree in &entry.function_types {
+                    hash_tree(tree, shallow, &mut h);
+                }
+            }
+            data.data.bases.len().hash(&mut h);
+            for base in &data.data.bases {
+                base.offset.hash(&mut h);
+                base.is_virtual.hash(&mut h);
+                base.vbase_offset.hash(&mut h);
+                hash_tree(&base.ty, shallow, &mut h);
+            }
+        }
+    }
+    h.finish()
+}
+
+fn hash_members(members: &[Member], shallow: &GoffMap<u64>, h: &mut FxHasher) {
+    members.len().hash(h);
+    for m in members {
+        m.offset.hash(h);
+        m.name.hash(h);
+        m.special.hash(h);
+        hash_tree(&m.ty, shallow, h);
+    }
+}
+
+fn hash_template_args(args: &[TemplateArg<Goff>], shallow: &GoffMap<u64>, h: &mut FxHasher) {
+    args.len().hash(h);
+    for arg in args {
+        match arg {
+            TemplateArg::Type(tree) => hash_tree(tree, shallow, h),
+            other => hash_template_value(other, h),
+        }
+    }
+}
+
+/// Hash the type tree, with the referenced types replaced by their shallow hashes
+fn hash_tree(tree: &Tree<Goff>, shallow: &GoffMap<u64>, h: &mut FxHasher) {
+    let tree = tree.clone().map(|goff| match goff.to_prim() {
+        Some(prim) => prim_hash(prim),
+        None => shallow.get(&goff).copied().unwrap_or_default(),
+    });
+    tree.hash(h);
+}
+
+fn hash_fqname(name: &FullQualName, h: &mut FxHasher) {
+    match name {
+        FullQualName::Name(name) => hash_templated_name(name, h),
+        FullQualName::Goff(name) => {
+            // the template types are only known by Goff
+            hash_namespaced_name(&name.base, h);
+            name.templates.len().hash(h);
+        }
+    }
+}
+
+fn hash_templated_name(name: &NamespacedTemplatedName, h: &mut FxHasher) {
+    hash_namespaced_name(&name.base, h);
+    name.templates.len().hash(h);
+    for arg in &name.templates {
+        match arg {
+            TemplateArg::Type(tree) => {
+                let tree = tree.clone().map(|name| {
+                    let mut inner = FxHasher::default();
+                    hash_templated_name(&name, &mut inner);
+                    inner.finish()
+                });
+                tree.hash(h);
+            }
+            other => hash_template_value(other, h),
+        }
+    }
+}
+
+/// Hash the template argument that does not reference other types
+fn hash_template_value<T: TreeRepr + rkyv::Archive>(arg: &TemplateArg<T>, h: &mut FxHasher) {
+    match arg {
+        TemplateArg::Const(value) => value.hash(h),
+        TemplateArg::Type(_) => {}
+        TemplateArg::StaticConst => "[static]".hash(h),
+        TemplateArg::Unknown(spelling) => spelling.hash(h),
+    }
+}
+
+/// Hash the namespaced name without the Goffs in the namespace segments
+fn hash_namespaced_name(name: &NamespacedName, h: &mut FxHasher) {
+    for seg in &name.namespace().0 {
+        match seg {
+            NameSeg::Name(s) | NameSeg::Type(_, s) | NameSeg::Subprogram(_, s, _) => s.hash(h),
+            NameSeg::Anonymous => "[anonymous]".hash(h),
+            NameSeg::AnonymousNamespace(unit) => {
+                "[anonymous namespace]".hash(h);
+                unit.hash(h);
+            }
+        }
+    }
+    name.basename().hash(h);
+}
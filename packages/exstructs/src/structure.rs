@@ -2,7 +2,8 @@ use cu::pre::*;
 use tyyaml::{Prim, Tree};
 
 use crate::{
-    ArcStr, FullQualName, Goff, Namespace, NamespacedName, NamespacedTemplatedName, TemplateArg,
+    ArcStr, FullQualName, Goff, Namespace, NamespacedName, NamespacedTemplatedName, Qualifiers,
+    SourceLoc, TemplateArg,
 };
 
 /// High-level (H) Type data
@@ -12,7 +13,7 @@ use crate::{
 ///     i.e. as `struct Foo;`
 /// - Typedef names are merged with the definitions
 /// - All compile units are linked together
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum HType {
     /// Pritimive type
     Prim(Prim),
@@ -25,6 +26,15 @@ pub enum HType {
 }
 
 impl HType {
+    /// Byte size of the type, None if unsized
+    pub fn byte_size(&self) -> Option<u32> {
+        match self {
+            HType::Prim(prim) => prim.byte_size(),
+            HType::Enum(data) => Some(data.data.byte_size),
+            HType::Union(data) => Some(data.data.byte_size),
+            HType::Struct(data) => Some(data.data.byte_size),
+        }
+    }
     pub fn as_enum_mut(&mut self) -> cu::Result<&mut HTypeData<Enum>> {
         match self {
             HType::Enum(data) => Ok(data),
@@ -63,13 +73,16 @@ impl HType {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct HTypeData<T> {
     /// All fually qualified names of this type. Empty means this type is currently
     /// anonymous
     pub fqnames: Vec<FullQualName>,
     /// The data of the type
     pub data: T,
+    /// Where the type is defined in the original source, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceLoc>,
 }
 
 mod imp_mtype {
@@ -133,6 +146,9 @@ mod imp_mtype {
         pub decl_names: Vec<NamespacedTemplatedName>,
         /// The data
         pub data: T,
+        /// Where the type is defined in the original source, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<SourceLoc>,
     }
 
     /// Declaration data of an MType
@@ -224,6 +240,9 @@ mod imp_ltype {
         pub name: Option<NamespacedName>,
         /// The data
         pub data: T,
+        /// Where the type is defined in the original source, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<SourceLoc>,
     }
 
     /// Declaration data of a LType
@@ -258,6 +277,9 @@ mod imp_enum {
     pub struct Enum {
         /// Base type, used to determine the size
         pub byte_size: u32,
+        /// If the underlying type is signed
+        #[serde(default)]
+        pub is_signed: bool,
         /// Enumerators of the enum, in the order they appear in DWARF
         pub enumerators: Vec<Enumerator>,
     }
@@ -272,6 +294,8 @@ mod imp_enum {
     pub struct EnumUndeterminedSize {
         /// Base type, used to determine the size
         pub byte_size_or_base: Result<u32, Goff>,
+        /// If the underlying type is signed, None if not resolved yet
+        pub is_signed: Option<bool>,
         /// Enumerators of the enum, in the order they appear in DWARF
         pub enumerators: Vec<Enumerator>,
     }
@@ -314,11 +338,19 @@ mod imp_struct {
         pub byte_size: u32,
         /// Template specialization of the struct, if any
         pub template_args: Vec<TemplateArg<Goff>>,
-        /// Members of the struct
+        /// Members of the struct. Non-virtual base classes are also
+        /// members, with special of `Base`, for the layout
         pub members: Vec<Member>,
         /// Vtable of the struct. (index, entry).
         /// Dtors will have an index of 0
         pub vtable: Vec<(u32, VtableEntry)>,
+        /// Direct base classes, in declaration order
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub bases: Vec<BaseClass>,
+        /// If the struct is only declared (`DW_AT_declaration`) and not defined
+        /// in any compilation unit, so the layout is unknown
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub is_decl: bool,
     }
 }
 pub use imp_struct::Struct;
@@ -334,8 +366,41 @@ impl Struct {
             template_args,
             members: vec![],
             vtable: vec![],
+            bases: vec![],
+            is_decl: false,
+        }
+    }
+
+    /// Create a placeholder for a struct that is declared but never defined,
+    /// which is a zero-sized type with [`Struct::is_decl`] set
+    pub fn decl() -> Self {
+        Self {
+            is_decl: true,
+            ..Self::zst()
         }
     }
+
+    /// Replace all members with a single `u8` array member named `data`
+    /// that covers the whole struct. Return false if already opaque.
+    ///
+    /// The caller needs to make sure the `u8` primitive type exists
+    pub fn make_opaque(&mut self) -> bool {
+        let blob = Member {
+            offset: 0,
+            name: Some(ArcStr::from("data")),
+            ty: Tree::Array(Box::new(Tree::Base(Goff::prim(Prim::U8))), self.byte_size),
+            special: None,
+            qualifiers: Qualifiers::default(),
+            access: None,
+        };
+        if self.members.len() == 1 && self.members[0] == blob {
+            return false;
+        }
+        self.members = vec![blob];
+        // the base classes are no longer part of the layout
+        self.bases.clear();
+        true
+    }
 }
 
 mod imp_enumerator {
@@ -378,6 +443,13 @@ mod imp_member {
         pub ty: Tree<Goff>,
         /// Special-case member, None for union
         pub special: Option<SpecialMember>,
+        /// cv-qualifiers of the member type, if qualifiers are kept
+        #[serde(default, skip_serializing_if = "Qualifiers::is_empty")]
+        pub qualifiers: Qualifiers,
+        /// Access specifier of the member or base class (`DW_AT_accessibility`).
+        /// None if not specified, which is the default of the struct or class
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub access: Option<Access>,
     }
 }
 pub use imp_member::Member;
@@ -388,6 +460,45 @@ impl Member {
     }
 }
 
+impl Struct {
+    /// If the struct has direct virtual base classes
+    pub fn has_virtual_bases(&self) -> bool {
+        self.bases.iter().any(|b| b.is_virtual)
+    }
+}
+
+mod imp_base_class {
+    use super::*;
+    /// A direct base class of a struct (`DW_TAG_inheritance`)
+    #[rustfmt::skip]
+    #[derive(
+        Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize,
+        rkyv::Archive, rkyv::Serialize, rkyv::Deserialize
+    )]
+    #[rkyv(derive(PartialEq))]
+    #[rkyv(compare(PartialEq))]
+    pub struct BaseClass {
+        /// Offset of the base class subobject. None for virtual bases,
+        /// which are located at runtime through the vtable
+        pub offset: Option<u32>,
+        /// If the base class is virtual
+        #[serde(rename = "virtual", default, skip_serializing_if = "std::ops::Not::not")]
+        pub is_virtual: bool,
+        /// Offset in the vtable (from the address point) where the offset of
+        /// the virtual base is stored. None if not virtual, or if the location
+        /// is not in a recognized form
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub vbase_offset: Option<i64>,
+        /// Access specifier of the inheritance.
+        /// None if not specified, which is the default of the struct or class
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub access: Option<Access>,
+        /// Type of the base class. Might be unflattened, depending on the stage
+        pub ty: Tree<Goff>,
+    }
+}
+pub use imp_base_class::BaseClass;
+
 mod imp_special_member {
     use super::*;
     /// Special member type
@@ -401,11 +512,70 @@ mod imp_special_member {
     pub enum SpecialMember {
         Base,
         Vfptr,
+        /// Bitfields collapsed into one member
         Bitfield(u32 /* byte_size */),
+        /// Bitfields sharing the storage of the member,
+        /// if bitfields are preserved in the config
+        BitfieldGroup(u32 /* byte_size */, Vec<Bitfield>),
     }
 }
 pub use imp_special_member::SpecialMember;
 
+mod imp_access {
+    use super::*;
+    /// C++ access specifier
+    #[rustfmt::skip]
+    #[derive(
+        Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize,
+        rkyv::Archive, rkyv::Serialize, rkyv::Deserialize
+    )]
+    #[rkyv(derive(PartialEq))]
+    #[rkyv(compare(PartialEq))]
+    #[serde(rename_all = "lowercase")]
+    pub enum Access {
+        #[display("public")]
+        Public,
+        #[display("protected")]
+        Protected,
+        #[display("private")]
+        Private,
+    }
+}
+pub use imp_access::Access;
+
+impl Access {
+    /// Get the more permissive of the 2 access specifiers
+    pub fn most_permissive(self, other: Self) -> Self {
+        match (self, other) {
+            (Access::Public, _) | (_, Access::Public) => Access::Public,
+            (Access::Protected, _) | (_, Access::Protected) => Access::Protected,
+            (Access::Private, Access::Private) => Access::Private,
+        }
+    }
+}
+
+mod imp_bitfield {
+    use super::*;
+    /// A bitfield in a bitfield group member
+    #[rustfmt::skip]
+    #[derive(
+        Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize,
+        rkyv::Archive, rkyv::Serialize, rkyv::Deserialize
+    )]
+    #[rkyv(derive(PartialEq))]
+    #[rkyv(compare(PartialEq))]
+    pub struct Bitfield {
+        /// Name of the bitfield. None for unnamed padding bitfield
+        pub name: Option<ArcStr>,
+        /// Offset of the first bit from the start of the member,
+        /// counting from the least significant bit
+        pub bit_offset: u32,
+        /// Number of bits
+        pub bit_size: u32,
+    }
+}
+pub use imp_bitfield::Bitfield;
+
 mod imp_vtable_entry {
     use super::*;
     /// An entry in the virtual function table
@@ -421,6 +591,9 @@ mod imp_vtable_entry {
         pub name: ArcStr,
         /// Types to make up the subroutine type
         pub function_types: Vec<Tree<Goff>>,
+        /// Access specifier of the virtual function, if specified
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub access: Option<Access>,
     }
 }
 pub use imp_vtable_entry::VtableEntry;
@@ -2,7 +2,7 @@ use tyyaml::Tree;
 
 use cu::pre::*;
 
-use crate::{Goff, TemplateArg};
+use crate::{Goff, Qualifiers, SourceLoc, TemplateArg};
 
 mod imp {
     use super::*;
@@ -35,9 +35,55 @@ mod imp {
         pub param_names: Vec<String>,
         /// Function template instantiation
         pub template_args: Vec<TemplateArg<Goff>>,
+        /// cv-qualifiers of the type, if qualifiers are kept
+        #[serde(default, skip_serializing_if = "Qualifiers::is_empty")]
+        pub qualifiers: Qualifiers,
+        /// Where the symbol is declared in the original source, if known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<SourceLoc>,
+        /// Metadata of the declaration in the class, if the symbol is a member function
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub method: Option<MethodInfo>,
+    }
+
+    /// Metadata of a member function, for reconstructing the declaration in the class
+    #[derive(
+        Debug,
+        Clone,
+        Default,
+        PartialEq,
+        Eq,
+        Hash,
+        Serialize,
+        Deserialize,
+        rkyv::Archive,
+        rkyv::Serialize,
+        rkyv::Deserialize,
+    )]
+    #[rkyv(derive(PartialEq))]
+    #[rkyv(compare(PartialEq))]
+    pub struct MethodInfo {
+        /// If the first parameter is the implicit `this` pointer. The type of `this`
+        /// is the first parameter type in the function type tree.
+        /// False for static member functions
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub has_this: bool,
+        /// cv-qualifiers of the function (i.e. of the object pointed to by `this`),
+        /// as a bit set of [`Qualifiers::CONST`] and [`Qualifiers::VOLATILE`]
+        #[serde(default)]
+        pub qualifiers: u8,
+        /// Index of the function in the vtable, if virtual
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub virtual_index: Option<u32>,
+        /// If the function is generated by the compiler, like implicit constructors
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub is_artificial: bool,
+        /// If the function is declared as `= delete`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pub is_deleted: bool,
     }
 }
-pub use imp::SymbolInfo;
+pub use imp::{MethodInfo, SymbolInfo};
 impl SymbolInfo {
     pub fn new_data(linkage_name: String, ty: Goff) -> Self {
         Self {
@@ -46,6 +92,9 @@ impl SymbolInfo {
             ty: Tree::Base(ty),
             param_names: vec![],
             template_args: Default::default(),
+            qualifiers: Default::default(),
+            source: None,
+            method: None,
         }
     }
     pub fn new_func(
@@ -77,6 +126,9 @@ impl SymbolInfo {
             ty: Tree::Sub(types),
             param_names,
             template_args,
+            qualifiers: Default::default(),
+            source: None,
+            method: None,
         }
     }
 
@@ -105,6 +157,12 @@ impl SymbolInfo {
             self.param_names == other.param_names,
             "cannot merge symbol info with different param_names"
         )?;
+        if self.source.is_none() {
+            self.source = other.source.clone();
+        }
+        if self.method.is_none() {
+            self.method = other.method.clone();
+        }
         Ok(())
     }
 
@@ -124,6 +182,17 @@ impl SymbolInfo {
             self.param_names == other.param_names,
             "cannot merge symbol info with different param_names"
         )?;
+        // qualifiers of parameters could be different in declarations and definitions
+        if self.qualifiers.is_empty() {
+            self.qualifiers = other.qualifiers.clone();
+        }
+        // declarations could be missing the location
+        if self.source.is_none() {
+            self.source = other.source.clone();
+        }
+        if self.method.is_none() {
+            self.method = other.method.clone();
+        }
         // some info does not have template args, in which case we fill it in
         match (
             self.template_args.is_empty(),
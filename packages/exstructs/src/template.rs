@@ -4,7 +4,7 @@ use tyyaml::{Tree, TreeRepr};
 use crate::{Goff, GoffMap, NamespacedName};
 
 #[derive(Debug, Clone, From, Into)]
-pub struct FullQualNameMap(GoffMap<Vec<FullQualName>>);
+pub struct FullQualNameMap(pub(crate) GoffMap<Vec<FullQualName>>);
 impl FullQualNameMap {
     pub fn get(&self, goff: Goff) -> cu::Result<&[FullQualName]> {
         Ok(cu::check!(
@@ -17,7 +17,7 @@ impl FullQualNameMap {
 /// Fully-qualified name: namespace, name, templates
 /// Structured name data that generates all string representations of this name
 /// by permutating each segment in the namespaced name
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum FullQualName {
     Name(NamespacedTemplatedName),
     Goff(NamespacedTemplatedGoffName),
@@ -107,6 +107,11 @@ mod imp {
         /// A constant value assigned by compiler (like a function address)
         #[display("[static]")]
         StaticConst,
+
+        /// An argument that could not be parsed. The clang spelling is kept
+        /// so names can still be compared and merged by string equality
+        #[display("{}", _0)]
+        Unknown(String),
     }
 }
 pub use imp::{NamespacedTemplatedName, TemplateArg};
@@ -0,0 +1,527 @@
+use std::collections::BTreeMap;
+
+use cu::pre::*;
+use tyyaml::{
+    BaseDef, BitfieldDef, Document, EnumeratorDef, MemberDef, SymbolDef, Tree, Ty, TyYaml, TypeDef,
+    VfuncDef,
+};
+
+use crate::{
+    Access, ArcStr, BaseClass, Bitfield, Enum, Enumerator, FullQualName, Goff, GoffMap, HType,
+    HTypeData, Member, NamespacedName, NamespacedTemplatedName, SourceLoc, SpecialMember, Struct,
+    SymbolInfo, Union, VtableEntry,
+};
+
+/// Name of a type that has no name, in TyYAML and other outputs
+pub fn anonymous_type_name(k: Goff) -> String {
+    format!("[anonymous {k}]")
+}
+
+/// Get the Goff back from the name of an anonymous type
+fn parse_anonymous_type_name(name: &str) -> Option<Goff> {
+    let hex = name.strip_prefix("[anonymous 0x")?.strip_suffix(']')?;
+    usize::from_str_radix(hex, 16).ok().map(Goff)
+}
+
+/// Convert the types, symbols and named typedefs to a TyYAML document, naming the types with `names`.
+/// Types not in `names` are named with [`anonymous_type_name`].
+pub fn to_tyyaml_document(
+    types: &GoffMap<HType>,
+    symbols: &BTreeMap<String, SymbolInfo>,
+    typedefs: &BTreeMap<String, Tree<Goff>>,
+    names: &GoffMap<String>,
+) -> Document {
+    let type_name = |k: Goff| {
+        names
+            .get(&k)
+            .cloned()
+            .unwrap_or_else(|| anonymous_type_name(k))
+    };
+    let to_tyyaml = |tree: &Tree<Goff>| -> TyYaml {
+        tree.clone().map(|k| match types.get(&k) {
+            Some(HType::Prim(p)) => Ty::Prim(*p),
+            _ => Ty::Named(type_name(k)),
+        })
+    };
+
+    let mut doc = Document::default();
+    for (k, t) in types {
+        let def = match t {
+            HType::Prim(_) => continue,
+            HType::Enum(data) => TypeDef::Enum {
+                size: data.data.byte_size,
+                signed: data.data.is_signed,
+                enumerators: data
+                    .data
+                    .enumerators
+                    .iter()
+                    .map(|e| EnumeratorDef {
+                        name: e.name.to_string(),
+                        value: e.value,
+                    })
+                    .collect(),
+                source: data.source.as_ref().map(|x| x.to_string()),
+            },
+            HType::Union(data) => TypeDef::Union {
+                size: data.data.byte_size,
+                members: member_defs(&data.data.members, to_tyyaml),
+                source: data.source.as_ref().map(|x| x.to_string()),
+            },
+            HType::Struct(data) => TypeDef::Struct {
+                size: data.data.byte_size,
+                members: member_defs(&data.data.members, to_tyyaml),
+                vtable: data
+                    .data
+                    .vtable
+                    .iter()
+                    .map(|(i, entry)| VfuncDef {
+                        index: *i,
+                        name: entry.name.to_string(),
+                        ty: to_tyyaml(&Tree::Sub(entry.function_types.clone())),
+                        access: entry.access.map(to_tyyaml_access),
+                    })
+                    .collect(),
+                bases: data
+                    .data
+                    .bases
+                    .iter()
+                    .map(|b| BaseDef {
+                        offset: b.offset,
+                        is_virtual: b.is_virtual,
+                        vbase_offset: b.vbase_offset,
+                        access: b.access.map(to_tyyaml_access),
+                        ty: to_tyyaml(&b.ty),
+                    })
+                    .collect(),
+                source: data.source.as_ref().map(|x| x.to_string()),
+            },
+        };
+        doc.types.insert(type_name(*k), def);
+    }
+
+    for (name, tree) in typedefs {
+        let ty = to_tyyaml(tree);
+        doc.types.insert(name.clone(), TypeDef::Typedef { ty });
+    }
+
+    for symbol in symbols.values() {
+        let def = SymbolDef {
+            address: symbol.address,
+            ty: to_tyyaml(&symbol.ty),
+            params: symbol.param_names.clone(),
+            source: symbol.source.as_ref().map(|x| x.to_string()),
+        };
+        doc.symbols.insert(symbol.link_name.clone(), def);
+    }
+    doc
+}
+
+fn member_defs(members: &[Member], to_tyyaml: impl Fn(&Tree<Goff>) -> TyYaml) -> Vec<MemberDef> {
+    members
+        .iter()
+        .map(|m| MemberDef {
+            offset: m.offset,
+            name: m.name.as_ref().map(|x| x.to_string()),
+            ty: to_tyyaml(&m.ty),
+            special: m.special.as_ref().map(|s| match s {
+                SpecialMember::Base => "base".to_string(),
+                SpecialMember::Vfptr => "vfptr".to_string(),
+                SpecialMember::Bitfield(_) | SpecialMember::BitfieldGroup(..) => {
+                    "bitfield".to_string()
+                }
+            }),
+            bitfields: match &m.special {
+                Some(SpecialMember::BitfieldGroup(_, bitfields)) => bitfields
+                    .iter()
+                    .map(|b| BitfieldDef {
+                        name: b.name.as_ref().map(|x| x.to_string()),
+                        bit_offset: b.bit_offset,
+                        bit_size: b.bit_size,
+                    })
+                    .collect(),
+                _ => vec![],
+            },
+            access: m.access.map(to_tyyaml_access),
+        })
+        .collect()
+}
+
+/// Types, symbols and typedefs converted from a TyYAML document
+pub type TyYamlDocumentData = (
+    GoffMap<HType>,
+    BTreeMap<String, SymbolInfo>,
+    BTreeMap<String, Tree<Goff>>,
+);
+
+/// Convert a TyYAML document back to types, symbols (by link name) and typedefs (by name).
+///
+/// Named types are assigned new Goffs, since the document does not have them.
+/// Anonymous types keep the Goff in their name, so the names are the same when
+/// converted back. Each type has its TyYAML name as the only fully-qualified name
+pub fn from_tyyaml_document(doc: &Document) -> cu::Result<TyYamlDocumentData> {
+    let mut goffs = BTreeMap::new();
+    for name in doc.types.keys() {
+        if let Some(k) = parse_anonymous_type_name(name) {
+            goffs.insert(name.as_str(), k);
+        }
+    }
+    let mut next_goff = goffs.values().map(|k| k.0 + 1).max().unwrap_or(1);
+    for (name, def) in &doc.types {
+        if matches!(def, TypeDef::Typedef { .. }) || goffs.contains_key(name.as_str()) {
+            continue;
+        }
+        goffs.insert(name.as_str(), Goff(next_goff));
+        next_goff += 1;
+    }
+
+    let mut types = GoffMap::default();
+    let mut to_tree = |ty: &TyYaml| -> cu::Result<Tree<Goff>> {
+        ty.for_each(|t| {
+            if let Ty::Named(name) = t {
+                cu::ensure!(
+                    goffs.contains_key(name.as_str()),
+                    "type is not defined: {name}"
+                )?;
+            }
+            Ok(())
+        })?;
+        Ok(ty.clone().map(|t| match t {
+            Ty::Prim(p) => {
+                types.insert(Goff::prim(p), HType::Prim(p));
+                Goff::prim(p)
+            }
+            Ty::Named(name) => goffs[name.as_str()],
+        }))
+    };
+
+    let mut symbols = BTreeMap::new();
+    let mut typedefs = BTreeMap::new();
+    let mut defined = GoffMap::default();
+    for (name, def) in &doc.types {
+        let t = match def {
+            TypeDef::Typedef { ty } => {
+                let tree = cu::check!(to_tree(ty), "failed to convert typedef {name}")?;
+                typedefs.insert(name.clone(), tree);
+                continue;
+            }
+            TypeDef::Enum {
+                size,
+                signed,
+                enumerators,
+                source,
+            } => HType::Enum(HTypeData {
+                fqnames: fqnames(name),
+                data: Enum {
+                    byte_size: *size,
+                    is_signed: *signed,
+                    enumerators: enumerators
+                        .iter()
+                        .map(|e| Enumerator {
+                            name: ArcStr::from(e.name.as_str()),
+                            value: e.value,
+                        })
+                        .collect(),
+                },
+                source: source.as_deref().map(parse_source),
+            }),
+            TypeDef::Union {
+                size,
+                members,
+                source,
+            } => {
+                let members = cu::check!(
+                    members_from_defs(doc, members, &mut to_tree),
+                    "failed to convert members of union {name}"
+                )?;
+                HType::Union(HTypeData {
+                    fqnames: fqnames(name),
+                    data: Union {
+                        byte_size: *size,
+                        template_args: vec![],
+                        members,
+                    },
+                    source: source.as_deref().map(parse_source),
+                })
+            }
+            TypeDef::Struct {
+                size,
+                members,
+                vtable,
+                bases,
+                source,
+            } => {
+                let members = cu::check!(
+                    members_from_defs(doc, members, &mut to_tree),
+                    "failed to convert members of struct {name}"
+                )?;
+                let mut entries = Vec::with_capacity(vtable.len());
+                for v in vtable {
+                    let tree = to_tree(&v.ty)?;
+                    let Tree::Sub(function_types) = tree else {
+                        cu::bail!(
+                            "virtual function {} of struct {name} is not a subroutine type",
+                            v.name
+                        );
+                    };
+                    let entry = VtableEntry {
+                        name: ArcStr::from(v.name.as_str()),
+                        function_types,
+                        access: v.access.map(from_tyyaml_access),
+                    };
+                    entries.push((v.index, entry));
+                }
+                let bases = bases
+                    .iter()
+                    .map(|b| {
+                        Ok(BaseClass {
+                            offset: b.offset,
+                            is_virtual: b.is_virtual,
+                            vbase_offset: b.vbase_offset,
+                            access: b.access.map(from_tyyaml_access),
+                            ty: to_tree(&b.ty)?,
+                        })
+                    })
+                    .collect::<cu::Result<Vec<_>>>()?;
+                HType::Struct(HTypeData {
+                    fqnames: fqnames(name),
+                    data: Struct {
+                        byte_size: *size,
+                        template_args: vec![],
+                        members,
+                        vtable: entries,
+                        bases,
+                        is_decl: false,
+                    },
+                    source: source.as_deref().map(parse_source),
+                })
+            }
+        };
+        defined.insert(goffs[name.as_str()], t);
+    }
+
+    for (link_name, def) in &doc.symbols {
+        let ty = cu::check!(to_tree(&def.ty), "failed to convert symbol {link_name}")?;
+        let symbol = SymbolInfo {
+            address: def.address,
+            link_name: link_name.clone(),
+            ty,
+            param_names: def.params.clone(),
+            template_args: vec![],
+            qualifiers: Default::default(),
+            source: def.source.as_deref().map(parse_source),
+            method: None,
+        };
+        symbols.insert(link_name.clone(), symbol);
+    }
+
+    types.extend(defined);
+    Ok((types, symbols, typedefs))
+}
+
+fn members_from_defs(
+    doc: &Document,
+    members: &[MemberDef],
+    to_tree: &mut impl FnMut(&TyYaml) -> cu::Result<Tree<Goff>>,
+) -> cu::Result<Vec<Member>> {
+    let mut output = Vec::with_capacity(members.len());
+    for m in members {
+        let special = match m.special.as_deref() {
+            None => None,
+            Some("base") => Some(SpecialMember::Base),
+            Some("vfptr") => Some(SpecialMember::Vfptr),
+            Some("bitfield") => {
+                let byte_size = cu::check!(
+                    storage_byte_size(doc, &m.ty),
+                    "cannot get the storage size of bitfield member at offset {}",
+                    m.offset
+                )?;
+                if m.bitfields.is_empty() {
+                    Some(SpecialMember::Bitfield(byte_size))
+                } else {
+                    let bitfields = m
+                        .bitfields
+                        .iter()
+                        .map(|b| Bitfield {
+                            name: b.name.as_deref().map(ArcStr::from),
+                            bit_offset: b.bit_offset,
+                            bit_size: b.bit_size,
+                        })
+                        .collect();
+                    Some(SpecialMember::BitfieldGroup(byte_size, bitfields))
+                }
+            }
+            Some(other) => cu::bail!("unknown special member kind: {other}"),
+        };
+        output.push(Member {
+            offset: m.offset,
+            name: m.name.as_deref().map(ArcStr::from),
+            ty: to_tree(&m.ty)?,
+            special,
+            qualifiers: Default::default(),
+            access: m.access.map(from_tyyaml_access),
+        });
+    }
+    Ok(output)
+}
+
+/// Size of the storage of a bitfield member, which is a primitive or an enum
+fn storage_byte_size(doc: &Document, ty: &TyYaml) -> Option<u32> {
+    match ty {
+        Tree::Base(Ty::Prim(p)) => p.byte_size(),
+        Tree::Base(Ty::Named(name)) => match doc.types.get(name)? {
+            TypeDef::Enum { size, .. } => Some(*size),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn fqnames(name: &str) -> Vec<FullQualName> {
+    if parse_anonymous_type_name(name).is_some() {
+        return vec![];
+    }
+    vec![FullQualName::Name(NamespacedTemplatedName::new(
+        NamespacedName::unnamespaced(name),
+    ))]
+}
+
+fn parse_source(source: &str) -> SourceLoc {
+    match source
+        .rsplit_once(':')
+        .map(|(file, line)| (file, cu::parse::<u32>(line)))
+    {
+        Some((file, Ok(line))) => SourceLoc {
+            file: ArcStr::from(file),
+            line,
+        },
+        _ => SourceLoc {
+            file: ArcStr::from(source),
+            line: 0,
+        },
+    }
+}
+
+fn to_tyyaml_access(access: Access) -> tyyaml::Access {
+    match access {
+        Access::Public => tyyaml::Access::Public,
+        Access::Protected => tyyaml::Access::Protected,
+        Access::Private => tyyaml::Access::Private,
+    }
+}
+
+fn from_tyyaml_access(access: tyyaml::Access) -> Access {
+    match access {
+        tyyaml::Access::Public => Access::Public,
+        tyyaml::Access::Protected => Access::Protected,
+        tyyaml::Access::Private => Access::Private,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tyyaml::Prim;
+
+    fn example() -> Document {
+        let mut doc = Document::default();
+        doc.types.insert(
+            "Foo".to_string(),
+            TypeDef::Struct {
+                size: 0x18,
+                members: vec![
+                    MemberDef {
+                        offset: 0,
+                        name: None,
+                        ty: TyYaml::ptr(TyYaml::ptr(Prim::Void)),
+                        special: Some("vfptr".to_string()),
+                        bitfields: vec![],
+                        access: None,
+                    },
+                    MemberDef {
+                        offset: 8,
+                        name: Some("mFlags".to_string()),
+                        ty: Prim::U32.into(),
+                        special: Some("bitfield".to_string()),
+                        bitfields: vec![BitfieldDef {
+                            name: Some("mIsValid".to_string()),
+                            bit_offset: 0,
+                            bit_size: 1,
+                        }],
+                        access: Some(tyyaml::Access::Private),
+                    },
+                    MemberDef {
+                        offset: 0x10,
+                        name: Some("mData".to_string()),
+                        ty: TyYaml::ptr(TyYaml::named("[anonymous 0x00000020]")),
+                        special: None,
+                        bitfields: vec![],
+                        access: None,
+                    },
+                ],
+                vtable: vec![VfuncDef {
+                    index: 0,
+                    name: "~Foo".to_string(),
+                    ty: TyYaml::Sub(vec![Prim::Void.into(), TyYaml::ptr(TyYaml::named("Foo"))]),
+                    access: Some(tyyaml::Access::Public),
+                }],
+                bases: vec![],
+                source: Some("foo.h:12".to_string()),
+            },
+        );
+        doc.types.insert(
+            "[anonymous 0x00000020]".to_string(),
+            TypeDef::Union {
+                size: 4,
+                members: vec![MemberDef {
+                    offset: 0,
+                    name: Some("f".to_string()),
+                    ty: Prim::F32.into(),
+                    special: None,
+                    bitfields: vec![],
+                    access: None,
+                }],
+                source: None,
+            },
+        );
+        doc.types.insert(
+            "FooCallback".to_string(),
+            TypeDef::Typedef {
+                ty: TyYaml::ptr(TyYaml::Sub(vec![
+                    Prim::Bool.into(),
+                    TyYaml::ptr(TyYaml::named("Foo")),
+                ])),
+            },
+        );
+        doc.symbols.insert(
+            "gFoo".to_string(),
+            SymbolDef {
+                address: 0x1234,
+                ty: TyYaml::named("Foo"),
+                params: vec![],
+                source: None,
+            },
+        );
+        doc
+    }
+
+    #[test]
+    fn test_round_trip() -> cu::Result<()> {
+        let doc = example();
+        let (types, symbols, typedefs) = from_tyyaml_document(&doc)?;
+        let mut names = GoffMap::default();
+        for (k, t) in &types {
+            if let Ok([FullQualName::Name(name)]) = t.fqnames() {
+                names.insert(*k, name.base.basename().to_string());
+            }
+        }
+        assert_eq!(to_tyyaml_document(&types, &symbols, &typedefs, &names), doc);
+        Ok(())
+    }
+
+    #[test]
+    fn test_undefined_type() {
+        let mut doc = example();
+        doc.symbols.get_mut("gFoo").unwrap().ty = TyYaml::named("Bar");
+        assert!(from_tyyaml_document(&doc).is_err());
+    }
+}
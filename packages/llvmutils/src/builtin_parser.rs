@@ -0,0 +1,910 @@
+use cu::pre::*;
+use exstructs::{Namespace, NamespaceMaps, NamespacedName, NamespacedTemplatedName, TemplateArg};
+use tyyaml::Tree;
+
+use crate::NameParser;
+use crate::name_parser::{builtin_prim_name, to_namespaced_name};
+
+const ANONYMOUS_NAMESPACE: &str = "(anonymous namespace)";
+
+/// Keywords that are part of a type specifier, but don't change the type tree
+const IGNORED_SPECIFIERS: &[&str] = &[
+    "const",
+    "volatile",
+    "restrict",
+    "__restrict",
+    "struct",
+    "class",
+    "union",
+    "enum",
+    "typename",
+];
+
+/// Keywords of the builtin types, which can be combined like `unsigned long int`
+const BUILTIN_WORDS: &[&str] = &[
+    "void", "bool", "char", "wchar_t", "char8_t", "char16_t", "char32_t", "short", "int", "long",
+    "signed", "unsigned", "float", "double", "__int128",
+];
+
+/// Keywords that need semantic analysis to get the type
+const NEEDS_CLANG: &[&str] = &[
+    "decltype",
+    "typeof",
+    "__typeof__",
+    "__underlying_type",
+    "sizeof",
+    "alignof",
+    "noexcept",
+    "operator",
+    "nullptr",
+    "auto",
+];
+
+/// Parse a templated type name without clang, into the same output as
+/// parsing the clang AST.
+///
+/// This is a recursive-descent parser for the subset of the C++ type syntax
+/// in DWARF names: qualified names, templates, cv-qualifiers, pointers, references,
+/// function types and member pointers. Names that need semantic analysis,
+/// like `decltype` or constant expressions, are errors
+pub(crate) fn parse_name(
+    basename: &str,
+    namespace: &Namespace,
+    ns: &NamespaceMaps,
+    parser: &NameParser,
+) -> cu::Result<NamespacedTemplatedName> {
+    let tokens = tokenize(basename)?;
+    let mut p = Parser {
+        source: basename,
+        tokens,
+        pos: 0,
+        allow_unknown: parser.allow_unknown_template_args,
+    };
+    let (name, args) = p.top_level()?;
+    let templates = resolve_args(args, ns, parser)?;
+    // the base name from clang does not have the ABI tags
+    let (name, _) = exstructs::split_abi_tags(&name);
+    Ok(NamespacedTemplatedName::with_templates(
+        NamespacedName::namespaced(namespace, &name),
+        templates,
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(String),
+    Scope,
+    Lt,
+    Gt,
+    Comma,
+    Star,
+    Amp,
+    AmpAmp,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Minus,
+    Ellipsis,
+}
+
+#[derive(Debug)]
+struct Token {
+    tok: Tok,
+    /// Byte range in the source
+    start: usize,
+    end: usize,
+}
+
+fn tokenize(source: &str) -> cu::Result<Vec<Token>> {
+    let bytes = source.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let tok = match bytes[i] {
+            b' ' | b'\t' | b'\n' => {
+                i += 1;
+                continue;
+            }
+            b'(' => {
+                let rest = &source[i + 1..];
+                if source[i..].starts_with(ANONYMOUS_NAMESPACE) {
+                    i += ANONYMOUS_NAMESPACE.len();
+                    Tok::Ident(ANONYMOUS_NAMESPACE.to_string())
+                } else if rest.starts_with("anonymous ")
+                    || rest.starts_with("unnamed ")
+                    || rest.starts_with("lambda ")
+                {
+                    cu::bail!("anonymous type at column {i} in '{source}' needs clang");
+                } else {
+                    i += 1;
+                    Tok::LParen
+                }
+            }
+            b':' => {
+                cu::ensure!(
+                    bytes.get(i + 1) == Some(&b':'),
+                    "unexpected ':' at column {i} in '{source}'"
+                )?;
+                i += 2;
+                Tok::Scope
+            }
+            b'&' if bytes.get(i + 1) == Some(&b'&') => {
+                i += 2;
+                Tok::AmpAmp
+            }
+            b'.' => {
+                cu::ensure!(
+                    source[i..].starts_with("..."),
+                    "unexpected '.' at column {i} in '{source}'"
+                )?;
+                i += 3;
+                Tok::Ellipsis
+            }
+            b'0'..=b'9' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'\'') {
+                    i += 1;
+                }
+                Tok::Number(source[start..i].to_string())
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' || c == b'$' => {
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$')
+                {
+                    i += 1;
+                }
+                // keep the ABI tags with the name, like `basic_string[abi:cxx11]`
+                while source[i..].starts_with("[abi:") {
+                    match source[i..].find(']') {
+                        Some(len) => i += len + 1,
+                        None => cu::bail!("unterminated ABI tag at column {i} in '{source}'"),
+                    }
+                }
+                Tok::Ident(source[start..i].to_string())
+            }
+            c => {
+                let tok = match c {
+                    b'&' => Tok::Amp,
+                    b'<' => Tok::Lt,
+                    b'>' => Tok::Gt,
+                    b',' => Tok::Comma,
+                    b'*' => Tok::Star,
+                    b')' => Tok::RParen,
+                    b'[' => Tok::LBracket,
+                    b']' => Tok::RBracket,
+                    b'-' => Tok::Minus,
+                    _ => {
+                        let c = source[i..].chars().next().unwrap_or_default();
+                        cu::bail!(
+                            "unexpected character '{c}' at column {i} in '{source}', this name needs clang"
+                        );
+                    }
+                };
+                i += 1;
+                tok
+            }
+        };
+        tokens.push(Token { tok, start, end: i });
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum ArgSyntax {
+    Const(i64),
+    /// The type, and the spelling of the arg
+    Type(TypeSyntax, String),
+    /// The spelling of an arg that cannot be parsed
+    Unknown(String),
+}
+
+#[derive(Debug)]
+struct TypeSyntax {
+    base: BaseSyntax,
+    /// Operations applied to the base type, from the innermost
+    ops: Vec<OpSyntax>,
+}
+
+#[derive(Debug)]
+enum BaseSyntax {
+    /// Builtin type, with the spelling printed by clang
+    Builtin(&'static str),
+    Name(NameSyntax),
+}
+
+#[derive(Debug)]
+struct NameSyntax {
+    /// The source of the qualified name, without the templates of the last segment
+    qualified: String,
+    templates: Option<Vec<ArgSyntax>>,
+}
+
+#[derive(Debug)]
+enum OpSyntax {
+    /// Pointer or reference
+    Ptr,
+    /// Pointer to member of the class
+    MemberPtr(NameSyntax),
+    /// Function returning the type, with the parameter types
+    Func(Vec<TypeSyntax>),
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Degrade unparsable template args to unknown
+    allow_unknown: bool,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<&Tok> {
+        self.tokens.get(self.pos + n).map(|t| &t.tok)
+    }
+
+    fn eat(&mut self, tok: &Tok) -> bool {
+        if self.peek() == Some(tok) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, tok: &Tok, what: &str) -> cu::Result<()> {
+        cu::ensure!(
+            self.eat(tok),
+            "expecting {what} at column {} in '{}'",
+            self.column(),
+            self.source
+        )
+    }
+
+    /// Column of the next token in the source
+    fn column(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some(t) => t.start,
+            None => self.source.len(),
+        }
+    }
+
+    /// Source of the tokens from the start to the current position
+    fn spelling(&self, start: usize) -> String {
+        if start >= self.pos {
+            return String::new();
+        }
+        let start = self.tokens[start].start;
+        let end = self.tokens[self.pos - 1].end;
+        self.source[start..end].to_string()
+    }
+
+    /// Parse the outermost name, which must be templated and unqualified
+    fn top_level(&mut self) -> cu::Result<(String, Vec<ArgSyntax>)> {
+        let Some(Tok::Ident(name)) = self.peek().cloned() else {
+            cu::bail!("expecting name at the start of '{}'", self.source);
+        };
+        self.pos += 1;
+        self.expect(&Tok::Lt, "'<'")?;
+        let args = self.template_args()?;
+        cu::ensure!(
+            self.pos == self.tokens.len(),
+            "unexpected tokens after the template args at column {} in '{}'",
+            self.column(),
+            self.source
+        )?;
+        Ok((name, args))
+    }
+
+    /// Parse the template args after `<`, including the closing `>`
+    fn template_args(&mut self) -> cu::Result<Vec<ArgSyntax>> {
+        let mut args = vec![];
+        if self.eat(&Tok::Gt) {
+            return Ok(args);
+        }
+        loop {
+            let start = self.pos;
+            let result = match self.template_arg() {
+                Ok(arg) => self.ensure_arg_end().map(|_| arg),
+                Err(e) => Err(e),
+            };
+            let arg = match result {
+                Ok(arg) => arg,
+                Err(e) => {
+                    if !self.allow_unknown {
+                        return Err(e);
+                    }
+                    self.pos = start;
+                    self.skip_arg()?;
+                    let spelling = self.spelling(start);
+                    cu::debug!("degrading unparsable template arg to unknown: {spelling}: {e:?}");
+                    ArgSyntax::Unknown(spelling)
+                }
+            };
+            args.push(arg);
+            if self.eat(&Tok::Comma) {
+                continue;
+            }
+            self.expect(&Tok::Gt, "',' or '>'")?;
+            return Ok(args);
+        }
+    }
+
+    fn ensure_arg_end(&self) -> cu::Result<()> {
+        cu::ensure!(
+            matches!(self.peek(), Some(Tok::Comma | Tok::Gt)),
+            "expecting ',' or '>' at column {} in '{}'",
+            self.column(),
+            self.source
+        )
+    }
+
+    /// Skip to the end of the template arg
+    fn skip_arg(&mut self) -> cu::Result<()> {
+        let mut depth = 0usize;
+        while let Some(tok) = self.peek() {
+            match tok {
+                Tok::Comma | Tok::Gt if depth == 0 => return Ok(()),
+                Tok::Lt | Tok::LParen | Tok::LBracket => depth += 1,
+                Tok::Gt | Tok::RParen | Tok::RBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        cu::bail!("unterminated template args in '{}'", self.source);
+    }
+
+    fn template_arg(&mut self) -> cu::Result<ArgSyntax> {
+        let start = self.pos;
+        match self.peek() {
+            Some(Tok::Number(_) | Tok::Minus | Tok::LParen) => {
+                Ok(ArgSyntax::Const(self.constant()?))
+            }
+            Some(Tok::Ident(x)) if x == "true" || x == "false" => {
+                Ok(ArgSyntax::Const(self.constant()?))
+            }
+            _ => {
+                let ty = self.type_id()?;
+                Ok(ArgSyntax::Type(ty, self.spelling(start)))
+            }
+        }
+    }
+
+    fn constant(&mut self) -> cu::Result<i64> {
+        // C-style cast, which is how enum template args are spelled, like `(Enum)1`
+        if self.eat(&Tok::LParen) {
+            self.type_id()?;
+            self.expect(&Tok::RParen, "')'")?;
+        }
+        let negative = self.eat(&Tok::Minus);
+        let value = match self.peek().cloned() {
+            Some(Tok::Ident(x)) if x == "true" && !negative => 1,
+            Some(Tok::Ident(x)) if x == "false" && !negative => 0,
+            Some(Tok::Number(x)) => parse_integer(&x)?,
+            _ => cu::bail!(
+                "expecting integer at column {} in '{}', constant expressions need clang",
+                self.column(),
+                self.source
+            ),
+        };
+        self.pos += 1;
+        Ok(if negative {
+            value.wrapping_neg()
+        } else {
+            value
+        })
+    }
+
+    fn type_id(&mut self) -> cu::Result<TypeSyntax> {
+        let base = self.type_specifier()?;
+        let ops = self.declarator()?;
+        Ok(TypeSyntax { base, ops })
+    }
+
+    /// Parse the base type, with the cv-qualifiers around it
+    fn type_specifier(&mut self) -> cu::Result<BaseSyntax> {
+        let mut words = vec![];
+        let mut name = None;
+        while let Some(Tok::Ident(ident)) = self.peek() {
+            let ident = ident.as_str();
+            if IGNORED_SPECIFIERS.contains(&ident) {
+                self.pos += 1;
+                continue;
+            }
+            if name.is_some() {
+                break;
+            }
+            if BUILTIN_WORDS.contains(&ident) {
+                words.push(ident.to_string());
+                self.pos += 1;
+                continue;
+            }
+            if !words.is_empty() {
+                break;
+            }
+            name = Some(self.qualified_name()?);
+        }
+        if let Some(name) = name {
+            return Ok(BaseSyntax::Name(name));
+        }
+        if words.is_empty() {
+            if self.peek() == Some(&Tok::Scope) {
+                return Ok(BaseSyntax::Name(self.qualified_name()?));
+            }
+            cu::bail!(
+                "expecting type at column {} in '{}'",
+                self.column(),
+                self.source
+            );
+        }
+        Ok(BaseSyntax::Builtin(canonical_builtin(&words)?))
+    }
+
+    fn qualified_name(&mut self) -> cu::Result<NameSyntax> {
+        self.eat(&Tok::Scope);
+        let start = self.column();
+        loop {
+            let Some(Tok::Ident(ident)) = self.peek() else {
+                cu::bail!(
+                    "expecting name at column {} in '{}'",
+                    self.column(),
+                    self.source
+                );
+            };
+            cu::ensure!(
+                !NEEDS_CLANG.contains(&ident.as_str()),
+                "'{ident}' at column {} in '{}' needs clang",
+                self.column(),
+                self.source
+            )?;
+            let end = self.tokens[self.pos].end;
+            self.pos += 1;
+            let templates = if self.eat(&Tok::Lt) {
+                Some(self.template_args()?)
+            } else {
+                None
+            };
+            if self.peek() == Some(&Tok::Scope) && matches!(self.peek_at(1), Some(Tok::Ident(_))) {
+                self.pos += 1;
+                continue;
+            }
+            return Ok(NameSyntax {
+                qualified: self.source[start..end].to_string(),
+                templates,
+            });
+        }
+    }
+
+    /// Parse the abstract declarator after the type specifier,
+    /// like `*`, `(*)(int)` or `Foo::*`
+    fn declarator(&mut self) -> cu::Result<Vec<OpSyntax>> {
+        let mut ops = vec![];
+        loop {
+            self.skip_cv();
+            match self.peek() {
+                Some(Tok::Star | Tok::Amp | Tok::AmpAmp) => {
+                    self.pos += 1;
+                    ops.push(OpSyntax::Ptr);
+                }
+                _ => match self.member_pointer() {
+                    Some(this) => ops.push(OpSyntax::MemberPtr(this)),
+                    None => break,
+                },
+            }
+        }
+        let mut inner = vec![];
+        if self.peek() == Some(&Tok::LParen) && self.is_nested_declarator() {
+            self.pos += 1;
+            inner = self.declarator()?;
+            self.expect(&Tok::RParen, "')'")?;
+        }
+        let mut suffixes = vec![];
+        loop {
+            match self.peek() {
+                Some(Tok::LParen) => {
+                    self.pos += 1;
+                    suffixes.push(OpSyntax::Func(self.params()?));
+                    self.function_qualifiers()?;
+                }
+                Some(Tok::LBracket) => cu::bail!(
+                    "array type at column {} in '{}' is not supported",
+                    self.column(),
+                    self.source
+                ),
+                _ => break,
+            }
+        }
+        // suffixes bind tighter than the pointers, and the outermost suffix is the first
+        ops.extend(suffixes.into_iter().rev());
+        ops.extend(inner);
+        Ok(ops)
+    }
+
+    fn skip_cv(&mut self) {
+        while let Some(Tok::Ident(ident)) = self.peek() {
+            if !matches!(
+                ident.as_str(),
+                "const" | "volatile" | "restrict" | "__restrict"
+            ) {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Try parsing `Foo::*`, without consuming anything if it's not a member pointer
+    fn member_pointer(&mut self) -> Option<NameSyntax> {
+        if !matches!(self.peek(), Some(Tok::Ident(_) | Tok::Scope)) {
+            return None;
+        }
+        let save = self.pos;
+        if let Ok(name) = self.qualified_name() {
+            if self.peek() == Some(&Tok::Scope) && self.peek_at(1) == Some(&Tok::Star) {
+                self.pos += 2;
+                return Some(name);
+            }
+        }
+        self.pos = save;
+        None
+    }
+
+    /// Check if the `(` starts a nested declarator like `(*)`, instead of function parameters
+    fn is_nested_declarator(&mut self) -> bool {
+        match self.peek_at(1) {
+            Some(Tok::Star | Tok::Amp | Tok::AmpAmp) => true,
+            Some(Tok::Ident(_) | Tok::Scope) => {
+                let save = self.pos;
+                self.pos += 1;
+                let is_member_pointer = self.member_pointer().is_some();
+                self.pos = save;
+                is_member_pointer
+            }
+            _ => false,
+        }
+    }
+
+    /// Parse the function parameters after `(`, including the closing `)`
+    fn params(&mut self) -> cu::Result<Vec<TypeSyntax>> {
+        let mut params = vec![];
+        if self.eat(&Tok::RParen) {
+            return Ok(params);
+        }
+        loop {
+            // variadic args are not part of the type
+            if self.eat(&Tok::Ellipsis) {
+                self.expect(&Tok::RParen, "')'")?;
+                break;
+            }
+            params.push(self.type_id()?);
+            if self.eat(&Tok::Comma) {
+                continue;
+            }
+            self.expect(&Tok::RParen, "',' or ')'")?;
+            break;
+        }
+        // `(void)` is the same as `()`
+        if let [param] = params.as_slice() {
+            if param.ops.is_empty() && matches!(param.base, BaseSyntax::Builtin("void")) {
+                params.clear();
+            }
+        }
+        Ok(params)
+    }
+
+    /// Skip the cv-qualifiers, ref-qualifiers and `noexcept` after the function parameters
+    fn function_qualifiers(&mut self) -> cu::Result<()> {
+        loop {
+            self.skip_cv();
+            match self.peek() {
+                Some(Tok::Amp | Tok::AmpAmp) => self.pos += 1,
+                Some(Tok::Ident(x)) if x == "noexcept" => {
+                    self.pos += 1;
+                    cu::ensure!(
+                        self.peek() != Some(&Tok::LParen),
+                        "conditional noexcept at column {} in '{}' needs clang",
+                        self.column(),
+                        self.source
+                    )?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Get the clang spelling of a builtin type from the keywords
+fn canonical_builtin(words: &[String]) -> cu::Result<&'static str> {
+    let mut signed = false;
+    let mut unsigned = false;
+    let mut short = 0;
+    let mut long = 0;
+    let mut base = None;
+    for word in words {
+        match word.as_str() {
+            "signed" => signed = true,
+            "unsigned" => unsigned = true,
+            "short" => short += 1,
+            "long" => long += 1,
+            other => {
+                cu::ensure!(base.is_none(), "invalid builtin type '{}'", words.join(" "))?;
+                base = Some(other);
+            }
+        }
+    }
+    let is_int = matches!(base, None | Some("int"));
+    let spelling = match (base, unsigned, short, long) {
+        (Some("char"), false, 0, 0) if signed => "signed char",
+        (Some("char"), false, 0, 0) => "char",
+        (Some("char"), true, 0, 0) if !signed => "unsigned char",
+        (_, false, 1, 0) if is_int => "short",
+        (_, true, 1, 0) if is_int && !signed => "unsigned short",
+        (_, false, 0, 1) if is_int => "long",
+        (_, true, 0, 1) if is_int && !signed => "unsigned long",
+        (_, false, 0, 2) if is_int => "long long",
+        (_, true, 0, 2) if is_int && !signed => "unsigned long long",
+        (_, false, 0, 0) if is_int => "int",
+        (_, true, 0, 0) if is_int && !signed => "unsigned int",
+        (Some("double"), false, 0, 1) if !signed => "long double",
+        (Some("__int128"), true, 0, 0) if !signed => "unsigned __int128",
+        (Some(base), false, 0, 0) if !signed => match base {
+            "void" => "void",
+            "bool" => "bool",
+            "wchar_t" => "wchar_t",
+            "char8_t" => "char8_t",
+            "char16_t" => "char16_t",
+            "char32_t" => "char32_t",
+            "float" => "float",
+            "double" => "double",
+            "__int128" => "__int128",
+            _ => cu::bail!("invalid builtin type '{}'", words.join(" ")),
+        },
+        _ => cu::bail!("invalid builtin type '{}'", words.join(" ")),
+    };
+    Ok(spelling)
+}
+
+/// Parse an integer literal, like `1`, `0x10` or `1ull`
+fn parse_integer(literal: &str) -> cu::Result<i64> {
+    let cleaned = literal
+        .trim_end_matches(['u', 'U', 'l', 'L'])
+        .replace('\'', "");
+    let (digits, radix) = if let Some(x) = cleaned
+        .strip_prefix("0x")
+        .or_else(|| cleaned.strip_prefix("0X"))
+    {
+        (x, 16)
+    } else if let Some(x) = cleaned
+        .strip_prefix("0b")
+        .or_else(|| cleaned.strip_prefix("0B"))
+    {
+        (x, 2)
+    } else if let Some(x) = cleaned.strip_prefix('0').filter(|x| !x.is_empty()) {
+        (x, 8)
+    } else {
+        (cleaned.as_str(), 10)
+    };
+    let value = cu::check!(
+        u64::from_str_radix(digits, radix),
+        "invalid integer literal '{literal}'"
+    )?;
+    // large unsigned values wrap around, like how they are stored in the DWARF
+    Ok(value as i64)
+}
+
+fn resolve_args(
+    args: Vec<ArgSyntax>,
+    ns: &NamespaceMaps,
+    parser: &NameParser,
+) -> cu::Result<Vec<TemplateArg<NamespacedTemplatedName>>> {
+    let mut output = Vec::with_capacity(args.len());
+    for arg in args {
+        let arg = match arg {
+            ArgSyntax::Const(value) => TemplateArg::Const(value),
+            ArgSyntax::Unknown(spelling) => TemplateArg::Unknown(spelling),
+            ArgSyntax::Type(ty, spelling) => match resolve_type(ty, ns, parser) {
+                Ok(ty) => TemplateArg::Type(ty),
+                Err(e) => {
+                    if !parser.allow_unknown_template_args {
+                        return Err(e);
+                    }
+                    cu::debug!("degrading unresolvable template arg to unknown: {spelling}: {e:?}");
+                    TemplateArg::Unknown(spelling)
+                }
+            },
+        };
+        output.push(arg);
+    }
+    Ok(output)
+}
+
+fn resolve_type(
+    ty: TypeSyntax,
+    ns: &NamespaceMaps,
+    parser: &NameParser,
+) -> cu::Result<Tree<NamespacedTemplatedName>> {
+    let mut tree = match ty.base {
+        BaseSyntax::Builtin(spelling) => Tree::Base(NamespacedTemplatedName::new(
+            NamespacedName::unnamespaced(builtin_prim_name(spelling, parser)?),
+        )),
+        BaseSyntax::Name(name) => Tree::Base(resolve_name(name, ns, parser)?),
+    };
+    for op in ty.ops {
+        tree = match op {
+            OpSyntax::Ptr => Tree::ptr(tree),
+            OpSyntax::MemberPtr(this) => {
+                let this = resolve_name(this, ns, parser)?;
+                match tree {
+                    Tree::Sub(pointee) => Tree::Ptmf(this, pointee),
+                    other => Tree::Ptmd(this, Box::new(other)),
+                }
+            }
+            OpSyntax::Func(params) => {
+                let mut types = Vec::with_capacity(params.len() + 1);
+                types.push(tree);
+                for param in params {
+                    types.push(resolve_type(param, ns, parser)?);
+                }
+                Tree::Sub(types)
+            }
+        };
+    }
+    Ok(tree)
+}
+
+fn resolve_name(
+    name: NameSyntax,
+    ns: &NamespaceMaps,
+    parser: &NameParser,
+) -> cu::Result<NamespacedTemplatedName> {
+    let base = cu::check!(
+        to_namespaced_name(ns, &name.qualified),
+        "failed to convert '{}' to namespaced name",
+        name.qualified
+    )?;
+    let templates = match name.templates {
+        Some(args) => resolve_args(args, ns, parser)?,
+        None => vec![],
+    };
+    Ok(NamespacedTemplatedName::with_templates(base, templates))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_type(source: &str) -> TypeSyntax {
+        let mut p = Parser {
+            source,
+            tokens: tokenize(source).unwrap(),
+            pos: 0,
+            allow_unknown: false,
+        };
+        let ty = p.type_id().unwrap();
+        assert_eq!(p.pos, p.tokens.len(), "not all tokens are parsed: {source}");
+        ty
+    }
+
+    fn base_name(ty: &TypeSyntax) -> &str {
+        match &ty.base {
+            BaseSyntax::Builtin(x) => x,
+            BaseSyntax::Name(name) => &name.qualified,
+        }
+    }
+
+    #[test]
+    fn test_builtin() {
+        assert_eq!(base_name(&parse_type("unsigned")), "unsigned int");
+        assert_eq!(base_name(&parse_type("long unsigned int")), "unsigned long");
+        assert_eq!(base_name(&parse_type("const signed char")), "signed char");
+        assert_eq!(base_name(&parse_type("long long")), "long long");
+    }
+
+    #[test]
+    fn test_pointers() {
+        let ty = parse_type("const char * const &");
+        assert_eq!(base_name(&ty), "char");
+        assert!(matches!(ty.ops.as_slice(), [OpSyntax::Ptr, OpSyntax::Ptr]));
+    }
+
+    #[test]
+    fn test_function_pointer() {
+        // pointer to function returning pointer
+        let ty = parse_type("int *(*)(float, void *)");
+        assert_eq!(base_name(&ty), "int");
+        let [OpSyntax::Ptr, OpSyntax::Func(params), OpSyntax::Ptr] = ty.ops.as_slice() else {
+            panic!("unexpected ops: {:?}", ty.ops);
+        };
+        assert_eq!(params.len(), 2);
+        assert!(parse_type("void (void)").ops.iter().all(|op| match op {
+            OpSyntax::Func(params) => params.is_empty(),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn test_member_pointers() {
+        let ty = parse_type("int ns::Foo<int>::*");
+        let [OpSyntax::MemberPtr(this)] = ty.ops.as_slice() else {
+            panic!("unexpected ops: {:?}", ty.ops);
+        };
+        assert_eq!(this.qualified, "ns::Foo");
+        let ty = parse_type("void (Foo::*)(int) const");
+        let [OpSyntax::Func(params), OpSyntax::MemberPtr(this)] = ty.ops.as_slice() else {
+            panic!("unexpected ops: {:?}", ty.ops);
+        };
+        assert_eq!(params.len(), 1);
+        assert_eq!(this.qualified, "Foo");
+    }
+
+    #[test]
+    fn test_qualified_templates() {
+        let ty = parse_type("std::__1::basic_string[abi:cxx11]<char, std::__1::allocator<char> >");
+        let BaseSyntax::Name(name) = &ty.base else {
+            panic!("expecting name");
+        };
+        assert_eq!(name.qualified, "std::__1::basic_string[abi:cxx11]");
+        assert_eq!(name.templates.as_ref().map(|x| x.len()), Some(2));
+        let ty = parse_type("Outer<int>::Inner");
+        assert_eq!(base_name(&ty), "Outer<int>::Inner");
+    }
+
+    #[test]
+    fn test_constants() {
+        let source = "Foo<1, -2, 0x10u, true, (Enum)3>";
+        let mut p = Parser {
+            source,
+            tokens: tokenize(source).unwrap(),
+            pos: 0,
+            allow_unknown: false,
+        };
+        let (name, args) = p.top_level().unwrap();
+        assert_eq!(name, "Foo");
+        let values = args
+            .iter()
+            .map(|arg| match arg {
+                ArgSyntax::Const(x) => *x,
+                _ => panic!("expecting constant: {arg:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![1, -2, 16, 1, 3]);
+    }
+
+    #[test]
+    fn test_needs_clang() {
+        for source in [
+            "Foo<decltype(x)>",
+            "Foo<1 + 2>",
+            "Foo<int[4]>",
+            "Foo<(lambda at foo.cpp:1:2)>",
+        ] {
+            let result = tokenize(source).and_then(|tokens| {
+                Parser {
+                    source,
+                    tokens,
+                    pos: 0,
+                    allow_unknown: false,
+                }
+                .top_level()
+            });
+            assert!(result.is_err(), "should fail: {source}");
+        }
+    }
+
+    #[test]
+    fn test_allow_unknown() {
+        let source = "Foo<int, decltype(x), char>";
+        let mut p = Parser {
+            source,
+            tokens: tokenize(source).unwrap(),
+            pos: 0,
+            allow_unknown: true,
+        };
+        let (_, args) = p.top_level().unwrap();
+        assert!(matches!(&args[1], ArgSyntax::Unknown(x) if x == "decltype(x)"));
+        assert!(matches!(&args[2], ArgSyntax::Type(..)));
+    }
+}
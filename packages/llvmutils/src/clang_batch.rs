@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use clang_ast::Node;
+
+use crate::name_parser::{Ast, ParseSource, TypeParseCommand};
+
+/// Id for naming the source files of the batches
+static BATCH_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Combines the clang invocations for parsing type names of compilation units
+/// with the same compile flags into one translation unit.
+///
+/// Units are processed concurrently, so a unit that needs to invoke clang waits
+/// for other units with the same flags. The batch is invoked when it's full, or when
+/// all running units are waiting, so the batches never wait for units that
+/// cannot start. Each unit in the batch has its own prefix for the tokens,
+/// and the cache files are saved for each unit like they are parsed separately
+pub struct ClangBatcher {
+    batch_size: usize,
+    state: Mutex<BatchState>,
+}
+
+#[derive(Default)]
+struct BatchState {
+    /// Number of units that are running
+    running: usize,
+    /// Number of units waiting in the groups
+    waiting: usize,
+    /// Requests waiting to be invoked, by the flags
+    groups: BTreeMap<Vec<String>, Vec<Pending>>,
+}
+
+struct Pending {
+    source: ParseSource,
+    tokens: BTreeSet<String>,
+    slot: Arc<Slot>,
+}
+
+/// What a waiting request should do when it's woken up
+enum Wake {
+    /// Invoke the batch, which is this request and the other requests
+    Lead(Vec<Pending>),
+    /// Invoke clang for the request separately
+    Solo,
+    /// The batch is invoked, with the AST nodes of the request and the dependencies
+    Done(BTreeMap<String, Node<Ast>>, Arc<Vec<String>>),
+}
+
+impl ClangBatcher {
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            state: Mutex::new(BatchState::default()),
+        }
+    }
+
+    /// Mark a unit as running until the guard is dropped. Batches are not invoked
+    /// until they are full, or all running units are waiting for a batch
+    pub fn enter(self: &Arc<Self>) -> BatchGuard {
+        if let Ok(mut state) = self.state.lock() {
+            state.running += 1;
+        }
+        BatchGuard(Arc::clone(self))
+    }
+
+    /// Parse the source in a batch with other units of the same flags
+    pub(crate) async fn invoke(
+        &self,
+        command: &TypeParseCommand,
+        source: &ParseSource,
+        tokens: BTreeSet<String>,
+    ) -> cu::Result<BTreeMap<String, Node<Ast>>> {
+        let slot = Arc::new(Slot::default());
+        let pending = Pending {
+            source: source.clone(),
+            tokens: tokens.clone(),
+            slot: Arc::clone(&slot),
+        };
+        {
+            let mut state = match self.state.lock() {
+                Ok(state) => state,
+                Err(_) => cu::bail!("clang batcher is poisoned"),
+            };
+            state.waiting += 1;
+            let group = state.groups.entry(command.flags.clone()).or_default();
+            group.push(pending);
+            if group.len() >= self.batch_size {
+                let batch = std::mem::take(group);
+                state.dispatch(batch);
+            }
+            state.dispatch_if_all_waiting();
+        }
+
+        match slot.wait().await {
+            Wake::Solo => command.invoke(&source.render(""), tokens).await,
+            Wake::Done(nodes, deps) => {
+                if let Err(e) = command.save_batched(&source.render(""), &nodes, &deps) {
+                    cu::error!("failed to save clang AST cache: {e:?}");
+                }
+                Ok(nodes)
+            }
+            Wake::Lead(others) => self.lead(command, source, tokens, others).await,
+        }
+    }
+
+    /// Invoke clang for the batch, and send the results to the other requests.
+    /// If the batch cannot be compiled, all requests are parsed separately
+    async fn lead(
+        &self,
+        command: &TypeParseCommand,
+        source: &ParseSource,
+        tokens: BTreeSet<String>,
+        others: Vec<Pending>,
+    ) -> cu::Result<BTreeMap<String, Node<Ast>>> {
+        let id = BATCH_ID.fetch_add(1, Ordering::Relaxed);
+        let batch = TypeParseCommand::with_flags(
+            &command.output_dir,
+            &format!("batch_{id}"),
+            command.flags.clone(),
+        )?;
+        let mut batch_source = String::new();
+        let mut batch_tokens = BTreeSet::new();
+        let members = std::iter::once((source, &tokens))
+            .chain(others.iter().map(|p| (&p.source, &p.tokens)))
+            .enumerate();
+        for (i, (source, tokens)) in members {
+            let prefix = format!("b{i}_");
+            batch_source.push_str(&source.render(&prefix));
+            batch_tokens.extend(tokens.iter().map(|t| format!("{prefix}{t}")));
+        }
+        cu::debug!("parsing {} units in {}", others.len() + 1, batch.cpp_file);
+
+        let result = batch.invoke_clang(&batch_source, batch_tokens, false).await;
+        let deps = result.and_then(|nodes| Ok((nodes, batch.read_deps()?)));
+        let (mut nodes, deps) = match deps {
+            Ok(x) => x,
+            Err(e) => {
+                cu::debug!("batch {} failed, parsing separately: {e:?}", batch.cpp_file);
+                for pending in others {
+                    pending.slot.set(Wake::Solo);
+                }
+                return command.invoke(&source.render(""), tokens).await;
+            }
+        };
+        if let Err(e) = batch.remove_files() {
+            cu::debug!("failed to remove batch files: {e:?}");
+        }
+        let deps = Arc::new(deps);
+        for (i, pending) in others.into_iter().enumerate() {
+            let prefixed = split_prefix(&mut nodes, &format!("b{}_", i + 1));
+            pending.slot.set(Wake::Done(prefixed, Arc::clone(&deps)));
+        }
+        let own_nodes = split_prefix(&mut nodes, "b0_");
+        if let Err(e) = command.save_batched(&source.render(""), &own_nodes, &deps) {
+            cu::error!("failed to save clang AST cache: {e:?}");
+        }
+        Ok(own_nodes)
+    }
+
+    fn exit(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.running = state.running.saturating_sub(1);
+            state.dispatch_if_all_waiting();
+        }
+    }
+}
+
+/// Marks a unit as running, see [`ClangBatcher::enter`]
+pub struct BatchGuard(Arc<ClangBatcher>);
+
+impl Drop for BatchGuard {
+    fn drop(&mut self) {
+        self.0.exit();
+    }
+}
+
+impl BatchState {
+    /// Wake up the requests in the batch. The first request invokes the batch
+    fn dispatch(&mut self, mut batch: Vec<Pending>) {
+        if batch.is_empty() {
+            return;
+        }
+        self.waiting = self.waiting.saturating_sub(batch.len());
+        let leader = batch.remove(0);
+        if batch.is_empty() {
+            leader.slot.set(Wake::Solo);
+        } else {
+            leader.slot.set(Wake::Lead(batch));
+        }
+    }
+
+    /// Invoke all the batches if every running unit is waiting,
+    /// since no more requests can come in
+    fn dispatch_if_all_waiting(&mut self) {
+        if self.waiting == 0 || self.waiting < self.running {
+            return;
+        }
+        for batch in std::mem::take(&mut self.groups).into_values() {
+            self.dispatch(batch);
+        }
+    }
+}
+
+/// Remove the nodes with the prefix, and strip the prefix from the tokens
+fn split_prefix(
+    nodes: &mut BTreeMap<String, Node<Ast>>,
+    prefix: &str,
+) -> BTreeMap<String, Node<Ast>> {
+    let tokens = nodes
+        .keys()
+        .filter(|token| token.starts_with(prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut output = BTreeMap::new();
+    for token in tokens {
+        if let Some(node) = nodes.remove(&token) {
+            output.insert(token[prefix.len()..].to_string(), node);
+        }
+    }
+    output
+}
+
+/// One-shot slot for waking up a waiting request
+#[derive(Default)]
+struct Slot(Mutex<(Option<Wake>, Option<Waker>)>);
+
+impl Slot {
+    fn set(&self, wake: Wake) {
+        let Ok(mut inner) = self.0.lock() else {
+            return;
+        };
+        inner.0 = Some(wake);
+        if let Some(waker) = inner.1.take() {
+            waker.wake();
+        }
+    }
+
+    fn wait(self: &Arc<Self>) -> SlotFuture {
+        SlotFuture(Arc::clone(self))
+    }
+}
+
+struct SlotFuture(Arc<Slot>);
+
+impl Future for SlotFuture {
+    type Output = Wake;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Wake> {
+        let mut inner = match self.0.0.lock() {
+            Ok(inner) => inner,
+            Err(e) => e.into_inner(),
+        };
+        match inner.0.take() {
+            Some(wake) => Poll::Ready(wake),
+            None => {
+                inner.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -23,6 +23,23 @@ pub struct CompileCommand {
     #[serde(deserialize_with = "deserialize_compile_command_args")]
     pub command: Vec<String>,
 }
+impl CompileCommand {
+    /// Create a compile command from a shell command, which includes the compiler
+    pub fn from_shell(file: String, command: &str) -> cu::Result<Self> {
+        let mut args = cu::check!(
+            shell_words::split(command),
+            "invalid shell command: {command}"
+        )?;
+        cu::ensure!(!args.is_empty(), "command must be non-empty")?;
+        // remove the compiler
+        args.remove(0);
+        Ok(Self {
+            file,
+            command: args,
+        })
+    }
+}
+
 fn deserialize_compile_command_args<'de, D: serde::Deserializer<'de>>(
     d: D,
 ) -> Result<Vec<String>, D::Error> {
@@ -53,3 +70,90 @@ fn deserialize_compile_command_args<'de, D: serde::Deserializer<'de>>(
         }
     }
 }
+
+/// Flags for clangd in `compile_flags.txt`, which clangd uses for files that are
+/// not in compile_commands.json (like headers).
+///
+/// These are the flags in every command, without the input and output files
+pub fn clangd_flags<'a>(commands: impl IntoIterator<Item = &'a CompileCommand>) -> Vec<String> {
+    let mut commands = commands.into_iter().map(clangd_flag_groups);
+    let Some(mut flags) = commands.next() else {
+        return vec![];
+    };
+    for groups in commands {
+        flags.retain(|x| groups.contains(x));
+    }
+    flags.into_iter().flatten().collect()
+}
+
+/// Flags that take the next arg as the value
+const FLAGS_WITH_VALUE: &[&str] = &[
+    "-o",
+    "-MF",
+    "-MT",
+    "-MQ",
+    "-I",
+    "-D",
+    "-U",
+    "-include",
+    "-imacros",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-isysroot",
+    "-target",
+    "-x",
+    "-Xclang",
+];
+
+/// Flags (and their values) that are specific to compiling one file
+const FILE_FLAGS: &[&str] = &["-o", "-MF", "-MT", "-MQ", "-c", "-MD", "-MMD"];
+
+/// Split the args of the command into groups of a flag and its value,
+/// without the flags for the input and output files
+fn clangd_flag_groups(command: &CompileCommand) -> Vec<Vec<String>> {
+    let mut groups = vec![];
+    let mut args = command.command.iter();
+    while let Some(arg) = args.next() {
+        let mut group = vec![arg.clone()];
+        if FLAGS_WITH_VALUE.contains(&arg.as_str()) {
+            group.extend(args.next().cloned());
+        }
+        if FILE_FLAGS.contains(&arg.as_str()) || *arg == command.file {
+            continue;
+        }
+        // the input file could be relative to the build directory
+        if !arg.starts_with('-') && command.file.ends_with(arg.as_str()) {
+            continue;
+        }
+        groups.push(group);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clangd_flags() {
+        let command = |file: &str, args: &str| CompileCommand {
+            file: file.to_string(),
+            command: shell_words::split(args).unwrap(),
+        };
+        let commands = [
+            command(
+                "/src/a.cpp",
+                "-std=c++17 -I /src/include -DA -c /src/a.cpp -o a.o",
+            ),
+            command(
+                "/src/b.cpp",
+                "-std=c++17 -I /src/include -I /src/b -c b.cpp -o b.o -MD -MF b.d",
+            ),
+        ];
+        assert_eq!(
+            clangd_flags(&commands),
+            ["-std=c++17", "-I", "/src/include"]
+        );
+    }
+}
@@ -0,0 +1,301 @@
+/// Prefixes of demangled special names for data, which are not split
+static SPECIAL_DATA_PREFIXES: &[&str] = &[
+    "vtable for ",
+    "construction vtable for ",
+    "VTT for ",
+    "typeinfo for ",
+    "typeinfo name for ",
+    "guard variable for ",
+    "reference temporary ",
+];
+
+/// Prefixes of demangled thunks, which are split as the function they call
+static THUNK_PREFIXES: &[&str] = &[
+    "non-virtual thunk to ",
+    "virtual thunk to ",
+    "covariant return thunk to ",
+];
+
+/// A demangled name split into its parts, for example
+/// `uking::ui::PauseMenuDataMgr::init(sead::Heap*)` is split into the scope
+/// `uking::ui::PauseMenuDataMgr`, the name `init`, and the parameter `sead::Heap*`.
+///
+/// The parts are spelled as in the demangled name, and are not parsed further
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DemangledName {
+    /// Namespace or class that the symbol is in, empty for global symbols
+    pub scope: String,
+    /// Unqualified name of the symbol, including the template arguments
+    pub name: String,
+    /// Types of the parameters, None if the symbol is not a function
+    pub params: Option<Vec<String>>,
+}
+
+impl DemangledName {
+    /// Split the output of the demangler. Names that are not mangled are
+    /// kept as the name
+    pub fn parse(demangled: &str) -> Self {
+        let s = demangled.trim();
+        if SPECIAL_DATA_PREFIXES.iter().any(|x| s.starts_with(x)) {
+            return Self {
+                scope: String::new(),
+                name: s.to_string(),
+                params: None,
+            };
+        }
+        let s = THUNK_PREFIXES
+            .iter()
+            .find_map(|x| s.strip_prefix(x))
+            .unwrap_or(s);
+        let (head, params) = match split_params(s) {
+            Some((head, params)) => (head, Some(params)),
+            None => (s, None),
+        };
+        let (scope, name) = split_scope(strip_return_type(head));
+        Self {
+            scope: scope.to_string(),
+            name: name.to_string(),
+            params,
+        }
+    }
+
+    /// The qualified name without the parameters
+    pub fn qualified_name(&self) -> String {
+        if self.scope.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}::{}", self.scope, self.name)
+        }
+    }
+}
+
+/// Split the parameter list at the end of a function, for example
+/// `foo(int, char) const` into `foo` and `[int, char]`
+fn split_params(s: &str) -> Option<(&str, Vec<String>)> {
+    let close = s.rfind(')')?;
+    // only cv and ref qualifiers can be after the parameters,
+    // otherwise the parentheses are part of the name, like `foo()::sInstance`
+    let is_qualifiers = s[close + 1..]
+        .split_whitespace()
+        .all(|x| matches!(x, "const" | "volatile" | "&" | "&&"));
+    if !is_qualifiers {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut open = None;
+    for (i, c) in s[..close].char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth == 0 => {
+                open = Some(i);
+                break;
+            }
+            '(' => depth -= 1,
+            _ => {}
+        }
+    }
+    let open = open?;
+    if open == 0 {
+        return None;
+    }
+    let params = split_top_level(&s[open + 1..close], ',')
+        .into_iter()
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_string())
+        .collect();
+    Some((&s[..open], params))
+}
+
+/// Remove the return type, which is only in the demangled names of
+/// template functions, like `void foo<int>`
+fn strip_return_type(s: &str) -> &str {
+    let end = find_operator(s).unwrap_or(s.len());
+    match rfind_top_level(&s[..end], " ") {
+        Some(i) => &s[i + 1..],
+        None => s,
+    }
+}
+
+/// Split the qualified name into the scope and the unqualified name
+fn split_scope(s: &str) -> (&str, &str) {
+    let end = find_operator(s).unwrap_or(s.len());
+    match rfind_top_level(&s[..end], "::") {
+        Some(i) => (&s[..i], &s[i + 2..]),
+        None => ("", s),
+    }
+}
+
+/// Find the `operator` keyword, since the operator names
+/// can have spaces and unbalanced brackets, like `operator<` or `operator new[]`
+fn find_operator(s: &str) -> Option<usize> {
+    s.match_indices("operator").map(|(i, _)| i).find(|i| {
+        let before = s[..*i].chars().next_back();
+        let after = s[i + "operator".len()..].chars().next();
+        let is_start = matches!(before, None | Some(':' | ' '));
+        let is_end = !matches!(after, Some(c) if c == '_' || c.is_ascii_alphanumeric());
+        is_start && is_end
+    })
+}
+
+/// Find the last occurrence of the pattern that is not inside brackets
+fn rfind_top_level(s: &str, pattern: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut found = None;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '<' | '[' | '{' => depth += 1,
+            ')' | '>' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && s[i..].starts_with(pattern) => found = Some(i),
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Split the string at the separator that is not inside brackets
+fn split_top_level(s: &str, separator: char) -> Vec<&str> {
+    let mut depth = 0usize;
+    let mut parts = vec![];
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '<' | '[' | '{' => depth += 1,
+            ')' | '>' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && c == separator => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(demangled: &str) -> (String, String, Option<Vec<String>>) {
+        let x = DemangledName::parse(demangled);
+        (x.scope, x.name, x.params)
+    }
+
+    fn params(params: &[&str]) -> Option<Vec<String>> {
+        Some(params.iter().map(|x| x.to_string()).collect())
+    }
+
+    #[test]
+    fn test_member_function() {
+        assert_eq!(
+            parse("uking::ui::PauseMenuDataMgr::init(sead::Heap*)"),
+            (
+                "uking::ui::PauseMenuDataMgr".to_string(),
+                "init".to_string(),
+                params(&["sead::Heap*"])
+            )
+        );
+        assert_eq!(
+            parse("Foo::get(int, char const*) const"),
+            (
+                "Foo".to_string(),
+                "get".to_string(),
+                params(&["int", "char const*"])
+            )
+        );
+        assert_eq!(
+            parse("Foo::Foo()"),
+            ("Foo".to_string(), "Foo".to_string(), params(&[]))
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        assert_eq!(
+            parse("void sead::Buffer<int>::foo<std::pair<int, int>>(std::pair<int, int>, int)"),
+            (
+                "sead::Buffer<int>".to_string(),
+                "foo<std::pair<int, int>>".to_string(),
+                params(&["std::pair<int, int>", "int"])
+            )
+        );
+    }
+
+    #[test]
+    fn test_function_pointer_param() {
+        assert_eq!(
+            parse("foo(void (*)(int, int), int)"),
+            (
+                String::new(),
+                "foo".to_string(),
+                params(&["void (*)(int, int)", "int"])
+            )
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            parse("Foo::operator<(Foo const&) const"),
+            (
+                "Foo".to_string(),
+                "operator<".to_string(),
+                params(&["Foo const&"])
+            )
+        );
+        assert_eq!(
+            parse("Foo::operator()(int)"),
+            (
+                "Foo".to_string(),
+                "operator()".to_string(),
+                params(&["int"])
+            )
+        );
+        assert_eq!(
+            parse("operator new[](unsigned long)"),
+            (
+                String::new(),
+                "operator new[]".to_string(),
+                params(&["unsigned long"])
+            )
+        );
+        assert_eq!(
+            parse("Foo::operator unsigned int() const"),
+            (
+                "Foo".to_string(),
+                "operator unsigned int".to_string(),
+                params(&[])
+            )
+        );
+    }
+
+    #[test]
+    fn test_data() {
+        assert_eq!(
+            parse("(anonymous namespace)::sInstance"),
+            (
+                "(anonymous namespace)".to_string(),
+                "sInstance".to_string(),
+                None
+            )
+        );
+        assert_eq!(
+            parse("Foo::get()::sInstance"),
+            ("Foo::get()".to_string(), "sInstance".to_string(), None)
+        );
+        assert_eq!(
+            parse("vtable for Foo"),
+            (String::new(), "vtable for Foo".to_string(), None)
+        );
+        assert_eq!(parse("main"), (String::new(), "main".to_string(), None));
+    }
+
+    #[test]
+    fn test_thunk() {
+        assert_eq!(
+            parse("non-virtual thunk to Foo::bar(int)"),
+            ("Foo".to_string(), "bar".to_string(), params(&["int"]))
+        );
+    }
+}
@@ -1,32 +1,74 @@
-use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
 use cu::pre::*;
 use dashmap::DashMap;
 
+/// Demangler with an on-disk cache, since invoking llvm-cxxfilt is slow.
+///
+/// The cache is an append-only JSON-lines file, where each line is `[mangled, demangled]`.
+/// New entries are appended as soon as they are demangled, so entries are not lost if
+/// the process crashes, and multiple processes can append to the same cache.
+///
+/// The file is rewritten when loaded if it has an incomplete or invalid line,
+/// or if too many lines are duplicates (appended by processes racing on the same symbol)
 pub struct Demangler {
     cache: DashMap<String, String>,
     cache_path: PathBuf,
+    /// None if the cache file cannot be opened, in which case
+    /// the cache is only in memory
+    cache_file: Option<File>,
     modification_count: AtomicUsize,
 }
 
 impl Demangler {
     pub fn try_new(cache_path: PathBuf) -> cu::Result<Self> {
-        let cache = match cu::fs::reader(&cache_path) {
-            Ok(x) => match json::read::<DashMap<String, String>>(x) {
-                Ok(x) => x,
-                Err(e) => {
-                    cu::warn!("failed to load demangler cache: {e}");
-                    Default::default()
+        let cache = DashMap::new();
+        if let Ok(content) = cu::fs::read_string(&cache_path) {
+            let mut line_count = 0;
+            let mut invalid_count = 0;
+            for line in content.lines() {
+                line_count += 1;
+                // the last line could be incomplete if the process crashed while appending
+                match json::parse::<(String, String)>(line) {
+                    Ok((mangled, demangled)) => {
+                        cache.insert(mangled, demangled);
+                    }
+                    Err(_) => invalid_count += 1,
                 }
-            },
-            Err(_) => Default::default(),
+            }
+            if invalid_count > 0 {
+                cu::warn!("skipped {invalid_count} invalid entries in demangler cache");
+            }
+            cu::debug!("loaded {} entries from demangler cache", cache.len());
+            // without the trailing newline, the next appended entry would be
+            // joined with the incomplete line and lost as well
+            let incomplete = !content.is_empty() && !content.ends_with('\n');
+            let duplicate_count = line_count - invalid_count - cache.len();
+            if incomplete || invalid_count > 0 || duplicate_count > cache.len() / 4 {
+                if let Err(e) = Self::rewrite_cache(&cache_path, &cache) {
+                    cu::warn!("failed to rewrite demangler cache: {e:?}");
+                }
+            }
+        }
+        let cache_file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&cache_path);
+        let cache_file = match cache_file {
+            Ok(x) => Some(x),
+            Err(e) => {
+                cu::warn!("failed to open demangler cache, new entries will not be saved: {e}");
+                None
+            }
         };
         Ok(Self {
             cache,
             cache_path,
+            cache_file,
             modification_count: AtomicUsize::new(0),
         })
     }
@@ -42,25 +84,73 @@ impl Demangler {
             self.demangle_with_cxxfilt(symbol),
             "failed to demangle '{symbol}'"
         )?;
-        self.cache.insert(symbol.to_string(), output.clone());
+        if self
+            .cache
+            .insert(symbol.to_string(), output.clone())
+            .is_none()
+        {
+            cu::check!(
+                self.append_entry(symbol, &output),
+                "failed to append to demangler cache"
+            )?;
+        }
 
-        // note this will lose a few entries in the end, which is fine
-        let c = self.modification_count.fetch_add(1, Ordering::SeqCst);
+        // sync to disk periodically, the count doesn't need to be exact
+        let c = self.modification_count.fetch_add(1, Ordering::Relaxed);
         if c >= 5000 {
             self.flush_cache()?;
-            self.modification_count.store(0, Ordering::Release);
+            self.modification_count.store(0, Ordering::Relaxed);
         }
         Ok(output)
     }
 
+    /// Make sure the appended entries are written to disk
     pub fn flush_cache(&self) -> cu::Result<()> {
-        let mut ordered = BTreeMap::new();
-        ordered.extend(self.cache.clone());
-        let cache_string = json::stringify_pretty(&ordered)?;
-        cu::fs::write(&self.cache_path, cache_string)?;
+        let Some(file) = &self.cache_file else {
+            return Ok(());
+        };
+        cu::check!(
+            file.sync_data(),
+            "failed to sync demangler cache {}",
+            self.cache_path.display()
+        )
+    }
+
+    /// Replace the cache file with one line per entry
+    fn rewrite_cache(cache_path: &Path, cache: &DashMap<String, String>) -> cu::Result<()> {
+        let mut content = String::new();
+        for entry in cache {
+            content.push_str(&json::stringify(&(entry.key(), entry.value()))?);
+            content.push('\n');
+        }
+        // write to a temporary file first, so the cache is not lost
+        // if the process crashes while writing
+        let temp_path = cache_path.with_extension("tmp");
+        cu::fs::write(&temp_path, content)?;
+        cu::check!(
+            std::fs::rename(&temp_path, cache_path),
+            "failed to replace {}",
+            cache_path.display()
+        )?;
+        cu::debug!("rewrote demangler cache with {} entries", cache.len());
         Ok(())
     }
 
+    fn append_entry(&self, mangled: &str, demangled: &str) -> cu::Result<()> {
+        let Some(mut file) = self.cache_file.as_ref() else {
+            return Ok(());
+        };
+        let mut line = json::stringify(&(mangled, demangled))?;
+        line.push('\n');
+        // one write per line, so lines appended by different threads
+        // or processes are not interleaved
+        cu::check!(
+            file.write_all(line.as_bytes()),
+            "failed to write to {}",
+            self.cache_path.display()
+        )
+    }
+
     fn demangle_with_cxxfilt(&self, symbol: &str) -> cu::Result<String> {
         let cxxfilt = cu::bin::find(
             "llvm-cxxfilt",
@@ -1,6 +1,13 @@
 mod demangler;
 pub use demangler::*;
+mod demangled_name;
+pub use demangled_name::*;
 mod compdb;
 pub use compdb::*;
 mod name_parser;
 pub use name_parser::*;
+mod builtin_parser;
+mod clang_batch;
+pub use clang_batch::*;
+mod name_cache;
+pub use name_cache::*;
@@ -0,0 +1,137 @@
+use std::fs::File;
+use std::io::Write as _;
+use std::path::PathBuf;
+
+use cu::pre::*;
+use dashmap::DashMap;
+use exstructs::{NamespacedTemplatedName, TemplateArg};
+
+/// Cache of parsed type names shared by all compilation units, since many units
+/// ask clang to parse the same templated names.
+///
+/// Like the demangler cache, this is an append-only JSON-lines file, where each line
+/// is `[key, name]`. The first line is the stamp the entries are valid for. If the stamp
+/// changed (for example, the program is rebuilt), the cache is cleared
+pub struct TypeNameCache {
+    cache: DashMap<String, NamespacedTemplatedName>,
+    cache_path: PathBuf,
+    /// None if the cache file cannot be opened, in which case
+    /// the cache is only in memory
+    cache_file: Option<File>,
+}
+
+impl TypeNameCache {
+    pub fn try_new(cache_path: PathBuf, stamp: &str) -> cu::Result<Self> {
+        let cache = DashMap::new();
+        let mut is_valid = false;
+        if let Ok(content) = cu::fs::read_string(&cache_path) {
+            let mut lines = content.lines();
+            is_valid = lines
+                .next()
+                .is_some_and(|line| json::parse::<String>(line).is_ok_and(|x| x == stamp));
+            if is_valid {
+                for line in lines {
+                    // the last line could be incomplete if the process crashed while appending
+                    if let Ok((key, name)) = json::parse::<(String, NamespacedTemplatedName)>(line)
+                    {
+                        cache.insert(key, name);
+                    }
+                }
+                cu::debug!("loaded {} entries from type name cache", cache.len());
+            } else {
+                cu::debug!("type name cache is outdated, clearing");
+            }
+        }
+        let cache_file = if is_valid {
+            std::fs::OpenOptions::new().append(true).open(&cache_path)
+        } else {
+            Self::create_file(&cache_path, stamp)
+        };
+        let cache_file = match cache_file {
+            Ok(x) => Some(x),
+            Err(e) => {
+                cu::warn!("failed to open type name cache, new entries will not be saved: {e}");
+                None
+            }
+        };
+        Ok(Self {
+            cache,
+            cache_path,
+            cache_file,
+        })
+    }
+
+    fn create_file(path: &PathBuf, stamp: &str) -> std::io::Result<File> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        let mut line = json::stringify(stamp).map_err(std::io::Error::other)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        Ok(file)
+    }
+
+    /// Get the parsed name for the key
+    pub fn get(&self, key: &str) -> Option<NamespacedTemplatedName> {
+        self.cache.get(key).map(|x| x.clone())
+    }
+
+    /// Add the parsed name to the cache, if it does not depend on the compilation unit
+    pub fn set(&self, key: String, name: &NamespacedTemplatedName) -> cu::Result<()> {
+        if !is_cacheable(name) {
+            return Ok(());
+        }
+        if self.cache.insert(key.clone(), name.clone()).is_some() {
+            return Ok(());
+        }
+        let Some(mut file) = self.cache_file.as_ref() else {
+            return Ok(());
+        };
+        let mut line = json::stringify(&(key, name))?;
+        line.push('\n');
+        // one write per line, so lines appended by different threads are not interleaved
+        cu::check!(
+            file.write_all(line.as_bytes()),
+            "failed to write to {}",
+            self.cache_path.display()
+        )
+    }
+
+    /// Make sure the appended entries are written to disk
+    pub fn flush_cache(&self) -> cu::Result<()> {
+        let Some(file) = &self.cache_file else {
+            return Ok(());
+        };
+        cu::check!(
+            file.sync_data(),
+            "failed to sync type name cache {}",
+            self.cache_path.display()
+        )
+    }
+}
+
+/// Names that refer to offsets or anonymous namespaces are only valid in the
+/// compilation unit, and names with unknown template args depend on the
+/// degradation level, so they are not cached
+fn is_cacheable(name: &NamespacedTemplatedName) -> bool {
+    let namespace = name.base.namespace();
+    if namespace.contains_offsets() || namespace.contains_anonymous() {
+        return false;
+    }
+    name.templates.iter().all(|arg| match arg {
+        TemplateArg::Const(_) | TemplateArg::StaticConst => true,
+        TemplateArg::Unknown(_) => false,
+        TemplateArg::Type(tree) => {
+            let mut cacheable = tree
+                .for_each(|name| {
+                    cu::ensure!(is_cacheable(name))?;
+                    Ok(())
+                })
+                .is_ok();
+            tree.for_each_ptm_base(|name| cacheable &= is_cacheable(name));
+            cacheable
+        }
+    })
+}
@@ -1,6 +1,10 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::{Path, PathBuf},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use clang_ast::Node;
@@ -11,13 +15,43 @@ use exstructs::{
 };
 use tyyaml::{Prim, Tree};
 
-use crate::CompileCommand;
+use crate::builtin_parser;
+use crate::{ClangBatcher, CompileCommand, TypeNameCache};
+
+/// Number of times clang is invoked for parsing names in this process
+static CLANG_INVOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Get the number of times clang was invoked for parsing names
+/// (i.e. AST cache misses) in this process
+pub fn clang_invocation_count() -> usize {
+    CLANG_INVOCATIONS.load(Ordering::Relaxed)
+}
+
+/// How the templated type names are parsed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NameParserBackend {
+    /// Invoke clang, and parse the AST
+    #[default]
+    Clang,
+    /// Use the built-in parser, which only supports the common type syntax
+    Builtin,
+    /// Use clang if it can be found, otherwise the built-in parser
+    Auto,
+}
 
 pub struct NameParser {
+    pub backend: NameParserBackend,
     pub output_dir: PathBuf,
     pub system_header_paths: Vec<PathBuf>,
     pub char_repr: Prim,
     pub wchar_repr: Prim,
+    /// Degrade unparsable template args to `TemplateArg::Unknown`
+    /// instead of failing
+    pub allow_unknown_template_args: bool,
+    /// Combine the clang invocations with other units, if batching is enabled
+    pub batcher: Option<Arc<ClangBatcher>>,
+    /// Names parsed by any compilation unit, which are not parsed again
+    pub name_cache: Option<Arc<TypeNameCache>>,
 }
 
 impl NameParser {
@@ -27,6 +61,14 @@ impl NameParser {
         namespaces: &NamespaceMaps,
         types: &GoffMap<LType>,
     ) -> cu::Result<GoffMap<NamespacedTemplatedName>> {
+        let use_builtin = match self.backend {
+            NameParserBackend::Clang => false,
+            NameParserBackend::Builtin => true,
+            NameParserBackend::Auto => !clang_available(),
+        };
+        if use_builtin {
+            return self.parse_builtin(namespaces, types);
+        }
         let file = &compile_command.file;
         let command = cu::check!(
             TypeParseCommand::try_new(&self, &compile_command),
@@ -47,8 +89,9 @@ impl NameParser {
         let mut tokens = BTreeSet::default();
         // put the command into the output cpp file for debugging
         let args = shell_words::join(&command.args);
-        let mut source = format!(
-            r##"
+        let mut source = ParseSource {
+            header: format!(
+                r##"
 // clang {args}
 
 // this is a hack for private declarations
@@ -56,11 +99,12 @@ impl NameParser {
 #define protected public
 #include "{file}"
 "##,
-        );
+            ),
+            typedefs: vec![],
+        };
 
         // load up the source
         for (k, t) in types {
-            use std::fmt::Write;
             let (name, namespace) = match t {
                 LType::Typedef { name, .. } => (name, None),
                 LType::EnumDecl(decl) | LType::UnionDecl(decl) | LType::StructDecl(decl) => {
@@ -76,38 +120,171 @@ impl NameParser {
             let mut name_source = name.to_cpp_typedef_source()?;
             clean_up_name_cpp_source(&mut name_source);
             let token = make_parse_ident(&name_source);
-            if let Some(ns) = namespace {
-                let ns_source = ns.to_cpp_typedef_source()?;
+            let namespace = match namespace {
                 // no need to clean ns_source, since we already processed anonymous as part of the DWARF tree
-                let _ = write!(source, "\nnamespace {ns_source}{{");
-                let _ = write!(source, "\ntypedef\n{name_source}\n{token};");
-                let _ = write!(source, "\n}}");
-            } else {
-                let _ = write!(source, "\ntypedef\n{name_source}\n{token};");
+                Some(ns) => Some(ns.to_cpp_typedef_source()?),
+                None => None,
+            };
+            // names qualified by types are only valid in this unit
+            let cache_key = match &self.name_cache {
+                Some(_) if name.namespace().contains_offsets() => None,
+                Some(_) => Some(format!(
+                    "{}|{}|{}",
+                    self.repr_key(),
+                    namespace.as_deref().unwrap_or_default(),
+                    name_source
+                )),
+                None => None,
+            };
+            if let (Some(cache), Some(key)) = (&self.name_cache, &cache_key) {
+                if let Some(parsed) = cache.get(key) {
+                    final_names.insert(*k, parsed);
+                    continue;
+                }
             }
+            source.typedefs.push(ParseTypedef {
+                namespace,
+                name: name_source,
+                token: token.clone(),
+            });
             let request = ParseRequest {
                 goff: *k,
                 token: token.clone(),
                 // source: name_source,
                 namespace: name.namespace(),
+                cache_key,
             };
             requests.push(request);
             tokens.insert(token);
         }
 
         if !requests.is_empty() {
-            let ast_nodes = match command.try_read_cached_ast(&source, &tokens) {
-                Some(x) => x,
-                None => cu::check!(
-                    command.invoke(&source, tokens).await,
+            let cached = command.try_read_cached_ast(&source.render(""), &tokens);
+            let ast_nodes = match (cached, &self.batcher) {
+                (Some(x), _) => x,
+                (None, Some(batcher)) => cu::check!(
+                    batcher.invoke(&command, &source, tokens).await,
+                    "failed to invoke batched AST parse command for: {file}",
+                )?,
+                (None, None) => cu::check!(
+                    command.invoke(&source.render(""), tokens).await,
                     "failed to invoke AST parse command for: {file}",
                 )?,
             };
             command.parse_ast_nodes(&requests, &ast_nodes, &namespaces, &self, &mut final_names)?;
+            if let Some(cache) = &self.name_cache {
+                for req in requests {
+                    let (Some(key), Some(parsed)) = (req.cache_key, final_names.get(&req.goff))
+                    else {
+                        continue;
+                    };
+                    if let Err(e) = cache.set(key, parsed) {
+                        cu::warn!("failed to save type name cache: {e:?}");
+                    }
+                }
+            }
         }
 
         Ok(final_names)
     }
+
+    /// Parse the names with the built-in parser. The source file is not needed,
+    /// and the names are not cached since parsing them is cheap
+    fn parse_builtin(
+        &self,
+        namespaces: &NamespaceMaps,
+        types: &GoffMap<LType>,
+    ) -> cu::Result<GoffMap<NamespacedTemplatedName>> {
+        let mut final_names = GoffMap::default();
+        for (k, t) in types {
+            let name = match t {
+                LType::Typedef { name, .. } => name,
+                LType::EnumDecl(decl) | LType::UnionDecl(decl) | LType::StructDecl(decl) => {
+                    &decl.name_with_tpl
+                }
+                _ => continue,
+            };
+            if !name.basename().contains('<') {
+                final_names.insert(*k, NamespacedTemplatedName::new(name.clone()));
+                continue;
+            }
+            let parsed =
+                builtin_parser::parse_name(name.basename(), name.namespace(), namespaces, self);
+            match parsed {
+                Ok(parsed) => {
+                    final_names.insert(*k, parsed);
+                }
+                Err(e) => {
+                    cu::hint!(
+                        "the built-in type parser only supports the common type syntax - parse this name with clang (see extract.type-parser.backend), or exclude it with extract.type-parser.abandon-typedefs"
+                    );
+                    cu::rethrow!(
+                        e,
+                        "failed to parse type name without clang: {}",
+                        name.basename()
+                    );
+                }
+            }
+        }
+        Ok(final_names)
+    }
+
+    /// The representations of builtin types in the parsed names
+    fn repr_key(&self) -> String {
+        format!("{}/{}", self.char_repr, self.wchar_repr)
+    }
+}
+
+/// Check if clang can be found for parsing names. Only checked once in the process
+fn clang_available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        let found = cu::bin::find("clang", [cu::bin::from_env("CLANG"), cu::bin::in_PATH()]);
+        if found.is_err() {
+            cu::warn!("clang is not found, using the built-in type name parser");
+        }
+        found.is_ok()
+    })
+}
+
+/// Source file generated for parsing the type names of a compilation unit
+#[derive(Debug, Clone)]
+pub(crate) struct ParseSource {
+    /// Includes the source file of the unit
+    header: String,
+    typedefs: Vec<ParseTypedef>,
+}
+
+#[derive(Debug, Clone)]
+struct ParseTypedef {
+    namespace: Option<String>,
+    /// The type name to parse
+    name: String,
+    /// Name of the typedef to find in the AST
+    token: String,
+}
+
+impl ParseSource {
+    /// Render the source, with the prefix added to the tokens
+    pub fn render(&self, prefix: &str) -> String {
+        use std::fmt::Write;
+        let mut source = self.header.clone();
+        for typedef in &self.typedefs {
+            let ParseTypedef {
+                namespace,
+                name,
+                token,
+            } = typedef;
+            if let Some(ns_source) = namespace {
+                let _ = write!(source, "\nnamespace {ns_source}{{");
+                let _ = write!(source, "\ntypedef\n{name}\n{prefix}{token};");
+                let _ = write!(source, "\n}}");
+            } else {
+                let _ = write!(source, "\ntypedef\n{name}\n{prefix}{token};");
+            }
+        }
+        source
+    }
 }
 
 fn clean_up_name_cpp_source(name: &mut String) {
@@ -163,40 +340,31 @@ fn clean_segment(seg: &str, out: &mut String) {
     out.push_str(&format!("$${len}_"));
 }
 
-struct TypeParseCommand {
+/// Get the name (without extension) of the type parse cache files for a source file.
+/// The name is based on the file path, for example `foo.cpp_0123456789abcdef`
+pub fn type_parse_cache_stem(file: &str) -> cu::Result<String> {
+    let hash = fxhash::hash64(file);
+    let base_name = Path::new(file).file_name_str()?;
+    Ok(format!("{base_name}_{hash:016x}"))
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TypeParseCommand {
+    pub output_dir: PathBuf,
     pub cpp_file: String,
     pub d_file: String,
     pub out_file: String,
     pub args: Vec<String>,
+    /// The args that don't depend on the file names. Units with the same flags
+    /// can be parsed in one batch
+    pub flags: Vec<String>,
 }
 impl TypeParseCommand {
     pub fn try_new(parser: &NameParser, command: &CompileCommand) -> cu::Result<Self> {
-        // create a temporary file name based on the file path
-        let hash = fxhash::hash64(&command.file);
-
-        let base_name = Path::new(&command.file).file_name_str()?;
-        let cpp_file = parser
-            .output_dir
-            .join(format!("{base_name}_{hash:016x}.cpp"))
-            .into_utf8()?;
-        let d_file = parser
-            .output_dir
-            .join(format!("{base_name}_{hash:016x}.d"))
-            .into_utf8()?;
-
-        let mut args = vec![
-            "-MD".to_string(),
-            "-MT".to_string(),
-            cpp_file.clone(),
-            "-MF".to_string(),
-            d_file.clone(),
-            "-Xclang".to_string(),
-            "-ast-dump=json".to_string(),
-            "-fsyntax-only".to_string(),
-            cpp_file.clone(),
-        ];
+        let stem = type_parse_cache_stem(&command.file)?;
+        let mut flags = vec![];
         for include in &parser.system_header_paths {
-            args.push(format!("-I{}", include.as_utf8()?))
+            flags.push(format!("-I{}", include.as_utf8()?))
         }
         let mut last_is_minus_o = false;
         for arg in &command.command {
@@ -213,15 +381,37 @@ impl TypeParseCommand {
             if arg == "-c" {
                 continue;
             }
-            args.push(arg.to_string());
+            flags.push(arg.to_string());
         }
+        Self::with_flags(&parser.output_dir, &stem, flags)
+    }
+
+    /// Create the command with the name of the cache files and the flags
+    pub fn with_flags(output_dir: &Path, stem: &str, flags: Vec<String>) -> cu::Result<Self> {
+        let cpp_file = output_dir.join(format!("{stem}.cpp")).into_utf8()?;
+        let d_file = output_dir.join(format!("{stem}.d")).into_utf8()?;
+
+        let mut args = vec![
+            "-MD".to_string(),
+            "-MT".to_string(),
+            cpp_file.clone(),
+            "-MF".to_string(),
+            d_file.clone(),
+            "-Xclang".to_string(),
+            "-ast-dump=json".to_string(),
+            "-fsyntax-only".to_string(),
+            cpp_file.clone(),
+        ];
+        args.extend(flags.iter().cloned());
         let out_file = format!("{cpp_file}.json");
 
         Ok(Self {
+            output_dir: output_dir.to_path_buf(),
             cpp_file,
             d_file,
             out_file,
             args,
+            flags,
         })
     }
 
@@ -292,16 +482,28 @@ impl TypeParseCommand {
         Some(old_output)
     }
 
-    async fn invoke(
+    pub async fn invoke(
+        &self,
+        source: &str,
+        tokens: BTreeSet<String>,
+    ) -> cu::Result<BTreeMap<String, Node<Ast>>> {
+        self.invoke_clang(source, tokens, true).await
+    }
+
+    /// Invoke clang on the source, and get the AST nodes of the typedefs named by the tokens.
+    /// The clang errors are printed if `report_errors` is true
+    pub async fn invoke_clang(
         &self,
         source: &str,
         mut tokens: BTreeSet<String>,
+        report_errors: bool,
     ) -> cu::Result<BTreeMap<String, Node<Ast>>> {
         cu::fs::write(&self.cpp_file, source)?;
         cu::fs::remove(&self.out_file)?;
         // call clang and get the AST output
         let tu_node = {
             let clang = cu::bin::find("clang", [cu::bin::from_env("CLANG"), cu::bin::in_PATH()])?;
+            CLANG_INVOCATIONS.fetch_add(1, Ordering::Relaxed);
             let (child, out, err) = clang
                 .command()
                 .args(&self.args)
@@ -312,6 +514,10 @@ impl TypeParseCommand {
                 .await?;
             if let Err(e) = child.co_wait_nz().await {
                 let err = err.co_join().await??;
+                if !report_errors {
+                    cu::debug!("stderr from clang:\n{err}");
+                    return Err(e);
+                }
                 cu::error!("stderr from clang:\n{err}");
                 cu::hint!(
                     "failed to compile the source for type parsing - this usually means the type expression has unparsable syntax."
@@ -369,6 +575,55 @@ impl TypeParseCommand {
 
         Ok(output)
     }
+    /// Read the dependencies of the source from the depfile written by clang
+    pub fn read_deps(&self) -> cu::Result<Vec<String>> {
+        let d_file = cu::fs::read_string(&self.d_file)?;
+        let d_file = cu::check!(
+            depfile::parse(&d_file).ok(),
+            "failed to parse depfile from {}",
+            self.d_file
+        )?;
+        let deps = cu::check!(
+            d_file.find(&self.cpp_file),
+            "failed to find source file in depfile {}",
+            self.d_file
+        )?;
+        let mut output = vec![];
+        for dep in deps {
+            if dep == &self.cpp_file {
+                continue;
+            }
+            output.push(dep.to_string());
+        }
+        Ok(output)
+    }
+
+    /// Save the cache files for a source that is parsed in a batch,
+    /// so the cache is the same as if the source is parsed separately
+    pub fn save_batched(
+        &self,
+        source: &str,
+        nodes: &BTreeMap<String, Node<Ast>>,
+        deps: &[String],
+    ) -> cu::Result<()> {
+        cu::fs::write(&self.cpp_file, source)?;
+        let mut d_file = format!("{}:", escape_dep(&self.cpp_file));
+        for dep in deps {
+            d_file.push_str(" \\\n  ");
+            d_file.push_str(&escape_dep(dep));
+        }
+        d_file.push('\n');
+        cu::fs::write(&self.d_file, d_file)?;
+        cu::fs::write_json_pretty(&self.out_file, nodes)
+    }
+
+    /// Remove the source, depfile and output of the command
+    pub fn remove_files(&self) -> cu::Result<()> {
+        cu::fs::remove(&self.cpp_file)?;
+        cu::fs::remove(&self.d_file)?;
+        cu::fs::remove(&self.out_file)
+    }
+
     fn parse_ast_nodes(
         &self,
         requests: &[ParseRequest<'_>],
@@ -408,6 +663,10 @@ impl TypeParseCommand {
     }
 }
 
+fn escape_dep(path: &str) -> String {
+    path.replace(' ', "\\ ")
+}
+
 struct ParseRequest<'a> {
     /// The goff of the type
     goff: Goff,
@@ -415,10 +674,12 @@ struct ParseRequest<'a> {
     token: String,
     /// The qualifying namespace to add this type to
     namespace: &'a Namespace,
+    /// Key in the type name cache, if the parsed name can be cached
+    cache_key: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-enum Ast {
+pub(crate) enum Ast {
     TranslationUnitDecl,
     NamespaceDecl {
         name: Option<String>,
@@ -437,7 +698,10 @@ enum Ast {
         /// The "untemplated name", but could have template in the qualifier
         template_name: String,
     },
-    TemplateArgument,
+    TemplateArgument {
+        #[serde(default, rename = "type")]
+        ty: Option<AstType>,
+    },
     ConstantExpr {
         value: String,
     },
@@ -469,7 +733,7 @@ enum Ast {
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct AstType {
+pub(crate) struct AstType {
     qual_type: String,
 }
 
@@ -520,18 +784,48 @@ fn parse_template_spec_ast(
     let mut template_args = Vec::new();
     // iterate through the templates
     for n in &node.inner {
-        if n.kind != Ast::TemplateArgument {
+        let Ast::TemplateArgument { ty } = &n.kind else {
             continue;
-        }
+        };
         cu::ensure!(
             n.inner.len() == 1,
             "TemplateArgument node should have inner length 1"
         )?;
-        template_args.push(parse_template_arg_ast(&n.inner[0], ns, parser)?);
+        match parse_template_arg_ast(&n.inner[0], ns, parser) {
+            Ok(arg) => template_args.push(arg),
+            Err(e) => {
+                if !parser.allow_unknown_template_args {
+                    return Err(e);
+                }
+                let spelling = template_arg_spelling(n, ty.as_ref());
+                cu::debug!("degrading unparsable template arg to unknown: {spelling}: {e:?}");
+                template_args.push(TemplateArg::Unknown(spelling));
+            }
+        }
     }
 
     Ok(template_args)
 }
+
+/// Get the best-effort clang spelling of a TemplateArgument node
+fn template_arg_spelling(node: &Node<Ast>, ty: Option<&AstType>) -> String {
+    if let Some(ty) = ty {
+        return ty.qual_type.clone();
+    }
+    for n in &node.inner {
+        match &n.kind {
+            Ast::ConstantExpr { value } => return value.clone(),
+            Ast::ElaboratedType { ty, .. }
+            | Ast::BuiltinType { ty }
+            | Ast::TypedefType { ty }
+            | Ast::RecordType { ty }
+            | Ast::EnumType { ty } => return ty.qual_type.clone(),
+            Ast::TemplateSpecializationType { template_name } => return template_name.clone(),
+            _ => {}
+        }
+    }
+    "[unknown]".to_string()
+}
 fn parse_template_arg_ast(
     node: &Node<Ast>,
     ns: &NamespaceMaps,
@@ -560,28 +854,7 @@ fn parse_template_arg_ast_recur(
             }
         },
         Ast::BuiltinType { ty } => {
-            let prim_name = match ty.qual_type.as_str() {
-                "void" => "void",
-                "bool" => "bool",
-                "unsigned char" => "u8",
-                "unsigned short" => "u16",
-                "unsigned int" => "u32",
-                "unsigned long" => "u64", // in most cases
-                "short" => "i16",
-                "int" => "i32",
-                "long" => "i64", // in most cases
-                "float" => "f32",
-                "double" => "f64",
-                // implementation defined:
-                "char" => parser.char_repr.to_str(),
-                "wchar_t" => parser.wchar_repr.to_str(),
-                _ => {
-                    cu::bail!(
-                        "unexpected builtin qual_type: {} (please add it if you need).",
-                        ty.qual_type
-                    );
-                }
-            };
+            let prim_name = builtin_prim_name(&ty.qual_type, parser)?;
             Ok(TemplateArg::Type(Tree::Base(NamespacedTemplatedName::new(
                 NamespacedName::unnamespaced(prim_name),
             ))))
@@ -750,6 +1023,30 @@ fn parse_template_arg_ast_recur(
         }
     }
 }
+/// Get the primitive name of a builtin type from the clang spelling
+pub(crate) fn builtin_prim_name(qual_type: &str, parser: &NameParser) -> cu::Result<&'static str> {
+    let prim_name = match qual_type {
+        "void" => "void",
+        "bool" => "bool",
+        "unsigned char" => "u8",
+        "unsigned short" => "u16",
+        "unsigned int" => "u32",
+        "unsigned long" => "u64", // in most cases
+        "short" => "i16",
+        "int" => "i32",
+        "long" => "i64", // in most cases
+        "float" => "f32",
+        "double" => "f64",
+        // implementation defined:
+        "char" => parser.char_repr.to_str(),
+        "wchar_t" => parser.wchar_repr.to_str(),
+        _ => {
+            cu::bail!("unexpected builtin qual_type: {qual_type} (please add it if you need).");
+        }
+    };
+    Ok(prim_name)
+}
+
 fn parse_template_arg_ast_recur_paren_type(
     node: &Node<Ast>,
     ns: &NamespaceMaps,
@@ -789,7 +1086,10 @@ fn to_namespaced_name_with_fallback(
     }
 }
 
-fn to_namespaced_name(ns: &NamespaceMaps, source: &str) -> cu::Result<NamespacedName> {
+pub(crate) fn to_namespaced_name(ns: &NamespaceMaps, source: &str) -> cu::Result<NamespacedName> {
+    // the namespaces are recorded without the ABI tags
+    let (source, _) = exstructs::split_abi_tags(source);
+    let source = source.as_ref();
     let (namespace, base) = cu::check!(
         split_namespace(source),
         "failed to split namespace from type name in '{source}'"
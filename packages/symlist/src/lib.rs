@@ -1,33 +1,99 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
 use cu::pre::*;
 
-use dejj_utils::SymListConfig;
+use dejj_utils::SymbolSource;
 use llvmutils::Demangler;
 
 /// Data structure that lists symbols and their addresses
 #[derive(Default)]
 pub struct SymbolList {
     map: BTreeMap<String, u32>,
+    /// Only fabricate the missing ctor/dtor variant for classes without a VTT
+    check_virtual_bases: bool,
+    /// Demangled names of the classes with a VTT (i.e. classes with virtual bases)
+    vtt_classes: BTreeSet<String>,
+    fabricated: BTreeMap<String, FabricatedSymbol>,
+}
+
+/// A ctor or dtor symbol that is not in the listing, and is assumed
+/// to be at the same address as the other variant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FabricatedSymbol {
+    pub address: u32,
+    /// The symbol in the listing that this symbol is fabricated from
+    pub from: String,
 }
 
 impl SymbolList {
+    /// Create an empty symbol list. If `check_virtual_bases` is true, the C1/C2 and D1/D2
+    /// variants of ctors and dtors are only fabricated for classes without virtual bases,
+    /// since the variants are different functions for those classes.
+    ///
+    /// Classes with virtual bases are detected from their VTT symbol (`_ZTT`), which
+    /// needs to be in the listing
+    pub fn new(check_virtual_bases: bool) -> Self {
+        Self {
+            check_virtual_bases,
+            ..Default::default()
+        }
+    }
     pub fn len(&self) -> usize {
         self.map.len()
     }
-    pub fn load_data(&mut self, config: &SymListConfig) -> cu::Result<()> {
-        let map = cu::check!(load_symbol_csv(config), "failed to load data symbols")?;
-        self.map.extend(map);
+    /// Load the symbols from the source
+    pub async fn load(
+        &mut self,
+        source: &(impl SymbolSource + ?Sized),
+        demangler: Arc<Demangler>,
+    ) -> cu::Result<()> {
+        let maps = cu::check!(
+            source.load(),
+            "failed to load symbols from {}",
+            source.name()
+        )?;
+        cu::debug!(
+            "found {} function and {} data symbols in {}",
+            maps.funcs.len(),
+            maps.data.len(),
+            source.name()
+        );
+        if self.check_virtual_bases {
+            let vtts = maps.data.keys().chain(maps.funcs.keys());
+            self.add_vtt_classes(vtts, &demangler)?;
+        }
+        self.extend_data(maps.data);
+        self.extend_func(maps.funcs, demangler).await
+    }
+    /// Record the classes with a VTT from the symbols
+    fn add_vtt_classes<'a>(
+        &mut self,
+        symbols: impl IntoIterator<Item = &'a String>,
+        demangler: &Demangler,
+    ) -> cu::Result<()> {
+        for symbol in symbols {
+            if !symbol.starts_with("_ZTT") {
+                continue;
+            }
+            let demangled = demangler.demangle(symbol)?;
+            if let Some(class) = demangled.strip_prefix("VTT for ") {
+                self.vtt_classes.insert(class.to_string());
+            }
+        }
+        cu::debug!("found {} classes with VTT", self.vtt_classes.len());
         Ok(())
     }
-    pub async fn load_func(
+    /// Add data symbols with their (relative) addresses
+    pub fn extend_data(&mut self, map: BTreeMap<String, u32>) {
+        self.map.extend(map);
+    }
+    /// Add function symbols with their (relative) addresses
+    pub async fn extend_func(
         &mut self,
-        config: &SymListConfig,
+        map: BTreeMap<String, u32>,
         demangler: Arc<Demangler>,
     ) -> cu::Result<()> {
-        let map = cu::check!(load_symbol_csv(config), "failed to load func symbols")?;
-
         // fabricate D1/D2 and C1/C2 if either is missing
         let pool = cu::co::pool(-1);
         let mut handles = vec![];
@@ -37,7 +103,7 @@ impl SymbolList {
             let symbol = symbol.to_string();
             let handle = pool.spawn(async move {
                 let result = get_all_possible_symbols(&symbol, &demangler)?;
-                cu::Ok((result, addr))
+                cu::Ok((result, symbol, addr))
             });
             handles.push(handle);
         }
@@ -48,80 +114,49 @@ impl SymbolList {
         let mut set = cu::co::set(handles);
         while let Some(result) = set.next().await {
             cu::progress!(bar += 1);
-            let (symbols, addr) =
+            let (symbols, from, addr) =
                 cu::check!(result.flatten(), "failed to get all possible symbols")?;
-            match symbols {
+            let (v1, v2, class) = match symbols {
                 PossibleSymbols::Only(_) => continue,
-                PossibleSymbols::Dtor12(d1, d2) => {
-                    if !map.contains_key(&d1) {
-                        self.map.insert(d1, addr);
-                    }
-                    if !map.contains_key(&d2) {
-                        self.map.insert(d2, addr);
-                    }
-                }
-                PossibleSymbols::Ctor12(c1, c2) => {
-                    if !map.contains_key(&c1) {
-                        self.map.insert(c1, addr);
-                    }
-                    if !map.contains_key(&c2) {
-                        self.map.insert(c2, addr);
-                    }
+                PossibleSymbols::Dtor12(d1, d2, class) => (d1, d2, class),
+                PossibleSymbols::Ctor12(c1, c2, class) => (c1, c2, class),
+            };
+            if self.check_virtual_bases && self.vtt_classes.contains(&class) {
+                cu::trace!("not fabricating ctor/dtor variants of {from}, {class} has a VTT");
+                continue;
+            }
+            for symbol in [v1, v2] {
+                if map.contains_key(&symbol) {
+                    continue;
                 }
+                self.map.insert(symbol.clone(), addr);
+                let from = from.clone();
+                self.fabricated.insert(
+                    symbol,
+                    FabricatedSymbol {
+                        address: addr,
+                        from,
+                    },
+                );
             }
         }
         self.map.extend(map);
+        // the fabricated symbol could be in the listing as data
+        self.fabricated
+            .retain(|symbol, f| self.map.get(symbol) == Some(&f.address));
         Ok(())
     }
+    /// Get the ctor and dtor symbols that are not in the listing
+    /// and fabricated from their other variants
+    pub fn fabricated(&self) -> &BTreeMap<String, FabricatedSymbol> {
+        &self.fabricated
+    }
     /// Get the address of symbol
     pub fn get_address(&self, symbol: &str) -> Option<u32> {
         self.map.get(symbol).copied()
     }
 }
 
-pub fn load_symbol_csv(config: &SymListConfig) -> cu::Result<BTreeMap<String, u32>> {
-    let content = cu::fs::read_string(&config.path)?;
-    let address_column = config.address_column;
-    let symbol_column = config.symbol_column;
-
-    let mut map = BTreeMap::default();
-
-    for (i, line) in content.lines().enumerate().skip(config.skip_rows) {
-        let row = i + 1;
-        let parts = line.split(',').collect::<Vec<_>>();
-        let address = cu::check!(
-            parts.get(address_column),
-            "failed to get address column at row {row} (address_column={address_column})"
-        )?;
-        let address = cu::check!(
-            cu::parse::<u64>(address),
-            "failed to parse address at row {row}"
-        )?;
-        let rel_address = cu::check!(
-            address.checked_sub(config.base_address),
-            "address is less than base address at row {row}"
-        )?;
-        cu::ensure!(
-            rel_address <= u32::MAX as u64,
-            "relative address at row {row} is too big, this is likely wrong"
-        )?;
-
-        let symbol = cu::check!(
-            parts.get(symbol_column),
-            "failed to get symbol column at row {row} (symbol_column={symbol_column})"
-        )?;
-        let symbol = symbol.trim();
-
-        if symbol.is_empty() {
-            continue;
-        }
-
-        map.insert(symbol.to_string(), rel_address as u32);
-    }
-
-    Ok(map)
-}
-
 fn get_all_possible_symbols(symbol: &str, demangler: &Demangler) -> cu::Result<PossibleSymbols> {
     // demangle the symbol
     let demangled = demangler.demangle(symbol)?;
@@ -176,7 +211,8 @@ fn get_all_possible_symbols(symbol: &str, demangler: &Demangler) -> cu::Result<P
         set_str_byte(&mut buf, i, '1');
         let d1 = buf.clone();
         set_str_byte(&mut buf, i, '2');
-        return Ok(PossibleSymbols::Dtor12(d1, buf));
+        let class = structor_class(&demangled).to_string();
+        return Ok(PossibleSymbols::Dtor12(d1, buf, class));
     }
 
     // might be ctor or regular function
@@ -227,7 +263,27 @@ fn get_all_possible_symbols(symbol: &str, demangler: &Demangler) -> cu::Result<P
     set_str_byte(&mut buf, i, '1');
     let c1 = buf.clone();
     set_str_byte(&mut buf, i, '2');
-    Ok(PossibleSymbols::Ctor12(c1, buf))
+    let class = structor_class(&demangled).to_string();
+    Ok(PossibleSymbols::Ctor12(c1, buf, class))
+}
+
+/// Get the class of a demangled ctor or dtor, for example
+/// `ns::Foo<int>` for `ns::Foo<int>::~Foo()`
+fn structor_class(demangled: &str) -> &str {
+    // find the last scope before the parameters, outside of templates
+    let mut depth = 0;
+    let mut scope_end = 0;
+    let bytes = demangled.as_bytes();
+    for (i, c) in bytes.iter().enumerate() {
+        match c {
+            b'<' => depth += 1,
+            b'>' => depth -= 1,
+            b'(' if depth == 0 => break,
+            b':' if depth == 0 && bytes.get(i + 1) == Some(&b':') => scope_end = i,
+            _ => {}
+        }
+    }
+    &demangled[..scope_end]
 }
 
 fn is_dtor(symbol: &str) -> bool {
@@ -280,7 +336,7 @@ enum PossibleSymbols {
     // D1 and D2 might be the same function
     // and referred to differently in different places,
     // so if we detect a D1/D2, it could be either,
-    // same for C1 and C2
-    Dtor12(String, String), // D1, D2
-    Ctor12(String, String), // C1, C2
+    // same for C1 and C2. The last string is the class
+    Dtor12(String, String, String), // D1, D2, class
+    Ctor12(String, String, String), // C1, C2, class
 }
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cu::pre::*;
+
+use crate::TyYaml;
+
+/// Version of the document format written by this crate.
+///
+/// Documents without the version header are from before the header
+/// was added, and are version 1
+pub const TYYAML_VERSION: u32 = 1;
+
+/// Comment on the first line of each document file
+const VERSION_HEADER: &str = "# TyYAML version ";
+
+/// A TyYAML type database, which is `types.yaml` and `symbols.yaml` in a directory.
+///
+/// Each file is a mapping from the name to the definition. Large files can be split into
+/// parts (`types.part1.yaml`, `types.part2.yaml`, ...), where each part is a mapping
+/// by itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// Format version of the loaded document
+    pub version: u32,
+    /// Type definitions by name, including named function pointer types
+    pub types: BTreeMap<String, TypeDef>,
+    /// Symbols by link name
+    pub symbols: BTreeMap<String, SymbolDef>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            version: TYYAML_VERSION,
+            types: Default::default(),
+            symbols: Default::default(),
+        }
+    }
+}
+
+/// Type definition in `types.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TypeDef {
+    Enum {
+        size: u32,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        signed: bool,
+        enumerators: Vec<EnumeratorDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
+    Union {
+        size: u32,
+        members: Vec<MemberDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
+    Struct {
+        size: u32,
+        members: Vec<MemberDef>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        vtable: Vec<VfuncDef>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        bases: Vec<BaseDef>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        source: Option<String>,
+    },
+    /// Named function pointer type, or alias of a type with an identical layout
+    Typedef {
+        #[serde(rename = "type")]
+        ty: TyYaml,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumeratorDef {
+    pub name: String,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemberDef {
+    pub offset: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: TyYaml,
+    /// "base", "vfptr" or "bitfield"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special: Option<String>,
+    /// Bitfields in the member, if bitfields are preserved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bitfields: Vec<BitfieldDef>,
+    /// Access specifier, if recorded in the debug info
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BitfieldDef {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub bit_offset: u32,
+    pub bit_size: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VfuncDef {
+    pub index: u32,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: TyYaml,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaseDef {
+    /// None for virtual bases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    #[serde(
+        rename = "virtual",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub is_virtual: bool,
+    /// Offset in the vtable where the offset of the virtual base is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vbase_offset: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<Access>,
+    #[serde(rename = "type")]
+    pub ty: TyYaml,
+}
+
+/// Symbol in `symbols.yaml`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SymbolDef {
+    pub address: u32,
+    #[serde(rename = "type")]
+    pub ty: TyYaml,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<String>,
+    /// Declared location in the original source, as `file:line`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// C++ access specifier of a member, base class or virtual function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Public,
+    Protected,
+    Private,
+}
+
+impl Document {
+    pub const TYPES_FILE: &str = "types.yaml";
+    pub const SYMBOLS_FILE: &str = "symbols.yaml";
+
+    /// Load `types.yaml` and `symbols.yaml` from the directory.
+    /// If a file is split, all of its parts are loaded
+    pub fn load(dir: &Path) -> cu::Result<Self> {
+        let mut doc = Self {
+            // files without the header are the oldest version
+            version: 1,
+            ..Default::default()
+        };
+        for path in file_parts(dir, Self::TYPES_FILE)? {
+            let content = cu::fs::read_string(&path)?;
+            cu::check!(
+                doc.add_types(&content),
+                "failed to load types from {}",
+                path.display()
+            )?;
+        }
+        for path in file_parts(dir, Self::SYMBOLS_FILE)? {
+            let content = cu::fs::read_string(&path)?;
+            cu::check!(
+                doc.add_symbols(&content),
+                "failed to load symbols from {}",
+                path.display()
+            )?;
+        }
+        Ok(doc)
+    }
+
+    /// Save the document to `types.yaml` and `symbols.yaml` in the directory, without splitting
+    pub fn save(&self, dir: &Path) -> cu::Result<()> {
+        cu::fs::write(dir.join(Self::TYPES_FILE), self.type_records()?.concat())?;
+        cu::fs::write(
+            dir.join(Self::SYMBOLS_FILE),
+            self.symbol_records()?.concat(),
+        )
+    }
+
+    /// Parse a types file (or a part of it), and add the types to the document
+    pub fn add_types(&mut self, content: &str) -> cu::Result<()> {
+        self.check_version(content)?;
+        let types = yaml::parse::<Option<BTreeMap<String, TypeDef>>>(content)?;
+        self.types.extend(types.unwrap_or_default());
+        Ok(())
+    }
+
+    /// Parse a symbols file (or a part of it), and add the symbols to the document
+    pub fn add_symbols(&mut self, content: &str) -> cu::Result<()> {
+        self.check_version(content)?;
+        let symbols = yaml::parse::<Option<BTreeMap<String, SymbolDef>>>(content)?;
+        self.symbols.extend(symbols.unwrap_or_default());
+        Ok(())
+    }
+
+    fn check_version(&mut self, content: &str) -> cu::Result<()> {
+        let Some(line) = content.lines().next() else {
+            return Ok(());
+        };
+        let Some(version) = line.strip_prefix(VERSION_HEADER) else {
+            return Ok(());
+        };
+        let version = cu::check!(
+            cu::parse::<u32>(version.trim()),
+            "invalid TyYAML version header: {line}"
+        )?;
+        cu::ensure!(
+            version <= TYYAML_VERSION,
+            "TyYAML version {version} is newer than the supported version {TYYAML_VERSION}"
+        )?;
+        self.version = version;
+        Ok(())
+    }
+
+    /// Serialize the types as YAML records, with the version header before the first record.
+    ///
+    /// Each record is a mapping with one entry, so that any concatenation
+    /// of the records is a valid YAML mapping
+    pub fn type_records(&self) -> cu::Result<Vec<String>> {
+        yaml_records(&self.types)
+    }
+
+    /// Serialize the symbols as YAML records, see [`Document::type_records`]
+    pub fn symbol_records(&self) -> cu::Result<Vec<String>> {
+        yaml_records(&self.symbols)
+    }
+}
+
+fn yaml_records<T: Serialize>(map: &BTreeMap<String, T>) -> cu::Result<Vec<String>> {
+    let mut records = if map.is_empty() {
+        vec![yaml::stringify(map)?]
+    } else {
+        map.iter()
+            .map(|(k, v)| yaml::stringify(&BTreeMap::from([(k, v)])))
+            .collect::<cu::Result<Vec<_>>>()?
+    };
+    records[0].insert_str(0, &format!("{VERSION_HEADER}{TYYAML_VERSION}\n"));
+    Ok(records)
+}
+
+/// Get the paths of the file, or the parts of the file if it's split
+fn file_parts(dir: &Path, file_name: &str) -> cu::Result<Vec<PathBuf>> {
+    let path = dir.join(file_name);
+    if path.exists() {
+        return Ok(vec![path]);
+    }
+    let (stem, extension) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    let mut paths = vec![];
+    loop {
+        let n = paths.len() + 1;
+        let part = if extension.is_empty() {
+            dir.join(format!("{stem}.part{n}"))
+        } else {
+            dir.join(format!("{stem}.part{n}.{extension}"))
+        };
+        if !part.exists() {
+            break;
+        }
+        paths.push(part);
+    }
+    cu::ensure!(
+        !paths.is_empty(),
+        "cannot find {file_name} in {}",
+        dir.display()
+    )?;
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Prim;
+
+    fn example() -> Document {
+        let mut doc = Document::default();
+        doc.types.insert(
+            "Foo".to_string(),
+            TypeDef::Struct {
+                size: 0x10,
+                members: vec![
+                    MemberDef {
+                        offset: 0,
+                        name: Some("mValue".to_string()),
+                        ty: Prim::U32.into(),
+                        special: None,
+                        bitfields: vec![],
+                        access: Some(Access::Private),
+                    },
+                    MemberDef {
+                        offset: 8,
+                        name: Some("mNext".to_string()),
+                        ty: TyYaml::ptr(TyYaml::named("Foo")),
+                        special: None,
+                        bitfields: vec![],
+                        access: None,
+                    },
+                ],
+                vtable: vec![],
+                bases: vec![],
+                source: Some("foo.h:3".to_string()),
+            },
+        );
+        doc.types.insert(
+            "Kind".to_string(),
+            TypeDef::Enum {
+                size: 4,
+                signed: false,
+                enumerators: vec![EnumeratorDef {
+                    name: "Kind_A".to_string(),
+                    value: 1,
+                }],
+                source: None,
+            },
+        );
+        doc.symbols.insert(
+            "_Z3getv".to_string(),
+            SymbolDef {
+                address: 0x1000,
+                ty: TyYaml::Sub(vec![TyYaml::ptr(TyYaml::named("Foo"))]),
+                params: vec![],
+                source: None,
+            },
+        );
+        doc
+    }
+
+    #[test]
+    fn test_round_trip() -> cu::Result<()> {
+        let doc = example();
+        let mut loaded = Document::default();
+        loaded.add_types(&doc.type_records()?.concat())?;
+        loaded.add_symbols(&doc.symbol_records()?.concat())?;
+        assert_eq!(doc, loaded);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_records() -> cu::Result<()> {
+        let doc = example();
+        let records = doc.type_records()?;
+        assert_eq!(records.len(), 2);
+        assert!(records[0].starts_with(VERSION_HEADER));
+        // each part is a valid mapping by itself
+        let mut loaded = Document::default();
+        for record in &records {
+            loaded.add_types(record)?;
+        }
+        assert_eq!(doc.types, loaded.types);
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty() -> cu::Result<()> {
+        let doc = Document::default();
+        let mut loaded = Document::default();
+        loaded.add_types(&doc.type_records()?.concat())?;
+        assert!(loaded.types.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_version() {
+        let mut doc = Document::default();
+        let content = format!("{VERSION_HEADER}{}\n{{}}\n", TYYAML_VERSION + 1);
+        assert!(doc.add_types(&content).is_err());
+    }
+}
@@ -4,3 +4,5 @@ mod type_repr;
 pub use type_repr::*;
 mod type_tree;
 pub use type_tree::*;
+mod document;
+pub use document::*;
@@ -37,8 +37,12 @@ impl Ty {
 }
 
 impl TreeRepr for Ty {
+    /// The spec is a YAML scalar, quoting it for TyYAML is left to the serializer
     fn serialize_spec(&self) -> cu::Result<String> {
-        Ok(self.to_tyyaml())
+        match self {
+            Self::Prim(ty) => Ok(ty.to_string()),
+            Self::Named(name) => Ok(format!("\"{name}\"")),
+        }
     }
     fn deserialize_void() -> Self {
         Self::Prim(Prim::Void)
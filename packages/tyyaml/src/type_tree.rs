@@ -303,35 +303,48 @@ impl<Repr> Tree<Repr> {
             Tree::Array(x, len) => Ok(x.to_replaced_impl(f)?.map(|elem| Self::array(elem, *len))),
             Tree::Ptr(x) => Ok(x.to_replaced_impl(f)?.map(Self::ptr)),
             Tree::Sub(x) => Ok(Self::to_replaced_impl_vec(x, f)?.map(Tree::Sub)),
-            Tree::Ptmd(base, x) => match f(base) {
-                None => Ok(x
-                    .to_replaced_impl(f)?
-                    .map(|new_x| Self::ptmd(base.clone(), new_x))),
-                Some(Tree::Base(base)) => {
-                    Ok(x.to_replaced_impl(f)?.map(|new_x| Self::ptmd(base, new_x)))
+            Tree::Ptmd(base, x) => {
+                let new_base = cu::check!(
+                    Self::to_replaced_ptm_base(base, f.as_mut()),
+                    "ptmd base type cannot be replaced with tree! check if the type is replacable before calling to_replaced"
+                )?;
+                let new_x = x.to_replaced_impl(f)?;
+                if new_base.is_none() && new_x.is_none() {
+                    return Ok(None);
                 }
-                _ => {
-                    cu::bail!(
-                        "ptmd base type cannot be replaced with tree! check if the type is replacable before calling to_replaced"
-                    );
-                }
-            },
+                Ok(Some(Self::Ptmd(
+                    new_base.unwrap_or_else(|| base.clone()),
+                    new_x.map(Box::new).unwrap_or_else(|| x.clone()),
+                )))
+            }
             Tree::Ptmf(base, x) => {
-                match f(base) {
-                    None => Ok(Self::to_replaced_impl_vec(x, f)?
-                        .map(|new_x| Self::ptmf(base.clone(), new_x))),
-                    Some(Tree::Base(base)) => {
-                        Ok(Self::to_replaced_impl_vec(x, f)?.map(|new_x| Self::ptmf(base, new_x)))
-                    }
-                    _ => {
-                        cu::bail!(
-                            "ptmf base type cannot be replaced with tree! check if the type is replacable before calling to_replaced"
-                        );
-                    }
+                let new_base = cu::check!(
+                    Self::to_replaced_ptm_base(base, f.as_mut()),
+                    "ptmf base type cannot be replaced with tree! check if the type is replacable before calling to_replaced"
+                )?;
+                let new_x = Self::to_replaced_impl_vec(x, f)?;
+                if new_base.is_none() && new_x.is_none() {
+                    return Ok(None);
                 }
+                Ok(Some(Self::Ptmf(
+                    new_base.unwrap_or_else(|| base.clone()),
+                    new_x.unwrap_or_else(|| x.clone()),
+                )))
             }
         }
     }
+    /// Replace the base type of a PTMD or PTMF, which must stay a base type.
+    /// The base is replaced even if the pointee is unchanged
+    fn to_replaced_ptm_base(
+        base: &Repr,
+        f: &mut dyn FnMut(&Repr) -> Option<Self>,
+    ) -> cu::Result<Option<Repr>> {
+        match f(base) {
+            None => Ok(None),
+            Some(Tree::Base(new_base)) => Ok(Some(new_base)),
+            Some(_) => cu::bail!("cannot replace base type with a composite type"),
+        }
+    }
     fn to_replaced_impl_vec<'a>(
         v: &[Self],
         f: &mut Box<dyn FnMut(&Repr) -> Option<Self> + 'a>,
@@ -668,3 +681,95 @@ impl<'de, T: TreeRepr> Deserialize<'de> for Tree<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(tree: &Tree<u32>, from: u32, to: Tree<u32>) -> cu::Result<Option<Tree<u32>>> {
+        let mut tree = tree.clone();
+        tree.to_replaced(|x| if *x == from { Some(to.clone()) } else { None })
+    }
+
+    #[test]
+    fn test_replace_ptmf_base_only() -> cu::Result<()> {
+        let tree = Tree::ptmf(1u32, vec![Tree::Base(2), Tree::Base(3)]);
+        let replaced = replace(&tree, 1, Tree::Base(4))?;
+        assert_eq!(
+            replaced,
+            Some(Tree::ptmf(4u32, vec![Tree::Base(2), Tree::Base(3)]))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_ptmd_base_only() -> cu::Result<()> {
+        let tree = Tree::ptmd(1u32, Tree::Base(2));
+        let replaced = replace(&tree, 1, Tree::Base(4))?;
+        assert_eq!(replaced, Some(Tree::ptmd(4u32, Tree::Base(2))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_ptmf_base_and_params() -> cu::Result<()> {
+        // member function of the class taking the class by pointer
+        let tree = Tree::ptmf(1u32, vec![Tree::Base(2), Tree::ptr(Tree::Base(1))]);
+        let replaced = replace(&tree, 1, Tree::Base(4))?;
+        assert_eq!(
+            replaced,
+            Some(Tree::ptmf(
+                4u32,
+                vec![Tree::Base(2), Tree::ptr(Tree::Base(4))]
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_ptmf_params_only() -> cu::Result<()> {
+        let tree = Tree::ptmf(1u32, vec![Tree::Base(2), Tree::Base(3)]);
+        let replaced = replace(&tree, 3, Tree::ptr(Tree::Base(5)))?;
+        assert_eq!(
+            replaced,
+            Some(Tree::ptmf(
+                1u32,
+                vec![Tree::Base(2), Tree::ptr(Tree::Base(5))]
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_ptmf_unchanged() -> cu::Result<()> {
+        let tree = Tree::ptr(Tree::ptmf(1u32, vec![Tree::Base(2), Tree::Base(3)]));
+        assert_eq!(replace(&tree, 6, Tree::Base(4))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replace_ptmf_base_with_composite() {
+        let tree = Tree::ptmf(1u32, vec![Tree::Base(2)]);
+        assert!(replace(&tree, 1, Tree::ptr(Tree::Base(4))).is_err());
+        let tree = Tree::ptmd(1u32, Tree::Base(2));
+        assert!(replace(&tree, 1, Tree::array(Tree::Base(4), 2)).is_err());
+    }
+
+    #[test]
+    fn test_replace_nested_ptmf_in_sub() -> cu::Result<()> {
+        let tree = Tree::ptr(Tree::Sub(vec![
+            Tree::Base(0),
+            Tree::Base(2),
+            Tree::ptmf(1u32, vec![Tree::Base(0)]),
+        ]));
+        let replaced = replace(&tree, 1, Tree::Base(4))?;
+        assert_eq!(
+            replaced,
+            Some(Tree::ptr(Tree::Sub(vec![
+                Tree::Base(0),
+                Tree::Base(2),
+                Tree::ptmf(4u32, vec![Tree::Base(0)]),
+            ])))
+        );
+        Ok(())
+    }
+}
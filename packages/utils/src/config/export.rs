@@ -0,0 +1,82 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use cu::pre::*;
+
+/// Config for exporting the extracted database to other formats
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportConfig {
+    /// Directory for the exported files. Default is the extract output directory
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+    /// Formats to export at the end of extract
+    #[serde(default = "default_on_extract")]
+    pub on_extract: Vec<String>,
+    /// How to pick the name of a type out of all spellings of the name,
+    /// for the exported names
+    #[serde(default)]
+    pub canonical_name: CanonicalNamePolicy,
+    /// Options for each format, by the name of the format (for example, `[export.hover]`)
+    #[serde(flatten)]
+    pub formats: BTreeMap<String, ExportFormatConfig>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            output_dir: None,
+            on_extract: default_on_extract(),
+            canonical_name: Default::default(),
+            formats: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_on_extract() -> Vec<String> {
+    vec!["hover".to_string(), "tyyaml".to_string()]
+}
+
+impl ExportConfig {
+    /// Get the options for a format, which is the default if the format has no section
+    pub fn format(&self, name: &str) -> ExportFormatConfig {
+        self.formats.get(name).cloned().unwrap_or_default()
+    }
+}
+
+/// Policy for picking the canonical name of a type, out of all spellings of the name
+/// from typedefs and template args (for example `Foo<u64>` and `Foo<uint64_t>`)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CanonicalNamePolicy {
+    /// The lexicographically first spelling
+    #[default]
+    Lexical,
+    /// Prefer the spellings in the source, like typedefs in template args
+    PreferTypedef,
+    /// Prefer the spellings with typedefs resolved, like primitive types in template args
+    PreferPrimitive,
+    /// The shortest spelling
+    Shortest,
+}
+
+/// Options that all export formats have
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportFormatConfig {
+    /// Name of the exported file in the output directory.
+    /// Default is `<format>.<extension>`
+    #[serde(default)]
+    pub file_name: Option<String>,
+    /// Split the exported files into numbered parts if they are larger than this
+    /// many bytes, for formats that support splitting (tyyaml)
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    /// Template file to render, only used by the `template` format
+    #[serde(default)]
+    pub template: Option<PathBuf>,
+    /// Inline the members of base classes into the derived structs, instead of
+    /// one member for each base. Only used by the formats with a C header (ghidra, ida)
+    #[serde(default)]
+    pub flatten_bases: bool,
+}
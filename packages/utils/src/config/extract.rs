@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use cu::pre::*;
 use regex::Regex;
 use tyyaml::Prim;
@@ -17,16 +19,105 @@ pub struct ExtractConfig {
     pub build_command_inherit_io: bool,
     /// Pointer width for the target platform, must be 8, 16, 32 or 64
     pub pointer_width: u8,
-    /// Representation of PTMD, as an array of primitive
-    pub ptmd_repr: (Prim, u32),
-    /// Representation of PTMF, as an array of primitive
-    pub ptmf_repr: (Prim, u32),
+    /// Representation of PTMD, as an array of primitive or a struct
+    pub ptmd_repr: PtmRepr,
+    /// Representation of PTMF, as an array of primitive or a struct
+    pub ptmf_repr: PtmRepr,
     /// Representation of char
     pub char_repr: Prim,
     /// Representation of wchar_t
     pub wchar_repr: Prim,
     /// Regex for the virtual function pointer field
     pub vfptr_field_regex: SerdeRegex,
+    /// Global offsets of DWARF entries to skip when loading. Skipped types
+    /// become opaque blobs, and skipped symbols are not loaded.
+    ///
+    /// This is a workaround for corrupted entries that break a compilation unit
+    #[serde(default)]
+    pub skip_offsets: BTreeSet<usize>,
+    /// Only load and match symbols with addresses in these `[start, end)` ranges,
+    /// for example to exclude SDK code that is statically linked into the program.
+    /// Addresses are relative to the base address, like in the database.
+    ///
+    /// Empty means all symbols are loaded
+    #[serde(default)]
+    pub symbol_address_ranges: Vec<(u32, u32)>,
+    /// Only fabricate the missing C1/C2 or D1/D2 variant of a ctor or dtor for classes
+    /// without virtual bases, where the variants are the same function. Classes
+    /// with virtual bases are detected from their VTT symbol (`_ZTT`) in the listing
+    #[serde(default)]
+    pub check_virtual_bases: bool,
+    /// How to merge vtables of the same type from different compilation units
+    #[serde(default)]
+    pub vtable_merge: VtableMergeMode,
+    /// How to link types defined in anonymous namespaces in different compilation units
+    #[serde(default)]
+    pub anonymous_namespaces: AnonymousNamespaceMode,
+    /// What to do with types defined inside functions
+    #[serde(default)]
+    pub function_local_types: FunctionLocalTypesMode,
+    /// Fail the extraction if types with different names are merged only because
+    /// they share a typedef name, instead of only reporting them
+    #[serde(default)]
+    pub fail_on_ambiguous_names: bool,
+    /// What to do with types that have the same name but cannot be merged.
+    /// The conflicts are always saved to `merge_conflicts.json`
+    #[serde(default)]
+    pub merge_conflicts: MergeConflictMode,
+    /// Max number of permutated names to keep for each type when linking, 0 for no limit.
+    ///
+    /// Names of deeply nested templates can have thousands of permutations from
+    /// the typedefs in the template args
+    #[serde(default)]
+    pub max_name_permutations: usize,
+    /// Keep the name and bit position of each bitfield, instead of
+    /// collapsing bitfields that share storage into one opaque member
+    #[serde(default)]
+    pub preserve_bitfields: bool,
+    /// Keep the const and volatile qualifiers of member and symbol types,
+    /// instead of dropping them when loading the types
+    #[serde(default)]
+    pub keep_qualifiers: bool,
+    /// Name anonymous enums that have no typedef after the enclosing scope
+    /// and the first enumerator, like `Parent::AnonEnum_First`, so they can be
+    /// merged and exported by name
+    #[serde(default)]
+    pub name_anonymous_enums: bool,
+    /// Add object-like macros with integer values to the constants table.
+    /// Only units compiled with macro debug info (for example, `-g3`) have macros
+    #[serde(default)]
+    pub macro_constants: bool,
+    /// Load the static call graph from the call site debug info
+    /// (`DW_TAG_call_site`), which is emitted by optimized builds
+    #[serde(default)]
+    pub call_graph: bool,
+    /// Save the stack offsets of parameters and local variables of each function
+    /// to `frames.json` in the extract output, for labeling locals in decompilers
+    #[serde(default)]
+    pub frame_layouts: bool,
+    /// Save the symbols that each inlined function is inlined into
+    /// (`DW_TAG_inlined_subroutine`) to `inlined-into.json` in the extract output,
+    /// for finding code of functions that do not exist as standalone symbols
+    #[serde(default)]
+    pub inline_report: bool,
+    /// Save the mapping from addresses to source lines from `.debug_line`
+    /// in the database
+    #[serde(default)]
+    pub line_table: LineTableMode,
+    /// Only extract the compilation units with names matching any of these regexes.
+    /// Empty to extract all units
+    #[serde(default)]
+    pub unit_include: Vec<SerdeRegex>,
+    /// Skip the compilation units with names matching any of these regexes
+    #[serde(default)]
+    pub unit_exclude: Vec<SerdeRegex>,
+    /// Demangle the symbols and save the demangled names in the database,
+    /// for looking up symbols by their names in the source code
+    #[serde(default)]
+    pub demangled_index: bool,
+    /// Rules for loading compile_commands.json
+    #[serde(default)]
+    pub compdb: ExtractCompdbConfig,
     /// Debug config
     pub debug: ExtractDebugConfig,
     /// Rules for the type parser
@@ -63,24 +154,230 @@ impl ExtractConfig {
     }
 
     pub fn ptmd_size(&self) -> cu::Result<u32> {
-        let mut size = cu::check!(
-            self.ptmd_repr.0.byte_size(),
-            "invalid unsized ptmd repr in config"
-        )?;
-        size *= self.ptmd_repr.1;
-        cu::ensure!(size != 0, "invalid zero-sized ptmd repr in config")?;
-        Ok(size)
+        cu::check!(self.ptmd_repr.byte_size(), "invalid ptmd repr in config")
     }
 
     pub fn ptmf_size(&self) -> cu::Result<u32> {
-        let mut size = cu::check!(
-            self.ptmf_repr.0.byte_size(),
-            "invalid unsized ptmf repr in config"
+        cu::check!(self.ptmf_repr.byte_size(), "invalid ptmf repr in config")
+    }
+}
+
+/// Representation of a pointer-to-member type
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum PtmRepr {
+    /// Array of a primitive, for example `["u64", 2]`
+    Array(Prim, u32),
+    /// Struct with members at the offsets, for example `{ fn-ptr = ..., adj = ... }`
+    /// in the Itanium ABI
+    Struct(PtmStructRepr),
+}
+
+/// Layout of a pointer-to-member type as a struct
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PtmStructRepr {
+    /// Members of the struct
+    pub members: Vec<PtmReprMember>,
+    /// Size of the struct. Default is the end of the last member,
+    /// rounded up to the alignment of the largest member
+    #[serde(default)]
+    pub size: Option<u32>,
+}
+
+/// Member of [`PtmStructRepr`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct PtmReprMember {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: Prim,
+    pub offset: u32,
+}
+
+impl PtmRepr {
+    /// Check that the members are sized, aligned and not overlapping,
+    /// and the representation is not empty
+    pub fn validate(&self) -> cu::Result<()> {
+        let members = match self {
+            Self::Array(prim, len) => {
+                cu::ensure!(prim.byte_size().is_some(), "repr type must be sized")?;
+                cu::ensure!(*len != 0, "repr type must be non-zero size")?;
+                return Ok(());
+            }
+            Self::Struct(repr) => &repr.members,
+        };
+        cu::ensure!(!members.is_empty(), "repr struct must have members")?;
+        let mut end = 0;
+        for m in members {
+            let size = cu::check!(
+                m.ty.byte_size(),
+                "repr struct member {} must be sized",
+                m.name
+            )?;
+            cu::ensure!(
+                m.offset % size == 0,
+                "repr struct member {} at 0x{:x} is not aligned",
+                m.name,
+                m.offset
+            )?;
+            cu::ensure!(
+                m.offset >= end,
+                "repr struct member {} at 0x{:x} overlaps the previous member",
+                m.name,
+                m.offset
+            )?;
+            end = m.offset + size;
+        }
+        let size = self.byte_size()?;
+        cu::ensure!(size >= end, "repr struct size 0x{size:x} is too small")?;
+        let align = self.largest_prim()?.byte_size().unwrap_or(1);
+        cu::ensure!(
+            size % align == 0,
+            "repr struct size 0x{size:x} is not aligned to 0x{align:x}"
         )?;
-        size *= self.ptmf_repr.1;
-        cu::ensure!(size != 0, "invalid zero-sized ptmf repr in config")?;
+        Ok(())
+    }
+
+    /// Get the size of the representation
+    pub fn byte_size(&self) -> cu::Result<u32> {
+        let size = match self {
+            Self::Array(prim, len) => {
+                let size = cu::check!(prim.byte_size(), "invalid unsized repr")?;
+                size * len
+            }
+            Self::Struct(repr) => match repr.size {
+                Some(size) => size,
+                None => {
+                    let end = repr
+                        .members
+                        .iter()
+                        .map(|m| m.offset + m.ty.byte_size().unwrap_or_default())
+                        .max()
+                        .unwrap_or_default();
+                    let align = self.largest_prim()?.byte_size().unwrap_or(1);
+                    end.next_multiple_of(align)
+                }
+            },
+        };
+        cu::ensure!(size != 0, "invalid zero-sized repr")?;
         Ok(size)
     }
+
+    /// Get an array of primitive with the same size and alignment, for
+    /// representing the type as an opaque blob
+    pub fn as_array(&self) -> cu::Result<(Prim, u32)> {
+        match self {
+            Self::Array(prim, len) => Ok((*prim, *len)),
+            Self::Struct(_) => {
+                let prim = self.largest_prim()?;
+                let size = cu::check!(prim.byte_size(), "invalid unsized repr")?;
+                Ok((prim, self.byte_size()? / size))
+            }
+        }
+    }
+
+    /// The primitive with the largest size, which determines the alignment
+    fn largest_prim(&self) -> cu::Result<Prim> {
+        let prim = match self {
+            Self::Array(prim, _) => Some(*prim),
+            Self::Struct(repr) => repr
+                .members
+                .iter()
+                .map(|m| m.ty)
+                .max_by_key(|p| p.byte_size().unwrap_or_default()),
+        };
+        cu::check!(prim, "repr struct must have members")
+    }
+}
+
+/// Which rows of the line table to keep
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineTableMode {
+    /// Do not load the line table
+    #[default]
+    Off,
+    /// Only keep the line of the entry point of each function
+    Functions,
+    /// Keep every address where the line changes
+    Full,
+}
+
+/// Mode for merging vtables
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VtableMergeMode {
+    /// Error if the same slot has different functions in different compilation units
+    #[default]
+    Strict,
+    /// Keep the first function if the same slot has different functions,
+    /// and allow the return types to differ (for covariant returns)
+    Lenient,
+}
+
+/// Mode for handling types with the same name that cannot be merged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeConflictMode {
+    /// Fail the extraction after reporting the conflicts
+    #[default]
+    Fail,
+    /// Keep both types, and rename the one from the later compilation unit
+    /// by suffixing its names with its offset. Types that contain the renamed
+    /// type are also kept separately
+    KeepBoth,
+}
+
+/// Mode for linking types in anonymous namespaces
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AnonymousNamespaceMode {
+    /// Merge types with the same name like other types. Structurally identical types
+    /// are merged, and different layouts with the same name are errors
+    #[default]
+    Merge,
+    /// Keep the types distinct by qualifying the anonymous namespace with
+    /// the name of the compilation unit
+    Distinct,
+}
+
+/// Mode for types defined inside functions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FunctionLocalTypesMode {
+    /// Keep the types, qualified by the function. Types in different functions
+    /// with the same export name are numbered to keep the names unique
+    #[default]
+    Keep,
+    /// Drop the types from the output, and replace references to them
+    /// with byte arrays of the same size
+    Drop,
+    /// Keep the types, qualified by the scope of the function as if they are defined
+    /// outside of the function. Types with the same name in different functions
+    /// are merged like other types
+    Hoist,
+}
+
+/// Rules for loading compile_commands.json, for example when the project
+/// is built in a container with paths that don't exist on the host
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtractCompdbConfig {
+    /// Path prefixes to replace in the file names and compile args, as `[from, to]` pairs.
+    /// The first matching prefix is used
+    #[serde(default)]
+    pub remap_prefixes: Vec<(String, String)>,
+    /// Only keep the entries with file names matching any of these regexes.
+    /// Empty to keep all entries. File names are matched before remapping
+    #[serde(default)]
+    pub include: Vec<SerdeRegex>,
+    /// Remove the entries with file names matching any of these regexes
+    #[serde(default)]
+    pub exclude: Vec<SerdeRegex>,
+    /// Compile command for compilation units without an entry, including the compiler.
+    /// `{file}` in the args is replaced with the remapped file name
+    #[serde(default)]
+    pub fallback_command: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,6 +395,10 @@ pub struct ExtractDebugConfig {
     /// Print hstage debug info to <outdir>/hstage.rs
     #[serde(default)]
     pub hstage: bool,
+    /// Export names folded by the type optimizer to <outdir>/name_graph.json
+    /// and <outdir>/name_graph.dot
+    #[serde(default)]
+    pub name_graph: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -108,6 +409,34 @@ pub struct ExtractTypeParserConfig {
     /// will be used instead of the typedef
     #[serde(default)]
     pub abandon_typedefs: Vec<SerdeRegex>,
+    /// If true, template arguments with unrecognized AST shape are kept
+    /// as opaque strings (the clang spelling) instead of failing the compilation unit
+    #[serde(default)]
+    pub allow_unknown_template_args: bool,
+    /// Max number of compilation units to parse type names for in one clang invocation.
+    /// Units with the same compile flags are combined into one translation unit.
+    /// 0 or 1 to invoke clang for each unit separately
+    #[serde(default)]
+    pub batch_size: usize,
+    /// How to parse the templated type names
+    #[serde(default)]
+    pub backend: TypeParserBackend,
+}
+
+/// Parser for the templated type names
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TypeParserBackend {
+    /// Invoke clang with `-ast-dump=json` on the source of the compilation unit
+    #[default]
+    Clang,
+    /// Use the built-in parser, which does not need clang or the sources.
+    /// It supports the common subset of the type syntax (qualified names, templates,
+    /// pointers, references, function types and member pointers), and fails
+    /// on names that need clang, like `decltype`
+    Builtin,
+    /// Use clang if it can be found, otherwise the built-in parser
+    Auto,
 }
 
 #[derive(Debug, Deserialize)]
@@ -123,6 +452,65 @@ pub struct ExtractTypeOptimizerConfig {
     /// Manually eliminate a struct/union to an enum
     #[serde(default)]
     pub enumeratorize: Vec<ExtractTypeOptimizerEnumeratorizeRule>,
+    /// Collapse internal helper types (for example, library implementation details)
+    /// into their owner types or into opaque blobs
+    #[serde(default)]
+    pub collapse: Vec<ExtractTypeOptimizerCollapseRule>,
+    /// Types with a name matching these regexes are never eliminated, collapsed
+    /// or given names by any optimizer
+    #[serde(default)]
+    pub exclude_types: Vec<SerdeRegex>,
+    /// After the other optimizers, keep one definition of the named structs and unions
+    /// with identical layouts, and emit the others as typedefs of that definition
+    #[serde(default)]
+    pub alias_identical_layouts: bool,
+    /// Types with a name matching these regexes are never aliased by
+    /// `alias-identical-layouts`, for example types that must stay distinct
+    /// for overloads in the decompiler
+    #[serde(default)]
+    pub keep_distinct: Vec<SerdeRegex>,
+    /// Turn the built-in optimizer passes on or off. The rule-based passes above
+    /// always run when they have rules
+    #[serde(default)]
+    pub passes: ExtractTypeOptimizerPasses,
+}
+
+/// Built-in optimizer passes that simplify trivially collapsible types
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct ExtractTypeOptimizerPasses {
+    /// Eliminate structs with only one member into the member type
+    pub single_member_struct: bool,
+    /// Inline the members of the base class into a struct that only has the
+    /// base class as member, but cannot be eliminated (for example, because it
+    /// has its own virtual functions)
+    pub single_base_member_struct: bool,
+    /// Convert unions with no members into empty structs
+    pub empty_union: bool,
+    /// Eliminate unions with only one member into the member type
+    pub single_member_union: bool,
+    /// Eliminate unions with one composite member and one non-composite member
+    /// into the composite member, if it has the same size as the union
+    pub two_member_union: bool,
+    /// Eliminate unions whose members are all the same type into that type
+    pub same_type_union: bool,
+    /// Eliminate unions with 2 composite members of the same size as the union
+    /// into the member whose type is used elsewhere
+    pub two_member_union_by_usage: bool,
+}
+
+impl Default for ExtractTypeOptimizerPasses {
+    fn default() -> Self {
+        Self {
+            single_member_struct: true,
+            single_base_member_struct: false,
+            empty_union: true,
+            single_member_union: true,
+            two_member_union: true,
+            same_type_union: true,
+            two_member_union_by_usage: true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -138,6 +526,30 @@ pub struct ExtractTypeOptimizerPickUnionMemberRule {
     pub pick: usize,
 }
 
+/// Collapse a struct matching the regex, so internal helper types like
+/// `std::_Vector_base` or `std::__compressed_pair` don't show up in the output.
+///
+/// It is a match as long as any permutation in the fully-qualified name of the type
+/// matches the regex.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExtractTypeOptimizerCollapseRule {
+    /// Regex for matching the helper type name
+    pub regex: SerdeRegex,
+    /// What to collapse the helper type into
+    pub into: ExtractTypeOptimizerCollapseInto,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtractTypeOptimizerCollapseInto {
+    /// Inline the members of the helper type into every struct
+    /// that has it as a base class or a member
+    Owner,
+    /// Replace the members of the helper type with a byte array of the same size
+    Opaque,
+}
+
 /// (Struct/Union name, Enum name)
 /// When matched a struct or union type and an enum type, the struct/union will be replaced
 /// with the enum type, and give the names to the enum.
@@ -173,6 +585,17 @@ pub struct ExtractNameResolutionConfig {
     /// Tests for the rules. The first name should be preferred over the second
     #[serde(default)]
     pub test: Vec<(String, String)>,
+    /// Types with a name matching any of these regexes are dropped from the output,
+    /// and references to them are replaced with byte arrays of the same size.
+    ///
+    /// It is a match as long as any permutation in the fully-qualified name of the type
+    /// matches the regex.
+    #[serde(default)]
+    pub deny: Vec<SerdeRegex>,
+    /// Types with a name matching any of these regexes are kept,
+    /// even if the name matches a deny rule
+    #[serde(default)]
+    pub allow: Vec<SerdeRegex>,
 }
 
 impl ExtractNameResolutionConfig {
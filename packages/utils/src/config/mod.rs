@@ -5,9 +5,10 @@ mod paths;
 pub use paths::*;
 mod extract;
 pub use extract::*;
+mod export;
+pub use export::*;
 
 use cu::pre::*;
-use tyyaml::Prim;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
@@ -15,6 +16,8 @@ pub struct Config {
     pub hash: u64,
     pub paths: PathsConfig,
     pub extract: ExtractConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
 }
 
 impl Config {
@@ -28,6 +31,47 @@ impl Config {
 
         let base = path.parent_abs()?;
         config.paths.resolve_paths(&base)?;
+        if let Some(output_dir) = &mut config.export.output_dir {
+            paths::resolve_path(&base, output_dir)?;
+        }
+        for format in config.export.formats.values_mut() {
+            if let Some(template) = &mut format.template {
+                paths::resolve_path(&base, template)?;
+            }
+        }
+
+        // validate [paths]
+        match config.paths.symbols.source {
+            SymbolsSource::Csv => {
+                if config.paths.functions_csv.is_none() || config.paths.data_csv.is_none() {
+                    cu::bail!(
+                        "config.paths.functions-csv and config.paths.data-csv are required when config.paths.symbols.source = \"csv\""
+                    );
+                }
+            }
+            SymbolsSource::Elf => {}
+            SymbolsSource::MapFile | SymbolsSource::Nm | SymbolsSource::LinkerMap => {
+                if config.paths.symbols.path.is_none() {
+                    cu::bail!(
+                        "config.paths.symbols.path is required when loading symbols from a map file, nm output or linker map"
+                    );
+                }
+            }
+        }
+
+        for r in &config.paths.symbols.relocations {
+            if r.size == 0 || r.start.checked_add(r.size).is_none() {
+                cu::bail!(
+                    "invalid relocation in config.paths.symbols.relocations at 0x{:x}: size must be non-zero and the section must not overflow",
+                    r.start
+                );
+            }
+        }
+        if config.paths.symbols.detect_base_address && config.paths.symbols.base_address != 0 {
+            cu::bail!(
+                "config.paths.symbols.base-address and config.paths.symbols.detect-base-address cannot be both set"
+            );
+        }
 
         // validate [extract]
         match config.extract.pointer_width {
@@ -35,17 +79,20 @@ impl Config {
             _ => cu::bail!("invalid config.extract.pointer-width. must be 8, 16, 32 or 64"),
         }
 
-        if Prim::Void == config.extract.ptmf_repr.0 {
-            cu::bail!("PTMF repr type must be sized");
-        }
-        if config.extract.ptmf_repr.1 == 0 {
-            cu::bail!("PTMF repr type must be non-zero size");
-        }
-        if Prim::Void == config.extract.ptmd_repr.0 {
-            cu::bail!("PTMD repr type must be sized");
-        }
-        if config.extract.ptmd_repr.1 == 0 {
-            cu::bail!("PTMD repr type must be non-zero size");
+        cu::check!(
+            config.extract.ptmf_repr.validate(),
+            "invalid config.extract.ptmf-repr"
+        )?;
+        cu::check!(
+            config.extract.ptmd_repr.validate(),
+            "invalid config.extract.ptmd-repr"
+        )?;
+        for (start, end) in &config.extract.symbol_address_ranges {
+            if start >= end {
+                cu::bail!(
+                    "invalid range in config.extract.symbol-address-ranges: [0x{start:x}, 0x{end:x}], start must be less than end"
+                );
+            }
         }
         if config.extract.build_command.is_empty() {
             cu::bail!("config.extract.build-command must be non-empty")
@@ -68,6 +115,20 @@ impl Config {
             }
         }
 
+        let mut seen_regex = BTreeSet::new();
+        for regex in &config.extract.type_optimizer.exclude_types {
+            if !seen_regex.insert(regex.to_str()) {
+                cu::bail!("exclude-types has duplicate regex: {regex}");
+            }
+        }
+
+        let mut seen_regex = BTreeSet::new();
+        for rule in &config.extract.type_optimizer.collapse {
+            if !seen_regex.insert(rule.regex.to_str()) {
+                cu::bail!("collapse rule has duplicate rule: {}", rule.regex);
+            }
+        }
+
         Ok(config)
     }
 }
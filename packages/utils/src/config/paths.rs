@@ -11,7 +11,8 @@ use cu::pre::*;
 pub struct PathsConfig {
     /// Path to the directory to invoke the build command
     pub build_dir: PathBuf,
-    /// Path to the ELF file for extract.
+    /// Path to the ELF file for extract. Mach-O (including dSYM) and PE images
+    /// with DWARF sections are also supported
     pub elf: PathBuf,
     /// Path to the output directory for the extract command.
     pub extract_output: PathBuf,
@@ -23,14 +24,19 @@ pub struct PathsConfig {
     /// option.
     pub system_header_paths: Vec<PathBuf>,
 
-    /// Configuration for the functions CSV file
+    /// Where to load the symbol listing from
+    #[serde(default)]
+    pub symbols: SymbolsConfig,
+    /// Configuration for the functions CSV file. Required if symbols are loaded from CSV
     ///
     /// **This is deprecated and the format for symbol listing will change in the future**
-    pub functions_csv: SymListConfig,
-    /// Configuration for the data CSV file
+    #[serde(default)]
+    pub functions_csv: Option<SymListConfig>,
+    /// Configuration for the data CSV file. Required if symbols are loaded from CSV
     ///
     /// **This is deprecated and the format for symbol listing will change in the future**
-    pub data_csv: SymListConfig,
+    #[serde(default)]
+    pub data_csv: Option<SymListConfig>,
 }
 
 impl PathsConfig {
@@ -43,12 +49,74 @@ impl PathsConfig {
             .iter_mut()
             .map(|x| resolve_path(base, x))
             .collect::<Result<Vec<()>, _>>()?;
-        resolve_path(base, &mut self.functions_csv.path)?;
-        resolve_path(base, &mut self.data_csv.path)?;
+        if let Some(path) = &mut self.symbols.path {
+            resolve_path(base, path)?;
+        }
+        if let Some(csv) = &mut self.functions_csv {
+            resolve_path(base, &mut csv.path)?;
+        }
+        if let Some(csv) = &mut self.data_csv {
+            resolve_path(base, &mut csv.path)?;
+        }
         Ok(())
     }
 }
 
+/// Configuration for loading the symbol listing
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SymbolsConfig {
+    /// Source of the symbol listing
+    #[serde(default)]
+    pub source: SymbolsSource,
+    /// Base address to subtract from symbol values, when loading from the ELF,
+    /// map file, nm output or linker map
+    #[serde(default)]
+    pub base_address: u64,
+    /// Path to the symbol file. Required for the map file, nm output and linker map sources
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Use the lowest virtual address of the loadable segments in the ELF program headers
+    /// as the base address, instead of `base-address`
+    #[serde(default)]
+    pub detect_base_address: bool,
+    /// Relocations of the addresses in the symbol listing by section, for listings where
+    /// the sections are not loaded at the same offsets as in the ELF (for example,
+    /// runtime dumps). Applies to all sources, including CSV. Addresses not in any
+    /// relocation fall back to subtracting the base address
+    #[serde(default)]
+    pub relocations: Vec<SymbolRelocation>,
+}
+
+/// Relocation of the addresses in one section of the symbol listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SymbolRelocation {
+    /// Start address of the section in the symbol listing
+    pub start: u64,
+    /// Size of the section in bytes
+    pub size: u64,
+    /// Relative address of the start of the section, for example the virtual
+    /// address of the section in the ELF minus the base address
+    pub target: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymbolsSource {
+    /// Load from `paths.functions-csv` and `paths.data-csv`
+    #[default]
+    Csv,
+    /// Load from `.symtab` and `.dynsym` of `paths.elf`
+    Elf,
+    /// Load from `paths.symbols.path` with one `name=address` per line
+    MapFile,
+    /// Load from `paths.symbols.path` with the output of `nm`
+    Nm,
+    /// Load from `paths.symbols.path` with the map file from the linker
+    LinkerMap,
+}
+
 /// Configuration for CSV data
 ///
 /// **This is deprecated and the format for symbol listing will change in the future**
@@ -68,7 +136,7 @@ pub struct SymListConfig {
     pub skip_rows: usize,
 }
 
-fn resolve_path(base: &Path, path: &mut PathBuf) -> cu::Result<()> {
+pub(crate) fn resolve_path(base: &Path, path: &mut PathBuf) -> cu::Result<()> {
     if !path.is_absolute() {
         *path = base.join(&path).normalize()?;
     }
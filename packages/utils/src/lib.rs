@@ -2,4 +2,6 @@ mod serde_impl;
 pub use serde_impl::*;
 mod config;
 pub use config::*;
+mod symbol_source;
+pub use symbol_source::*;
 pub mod persist_map;
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use cu::pre::*;
+
+use crate::{PathsConfig, SymListConfig, SymbolRelocation, SymbolsSource};
+
+/// Function and data symbols, with addresses relative to a base address
+#[derive(Debug, Default)]
+pub struct SymbolMaps {
+    pub funcs: BTreeMap<String, u32>,
+    pub data: BTreeMap<String, u32>,
+}
+
+/// A format of symbol listing that symbols can be loaded from.
+///
+/// Sources that cannot tell functions and data apart put every symbol in
+/// the functions. This only means the ctor and dtor variants are also checked
+/// for every symbol when loading
+pub trait SymbolSource: Send + Sync {
+    /// Name of the format for messages, for example `"CSV"`
+    fn name(&self) -> &'static str;
+    /// Load the symbols, with addresses relative to the base address
+    fn load(&self) -> cu::Result<SymbolMaps>;
+}
+
+impl SymbolMaps {
+    /// Remove the symbols with addresses not matching the predicate
+    pub fn retain_addresses(&mut self, mut f: impl FnMut(u32) -> bool) {
+        self.funcs.retain(|_, address| f(*address));
+        self.data.retain(|_, address| f(*address));
+    }
+}
+
+/// Converts the addresses in a symbol listing to addresses relative to the program
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AddressRelocator<'a> {
+    /// Base address to subtract from addresses not in any relocation
+    pub base_address: u64,
+    pub relocations: &'a [SymbolRelocation],
+}
+
+impl AddressRelocator<'_> {
+    /// Get the relative address. Addresses in a relocated section are relative to
+    /// the target of the section, and the others are relative to the base address.
+    /// None if the address is less than the base address
+    pub fn relocate(&self, address: u64) -> Option<u64> {
+        for r in self.relocations {
+            if (r.start..r.start + r.size).contains(&address) {
+                return Some(address - r.start + r.target);
+            }
+        }
+        address.checked_sub(self.base_address)
+    }
+}
+
+impl PathsConfig {
+    /// Get the symbol source that reads the symbols from files, as configured in
+    /// `paths.symbols.source`. None if the symbols are loaded from the ELF,
+    /// which is read by the extractor.
+    ///
+    /// `base_address` is from the config, or detected from the ELF if configured
+    pub fn symbol_file_source(
+        &self,
+        base_address: u64,
+    ) -> cu::Result<Option<Box<dyn SymbolSource + '_>>> {
+        let relocator = self.symbol_relocator(base_address);
+        let source: Box<dyn SymbolSource + '_> = match self.symbols.source {
+            SymbolsSource::Elf => return Ok(None),
+            SymbolsSource::Csv => {
+                // validated when loading the config
+                let functions =
+                    cu::check!(self.functions_csv.as_ref(), "missing functions CSV config")?;
+                let data = cu::check!(self.data_csv.as_ref(), "missing data CSV config")?;
+                Box::new(CsvSymbolSource {
+                    functions,
+                    data,
+                    relocations: &self.symbols.relocations,
+                })
+            }
+            SymbolsSource::MapFile => Box::new(MapFileSymbolSource {
+                path: self.symbol_path()?,
+                relocator,
+            }),
+            SymbolsSource::Nm => Box::new(NmSymbolSource {
+                path: self.symbol_path()?,
+                relocator,
+            }),
+            SymbolsSource::LinkerMap => Box::new(LinkerMapSymbolSource {
+                path: self.symbol_path()?,
+                relocator,
+            }),
+        };
+        Ok(Some(source))
+    }
+
+    /// Get the relocator for the addresses in the symbol listing, with the
+    /// relocations in `paths.symbols.relocations`
+    pub fn symbol_relocator(&self, base_address: u64) -> AddressRelocator<'_> {
+        AddressRelocator {
+            base_address,
+            relocations: &self.symbols.relocations,
+        }
+    }
+
+    fn symbol_path(&self) -> cu::Result<&Path> {
+        // validated when loading the config
+        cu::check!(
+            self.symbols.path.as_deref(),
+            "missing paths.symbols.path config"
+        )
+    }
+}
+
+/// Symbols from another source, limited to the `[start, end)` address ranges
+pub struct AddressRangeSymbolSource<'a> {
+    pub inner: Box<dyn SymbolSource + 'a>,
+    pub ranges: &'a [(u32, u32)],
+}
+
+impl SymbolSource for AddressRangeSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let mut maps = self.inner.load()?;
+        let total = maps.funcs.len() + maps.data.len();
+        maps.retain_addresses(|address| {
+            self.ranges
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&address))
+        });
+        cu::debug!(
+            "skipped {} symbols outside of the address ranges",
+            total - maps.funcs.len() - maps.data.len()
+        );
+        Ok(maps)
+    }
+}
+
+/// Functions and data from separate CSV files
+///
+/// **This is deprecated and the format for symbol listing will change in the future**
+pub struct CsvSymbolSource<'a> {
+    pub functions: &'a SymListConfig,
+    pub data: &'a SymListConfig,
+    pub relocations: &'a [SymbolRelocation],
+}
+
+impl SymbolSource for CsvSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        "CSV"
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let funcs = cu::check!(
+            load_symbol_csv(self.functions, self.relocations),
+            "failed to load func symbols"
+        )?;
+        let data = cu::check!(
+            load_symbol_csv(self.data, self.relocations),
+            "failed to load data symbols"
+        )?;
+        Ok(SymbolMaps { funcs, data })
+    }
+}
+
+/// Load symbols from the CSV file. The addresses in the relocated sections are relocated,
+/// and the others are relative to the base address of the CSV file
+pub fn load_symbol_csv(
+    config: &SymListConfig,
+    relocations: &[SymbolRelocation],
+) -> cu::Result<BTreeMap<String, u32>> {
+    let content = cu::fs::read_string(&config.path)?;
+    let relocator = AddressRelocator {
+        base_address: config.base_address,
+        relocations,
+    };
+    let address_column = config.address_column;
+    let symbol_column = config.symbol_column;
+
+    let mut map = BTreeMap::default();
+
+    for (i, line) in content.lines().enumerate().skip(config.skip_rows) {
+        let row = i + 1;
+        let parts = line.split(',').collect::<Vec<_>>();
+        let address = cu::check!(
+            parts.get(address_column),
+            "failed to get address column at row {row} (address_column={address_column})"
+        )?;
+        let address = cu::check!(
+            cu::parse::<u64>(address),
+            "failed to parse address at row {row}"
+        )?;
+        let rel_address = relative_address(address, &relocator, row)?;
+
+        let symbol = cu::check!(
+            parts.get(symbol_column),
+            "failed to get symbol column at row {row} (symbol_column={symbol_column})"
+        )?;
+        let symbol = symbol.trim();
+
+        if symbol.is_empty() {
+            continue;
+        }
+
+        map.insert(symbol.to_string(), rel_address);
+    }
+
+    Ok(map)
+}
+
+/// Text file with one `name=address` per line. Addresses are hex with `0x`, or decimal.
+/// Empty lines and lines starting with `#` are ignored
+pub struct MapFileSymbolSource<'a> {
+    pub path: &'a Path,
+    pub relocator: AddressRelocator<'a>,
+}
+
+impl SymbolSource for MapFileSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        "map file"
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let content = cu::fs::read_string(self.path)?;
+        let mut output = SymbolMaps::default();
+        for (i, line) in content.lines().enumerate() {
+            let row = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // names could contain '=' (for example, operator=)
+            let (name, address) = cu::check!(
+                line.rsplit_once('='),
+                "expecting name=address at line {row}"
+            )?;
+            let address = cu::check!(
+                parse_address(address.trim()),
+                "failed to parse address at line {row}"
+            )?;
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            let rel_address = relative_address(address, &self.relocator, row)?;
+            output.funcs.insert(name.to_string(), rel_address);
+        }
+        Ok(output)
+    }
+}
+
+/// Output of `nm` (with or without `-S`), for example `0000000000401000 T main`
+pub struct NmSymbolSource<'a> {
+    pub path: &'a Path,
+    pub relocator: AddressRelocator<'a>,
+}
+
+impl SymbolSource for NmSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        "nm output"
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let content = cu::fs::read_string(self.path)?;
+        let mut output = SymbolMaps::default();
+        for (i, line) in content.lines().enumerate() {
+            let row = i + 1;
+            let parts = line.split_whitespace().collect::<Vec<_>>();
+            // address, (size), type, name. Undefined symbols have no address
+            let (address, kind, name) = match parts.as_slice() {
+                [address, kind, name] if kind.len() == 1 => (*address, *kind, *name),
+                [address, _, kind, name] if kind.len() == 1 => (*address, *kind, *name),
+                _ => continue,
+            };
+            let map = match kind {
+                "T" | "t" | "W" | "w" | "i" => &mut output.funcs,
+                "D" | "d" | "B" | "b" | "R" | "r" | "V" | "v" | "G" | "g" | "S" | "s" => {
+                    &mut output.data
+                }
+                _ => continue,
+            };
+            let address = cu::check!(
+                u64::from_str_radix(address, 16).ok(),
+                "failed to parse address at line {row}"
+            )?;
+            if map.contains_key(name) {
+                continue;
+            }
+            let rel_address = relative_address(address, &self.relocator, row)?;
+            map.insert(name.to_string(), rel_address);
+        }
+        Ok(output)
+    }
+}
+
+/// Map file from the linker (`-Map`), in the format of GNU ld or LLD.
+/// Symbols in `.text` sections are functions, and the others are data
+pub struct LinkerMapSymbolSource<'a> {
+    pub path: &'a Path,
+    pub relocator: AddressRelocator<'a>,
+}
+
+impl SymbolSource for LinkerMapSymbolSource<'_> {
+    fn name(&self) -> &'static str {
+        "linker map"
+    }
+    fn load(&self) -> cu::Result<SymbolMaps> {
+        let content = cu::fs::read_string(self.path)?;
+        let mut output = SymbolMaps::default();
+        let mut is_text = false;
+        for (i, line) in content.lines().enumerate() {
+            let row = i + 1;
+            let Some((address, name)) = parse_linker_map_line(line, &mut is_text) else {
+                continue;
+            };
+            let map = if is_text {
+                &mut output.funcs
+            } else {
+                &mut output.data
+            };
+            if map.contains_key(name) {
+                continue;
+            }
+            let rel_address = relative_address(address, &self.relocator, row)?;
+            map.insert(name.to_string(), rel_address);
+        }
+        Ok(output)
+    }
+}
+
+/// Parse a line in the linker map as (address, symbol name).
+/// Lines of output sections update if the following symbols are in `.text`
+fn parse_linker_map_line<'a>(line: &'a str, is_text: &mut bool) -> Option<(u64, &'a str)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        // GNU ld output section: .text 0x0000000000401000 0x1234
+        [section, address, ..] if section.starts_with('.') && address.starts_with("0x") => {
+            if !line.starts_with(' ') {
+                *is_text = is_text_section(section);
+            }
+            None
+        }
+        // GNU ld symbol: 0x0000000000401000 main
+        [address, name] if address.starts_with("0x") && is_symbol_name(name) => {
+            Some((parse_address(address)?, *name))
+        }
+        // LLD: VMA LMA Size Align then output section, input section, or symbol
+        [vma, lma, size, align, name] if [lma, size, align].iter().all(|x| is_hex(x)) => {
+            let vma = u64::from_str_radix(vma, 16).ok()?;
+            if name.starts_with('.') {
+                // output sections are indented less than input sections and symbols
+                if line.len() - line.trim_start().len() <= 8 {
+                    *is_text = is_text_section(name);
+                }
+                return None;
+            }
+            is_symbol_name(name).then_some((vma, *name))
+        }
+        _ => None,
+    }
+}
+
+fn is_text_section(name: &str) -> bool {
+    name == ".text" || name.starts_with(".text.")
+}
+
+fn is_symbol_name(name: &str) -> bool {
+    // input sections are like file.o:(.text) and assignments contain '='
+    !name.starts_with('.') && !name.contains(":(") && !name.contains('=') && !name.contains('*')
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Parse a hex address with `0x`, or a decimal address
+fn parse_address(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn relative_address(address: u64, relocator: &AddressRelocator, row: usize) -> cu::Result<u32> {
+    let rel_address = cu::check!(
+        relocator.relocate(address),
+        "address is less than base address and not in any relocated section at row {row}"
+    )?;
+    cu::ensure!(
+        rel_address <= u32::MAX as u64,
+        "relative address at row {row} is too big, this is likely wrong"
+    )?;
+    Ok(rel_address as u32)
+}